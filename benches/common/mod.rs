@@ -0,0 +1,136 @@
+//! generated datasets shared by the benchmarks. seeded, so every run sees the same rows.
+
+#![allow(dead_code)]
+
+use rust_decimal::Decimal;
+use txn::{ClientId, Txn, TxnId, TxnType};
+
+/// xorshift, enough to spread ids & amounts without pulling in a rng
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Workload {
+    /// deposits with the odd withdrawal, over a hundred clients
+    DepositHeavy,
+    /// every deposit disputed, then resolved or now & then charged back, over ten thousand clients
+    DisputeHeavy,
+    /// deposits & withdrawals spread over every client id
+    ManyClients,
+    /// one client taking everything
+    HotClient
+}
+
+pub const WORKLOADS: [Workload; 4] = [Workload::DepositHeavy, Workload::DisputeHeavy, Workload::ManyClients, Workload::HotClient];
+
+impl Workload {
+    pub fn name(self) -> &'static str {
+        match self {
+            Workload::DepositHeavy => "deposit_heavy",
+            Workload::DisputeHeavy => "dispute_heavy",
+            Workload::ManyClients => "many_clients",
+            Workload::HotClient => "hot_client"
+        }
+    }
+}
+
+/// a csv row: type, client, tx, amount in ten-thousandths
+pub struct Row(TxnType, u16, u32, Option<i64>);
+
+impl Row {
+    pub fn to_txn(&self) -> Txn {
+        Txn::builder(self.0.clone(), ClientId::from(self.1), TxnId::from(self.2)).amount(self.3.map(|a| Decimal::new(a, 4))).build().unwrap()
+    }
+
+    fn to_line(&self) -> String {
+        let amount = self.3.map(|a| Decimal::new(a, 4).to_string()).unwrap_or_default();
+        format!("{},{},{},{}\n", self.type_name(), self.1, self.2, amount)
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self.0 {
+            TxnType::Deposit => "deposit",
+            TxnType::Withdrawal => "withdrawal",
+            TxnType::Dispute => "dispute",
+            TxnType::Resolve => "resolve",
+            TxnType::Chargeback => "chargeback"
+        }
+    }
+}
+
+pub fn generate(workload: Workload, rows: usize) -> Vec<Row> {
+    stream(workload).take(rows).collect()
+}
+
+/// the workload's rows, endlessly
+pub fn stream(workload: Workload) -> impl Iterator<Item = Row> {
+    let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+    let mut tx = 0u32;
+    std::iter::from_fn(move || {
+        tx += 1;
+        let amount = Some(1 + rng.below(1_000_000) as i64);
+        let rows = match workload {
+            Workload::DepositHeavy => {
+                let client = rng.below(100) as u16;
+                let txntype = if rng.below(10) == 0 { TxnType::Withdrawal } else { TxnType::Deposit };
+                vec![Row(txntype, client, tx, amount)]
+            },
+            Workload::DisputeHeavy => {
+                let client = rng.below(10_000) as u16;
+                // chargebacks lock the account, so they're kept rare enough that few clients end up locked
+                let settle = if rng.below(50) == 0 { TxnType::Chargeback } else { TxnType::Resolve };
+                vec![Row(TxnType::Deposit, client, tx, amount), Row(TxnType::Dispute, client, tx, None), Row(settle, client, tx, None)]
+            },
+            Workload::ManyClients => {
+                let client = rng.next() as u16;
+                let txntype = if rng.below(4) == 0 { TxnType::Withdrawal } else { TxnType::Deposit };
+                vec![Row(txntype, client, tx, amount)]
+            },
+            Workload::HotClient => {
+                let txntype = match rng.below(10) {
+                    0..=5 => TxnType::Deposit,
+                    6..=8 => TxnType::Withdrawal,
+                    _ => TxnType::Dispute
+                };
+                let amount = if txntype == TxnType::Dispute { None } else { amount };
+                let target = if txntype == TxnType::Dispute { tx - 1 } else { tx };
+                vec![Row(txntype, 1, target, amount)]
+            }
+        };
+        Some(rows)
+    }).flatten()
+}
+
+/// rows as a csv file, header included
+pub fn to_csv(rows: &[Row]) -> String {
+    let mut csv = String::from("type,client,tx,amount\n");
+    for row in rows {
+        csv.push_str(&row.to_line());
+    }
+    csv
+}
+
+/// `rows` of the workload as a csv file, without holding them all first
+pub fn csv_bytes(workload: Workload, rows: usize) -> Vec<u8> {
+    let mut csv = b"type,client,tx,amount\n".to_vec();
+    for row in stream(workload).take(rows) {
+        csv.extend_from_slice(row.to_line().as_bytes());
+    }
+    csv
+}
@@ -0,0 +1,98 @@
+//! throughput of the hot paths, `execute_with`, `execute_columns` (`--columnar`), `deserialize_record` &
+//! `write_out`, per generated workload.
+//!
+//! `cargo bench --bench engine`, or i.e. `cargo bench --bench engine -- dispute_heavy` for one workload
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use txn::config::{Config, OutputOptions};
+use txn::{deserialize_record, execute_columns, execute_with, write_out, Accounts, Columns, Txn, CURRENCY_PRECISION};
+
+mod common;
+
+use common::{generate, to_csv, WORKLOADS};
+
+const ROWS: usize = 100_000;
+/// rows per batch, as `--columnar` executes them
+const BATCH: usize = 64 * 1024;
+
+fn run(txns: &[Txn], config: &Config) -> Accounts {
+    let mut accounts = Accounts::default();
+    for txn in txns {
+        let _ = execute_with(&mut accounts, txn.clone(), config);
+    }
+    accounts
+}
+
+fn execute(c: &mut Criterion) {
+    let config = Config::default();
+    let mut group = c.benchmark_group("execute");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    for workload in WORKLOADS {
+        let txns: Vec<Txn> = generate(workload, ROWS).iter().map(|r| r.to_txn()).collect();
+        group.bench_with_input(BenchmarkId::from_parameter(workload.name()), &txns, |b, txns| {
+            b.iter(|| run(black_box(txns), &config).len())
+        });
+    }
+    group.finish();
+}
+
+fn execute_batched(c: &mut Criterion) {
+    let config = Config::default();
+    let mut group = c.benchmark_group("execute_columns");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    for workload in WORKLOADS {
+        let batches: Vec<Columns> = generate(workload, ROWS).chunks(BATCH)
+            .map(|rows| rows.iter().map(|r| r.to_txn()).collect())
+            .collect();
+        group.bench_with_input(BenchmarkId::from_parameter(workload.name()), &batches, |b, batches| {
+            b.iter(|| {
+                let mut accounts = Accounts::default();
+                for batch in batches {
+                    black_box(execute_columns(&mut accounts, batch, &config));
+                }
+                accounts.len()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize_record");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    for workload in WORKLOADS {
+        let csv = to_csv(&generate(workload, ROWS));
+        let records: Vec<csv::StringRecord> = csv::Reader::from_reader(csv.as_bytes()).into_records()
+            .map(Result::unwrap)
+            .collect();
+        group.bench_with_input(BenchmarkId::from_parameter(workload.name()), &records, |b, records| {
+            b.iter(|| {
+                for record in records {
+                    black_box(deserialize_record(&mut record.clone(), CURRENCY_PRECISION).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn output(c: &mut Criterion) {
+    let path = std::env::temp_dir().join(format!("txn-bench-{}.csv", std::process::id()));
+    let options = OutputOptions { path: Some(path.clone()), sort: true, ..OutputOptions::default() };
+    let mut group = c.benchmark_group("write_out");
+    for workload in WORKLOADS {
+        let txns: Vec<Txn> = generate(workload, ROWS).iter().map(|r| r.to_txn()).collect();
+        let accounts = run(&txns, &Config::default());
+        group.throughput(Throughput::Elements(accounts.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(workload.name()), &accounts, |b, accounts| {
+            b.iter(|| write_out(accounts, &options).unwrap())
+        });
+    }
+    group.finish();
+    let _ = std::fs::remove_file(&path);
+}
+
+criterion_group!(benches, execute, execute_batched, deserialize, output);
+criterion_main!(benches);
@@ -0,0 +1,104 @@
+//! the account & transaction log maps under SipHash (std's default), FxHash (what txn uses) and, for client ids,
+//! a flat table indexed by id. ids are generated the way they tend to arrive: tx ids climbing with gaps, client ids
+//! spread across the whole u16 range.
+//!
+//! `cargo bench --bench hashers`
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, BuildHasherDefault, RandomState};
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_decimal::Decimal;
+use rustc_hash::FxHasher;
+use txn::config::Config;
+use txn::{execute_with, Accounts, ClientId, Txn, TxnId, TxnType};
+
+mod common;
+
+use common::Rng;
+
+const ROWS: usize = 100_000;
+
+fn txn_ids() -> Vec<u32> {
+    let mut ids = Rng::new(0x2545_f491_4f6c_dd1d);
+    let mut tx = 0u32;
+    (0..ROWS).map(|_| {
+        tx += 1 + (ids.next() % 8) as u32;
+        tx
+    }).collect()
+}
+
+fn client_ids() -> Vec<u16> {
+    let mut ids = Rng::new(0x9e37_79b9_7f4a_7c15);
+    (0..ROWS).map(|_| ids.next() as u16).collect()
+}
+
+fn log_and_lookup<S: BuildHasher + Default>(ids: &[u32]) -> u64 {
+    let mut log: HashMap<u32, u64, S> = HashMap::default();
+    for &id in ids {
+        log.insert(id, id as u64);
+    }
+    ids.iter().map(|id| log[id]).sum()
+}
+
+fn tally<S: BuildHasher + Default>(clients: &[u16]) -> u64 {
+    let mut accounts: HashMap<u16, u64, S> = HashMap::default();
+    for &client in clients {
+        *accounts.entry(client).or_default() += 1;
+    }
+    accounts.len() as u64
+}
+
+fn tally_table(clients: &[u16]) -> u64 {
+    let mut accounts = vec![0u64; u16::MAX as usize + 1];
+    for &client in clients {
+        accounts[client as usize] += 1;
+    }
+    accounts.iter().filter(|&&n| n > 0).count() as u64
+}
+
+fn maps(c: &mut Criterion) {
+    let txns = txn_ids();
+    let mut group = c.benchmark_group("txnlog");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    group.bench_function("siphash", |b| b.iter(|| log_and_lookup::<RandomState>(black_box(&txns))));
+    group.bench_function("fxhash", |b| b.iter(|| log_and_lookup::<BuildHasherDefault<FxHasher>>(black_box(&txns))));
+    group.finish();
+
+    let clients = client_ids();
+    let mut group = c.benchmark_group("accounts");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    group.bench_function("siphash", |b| b.iter(|| tally::<RandomState>(black_box(&clients))));
+    group.bench_function("fxhash", |b| b.iter(|| tally::<BuildHasherDefault<FxHasher>>(black_box(&clients))));
+    group.bench_function("table", |b| b.iter(|| tally_table(black_box(&clients))));
+    group.finish();
+}
+
+/// the engine as built, over deposits, a withdrawal for every fourth and a dispute for every tenth
+fn engine(c: &mut Criterion) {
+    let config = Config::default();
+    let clients = client_ids();
+    let txns: Vec<Txn> = txn_ids().into_iter().zip(clients.iter().map(|c| c % 1000)).enumerate().map(|(i, (tx, client))| {
+        let (client, tx) = (ClientId::from(client), TxnId::from(tx));
+        match i % 10 {
+            9 => Txn::new(TxnType::Dispute, client, TxnId(tx.0 - 1), None),
+            3 | 7 => Txn::builder(TxnType::Withdrawal, client, tx).amount(Decimal::new(5, 1)).build().unwrap(),
+            _ => Txn::builder(TxnType::Deposit, client, tx).amount(Decimal::new(15, 1)).build().unwrap()
+        }
+    }).collect();
+
+    let mut group = c.benchmark_group("execute");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(ROWS), &txns, |b, txns| b.iter(|| {
+        let mut accounts = Accounts::default();
+        for txn in txns {
+            let _ = execute_with(&mut accounts, txn.clone(), &config);
+        }
+        accounts.len()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, maps, engine);
+criterion_main!(benches);
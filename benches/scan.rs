@@ -0,0 +1,48 @@
+//! splitting `--mmap`'s chunks into records: csv-core's reader, as the mmap path runs without `--features simd`,
+//! against the vectorized scan (see src/scan.rs), over the deposit heavy workload (`benches/common`).
+//!
+//! `cargo bench --bench scan --features simd`, over a million rows. `TXN_BENCH_ROWS=100000000` runs it over the
+//! 100M row dataset instead, which takes ~2.5 GB of memory to hold
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+mod common;
+
+use common::{csv_bytes, Workload};
+
+const ROWS: usize = 1_000_000;
+
+/// fields seen, so neither side's work is optimized away. both read every row into the one record, as the mmap
+/// path does
+macro_rules! fields {
+    ($records:expr) => {{
+        let (mut records, mut record, mut fields) = ($records, csv::ByteRecord::new(), 0);
+        while records.read_byte_record(&mut record).unwrap() {
+            fields += record.len();
+        }
+        fields
+    }};
+}
+
+fn scan(c: &mut Criterion) {
+    let rows = std::env::var("TXN_BENCH_ROWS").ok().and_then(|r| r.parse().ok()).unwrap_or(ROWS);
+    let csv = csv_bytes(Workload::DepositHeavy, rows);
+    let body = &csv[csv.iter().position(|&b| b == b'\n').unwrap() + 1..];
+    assert_eq!(fields!(txn::scan::records(body)), rows * 4);
+
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Bytes(body.len() as u64));
+    if rows > ROWS {
+        group.sample_size(10);
+    }
+    group.bench_function("csv_core", |b| {
+        b.iter(|| fields!(csv::ReaderBuilder::new().has_headers(false).from_reader(black_box(body))))
+    });
+    group.bench_function("simd", |b| b.iter(|| fields!(txn::scan::records(black_box(body)))));
+    group.finish();
+}
+
+criterion_group!(benches, scan);
+criterion_main!(benches);
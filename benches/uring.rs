@@ -0,0 +1,50 @@
+//! reading a csv file: the standard reader, a read at a time, against the io_uring reader (see src/uring.rs), over
+//! the deposit heavy workload (`benches/common`) written to a temporary file, both through csv's reader as the file
+//! path parses it.
+//!
+//! `cargo bench --bench uring --features io-uring`, over a million rows. `TXN_BENCH_ROWS` sets another count. the
+//! file's in the page cache after the first iteration, so this times the reads' overhead & overlap with parsing
+//! rather than the disk: for a cold read, drop the cache first (`echo 3 > /proc/sys/vm/drop_caches`) and time
+//! `txn` on a file itself
+
+use std::fs::File;
+use std::io::Read;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+mod common;
+
+use common::{csv_bytes, Workload};
+
+const ROWS: usize = 1_000_000;
+
+/// fields read, so neither side's work is optimized away
+fn fields<R: Read>(reader: R) -> usize {
+    let (mut records, mut record, mut fields) = (csv::Reader::from_reader(reader), csv::ByteRecord::new(), 0);
+    while records.read_byte_record(&mut record).unwrap() {
+        fields += record.len();
+    }
+    fields
+}
+
+fn uring(c: &mut Criterion) {
+    let rows = std::env::var("TXN_BENCH_ROWS").ok().and_then(|r| r.parse().ok()).unwrap_or(ROWS);
+    let csv = csv_bytes(Workload::DepositHeavy, rows);
+    let path = std::env::temp_dir().join(format!("txn-uring-bench-{}.csv", std::process::id()));
+    std::fs::write(&path, &csv).unwrap();
+    let open = || File::open(&path).unwrap();
+    assert_eq!(fields(txn::uring::open(open())), rows * 4);
+    if !txn::uring::open(open()).is_ring() {
+        eprintln!("no io_uring here, both read the file as usual");
+    }
+
+    let mut group = c.benchmark_group("read");
+    group.throughput(Throughput::Bytes(csv.len() as u64));
+    group.bench_function("std", |b| b.iter(|| fields(open())));
+    group.bench_function("io_uring", |b| b.iter(|| fields(txn::uring::open(open()))));
+    group.finish();
+    std::fs::remove_file(&path).unwrap();
+}
+
+criterion_group!(benches, uring);
+criterion_main!(benches);
@@ -0,0 +1,5 @@
+fn main() {
+    // the node addon leaves napi's symbols for node to resolve, which macOS has to be told to allow
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
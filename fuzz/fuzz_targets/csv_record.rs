@@ -0,0 +1,27 @@
+//! arbitrary bytes through the csv row parsers, `deserialize_record` or the fast parser: neither may panic, and a
+//! transaction either one reads must read back the same from its json, as every format holds transactions to the
+//! same rules. the first byte picks the parser (its top bit) & the precision, the rest is the file
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use txn::{CsvOptions, CsvSource, JsonSource, TxnSource};
+
+fuzz_target!(|data: &[u8]| {
+    let (first, csv) = match data.split_first() {
+        Some(split) => split,
+        None => return
+    };
+    let precision = u32::from(first & 0x7f) % 29;
+    let options = CsvOptions { fast_parse: first & 0x80 != 0, ..CsvOptions::default() };
+    let mut source = CsvSource::new(csv, options, precision);
+    while let Some(read) = source.next_txn() {
+        match read {
+            Ok(txn) => {
+                let json = serde_json::to_string(&txn).expect("a transaction serializes");
+                assert_eq!(JsonSource::new(json.as_bytes(), precision).next_txn(), Some(Ok(txn)), "{}", json);
+            },
+            Err(e) if e.is_fatal() => break,
+            Err(_) => ()
+        }
+    }
+});
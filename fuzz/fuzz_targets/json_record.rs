@@ -0,0 +1,25 @@
+//! arbitrary bytes through the newline-delimited json parser: it mustn't panic, and a transaction it reads must
+//! read back the same from its own serialization. the first byte picks the precision, the rest is the file
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use txn::{JsonSource, TxnSource};
+
+fuzz_target!(|data: &[u8]| {
+    let (first, json) = match data.split_first() {
+        Some(split) => split,
+        None => return
+    };
+    let precision = u32::from(*first) % 29;
+    let mut source = JsonSource::new(json, precision);
+    while let Some(read) = source.next_txn() {
+        match read {
+            Ok(txn) => {
+                let json = serde_json::to_string(&txn).expect("a transaction serializes");
+                assert_eq!(JsonSource::new(json.as_bytes(), precision).next_txn(), Some(Ok(txn)), "{}", json);
+            },
+            Err(e) if e.is_fatal() => break,
+            Err(_) => ()
+        }
+    }
+});
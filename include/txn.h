@@ -0,0 +1,116 @@
+/* generated by cbindgen from src/ffi.rs, don't edit */
+
+#ifndef TXN_H
+#define TXN_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define TXN_DEPOSIT 0
+
+#define TXN_WITHDRAWAL 1
+
+#define TXN_DISPUTE 2
+
+#define TXN_RESOLVE 3
+
+#define TXN_CHARGEBACK 4
+
+#define TXN_OK 0
+
+#define TXN_REJECTED_LOCKED 1
+
+#define TXN_REJECTED_OVER_LIMIT 2
+
+#define TXN_REJECTED_OVERFLOW 3
+
+#define TXN_REJECTED_INSUFFICIENT_FUNDS 4
+
+#define TXN_REJECTED_UNKNOWN_TXN 5
+
+#define TXN_REJECTED_ALREADY_DISPUTED 6
+
+#define TXN_REJECTED_WITHDRAWAL_DISPUTE 7
+
+#define TXN_REJECTED_NOT_DISPUTED 8
+
+#define TXN_REJECTED_LATE 9
+
+#define TXN_REJECTED_CHARGED_BACK 10
+
+#define TXN_REJECTED_REDISPUTE 11
+
+#define TXN_REJECTED_WRONG_CLIENT 12
+
+#define TXN_REJECTED_ESCROW_DISPUTE 13
+
+#define TXN_REJECTED_CURRENCY_MISMATCH 14
+
+#define TXN_REJECTED_DECLINED 15
+
+#define TXN_REJECTED_PRUNED 16
+
+#define TXN_REJECTED_DUPLICATE 17
+
+// a null engine or out pointer
+#define TXN_ERR_NULL -1
+
+// an unknown transaction type
+#define TXN_ERR_INVALID_TYPE -2
+
+// a negative amount, or an amount or balance being read that doesn't fit
+#define TXN_ERR_OUT_OF_RANGE -3
+
+// the client has no account
+#define TXN_ERR_NO_ACCOUNT -4
+
+#define TXN_ERR_PANIC -5
+
+// an engine with the default config, opaque to C
+typedef struct TxnEngine TxnEngine;
+
+// a client's balance, amounts in ten-thousandths
+typedef struct TxnBalance {
+  int64_t available;
+  int64_t held;
+  int64_t total;
+  bool locked;
+} TxnBalance;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// a new engine, to be freed with `txn_engine_free`. null if it couldn't be made
+struct TxnEngine *txn_engine_new(void);
+
+// executes a transaction of `txntype` (`TXN_DEPOSIT`...). `amount`, in ten-thousandths, is only read for deposits
+// & withdrawals
+//
+// # Safety
+// `engine` must be null or from `txn_engine_new`, not yet freed
+int32_t txn_engine_execute(struct TxnEngine *engine,
+                           uint8_t txntype,
+                           uint16_t client,
+                           uint32_t tx,
+                           int64_t amount);
+
+// writes the client's balance to `out`
+//
+// # Safety
+// `engine` must be null or from `txn_engine_new`, not yet freed, and `out` null or valid for writes
+int32_t txn_engine_balance(const struct TxnEngine *engine, uint16_t client, struct TxnBalance *out);
+
+// frees an engine from `txn_engine_new`. null is ignored
+//
+// # Safety
+// `engine` must be null or from `txn_engine_new`, and not freed before
+void txn_engine_free(struct TxnEngine *engine);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TXN_H */
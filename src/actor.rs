@@ -0,0 +1,253 @@
+//! `--actors`: an actor per client for server mode. each client seen gets a thread of its own that owns its
+//! account outright and works through a mailbox of transactions, so one client's transactions apply in the order
+//! they're routed to it while different clients' apply across every core, with no locks around the accounts.
+//!
+//! actors live as long as the server, a small stack each. a snapshot of the balances asks every actor in turn.
+//! each transaction carries the config it's executed under, so a reloaded config reaches every actor.
+
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::sync::mpsc::{channel, Receiver, Sender};
+use crate::sync::{Mutex, thread};
+use crate::{Account, Accounts, adjust_with, Amount, ClientId, execute_with, forget_with, Map, prune_with, Rejection, Txn, TxnId, validate_with};
+
+/// actors only run the engine, they don't need much
+const STACK_SIZE: usize = 256 * 1024;
+
+enum Message {
+    Execute(Txn, Arc<Config>, Sender<Result<(), Rejection>>),
+    /// what executing would come to, applying nothing
+    Validate(Txn, Arc<Config>, Sender<Result<(), Rejection>>),
+    /// an admin's adjustment, see admin.rs
+    Adjust(Amount, Arc<Config>, Sender<Result<(), Rejection>>),
+    /// an admin's erasure of who the client is, see admin.rs
+    Forget(Sender<Result<(), String>>),
+    /// prunes transactions up to the tx, see retention.rs
+    Prune(TxnId),
+    /// the account's balance & lock, None if it was never opened
+    Balance(Sender<Option<Account>>),
+    /// as well as what it has under dispute
+    Account(Sender<Option<Account>>),
+    Stop
+}
+
+pub(crate) struct Actors {
+    mailboxes: Mutex<Map<ClientId, Sender<Message>>>
+}
+
+impl Actors {
+    pub(crate) fn new() -> Self {
+        Actors { mailboxes: Mutex::new(Map::default()) }
+    }
+
+    /// routes to the client's actor, starting one if it has none yet, and waits on the outcome
+    pub(crate) fn execute(&self, txn: Txn, config: &Arc<Config>) -> Result<(), Rejection> {
+        let mailbox = self.mailbox(txn.client);
+        let (reply_tx, reply_rx) = channel();
+        mailbox.send(Message::Execute(txn, Arc::clone(config), reply_tx)).expect("client actor stopped");
+        reply_rx.recv().expect("client actor stopped")
+    }
+
+    pub(crate) fn balances(&self) -> Accounts {
+        let mailboxes: Vec<(ClientId, Sender<Message>)> = self.mailboxes.lock().unwrap()
+            .iter()
+            .map(|(client, mailbox)| (*client, mailbox.clone()))
+            .collect();
+        mailboxes.into_iter()
+            .filter_map(|(client, mailbox)| {
+                let (reply_tx, reply_rx) = channel();
+                mailbox.send(Message::Balance(reply_tx)).expect("client actor stopped");
+                Some((client, reply_rx.recv().expect("client actor stopped")?))
+            })
+            .collect()
+    }
+
+    /// what `execute` would come to for `txn`, applying nothing. a client without an actor isn't given one
+    pub(crate) fn validate(&self, txn: Txn, config: &Arc<Config>) -> Result<(), Rejection> {
+        let mailbox = match self.mailboxes.lock().unwrap().get(&txn.client) {
+            Some(mailbox) => mailbox.clone(),
+            None => return validate_with(None, txn, config)
+        };
+        let (reply_tx, reply_rx) = channel();
+        mailbox.send(Message::Validate(txn, Arc::clone(config), reply_tx)).expect("client actor stopped");
+        reply_rx.recv().expect("client actor stopped")
+    }
+
+    /// as `adjust_with`, through the client's actor
+    pub(crate) fn adjust(&self, client: ClientId, amount: Amount, config: &Arc<Config>) -> Result<(), Rejection> {
+        let mailbox = self.mailbox(client);
+        let (reply_tx, reply_rx) = channel();
+        mailbox.send(Message::Adjust(amount, Arc::clone(config), reply_tx)).expect("client actor stopped");
+        reply_rx.recv().expect("client actor stopped")
+    }
+
+    /// as `prune_with`, through every actor, not waiting on them
+    pub(crate) fn prune(&self, through: TxnId) {
+        for mailbox in self.mailboxes.lock().unwrap().values() {
+            mailbox.send(Message::Prune(through)).expect("client actor stopped");
+        }
+    }
+
+    /// as `forget_with`, through the client's actor. a client without an actor isn't given one
+    pub(crate) fn forget(&self, client: ClientId) -> Result<(), String> {
+        let mailbox = match self.mailboxes.lock().unwrap().get(&client) {
+            Some(mailbox) => mailbox.clone(),
+            None => return forget_with(&mut Accounts::default(), client)
+        };
+        let (reply_tx, reply_rx) = channel();
+        mailbox.send(Message::Forget(reply_tx)).expect("client actor stopped");
+        reply_rx.recv().expect("client actor stopped")
+    }
+
+    /// the client's account along with what it has under dispute, None if it has no actor
+    pub(crate) fn account(&self, client: ClientId) -> Option<Account> {
+        let mailbox = self.mailboxes.lock().unwrap().get(&client)?.clone();
+        let (reply_tx, reply_rx) = channel();
+        mailbox.send(Message::Account(reply_tx)).expect("client actor stopped");
+        reply_rx.recv().expect("client actor stopped")
+    }
+
+    fn mailbox(&self, client: ClientId) -> Sender<Message> {
+        let mut mailboxes = self.mailboxes.lock().unwrap();
+        if let Some(mailbox) = mailboxes.get(&client) {
+            return mailbox.clone();
+        }
+        let (mailbox, messages) = channel();
+        thread::Builder::new()
+            .name(format!("client-{}", client))
+            .stack_size(STACK_SIZE)
+            .spawn(move || run(client, messages))
+            .expect("failed to start a client actor");
+        mailboxes.insert(client, mailbox.clone());
+        mailbox
+    }
+}
+
+impl Drop for Actors {
+    /// stops every actor, told to rather than left to see its mailbox go, which loom's channels don't model
+    fn drop(&mut self) {
+        let mailboxes = self.mailboxes.get_mut().unwrap();
+        for mailbox in mailboxes.values() {
+            let _ = mailbox.send(Message::Stop);
+        }
+    }
+}
+
+/// an actor's loop, until it's stopped
+fn run(client: ClientId, messages: Receiver<Message>) {
+    // just the one account, so declined transactions leave it unopened exactly as they would in the shared map
+    let mut accounts = Accounts::default();
+    while let Ok(message) = messages.recv() {
+        match message {
+            Message::Execute(txn, config, reply) => {
+                let _ = reply.send(execute_with(&mut accounts, txn, &config));
+            },
+            Message::Validate(txn, config, reply) => {
+                let _ = reply.send(validate_with(accounts.get(&client), txn, &config));
+            },
+            Message::Adjust(amount, config, reply) => {
+                let _ = reply.send(adjust_with(&mut accounts, client, amount, &config));
+            },
+            Message::Forget(reply) => {
+                let _ = reply.send(forget_with(&mut accounts, client));
+            },
+            Message::Prune(through) => prune_with(&mut accounts, through),
+            Message::Balance(reply) => {
+                let _ = reply.send(accounts.get(&client).map(Account::summary));
+            },
+            Message::Account(reply) => {
+                let _ = reply.send(accounts.get(&client).map(Account::with_disputes));
+            },
+            Message::Stop => return
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::{Accounts, execute_with, Rejection, Txn};
+
+    use super::Actors;
+
+    #[test]
+    fn test_matches_sequential() {
+        let config = Arc::new(Config::default());
+        let actors = Actors::new();
+        let txns = |client: u16| {
+            let tx = client as u32 * 10;
+            vec![Txn::deposit(client, tx, dec!(10)), Txn::withdrawal(client, tx + 1, dec!(4)), Txn::dispute(client, tx),
+                 Txn::chargeback(client, tx), Txn::deposit(client, tx + 2, dec!(1))]
+        };
+
+        std::thread::scope(|s| {
+            for thread in 0..4u16 {
+                let (actors, config) = (&actors, &config);
+                s.spawn(move || {
+                    for client in (thread..50).step_by(4) {
+                        let results: Vec<_> = txns(client).into_iter().map(|t| actors.execute(t, config)).collect();
+                        assert_eq!(results, vec![Ok(()), Ok(()), Ok(()), Ok(()), Err(Rejection::Locked)]);
+                    }
+                });
+            }
+        });
+
+        let mut expected = Accounts::default();
+        for client in 0..50 {
+            for txn in txns(client) {
+                let _ = execute_with(&mut expected, txn, &config);
+            }
+        }
+        let balances = actors.balances();
+        assert_eq!(balances.len(), 50);
+        for (client, account) in &expected {
+            assert_eq!(balances[client].balance, account.balance);
+            assert_eq!(balances[client].locked, account.locked);
+        }
+    }
+
+    #[test]
+    fn test_declined_opens_no_account() {
+        let mut config = Config::default();
+        config.limits.max_amount = Some(dec!(1));
+        let actors = Actors::new();
+        assert_eq!(actors.execute(Txn::deposit(1, 1, dec!(2)), &Arc::new(config)), Err(Rejection::OverLimit));
+        assert!(actors.balances().is_empty());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use std::sync::Arc;
+
+    use loom::thread;
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::{ClientId, Txn};
+
+    use super::Actors;
+
+    #[test]
+    fn loom_one_actor_per_client() {
+        // two first transactions for a client racing to start its actor are both handed to the one started
+        loom::model(|| {
+            let (actors, config) = (Arc::new(Actors::new()), Arc::new(Config::default()));
+            let deposits: Vec<_> = [1, 2].iter().map(|&tx| {
+                let (actors, config) = (Arc::clone(&actors), Arc::clone(&config));
+                thread::spawn(move || actors.execute(Txn::deposit(1, tx, dec!(1)), &config))
+            }).collect();
+            for deposit in deposits {
+                assert_eq!(deposit.join().unwrap(), Ok(()));
+            }
+            let balances = actors.balances();
+            assert_eq!(balances.len(), 1);
+            assert_eq!(balances[&ClientId(1)].balance.total, dec!(2));
+        });
+    }
+}
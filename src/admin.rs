@@ -0,0 +1,346 @@
+//! admin adjustments: an admin's correction of a client's balance, made outside of its transactions, for operational
+//! fixes such as refunding a fee charged twice. an admin sends the server `adjust <client> <amount> <reason>`
+//! (`adjust 1 -2.5 refund of a duplicated fee`), or posts `{"amount":"-2.5","reason":"..."}` to
+//! `/accounts/1/adjustments` on the query api (see api.rs), and `txn admin adjust` sends the line for you:
+//! `txn admin adjust --client 1 --amount -2.5 --reason "..." --listen unix:/var/run/txn.sock`.
+//!
+//! - the amount is credited to available, or debited from it when negative, a debit declined as `insufficient funds`
+//!   if it'd take more than is available. it applies to a locked account too, and isn't a transaction to dispute
+//! - the reason is required, and each adjustment, applied or declined, is appended to `--audit-log` as a json line:
+//!   `{"at_ms":1718000000000,"operation":"adjust","client":1,"amount":"-2.5","reason":"...","key":"fcde2b2e",
+//!   "outcome":"ok"}`, the key named by its id (see auth.rs), null without keys. a server without an audit log
+//!   takes no adjustments
+//! - the account keeps what its adjustments have come to as `adjusted`, through checkpoints, and they're raised as
+//!   `BalanceAdjusted` events, apart from any transaction's (see event.rs)
+//! - transactions are what's replicated, so a primary and a standby take no adjustments, nor does a read-only server
+//!
+//! an admin erases who a client is, as data retention requires, with `forget <client>`, which `txn admin forget
+//! --client 123 --listen ...` sends. the account's name (from the clients file, see registry.rs) and its logged
+//! transactions are dropped, along with which were disputed, there being no memos or other text kept with them.
+//! what's left is an anonymous tombstone: the client id, balances, lock & kind, the totals charged back & adjusted,
+//! and `forgotten`, kept through checkpoints and raised as an `AccountForgotten` event.
+//!
+//! - it's refused (`failed: client 123 has disputes open`) while a dispute's open or a withdrawal's settling, as
+//!   those need their transactions. resolve them first
+//! - it's recorded in the audit log like an adjustment, without the amount & reason:
+//!   `{"at_ms":1718000000000,"operation":"forget","client":123,"key":null,"outcome":"ok"}`, and taken by the same
+//!   servers
+//! - with its transactions gone, the account's later ones can't dispute them, and a tx reused from them isn't
+//!   taken for a duplicate. the transactions stay in the input file, the clients file & any event log, which are
+//!   the operator's to erase
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::cli::Command;
+use crate::config::Config;
+use crate::server::{Address, State};
+use crate::{Amount, ClientId, Rejection};
+
+/// how long `txn admin` waits on the server, to connect & for each answer
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// an admin operation as appended to the audit log
+#[derive(Serialize, Debug, PartialEq)]
+struct Record<'a> {
+    at_ms: u64,
+    operation: &'a str,
+    client: ClientId,
+    /// an adjustment's, None for an erasure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amount: Option<Amount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+    /// the id of the key the admin authenticated with
+    key: Option<&'a str>,
+    /// `ok`, or the rejection
+    outcome: String
+}
+
+/// the audit log, appended to a line at a time
+pub(crate) struct Audit(Mutex<File>);
+
+impl Audit {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        Ok(Audit(Mutex::new(OpenOptions::new().create(true).append(true).open(path)?)))
+    }
+
+    /// appends the record, on disk before it returns
+    fn record(&self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record).expect("a record serializes");
+        line.push(b'\n');
+        let mut file = self.0.lock().unwrap();
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Adjustment {
+    pub(crate) client: ClientId,
+    /// credited, or debited when negative
+    pub(crate) amount: Decimal,
+    pub(crate) reason: String
+}
+
+impl Adjustment {
+    /// from the words after `adjust`: `<client> <amount> <reason>`
+    pub(crate) fn parse(args: &str) -> Result<Self, String> {
+        let mut words = args.trim().splitn(3, ' ');
+        let (client, amount, reason) = match (words.next(), words.next(), words.next()) {
+            (Some(client), Some(amount), Some(reason)) => (client, amount, reason),
+            _ => return Err("expected adjust <client> <amount> <reason>".into())
+        };
+        let client = client.parse().map_err(|_| format!("invalid client '{}'", client))?;
+        let amount = amount.parse().map_err(|_| format!("invalid amount '{}'", amount))?;
+        Adjustment::new(client, amount, reason)
+    }
+
+    pub(crate) fn new(client: ClientId, amount: Decimal, reason: &str) -> Result<Self, String> {
+        if amount.is_zero() {
+            return Err("an adjustment of 0 adjusts nothing".into());
+        }
+        if reason.trim().is_empty() || reason.contains('\n') {
+            return Err("an adjustment needs a reason, a line of text".into());
+        }
+        Ok(Adjustment { client, amount, reason: reason.trim().to_string() })
+    }
+
+    fn line(&self) -> String {
+        format!("adjust {} {} {}", self.client, self.amount, self.reason)
+    }
+}
+
+/// the audit log to record `what` in, Err if the server takes none
+fn audit<'a>(state: &'a State, what: &str) -> Result<&'a Audit, String> {
+    match &state.audit {
+        _ if state.config().api.read_only => Err(format!("a read-only server takes no {}", what)),
+        _ if state.standby.load(Ordering::Acquire) => Err(format!("a standby takes no {}", what)),
+        _ if state.primary.is_some() => Err(format!("{} aren't replicated, a primary takes none", what)),
+        Some(audit) => Ok(audit),
+        None => Err(format!("{} are only taken with an --audit-log to record them in", what))
+    }
+}
+
+fn now_ms(state: &State) -> u64 {
+    state.clock.now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// applies `adjustment` & records it, `key` being the id of the key the admin authenticated with. Err if the server
+/// takes no adjustments, Ok(Err) if it's declined
+pub(crate) fn adjust(adjustment: &Adjustment, key: Option<&str>, state: &State) -> Result<Result<(), Rejection>, String> {
+    let audit = audit(state, "adjustments")?;
+    let config = state.config();
+    let amount = Amount::from_decimal(adjustment.amount, config.amount_precision())
+        .ok_or_else(|| format!("amount {} out of range", adjustment.amount))?;
+    let result = state.engine.adjust(adjustment.client, amount, &config);
+    let record = Record {
+        at_ms: now_ms(state),
+        operation: "adjust",
+        client: adjustment.client,
+        amount: Some(amount),
+        reason: Some(&adjustment.reason),
+        key,
+        outcome: result.map_or_else(|r| r.to_string(), |()| "ok".into())
+    };
+    audit.record(&record).map_err(|e| format!("adjusted, but the audit log couldn't be written: {}", e))?;
+    Ok(result)
+}
+
+/// erases who the client is & records it, `key` being the id of the key the admin authenticated with. Err if the
+/// server takes no erasures or it's refused, which is recorded too
+pub(crate) fn forget(client: ClientId, key: Option<&str>, state: &State) -> Result<(), String> {
+    let audit = audit(state, "erasures")?;
+    let result = state.engine.forget(client);
+    let record = Record {
+        at_ms: now_ms(state),
+        operation: "forget",
+        client,
+        amount: None,
+        reason: None,
+        key,
+        outcome: result.clone().map_or_else(|e| e, |()| "ok".into())
+    };
+    audit.record(&record).map_err(|e| format!("forgotten, but the audit log couldn't be written: {}", e))?;
+    result
+}
+
+/// `txn admin adjust` & `txn admin forget`: sends the operation the config gives to the server it's `listen`ing on,
+/// through TLS over tcp when `--tls-ca` is given, the server's answer if it's `ok`
+pub(crate) fn send(command: Command, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+    let line = match (command, config.query.client, config.admin.amount, &config.admin.reason) {
+        (Command::Adjust, Some(client), Some(amount), Some(reason)) => Adjustment { client, amount, reason: reason.clone() }.line(),
+        (Command::Forget, Some(client), _, _) => format!("forget {}", client),
+        _ => return Err("txn admin adjust needs --client, --amount & --reason, txn admin forget --client".into())
+    };
+    let key = config.admin.key_file.as_deref().map(crate::auth::read_key).transpose()?;
+    let address = Address::parse(config.listen.as_deref().ok_or("txn admin needs the server's --listen")?)?;
+    let answer = match &address {
+        Address::Unix(path) => converse_unix(path, key.as_deref(), &line)?,
+        Address::Tcp(address) => {
+            let (reader, writer) = crate::server::tcp_connect(address, &config.tls, TIMEOUT)?;
+            converse(BufReader::new(reader), writer, key.as_deref(), &line)?
+        }
+    };
+    match answer.as_str() {
+        "ok" => Ok(answer),
+        _ => Err(format!("the server answered {}", answer).into())
+    }
+}
+
+#[cfg(unix)]
+fn converse_unix(path: &Path, key: Option<&str>, line: &str) -> io::Result<String> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    converse(BufReader::new(&stream), &stream, key, line)
+}
+
+#[cfg(not(unix))]
+fn converse_unix(_path: &Path, _key: Option<&str>, _line: &str) -> io::Result<String> {
+    Err(io::Error::other("unix sockets aren't supported on this platform"))
+}
+
+/// authenticates with `key` if there is one, then sends `line`, the server's answer to whichever it refuses first
+fn converse<R: BufRead, W: Write>(mut reader: R, mut writer: W, key: Option<&str>, line: &str) -> io::Result<String> {
+    let mut answer = String::new();
+    if let Some(key) = key {
+        writeln!(writer, "auth {}", key)?;
+        writer.flush()?;
+        reader.read_line(&mut answer)?;
+        if answer.trim() != "ok" {
+            return Ok(answer.trim().to_string());
+        }
+        answer.clear();
+    }
+    writeln!(writer, "{}", line)?;
+    writer.flush()?;
+    reader.read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use rust_decimal_macros::dec;
+
+    use crate::clock::MockClock;
+    use crate::config::Config;
+    use crate::server::{handle, State};
+    use crate::ClientId;
+
+    use super::{Adjustment, Audit, converse};
+
+    fn run(input: &str, state: &State) -> String {
+        let mut out = Vec::new();
+        handle(input.as_bytes(), &mut out, state).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Adjustment::parse(" 1 -2.5 refund of a duplicated fee "),
+                   Ok(Adjustment { client: ClientId(1), amount: dec!(-2.5), reason: "refund of a duplicated fee".into() }));
+        assert!(Adjustment::parse("1 -2.5").is_err());
+        assert!(Adjustment::parse("1 0 nothing").is_err());
+        assert!(Adjustment::parse("x 1 reason").is_err());
+        assert!(Adjustment::parse("1 1,5 reason").is_err());
+    }
+
+    #[test]
+    fn test_adjust() {
+        let path = std::env::temp_dir().join(format!("txn-audit-test-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        for config in [Config::default(), Config { actors: true, ..Config::default() }, Config { threads: Some(1), ..Config::default() }] {
+            let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1_718_000_000));
+            let mut state = State::with_clock(config, Arc::new(clock));
+            state.audit = Some(Audit::open(&path).unwrap());
+            assert_eq!(run("deposit,1,1,10\ndeposit,2,2,1\ndispute,2,2,\nchargeback,2,2,\n", &state), "ok\nok\nok\nok\n");
+
+            assert_eq!(run("adjust 1 -2.5 refund of a duplicated fee\nadjust 1 -8 too much\nadjust 2 3 goodwill\n", &state),
+                       "ok\nrejected: insufficient funds\nok\n");
+            // no account's opened for a debit
+            assert_eq!(run("adjust 3 -1 nothing there\nadjust 1 2\n", &state),
+                       "rejected: insufficient funds\nfailed: expected adjust <client> <amount> <reason>\n");
+            let balances = state.engine.balances();
+            assert_eq!(balances[&ClientId(1)].balance.available, dec!(7.5));
+            assert_eq!(balances[&ClientId(1)].adjusted, dec!(-2.5));
+            // locked by its chargeback, and adjusted all the same
+            assert_eq!(balances[&ClientId(2)].balance.total, dec!(3));
+            assert!(balances[&ClientId(2)].locked);
+            assert!(!balances.contains_key(&ClientId(3)));
+            // adjustments aren't transactions
+            assert_eq!(state.report.lock().unwrap().rejected_total(), 0);
+        }
+        let audit = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = audit.lines().collect();
+        assert_eq!(lines.len(), 12);
+        assert_eq!(lines[0], r#"{"at_ms":1718000000000,"operation":"adjust","client":1,"amount":"-2.5","reason":"refund of a duplicated fee","key":null,"outcome":"ok"}"#);
+        assert!(lines[1].ends_with(r#""reason":"too much","key":null,"outcome":"insufficient funds"}"#));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_forget() {
+        let path = std::env::temp_dir().join(format!("txn-audit-forget-test-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        for config in [Config::default(), Config { actors: true, ..Config::default() }, Config { threads: Some(1), ..Config::default() }] {
+            let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1_718_000_000));
+            let mut state = State::with_clock(config, Arc::new(clock));
+            state.audit = Some(Audit::open(&path).unwrap());
+            assert_eq!(run("deposit,1,1,10\ndeposit,1,2,5\ndispute,1,2,\nresolve,1,2,\ndeposit,2,3,1\ndispute,2,3,\n", &state),
+                       "ok\nok\nok\nok\nok\nok\n");
+
+            assert_eq!(run("forget 1\nforget 2\nforget 3\nforget x\n", &state),
+                       "ok\nfailed: client 2 has disputes open\nfailed: client 3 has no account\nfailed: expected forget <client>, got 'x'\n");
+            let account = &state.engine.balances()[&ClientId(1)];
+            assert!(account.forgotten && !account.locked);
+            assert_eq!(account.balance.available, dec!(15));
+            // its transactions are gone, there's nothing left to dispute, and it takes new ones
+            assert_eq!(run("dispute,1,1,\ndeposit,1,4,1\n", &state), "rejected: unknown transaction\nok\n");
+            assert_eq!(state.engine.balances()[&ClientId(1)].balance.total, dec!(16));
+            assert!(!state.engine.balances()[&ClientId(2)].forgotten);
+        }
+        let audit = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = audit.lines().collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], r#"{"at_ms":1718000000000,"operation":"forget","client":1,"key":null,"outcome":"ok"}"#);
+        assert!(lines[1].ends_with(r#""client":2,"key":null,"outcome":"client 2 has disputes open"}"#));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_refused() {
+        let state = State::new(Config::default());
+        assert_eq!(run("adjust 1 1 goodwill\n", &state), "failed: adjustments are only taken with an --audit-log to record them in\n");
+        let path = std::env::temp_dir().join(format!("txn-audit-standby-test-{}.jsonl", std::process::id()));
+        let mut state = State::new(Config::default());
+        state.audit = Some(Audit::open(&path).unwrap());
+        state.standby.store(true, Ordering::Release);
+        assert_eq!(run("adjust 1 1 goodwill\nforget 1\n", &state),
+                   "failed: a standby takes no adjustments\nfailed: a standby takes no erasures\n");
+        assert!(state.engine.balances().is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_converse() {
+        let mut sent = Vec::new();
+        assert_eq!(converse("ok\nrejected: insufficient funds\n".as_bytes(), &mut sent, Some("bar"), "adjust 1 -2 why").unwrap(),
+                   "rejected: insufficient funds");
+        assert_eq!(String::from_utf8(sent).unwrap(), "auth bar\nadjust 1 -2 why\n");
+        let mut sent = Vec::new();
+        assert_eq!(converse("unauthorized: unknown key\n".as_bytes(), &mut sent, Some("baz"), "adjust 1 -2 why").unwrap(),
+                   "unauthorized: unknown key");
+        assert_eq!(String::from_utf8(sent).unwrap(), "auth baz\n");
+    }
+}
@@ -0,0 +1,181 @@
+//! `txn disputes [--open] <file>`: every dispute in a timestamped csv input (rows with a fifth `timestamp` column, as
+//! for reordering), how long it's been open and what it holds, oldest first, so those nearing a resolution deadline
+//! can be chased:
+//! ```text
+//! client  tx  held  disputed  age  status
+//! 1        4   2.5       100  400  open
+//! 2        7    10       350  150  open
+//! 1        2     5       120   30  resolved
+//! ```
+//! a dispute's age runs from its timestamp to its resolve or chargeback, or while it's still open to the input's
+//! latest timestamp (`--as-of` says another). `--open` lists only the open ones. a resolved transaction disputed
+//! again is listed again. rows are executed in file order, as `txn history` does.
+
+use std::fmt;
+use std::io::Read;
+
+use crate::config::Config;
+use crate::pipeline::RowError;
+use crate::reorder::TimestampedRow;
+use crate::report::Report;
+use crate::{Accounts, Amount, ClientId, execute_with, malformatted, Map, TxnId, TxnType};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Status {
+    Open,
+    Resolved,
+    ChargedBack
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Status::Open => "open",
+            Status::Resolved => "resolved",
+            Status::ChargedBack => "charged back"
+        })
+    }
+}
+
+struct Dispute {
+    client: ClientId,
+    tx: TxnId,
+    held: Amount,
+    disputed: u64,
+    /// when it was resolved or charged back
+    closed: Option<u64>,
+    status: Status
+}
+
+pub(crate) struct Aging {
+    disputes: Vec<Dispute>,
+    /// the dispute each transaction has open, by index
+    open: Map<(ClientId, TxnId), usize>,
+    as_of: u64,
+    open_only: bool
+}
+
+impl Aging {
+    pub(crate) fn read<R: Read>(reader: R, config: &Config, report: &mut Report) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut accounts = Accounts::default();
+        let mut aging = Aging { disputes: Vec::new(), open: Map::default(), as_of: 0, open_only: config.aging.open };
+        for record in csv::Reader::from_reader(reader).into_records() {
+            let row = record.map_err(RowError::from).and_then(|mut r| {
+                r.trim();
+                Ok(r.deserialize::<TimestampedRow>(None)?.into_txn(config.amount_precision())?)
+            });
+            let (timestamp, txn) = match row {
+                Ok(t) => t,
+                Err(e) => {
+                    malformatted(config, report, "row", e)?;
+                    continue;
+                }
+            };
+            aging.as_of = aging.as_of.max(timestamp);
+            let (txntype, key) = (txn.txntype.clone(), (txn.client, txn.tx));
+            let outcome = execute_with(&mut accounts, txn, config);
+            report.record(outcome);
+            if outcome.is_err() {
+                continue;
+            }
+            let status = match txntype {
+                TxnType::Dispute => {
+                    let held = accounts[&key.0].txnlog[&key.1].amount();
+                    aging.open.insert(key, aging.disputes.len());
+                    aging.disputes.push(Dispute { client: key.0, tx: key.1, held, disputed: timestamp, closed: None, status: Status::Open });
+                    continue;
+                },
+                TxnType::Resolve => Status::Resolved,
+                TxnType::Chargeback => Status::ChargedBack,
+                TxnType::Deposit | TxnType::Withdrawal => continue
+            };
+            if let Some(dispute) = aging.open.remove(&key).map(|i| &mut aging.disputes[i]) {
+                dispute.closed = Some(timestamp);
+                dispute.status = status;
+            }
+        }
+        if let Some(as_of) = config.aging.as_of {
+            aging.as_of = as_of;
+        }
+        Ok(aging)
+    }
+}
+
+impl Dispute {
+    fn age(&self, as_of: u64) -> u64 {
+        self.closed.unwrap_or(as_of).saturating_sub(self.disputed)
+    }
+}
+
+impl fmt::Display for Aging {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut disputes: Vec<&Dispute> = self.disputes.iter().filter(|d| !self.open_only || d.status == Status::Open).collect();
+        // open ones ahead of those closed, the oldest first
+        disputes.sort_by_key(|d| (d.closed.is_some(), std::cmp::Reverse(d.age(self.as_of)), d.client, d.tx));
+        let cells: Vec<[String; 6]> = disputes.iter().map(|d| [
+            d.client.to_string(),
+            d.tx.to_string(),
+            d.held.to_decimal().normalize().to_string(),
+            d.disputed.to_string(),
+            d.age(self.as_of).to_string(),
+            d.status.to_string()
+        ]).collect();
+
+        let header = ["client", "tx", "held", "disputed", "age", "status"].map(String::from);
+        let mut widths = [0; 6];
+        for row in std::iter::once(&header).chain(&cells) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in std::iter::once(&header).chain(&cells) {
+            // the client & status left aligned, numbers right
+            let line = format!("{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}  {:>w4$}  {}", row[0], row[1], row[2], row[3], row[4],
+                               row[5], w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3], w4 = widths[4]);
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, ErrorPolicy};
+    use crate::report::Report;
+
+    use super::Aging;
+
+    const CSV: &str = "type,client,tx,amount,timestamp\ndeposit,1,2,5,10\ndeposit,1,4,2.5,20\ndeposit,2,7,10,30\n\
+                       dispute,1,4,,100\ndispute,1,2,,120\nresolve,1,2,,150\ndispute,2,7,,350\ndeposit,1,9,1\n\
+                       dispute,2,8,,400\ndeposit,1,10,1,500\n";
+
+    #[test]
+    fn test_aging() {
+        let config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        let mut report = Report::default();
+        let aging = Aging::read(CSV.as_bytes(), &config, &mut report).unwrap();
+
+        assert_eq!(aging.to_string(), "\
+client  tx  held  disputed  age  status
+1        4   2.5       100  400  open
+2        7    10       350  150  open
+1        2     5       120   30  resolved
+");
+        // the row without a timestamp
+        assert_eq!(report.skipped, 1);
+    }
+
+    #[test]
+    fn test_open_as_of() {
+        let mut config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        config.aging.open = true;
+        config.aging.as_of = Some(1000);
+        let aging = Aging::read(CSV.as_bytes(), &config, &mut Report::default()).unwrap();
+
+        assert_eq!(aging.to_string(), "\
+client  tx  held  disputed  age  status
+1        4   2.5       100  900  open
+2        7    10       350  650  open
+");
+    }
+}
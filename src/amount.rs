@@ -0,0 +1,371 @@
+//! monetary amounts. a `Decimal` by default; built with `--features fixed-point`, an i64 count of ten-thousandths,
+//! half the size and cheaper to add & compare, at the cost of range (about ±922 trillion). the fixed-point build
+//! holds 4 decimal places, so `precision` can be lowered but not raised.
+//!
+//! arithmetic is checked either way: a transaction that would overflow a balance is rejected, not wrapped or panicked on.
+//!
+//! amounts are rounded to the precision as `Precision` says, half to even (banker's rounding) unless `rounding` says
+//! otherwise: where they're read, and where the engine works one out (a merchant's reserve share).
+//!
+//! one with no more places than that, and at most 4, the most amounts carry, is read digit by digit straight into
+//! the representation (`Amount::parse`), there being nothing to round. only others go through a `Decimal` first.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(feature = "fixed-point")]
+use crate::CURRENCY_PRECISION;
+
+#[cfg(not(feature = "fixed-point"))]
+type Repr = Decimal;
+#[cfg(feature = "fixed-point")]
+type Repr = i64;
+
+/// decimal places a fixed-point amount holds
+#[cfg(feature = "fixed-point")]
+pub const SCALE: u32 = CURRENCY_PRECISION;
+
+/// decimal places `Amount::parse` reads
+const PARSE_PLACES: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
+pub struct Amount(Repr);
+
+/// how an amount with more decimal places than the precision is rounded, as `rounding` in the config
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// to the nearest, a tie to the even neighbour: 0.00125 -> 0.0012, 0.00135 -> 0.0014
+    #[default]
+    HalfEven,
+    /// to the nearest, a tie away from zero: 0.00125 -> 0.0013
+    HalfUp,
+    /// to the nearest, a tie toward zero: 0.00135 -> 0.0013
+    HalfDown,
+    /// toward zero, the excess digits dropped: 0.00129 -> 0.0012
+    Down,
+    /// away from zero: 0.00121 -> 0.0013
+    Up
+}
+
+impl Rounding {
+    pub(crate) fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfDown => RoundingStrategy::MidpointTowardZero,
+            Rounding::Down => RoundingStrategy::ToZero,
+            Rounding::Up => RoundingStrategy::AwayFromZero
+        }
+    }
+}
+
+/// the decimal places amounts are rounded to, and how. a bare count of places rounds half to even
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precision {
+    pub places: u32,
+    pub rounding: Rounding
+}
+
+impl Precision {
+    pub fn new(places: u32, rounding: Rounding) -> Self {
+        Precision { places, rounding }
+    }
+
+    pub(crate) fn round(self, amount: Decimal) -> Decimal {
+        amount.round_dp_with_strategy(self.places, self.rounding.strategy())
+    }
+}
+
+impl From<u32> for Precision {
+    fn from(places: u32) -> Self {
+        Precision::new(places, Rounding::HalfEven)
+    }
+}
+
+impl Amount {
+    #[cfg(not(feature = "fixed-point"))]
+    pub const ZERO: Amount = Amount(Decimal::ZERO);
+    #[cfg(feature = "fixed-point")]
+    pub const ZERO: Amount = Amount(0);
+
+    /// rounds to `precision` decimal places, None if that doesn't fit
+    #[cfg(not(feature = "fixed-point"))]
+    pub fn from_decimal(amount: Decimal, precision: impl Into<Precision>) -> Option<Self> {
+        Some(Amount(precision.into().round(amount)))
+    }
+
+    /// rounds to `precision` decimal places (at most `SCALE`), None if that doesn't fit
+    #[cfg(feature = "fixed-point")]
+    pub fn from_decimal(amount: Decimal, precision: impl Into<Precision>) -> Option<Self> {
+        use rust_decimal::prelude::ToPrimitive;
+
+        let precision = precision.into();
+        let rounded = Precision { places: precision.places.min(SCALE), ..precision }.round(amount);
+        rounded.checked_mul(Decimal::new(10i64.pow(SCALE), 0))?.to_i64().map(Amount)
+    }
+
+    #[cfg(not(feature = "fixed-point"))]
+    pub fn to_decimal(self) -> Decimal {
+        self.0
+    }
+
+    #[cfg(feature = "fixed-point")]
+    pub fn to_decimal(self) -> Decimal {
+        Decimal::new(self.0, SCALE)
+    }
+
+    /// `units` of 10^-`scale`, at most `PARSE_PLACES`, None if that doesn't fit
+    #[cfg(not(feature = "fixed-point"))]
+    fn from_units(units: i64, scale: u32) -> Option<Self> {
+        Some(Amount(Decimal::new(units, scale)))
+    }
+
+    #[cfg(feature = "fixed-point")]
+    fn from_units(units: i64, scale: u32) -> Option<Self> {
+        units.checked_mul(10i64.pow(SCALE - scale)).map(Amount)
+    }
+
+    /// `[+-]digits[.digits]` with no more decimal places than `places`, nor `PARSE_PLACES`, read without going through
+    /// a `Decimal`: what `from_decimal` would make of the value, parsed & rounded. None for anything else, more
+    /// places, an exponent, or more digits than an i64 holds, for the `Decimal` path to take. a zero's left to it too,
+    /// `from_decimal` giving one the precision's scale, so it's written out as it always was
+    pub(crate) fn parse(field: &[u8], places: u32) -> Option<Self> {
+        let (negative, digits) = match field.split_first() {
+            Some((b'-', rest)) => (true, rest),
+            Some((b'+', rest)) => (false, rest),
+            _ => (false, field)
+        };
+        let (integer, fraction) = match digits.iter().position(|&b| b == b'.') {
+            Some(point) => (&digits[..point], &digits[point + 1..]),
+            None => (digits, &digits[digits.len()..])
+        };
+        let scale = fraction.len() as u32;
+        if (integer.is_empty() && fraction.is_empty()) || scale > places.min(PARSE_PLACES) {
+            return None;
+        }
+        let mut units: i64 = 0;
+        for &b in integer.iter().chain(fraction) {
+            if !b.is_ascii_digit() {
+                return None;
+            }
+            units = units.checked_mul(10)?.checked_add(i64::from(b - b'0'))?;
+        }
+        if units == 0 {
+            return None;
+        }
+        Amount::from_units(if negative { -units } else { units }, scale)
+    }
+
+    /// an amount as it was written out, at its own scale
+    pub(crate) fn read(s: &str) -> Option<Self> {
+        Amount::parse(s.as_bytes(), PARSE_PLACES)
+            .or_else(|| Decimal::from_str(s).ok().and_then(|d| Amount::from_decimal(d, d.scale())))
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+}
+
+/// an amount too large to hold
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct OutOfRange;
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("amount out of range")
+    }
+}
+
+impl std::error::Error for OutOfRange {}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.to_decimal().fmt(f)
+    }
+}
+
+/// as a decimal string (`"1.5"`), never a float, and the same from either build
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.to_decimal().normalize())
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        if let Some(amount) = Amount::parse(s.as_bytes(), PARSE_PLACES) {
+            return Ok(amount);
+        }
+        let decimal = Decimal::from_str(&s).map_err(|_| D::Error::custom(format!("invalid amount '{}'", s)))?;
+        Amount::from_decimal(decimal, decimal.scale()).ok_or_else(|| D::Error::custom(OutOfRange))
+    }
+}
+
+impl PartialEq<Decimal> for Amount {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.to_decimal() == *other
+    }
+}
+
+impl PartialEq<Amount> for Decimal {
+    fn eq(&self, other: &Amount) -> bool {
+        *self == other.to_decimal()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use super::{Amount, Precision, Rounding};
+
+    #[test]
+    fn test_from_decimal() {
+        assert_eq!(Amount::from_decimal(dec!(3.14159), 4).unwrap(), dec!(3.1416));
+        assert_eq!(Amount::from_decimal(dec!(3.14159), 2).unwrap(), dec!(3.14));
+        assert_eq!(Amount::from_decimal(dec!(-1.5), 4).unwrap(), dec!(-1.5));
+        // no double rounding through the stored scale
+        assert_eq!(Amount::from_decimal(dec!(0.00149999), 3).unwrap(), dec!(0.001));
+        assert_eq!(Amount::from_decimal(dec!(0.00149999), 4).unwrap().to_string().parse::<f64>().unwrap(), 0.0015);
+    }
+
+    #[test]
+    fn test_rounding() {
+        // each strategy either side of, and at, the midpoint, and negated
+        let cases = [
+            (Rounding::HalfEven, [dec!(0.00125), dec!(0.00135), dec!(0.001251), dec!(0.001249)], [dec!(0.0012), dec!(0.0014), dec!(0.0013), dec!(0.0012)]),
+            (Rounding::HalfUp, [dec!(0.00125), dec!(0.00135), dec!(0.001251), dec!(0.001249)], [dec!(0.0013), dec!(0.0014), dec!(0.0013), dec!(0.0012)]),
+            (Rounding::HalfDown, [dec!(0.00125), dec!(0.00135), dec!(0.001251), dec!(0.001249)], [dec!(0.0012), dec!(0.0013), dec!(0.0013), dec!(0.0012)]),
+            (Rounding::Down, [dec!(0.00125), dec!(0.00135), dec!(0.001299), dec!(0.0012)], [dec!(0.0012), dec!(0.0013), dec!(0.0012), dec!(0.0012)]),
+            (Rounding::Up, [dec!(0.00125), dec!(0.00135), dec!(0.001201), dec!(0.0012)], [dec!(0.0013), dec!(0.0014), dec!(0.0013), dec!(0.0012)])
+        ];
+        for (rounding, amounts, rounded) in cases {
+            for (amount, rounded) in amounts.iter().zip(rounded) {
+                let precision = Precision::new(4, rounding);
+                assert_eq!(Amount::from_decimal(*amount, precision).unwrap(), rounded, "{} {:?}", amount, rounding);
+                assert_eq!(Amount::from_decimal(-amount, precision).unwrap(), -rounded, "-{} {:?}", amount, rounding);
+            }
+        }
+        // the default, as amounts have always been rounded
+        assert_eq!(Amount::from_decimal(dec!(2.5), 0).unwrap(), dec!(2));
+        assert_eq!(Amount::from_decimal(dec!(2.5), Precision::new(0, Rounding::HalfUp)).unwrap(), dec!(3));
+    }
+
+    #[test]
+    fn test_checked() {
+        let one = Amount::from_decimal(dec!(1), 4).unwrap();
+        assert_eq!(one.checked_add(one).unwrap(), dec!(2));
+        assert_eq!(Amount::ZERO.checked_sub(one).unwrap(), dec!(-1));
+        assert!(one < one.checked_add(one).unwrap());
+    }
+
+    #[test]
+    fn test_serde() {
+        let amount = Amount::from_decimal(dec!(2.50), 4).unwrap();
+        assert_eq!(serde_json::to_string(&amount).unwrap(), r#""2.5""#);
+        assert_eq!(serde_json::from_str::<Amount>(r#""2.5""#).unwrap(), amount);
+        assert!(serde_json::from_str::<Amount>(r#""two""#).is_err());
+        // floats aren't taken, they may not be the amount meant
+        assert!(serde_json::from_str::<Amount>("2.5").is_err());
+    }
+
+    /// what the `Decimal` path makes of `s`, as fastparse reads it: parsed exactly, then rounded
+    fn through_decimal(s: &str, places: u32) -> Option<Amount> {
+        let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+        if digits.is_empty() || digits == "." || !digits.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+            return None;
+        }
+        let decimal = Decimal::from_str(s).or_else(|_| Decimal::from_str(&format!("{}0", s))).ok()?;
+        Amount::from_decimal(decimal, places)
+    }
+
+    #[test]
+    fn test_parse_exhaustive() {
+        // every string of up to 5 of these, at every precision: what's read must be what the decimal path reads,
+        // and everything of at most the precision's places that fits must be read
+        let alphabet = b"0123456789+-.";
+        let mut strings: Vec<Vec<u8>> = vec![Vec::new()];
+        let mut from = 0;
+        for _ in 0..5 {
+            let to = strings.len();
+            for i in from..to {
+                for &b in alphabet {
+                    let mut s = strings[i].clone();
+                    s.push(b);
+                    strings.push(s);
+                }
+            }
+            from = to;
+        }
+        for s in &strings {
+            let text = std::str::from_utf8(s).unwrap();
+            for places in 0..=5 {
+                let expected = through_decimal(text, places);
+                match Amount::parse(s, places) {
+                    Some(amount) => {
+                        assert_eq!(Some(amount), expected, "{} at {}", text, places);
+                        assert_eq!(amount.to_string(), expected.unwrap().to_string(), "{} at {}", text, places);
+                    },
+                    None => {
+                        let fraction = text.split_once('.').map_or(0, |(_, f)| f.len() as u32);
+                        assert!(expected.is_none() || expected == Some(Amount::ZERO) || fraction > places.min(4), "{} at {}", text, places);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_fractions() {
+        // every 4 place fraction, on integers short & long, either sign
+        for integer in ["", "0", "1", "42", "999999", "922337203685"] {
+            for units in 0..10_000 {
+                if units == 0 && integer.trim_start_matches('0').is_empty() {
+                    continue;
+                }
+                for s in [format!("{}.{:04}", integer, units), format!("-{}.{:04}", integer, units)] {
+                    let parsed = Amount::parse(s.as_bytes(), 4).unwrap();
+                    assert_eq!(Some(parsed), through_decimal(&s, 4), "{}", s);
+                    assert_eq!(parsed, Decimal::from_str(&s).unwrap(), "{}", s);
+                }
+            }
+        }
+        // past what an i64 of units holds, left to the decimal path
+        assert_eq!(Amount::parse(b"9223372036854775808", 0), None);
+        assert_eq!(Amount::parse(b"1e5", 4), None);
+        assert_eq!(Amount::parse(b"1.23456", 4), None);
+        assert_eq!(Amount::parse(b"-0.00", 4), None);
+        assert_eq!(Amount::read("1.23456").unwrap(), dec!(1.23456).round_dp(if cfg!(feature = "fixed-point") { 4 } else { 5 }));
+    }
+
+    #[cfg(not(feature = "fixed-point"))]
+    #[test]
+    fn test_overflow() {
+        let max = Amount::from_decimal(rust_decimal::Decimal::MAX, 0).unwrap();
+        assert_eq!(max.checked_add(max), None);
+    }
+
+    #[cfg(feature = "fixed-point")]
+    #[test]
+    fn test_overflow() {
+        let max = Amount::from_decimal(dec!(922337203685477.5807), 4).unwrap();
+        assert_eq!(max.to_decimal(), dec!(922337203685477.5807));
+        assert_eq!(max.checked_add(Amount::from_decimal(dec!(0.0001), 4).unwrap()), None);
+        assert_eq!(Amount::from_decimal(dec!(922337203685477.5808), 4), None);
+        assert_eq!(std::mem::size_of::<Amount>(), 8);
+    }
+}
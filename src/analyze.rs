@@ -0,0 +1,242 @@
+//! `txn analyze <file>`: aggregates over a csv input, run through whole, for a look at it without exporting balances
+//! to another tool first:
+//! ```text
+//! type        applied  rejected
+//! deposit           3         0
+//! withdrawal        0         2
+//! dispute           2         0
+//! resolve           1         0
+//! chargeback        1         0
+//!
+//! chargeback losses: 10 over 1 chargeback
+//!
+//! top 10 clients by volume
+//! client  volume  txns  disputes  dispute rate  charged back
+//! 9         12.5     2         2        100.0%            10
+//! 2            5     1         0          0.0%             0
+//!
+//! top 10 clients by dispute rate
+//! client  disputes  txns  dispute rate
+//! 9              2     2        100.0%
+//!
+//! amount        deposits & withdrawals
+//! < 1                                0
+//! 1 - 10                             2
+//! 10 - 100                           1
+//! ...
+//! ```
+//! volume is what a client's applied deposits & withdrawals moved, `txns` how many of them there were, and a
+//! client's dispute rate its applied disputes over those. a chargeback's loss is the amount of the transaction it
+//! charged back. `--top n` lists more or fewer clients.
+
+use std::fmt;
+use std::io::Read;
+
+use rust_decimal::Decimal;
+
+use crate::config::Config;
+use crate::report::Report;
+use crate::{Accounts, ClientId, execute_with, malformatted, Map, read_record, TxnType};
+
+/// the transaction sizes tallied, in powers of ten
+const SIZES: [&str; 6] = ["< 1", "1 - 10", "10 - 100", "100 - 1000", "1000 - 10000", ">= 10000"];
+
+const TYPES: [TxnType; 5] = [TxnType::Deposit, TxnType::Withdrawal, TxnType::Dispute, TxnType::Resolve, TxnType::Chargeback];
+
+#[derive(Default)]
+struct ClientStats {
+    volume: Decimal,
+    /// applied deposits & withdrawals
+    txns: u64,
+    disputes: u64,
+    charged_back: Decimal
+}
+
+impl ClientStats {
+    fn dispute_rate(&self) -> f64 {
+        match self.txns {
+            0 => 0.0,
+            txns => self.disputes as f64 * 100.0 / txns as f64
+        }
+    }
+}
+
+pub(crate) struct Analysis {
+    top: usize,
+    clients: Map<ClientId, ClientStats>,
+    /// (applied, rejected) by type, in `TYPES` order
+    outcomes: [(u64, u64); 5],
+    /// applied deposits & withdrawals by size, in `SIZES` order
+    sizes: [u64; 6],
+    loss: Decimal,
+    chargebacks: u64
+}
+
+impl Analysis {
+    pub(crate) fn read<R: Read>(reader: R, config: &Config, report: &mut Report) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut accounts = Accounts::default();
+        let mut analysis = Analysis { top: config.analyze.top, clients: Map::default(), outcomes: [(0, 0); 5], sizes: [0; 6],
+                                      loss: Decimal::ZERO, chargebacks: 0 };
+        for record in csv::Reader::from_reader(reader).into_records() {
+            let txn = record.map_err(crate::pipeline::RowError::from)
+                .and_then(|mut r| read_record(&mut r, config));
+            let txn = match txn {
+                Ok(t) => t,
+                Err(e) => {
+                    malformatted(config, report, "row", e)?;
+                    continue;
+                }
+            };
+            let (txntype, client, tx, amount) = (txn.txntype.clone(), txn.client, txn.tx, txn.amount.map(|a| a.to_decimal()));
+            let outcome = execute_with(&mut accounts, txn, config);
+            report.record(outcome);
+            let outcomes = &mut analysis.outcomes[TYPES.iter().position(|t| *t == txntype).expect("every type is listed")];
+            if outcome.is_err() {
+                outcomes.1 += 1;
+                continue;
+            }
+            outcomes.0 += 1;
+            let stats = analysis.clients.entry(client).or_default();
+            match txntype {
+                TxnType::Deposit | TxnType::Withdrawal => {
+                    let amount = amount.unwrap_or_default();
+                    stats.volume = add(stats.volume, amount);
+                    stats.txns += 1;
+                    let size = SIZES.len() - 1;
+                    let size = (0..size).find(|i| amount < Decimal::from(10u64.pow(*i as u32))).unwrap_or(size);
+                    analysis.sizes[size] += 1;
+                },
+                TxnType::Dispute => stats.disputes += 1,
+                TxnType::Chargeback => {
+                    // still logged, with the amount it charged back
+                    let charged = accounts.get(&client).and_then(|a| a.txnlog.get(&tx)).and_then(|t| t.amount)
+                        .map_or(Decimal::ZERO, |a| a.to_decimal());
+                    stats.charged_back = add(stats.charged_back, charged);
+                    analysis.loss = add(analysis.loss, charged);
+                    analysis.chargebacks += 1;
+                },
+                TxnType::Resolve => ()
+            }
+        }
+        Ok(analysis)
+    }
+
+    /// up to `top` clients, most first by `key`
+    fn top<K: Ord>(&self, key: impl Fn(&ClientStats) -> K, pick: impl Fn(&ClientStats) -> bool) -> Vec<(&ClientId, &ClientStats)> {
+        let mut clients: Vec<_> = self.clients.iter().filter(|(_, s)| pick(s)).collect();
+        clients.sort_unstable_by(|(a, x), (b, y)| key(y).cmp(&key(x)).then(a.cmp(b)));
+        clients.truncate(self.top);
+        clients
+    }
+}
+
+/// totals past what a decimal holds stay at its largest
+fn add(a: Decimal, b: Decimal) -> Decimal {
+    a.checked_add(b).unwrap_or(Decimal::MAX)
+}
+
+fn amount(amount: Decimal) -> String {
+    amount.normalize().to_string()
+}
+
+fn rate(stats: &ClientStats) -> String {
+    format!("{:.1}%", stats.dispute_rate())
+}
+
+/// a table with its first column left aligned and the rest right
+pub(crate) fn table(f: &mut fmt::Formatter, header: &[&str], rows: Vec<Vec<String>>) -> fmt::Result {
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    let mut widths = vec![0; header.len()];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let mut line = String::new();
+        for (i, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            match i {
+                0 => line.push_str(&format!("{:<w$}", cell, w = width)),
+                _ => line.push_str(&format!("  {:>w$}", cell, w = width))
+            }
+        }
+        writeln!(f, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let outcomes = TYPES.iter().zip(&self.outcomes)
+            .map(|(t, (applied, rejected))| vec![format!("{:?}", t).to_lowercase(), applied.to_string(), rejected.to_string()])
+            .collect();
+        table(f, &["type", "applied", "rejected"], outcomes)?;
+
+        let plural = if self.chargebacks == 1 { "" } else { "s" };
+        writeln!(f, "\nchargeback losses: {} over {} chargeback{}", amount(self.loss), self.chargebacks, plural)?;
+
+        writeln!(f, "\ntop {} clients by volume", self.top)?;
+        let by_volume = self.top(|s| s.volume, |_| true).into_iter()
+            .map(|(c, s)| vec![c.to_string(), amount(s.volume), s.txns.to_string(), s.disputes.to_string(), rate(s),
+                               amount(s.charged_back)])
+            .collect();
+        table(f, &["client", "volume", "txns", "disputes", "dispute rate", "charged back"], by_volume)?;
+
+        writeln!(f, "\ntop {} clients by dispute rate", self.top)?;
+        // a rate's never negative, so its bits order as it does
+        let by_rate = self.top(|s| (s.dispute_rate().to_bits(), s.disputes), |s| s.disputes > 0).into_iter()
+            .map(|(c, s)| vec![c.to_string(), s.disputes.to_string(), s.txns.to_string(), rate(s)])
+            .collect();
+        table(f, &["client", "disputes", "txns", "dispute rate"], by_rate)?;
+
+        writeln!(f)?;
+        let sizes = SIZES.iter().zip(&self.sizes).map(|(size, count)| vec![size.to_string(), count.to_string()]).collect();
+        table(f, &["amount", "deposits & withdrawals"], sizes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, ErrorPolicy};
+    use crate::report::Report;
+
+    use super::Analysis;
+
+    #[test]
+    fn test_analysis() {
+        let csv = "type,client,tx,amount\ndeposit,9,1,10\ndeposit,2,2,5\nwithdrawal,9,3,100\nbogus\ndeposit,9,4,2.5\n\
+                   dispute,9,1,\ndispute,9,4,\nresolve,9,4,\nchargeback,9,1,\nwithdrawal,9,5,0.5\n";
+        let mut config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        config.analyze.top = 1;
+        let mut report = Report::default();
+        let analysis = Analysis::read(csv.as_bytes(), &config, &mut report).unwrap();
+
+        assert_eq!(analysis.to_string(), "\
+type        applied  rejected
+deposit           3         0
+withdrawal        0         2
+dispute           2         0
+resolve           1         0
+chargeback        1         0
+
+chargeback losses: 10 over 1 chargeback
+
+top 1 clients by volume
+client  volume  txns  disputes  dispute rate  charged back
+9         12.5     2         2        100.0%            10
+
+top 1 clients by dispute rate
+client  disputes  txns  dispute rate
+9              2     2        100.0%
+
+amount        deposits & withdrawals
+< 1                                0
+1 - 10                             2
+10 - 100                           1
+100 - 1000                         0
+1000 - 10000                       0
+>= 10000                           0
+");
+        assert_eq!(report.skipped, 1);
+    }
+}
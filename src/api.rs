@@ -0,0 +1,653 @@
+//! `--api-listen 127.0.0.1:8081`: the server's accounts as they stand, over plain http, for reporting to read
+//! without going through the transaction socket:
+//! - `GET /accounts`: the accounts, in client order, a page at a time:
+//!   `[{"client":1,"available":"1.5","held":"2.5","total":"4","locked":false}]`. `?locked=true` (or false) &
+//!   `?min_balance=100` (of the total) pick out which, `?limit=500` how many a page has, 1000 unless it's said and
+//!   10000 at most. a page with more after it comes with a `Link: </accounts?...&after=500>; rel="next"` header
+//!   for the next, which starts after the client the page ended on (see `Filter` in query.rs)
+//! - `GET /accounts/<client>`: the one, 404 if the client has none
+//! - `GET /accounts/<client>/disputes`: the transactions it has under dispute, in id order, as a csv row reads:
+//!   `[{"type":"deposit","client":1,"tx":4,"amount":"2.5"}]`
+//! - `GET /export/accounts.csv`: every account as the balances are written out, `[output]` saying how, for batch
+//!   consumers to pull. it's the balances as they stood when the request came, streamed out with chunked transfer
+//!   as the rows are formatted, so the response is never held whole. one that fails part way ends without its last
+//!   chunk, for the client to see it's cut short
+//! - `POST /validate`, with a transaction as the socket takes one for its body (`withdrawal,1,7,2.5`): what
+//!   executing it would come to as the accounts stand, without executing it, so upstream systems can check an
+//!   operation first. the row is parsed & checked as a line sent the server is, under the config current, then
+//!   tried against a copy of its account, lock, funds, limits & dispute eligibility and all:
+//!   `{"valid":true,"outcome":"ok"}`, `{"valid":false,"outcome":"rejected","reason":"insufficient funds"}` or
+//!   `{"valid":false,"outcome":"malformatted","reason":"..."}`. it's answered as of the moment it's tried: a
+//!   transaction executed meanwhile can change the outcome
+//! - `POST /accounts/<client>/adjustments`, with `{"amount":"-2.5","reason":"..."}` for its body: an admin's
+//!   correction of the client's balance, as `adjust` on the socket (see admin.rs). `{"outcome":"ok"}`, 422 with the
+//!   rejection if it's declined, 409 if the server takes no adjustments
+//!
+//! with `--auth-keys` a request needs `Authorization: Bearer <key>`, any role's key (see auth.rs), 401 otherwise,
+//! and an adjustment an admin's, 403 otherwise. without them adjustments are refused, 403, there being no admin to
+//! tell apart. `--tls-cert` serves it through TLS as it does the tcp listener (see tls.rs), and without it a server
+//! with keys only listens on a loopback address, so the keys don't cross the network in the clear.
+//!
+//! a request is answered on a thread of its own, `MAX_CONNECTIONS` at once, any more closed unanswered. its line &
+//! headers may be `MAX_HEAD` bytes all told, 431 past that, and the whole of it must have come within `DEADLINE`,
+//! however it's trickled.
+//!
+//! `--read-only --snapshot latest.snap` serves a checkpoint's accounts this way and nothing else: there's no
+//! transaction socket, so reporting load is kept off the instance ingesting, which checkpoints for it. the snapshot
+//! is read once as the replica starts, with `checkpoint.key` if it's encrypted, a newer one is served on restart.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::admin::Adjustment;
+use crate::auth::Role;
+use crate::checkpoint::{Checkpoint, Key};
+use crate::config::{Config, OutputOptions};
+use crate::query::Filter;
+use crate::server::{Halves, State};
+use crate::{Account, Accounts, Balance, ClientId, Rejection};
+
+/// a client that hasn't sent its request by now isn't waited on
+const DEADLINE: Duration = Duration::from_secs(10);
+/// requests answered at once, a thread each
+const MAX_CONNECTIONS: usize = 64;
+/// the most a request's line & headers may be, all told
+const MAX_HEAD: usize = 16 * 1024;
+/// accounts a page has unless the request says
+const PAGE: usize = 1000;
+/// and at most
+const MAX_PAGE: usize = 10_000;
+/// the most a request's body may be, a transaction being a line
+const MAX_BODY: usize = 64 * 1024;
+
+#[derive(Serialize)]
+struct Row {
+    client: ClientId,
+    #[serde(flatten)]
+    balance: Balance,
+    locked: bool
+}
+
+impl Row {
+    fn new(client: ClientId, account: &Account) -> Self {
+        Row { client, balance: account.balance, locked: account.locked }
+    }
+}
+
+#[derive(Serialize)]
+struct Validation {
+    valid: bool,
+    /// as the socket would answer: `ok`, `rejected` or `malformatted`
+    outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>
+}
+
+/// an adjustment's body
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Adjust {
+    /// a decimal string, as amounts are written
+    amount: String,
+    reason: String
+}
+
+#[derive(Serialize)]
+struct Failure<'a> {
+    error: &'a str
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Response {
+    status: &'static str,
+    body: String,
+    /// the next page's path
+    next: Option<String>,
+    /// balances streamed out as csv in place of the body
+    export: Option<Accounts>
+}
+
+impl Response {
+    fn ok(body: String) -> Self {
+        Response { status: "200 OK", body, next: None, export: None }
+    }
+
+    fn failed(status: &'static str, error: &str) -> Self {
+        Response { status, body: serde_json::to_string(&Failure { error }).unwrap(), next: None, export: None }
+    }
+}
+
+/// http/1.1 chunked transfer coding, a chunk per write
+struct Chunked<W: Write>(W);
+
+impl<W: Write> Chunked<W> {
+    /// the last chunk, saying the body's whole
+    fn finish(mut self) -> io::Result<()> {
+        self.0.write_all(b"0\r\n\r\n")?;
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Write for Chunked<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            write!(self.0, "{:x}\r\n", buf.len())?;
+            self.0.write_all(buf)?;
+            self.0.write_all(b"\r\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    /// the key an `Authorization: Bearer <key>` header gives
+    bearer: Option<String>,
+    body: String
+}
+
+/// binds, then answers queries on a thread of its own
+pub(crate) fn serve(address: &str, state: Arc<State>) -> Result<(), Box<dyn std::error::Error>> {
+    let split = crate::server::tcp_split(&state.config().tls)?;
+    let listener = TcpListener::bind(address).map_err(|e| format!("Error listening on {}: {}", address, e))?;
+    std::thread::spawn(move || listen(listener, split, &state));
+    Ok(())
+}
+
+/// whether `address` is only reachable from this host
+pub(crate) fn loopback(address: &str) -> bool {
+    let resolved: Vec<_> = address.to_socket_addrs().map(Iterator::collect).unwrap_or_default();
+    !resolved.is_empty() && resolved.iter().all(|a| a.ip().is_loopback())
+}
+
+/// `--read-only`: serves the snapshot's accounts until the listener fails
+pub(crate) fn read_only(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let (address, snapshot) = match (&config.api.listen, &config.api.snapshot) {
+        (Some(address), Some(snapshot)) => (address.clone(), snapshot.clone()),
+        _ => return Err("--read-only needs --api-listen & --snapshot".into())
+    };
+    let key = Key::read(&config.checkpoint)?;
+    let accounts = Checkpoint::read(&snapshot, key.as_ref())?.accounts()?;
+    let (health, storage) = (config.health.listen.clone(), config.storage);
+    let split = crate::server::tcp_split(&config.tls)?;
+    let state = Arc::new(State::read_only(config, accounts)?);
+    if let Some(health) = &health {
+        crate::health::serve(health, Arc::clone(&state), storage)?;
+    }
+    let listener = TcpListener::bind(&address).map_err(|e| format!("Error listening on {}: {}", address, e))?;
+    listen(listener, split, &state);
+    Ok(())
+}
+
+/// the requests being answered
+#[derive(Debug, Default)]
+struct Connections(AtomicUsize);
+
+impl Connections {
+    /// counts one more in, false if there are `MAX_CONNECTIONS` already
+    fn open(&self) -> bool {
+        self.0.fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| (open < MAX_CONNECTIONS).then_some(open + 1)).is_ok()
+    }
+
+    fn close(&self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// a reader that fails once `until` has passed, the socket it reads from waiting no longer than that on a read
+struct Deadline<R: Read> {
+    inner: R,
+    socket: TcpStream,
+    until: Instant
+}
+
+impl<R: Read> Read for Deadline<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request not sent in time"));
+        }
+        self.socket.set_read_timeout(Some(left))?;
+        self.inner.read(buf)
+    }
+}
+
+fn listen<F>(listener: TcpListener, split: F, state: &Arc<State>)
+    where F: Fn(TcpStream) -> io::Result<Halves>
+{
+    let connections = Arc::new(Connections::default());
+    for stream in listener.incoming() {
+        let halves = stream.and_then(|stream| Ok((stream.try_clone()?, split(stream)?)));
+        match halves {
+            // dropped, so it's closed
+            Ok(_) if !connections.open() => {},
+            Ok((socket, halves)) => {
+                // a dump of every account can take a while, it shouldn't hold up the queries behind it
+                let (state, connections) = (Arc::clone(state), Arc::clone(&connections));
+                std::thread::spawn(move || {
+                    if let Err(e) = answer(halves, socket, &state) {
+                        eprintln!("api error: {}", e);
+                    }
+                    connections.close();
+                });
+            },
+            Err(e) => eprintln!("accept error: {}", e)
+        }
+    }
+}
+
+fn answer((reader, mut stream): Halves, socket: TcpStream, state: &State) -> io::Result<()> {
+    let reader = Deadline { inner: reader, socket, until: Instant::now() + DEADLINE };
+    let response = match read_request(BufReader::new(reader))? {
+        Ok(request) => respond(&request, state),
+        Err(response) => response
+    };
+    if let Some(accounts) = &response.export {
+        let config = state.config();
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n")?;
+        return export(accounts, &config.output, Chunked(&mut stream));
+    }
+    let mut headers = String::new();
+    if response.status == "401 Unauthorized" {
+        headers.push_str("WWW-Authenticate: Bearer\r\n");
+    }
+    if let Some(next) = &response.next {
+        headers.push_str(&format!("Link: <{}>; rel=\"next\"\r\n", next));
+    }
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+           response.status, response.body.len(), headers, response.body)?;
+    stream.flush()
+}
+
+/// writes `accounts` out as csv to `out`, as `write_out` would to a file
+fn export<W: Write>(accounts: &Accounts, options: &OutputOptions, mut out: Chunked<W>) -> io::Result<()> {
+    let mut sink = crate::csv_sink(&mut out, options);
+    crate::write_listed(accounts, options, &|_| true, &mut sink).map_err(|e| io::Error::other(e.to_string()))?;
+    drop(sink);
+    out.finish()
+}
+
+/// the request line, the headers wanted of them & the body, Err the response to a request that can't be read
+fn read_request<R: BufRead>(mut reader: R) -> io::Result<Result<Request, Response>> {
+    let too_large = || Ok(Err(Response::failed("431 Request Header Fields Too Large", &format!("over {} bytes of headers", MAX_HEAD))));
+    let mut head = (&mut reader).take(MAX_HEAD as u64);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    if head.limit() == 0 {
+        return too_large();
+    }
+    let mut request = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [method, path, _] => Request { method: method.to_string(), path: path.to_string(), ..Request::default() },
+        _ => return Ok(Err(Response::failed("400 Bad Request", "expected <method> <path> HTTP/1.1")))
+    };
+    let mut length = 0;
+    loop {
+        line.clear();
+        let read = head.read_line(&mut line)?;
+        if head.limit() == 0 {
+            return too_large();
+        }
+        if read <= 2 {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "authorization" => request.bearer = value.trim().strip_prefix("Bearer ").map(|key| key.trim().to_string()),
+                "content-length" => length = value.trim().parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad content-length"))?,
+                _ => {}
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Ok(Err(Response::failed("413 Payload Too Large", &format!("a body of {} bytes, over {}", length, MAX_BODY))));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    request.body = String::from_utf8(body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Ok(request))
+}
+
+/// the response to `request`
+fn respond(request: &Request, state: &State) -> Response {
+    // the role, and the id of the key, for the audit log
+    let (role, key) = match &state.keys {
+        None => (Role::Admin, None),
+        Some(keys) => match request.bearer.as_deref().and_then(|key| Some((keys.role(key)?, key))) {
+            Some((role, key)) => (role, Some(crate::auth::id(key))),
+            None => return Response::failed("401 Unauthorized", "expected an api key, as Authorization: Bearer <key>")
+        }
+    };
+    let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').skip(1).collect();
+    let method = match segments[..] {
+        ["validate"] | ["accounts", _, "adjustments"] => "POST",
+        _ => "GET"
+    };
+    if request.method != method {
+        return Response::failed("405 Method Not Allowed", &format!("expected {}", method));
+    }
+    let client = |id: &str| id.parse::<ClientId>().map_err(|_| Response::failed("400 Bad Request", "expected a client id"));
+    let found = |account: Option<Account>| account.ok_or_else(|| Response::failed("404 Not Found", "no such account"));
+    let response = match segments[..] {
+        ["accounts"] => filter(query).map(|filter| accounts(&filter, state)),
+        ["accounts", id] => client(id)
+            .and_then(|client| found(state.engine.account(client)).map(|account| Row::new(client, &account)))
+            .map(|row| Response::ok(serde_json::to_string(&row).unwrap())),
+        ["accounts", id, "disputes"] => client(id)
+            .and_then(|client| found(state.engine.account(client)))
+            .map(|account| Response::ok(serde_json::to_string(&account.disputed()).unwrap())),
+        ["export", "accounts.csv"] if query.is_empty() => {
+            Ok(Response { export: Some(state.engine.balances()), ..Response::ok(String::new()) })
+        },
+        ["validate"] => Ok(validate(&request.body, state)),
+        ["accounts", _, "adjustments"] if key.is_none() => {
+            Err(Response::failed("403 Forbidden", "adjustments over http need the server's --auth-keys"))
+        },
+        ["accounts", _, "adjustments"] if role != Role::Admin => {
+            Err(Response::failed("403 Forbidden", &format!("adjust is an admin operation, this key is a {}", role)))
+        },
+        ["accounts", id, "adjustments"] => client(id).and_then(|client| adjust(client, &request.body, key.as_deref(), state)),
+        _ => Err(Response::failed("404 Not Found", "no such path"))
+    };
+    response.unwrap_or_else(|failed| failed)
+}
+
+/// what executing the transaction `line` would come to
+fn validate(line: &str, state: &State) -> Response {
+    let config = state.config();
+    let validation = match crate::server::parse_line(line.trim(), &config) {
+        Ok(txn) => match state.duplicate(&txn).map(|duplicate| if duplicate { Err(Rejection::Duplicate) } else { state.engine.validate(txn, &config) }) {
+            Ok(Ok(())) => Validation { valid: true, outcome: "ok", reason: None },
+            Ok(Err(r)) => Validation { valid: false, outcome: "rejected", reason: Some(r.to_string()) },
+            Err(e) => return Response::failed("500 Internal Server Error", &format!("dedup index: {}", e))
+        },
+        Err(e) => Validation { valid: false, outcome: "malformatted", reason: Some(e.to_string()) }
+    };
+    Response::ok(serde_json::to_string(&validation).unwrap())
+}
+
+/// adjusts the client's balance as `body` says
+fn adjust(client: ClientId, body: &str, key: Option<&str>, state: &State) -> Result<Response, Response> {
+    let invalid = |e: String| Response::failed("400 Bad Request", &e);
+    let body: Adjust = serde_json::from_str(body).map_err(|e| invalid(e.to_string()))?;
+    let amount = body.amount.parse().map_err(|_| invalid(format!("invalid amount '{}'", body.amount)))?;
+    let adjustment = Adjustment::new(client, amount, &body.reason).map_err(invalid)?;
+    match crate::admin::adjust(&adjustment, key, state) {
+        Ok(Ok(())) => Ok(Response::ok(r#"{"outcome":"ok"}"#.into())),
+        Ok(Err(r)) => Err(Response::failed("422 Unprocessable Entity", &r.to_string())),
+        Err(e) => Err(Response::failed("409 Conflict", &e))
+    }
+}
+
+/// the page `query`, `locked=true&limit=500` say
+fn filter(query: &str) -> Result<Filter, Response> {
+    let mut filter = Filter { limit: Some(PAGE), ..Filter::default() };
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+        let (name, value) = param.split_once('=').unwrap_or((param, ""));
+        let invalid = || Response::failed("400 Bad Request", &format!("invalid {} '{}'", name, value));
+        match name {
+            "locked" => filter.locked = Some(value.parse().map_err(|_| invalid())?),
+            "min_balance" => filter.min_balance = Some(value.parse().map_err(|_| invalid())?),
+            "after" => filter.after = Some(value.parse().map_err(|_| invalid())?),
+            "limit" => filter.limit = Some(value.parse().ok().filter(|l| (1..=MAX_PAGE).contains(l)).ok_or_else(invalid)?),
+            _ => return Err(Response::failed("400 Bad Request", &format!("unknown parameter '{}'", name)))
+        }
+    }
+    Ok(filter)
+}
+
+fn accounts(filter: &Filter, state: &State) -> Response {
+    // as a snapshot writes them out
+    let empty = state.config().output.empty_accounts;
+    let (page, next) = filter.page(state.engine.balances().into_iter().filter(|(_, a)| empty || !a.is_empty()));
+    let rows: Vec<Row> = page.iter().map(|(client, account)| Row::new(*client, account)).collect();
+    let next = next.map(|after| {
+        let mut path = format!("/accounts?limit={}&after={}", filter.limit.unwrap_or(PAGE), after);
+        if let Some(locked) = filter.locked {
+            path.push_str(&format!("&locked={}", locked));
+        }
+        if let Some(min) = filter.min_balance {
+            path.push_str(&format!("&min_balance={}", min));
+        }
+        path
+    });
+    Response { next, ..Response::ok(serde_json::to_string(&rows).unwrap()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::{Duration, Instant};
+
+    use rust_decimal_macros::dec;
+
+    use crate::admin::Audit;
+    use crate::auth::Keys;
+    use crate::config::{Config, Limits};
+    use crate::server::{handle, State};
+    use crate::{Accounts, ClientId, execute, Txn};
+
+    use super::{export, loopback, read_request, respond, Chunked, Connections, Deadline, Request, Response, MAX_CONNECTIONS,
+                MAX_HEAD};
+
+    fn get(path: &str) -> Request {
+        Request { method: "GET".into(), path: path.into(), ..Request::default() }
+    }
+
+    fn validate(line: &str, state: &State) -> String {
+        respond(&Request { method: "POST".into(), path: "/validate".into(), body: line.into(), ..Request::default() }, state).body
+    }
+
+    #[test]
+    fn test_respond() {
+        let state = State::new(Config::default());
+        handle("deposit,2,1,5\ndeposit,1,2,1.5\ndeposit,1,3,2.5\ndispute,1,3,\n".as_bytes(), std::io::sink(), &state).unwrap();
+
+        assert_eq!(respond(&get("/accounts"), &state), Response::ok(concat!(
+            r#"[{"client":1,"available":"1.5","held":"2.5","total":"4","locked":false},"#,
+            r#"{"client":2,"available":"5","held":"0","total":"5","locked":false}]"#).to_string()));
+        assert_eq!(respond(&get("/accounts/2/"), &state).body, r#"{"client":2,"available":"5","held":"0","total":"5","locked":false}"#);
+        assert_eq!(respond(&get("/accounts/1/disputes"), &state).body, r#"[{"type":"deposit","client":1,"tx":3,"amount":"2.5"}]"#);
+        assert_eq!(respond(&get("/accounts/2/disputes"), &state).body, "[]");
+        assert_eq!(respond(&get("/accounts/3"), &state).status, "404 Not Found");
+        assert_eq!(respond(&get("/accounts/x"), &state).status, "400 Bad Request");
+        assert_eq!(respond(&get("/transactions"), &state).status, "404 Not Found");
+        assert_eq!(respond(&Request { method: "POST".into(), ..get("/accounts") }, &state).status, "405 Method Not Allowed");
+    }
+
+    #[test]
+    fn test_pages() {
+        let state = State::new(Config::default());
+        for client in 1..=25 {
+            handle(format!("deposit,{},{},{}\n", client, client, client).as_bytes(), std::io::sink(), &state).unwrap();
+        }
+        handle("dispute,3,3,\nchargeback,3,3,\n".as_bytes(), std::io::sink(), &state).unwrap();
+        let clients = |response: &Response| {
+            let rows: Vec<serde_json::Value> = serde_json::from_str(&response.body).unwrap();
+            rows.iter().map(|r| r["client"].as_u64().unwrap()).collect::<Vec<_>>()
+        };
+
+        let first = respond(&get("/accounts?limit=10&min_balance=2.5"), &state);
+        assert_eq!(clients(&first), (4..=13).collect::<Vec<_>>());
+        assert_eq!(first.next.as_deref(), Some("/accounts?limit=10&after=13&min_balance=2.5"));
+        let last = respond(&get("/accounts?limit=10&after=13&min_balance=2.5"), &state);
+        assert_eq!(clients(&last), (14..=25).take(10).collect::<Vec<_>>());
+        let last = respond(&get(last.next.as_deref().unwrap()), &state);
+        assert_eq!((clients(&last), last.next), (vec![24, 25], None));
+
+        let locked = respond(&get("/accounts?locked=true"), &state);
+        assert_eq!((clients(&locked), locked.next), (vec![3], None));
+        assert_eq!(clients(&respond(&get("/accounts"), &state)).len(), 25);
+        for bad in ["/accounts?limit=0", "/accounts?limit=10001", "/accounts?locked=yes", "/accounts?sort=client"] {
+            assert_eq!(respond(&get(bad), &state).status, "400 Bad Request", "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(1, 1, dec!(3)));
+        execute(&mut accounts, Txn::dispute(1, 1));
+        let keys = std::env::temp_dir().join(format!("txn-api-keys-{}.txt", std::process::id()));
+        // the digest of "foo"
+        std::fs::write(&keys, "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  submitter\n").unwrap();
+        let mut config = Config::default();
+        config.api.read_only = true;
+        config.auth.keys_file = Some(keys.clone());
+        let state = State::read_only(config, accounts).unwrap();
+        std::fs::remove_file(&keys).unwrap();
+
+        assert_eq!(respond(&get("/accounts/1/disputes"), &state).status, "401 Unauthorized");
+        assert_eq!(respond(&Request { bearer: Some("bar".into()), ..get("/accounts") }, &state).status, "401 Unauthorized");
+        let authorized = Request { bearer: Some("foo".into()), ..get("/accounts/1/disputes") };
+        assert_eq!(respond(&authorized, &state).body, r#"[{"type":"deposit","client":1,"tx":1,"amount":"3"}]"#);
+        let authorized = Request { bearer: Some("foo".into()), ..get("/accounts") };
+        assert_eq!(respond(&authorized, &state).body, r#"[{"client":1,"available":"0","held":"3","total":"3","locked":false}]"#);
+    }
+
+    #[test]
+    fn test_adjust() {
+        let dir = std::env::temp_dir().join(format!("txn-api-adjust-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // the digests of "foo" & "bar"
+        std::fs::write(dir.join("keys.txt"), "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  submitter\n\
+                                             fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9  admin\n").unwrap();
+        let mut state = State::new(Config::default());
+        state.keys = Some(Keys::load(&dir.join("keys.txt")).unwrap());
+        let adjust = |key: &str, body: &str, state: &State| {
+            let request = Request { method: "POST".into(), path: "/accounts/1/adjustments".into(), bearer: Some(key.into()), body: body.into() };
+            respond(&request, state)
+        };
+        let body = r#"{"amount":"-2.5","reason":"refund of a duplicated fee"}"#;
+        assert_eq!(adjust("bar", body, &state).status, "409 Conflict");
+        // without keys there's no telling an admin
+        assert_eq!(adjust("bar", body, &State::new(Config::default())).status, "403 Forbidden");
+
+        state.audit = Some(Audit::open(&dir.join("audit.jsonl")).unwrap());
+        handle("auth foo\ndeposit,1,1,10\n".as_bytes(), std::io::sink(), &state).unwrap();
+        assert_eq!(adjust("foo", body, &state).status, "403 Forbidden");
+        assert_eq!(adjust("bar", body, &state).body, r#"{"outcome":"ok"}"#);
+        assert_eq!(adjust("bar", r#"{"amount":"-8","reason":"too much"}"#, &state),
+                   Response::failed("422 Unprocessable Entity", "insufficient funds"));
+        for bad in [r#"{"amount":"x","reason":"r"}"#, r#"{"amount":"1"}"#, r#"{"amount":"1","reason":" "}"#, "1"] {
+            assert_eq!(adjust("bar", bad, &state).status, "400 Bad Request", "{}", bad);
+        }
+        assert_eq!(respond(&Request { bearer: Some("bar".into()), ..get("/accounts/1/adjustments") }, &state).status, "405 Method Not Allowed");
+        assert_eq!(state.engine.balances()[&ClientId(1)].balance.available, dec!(7.5));
+        let audit = std::fs::read_to_string(dir.join("audit.jsonl")).unwrap();
+        assert_eq!(audit.lines().count(), 2);
+        assert!(audit.starts_with(r#"{"at_ms":"#) && audit.lines().all(|l| l.contains(r#""key":"fcde2b2e""#)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_loopback() {
+        assert!(loopback("127.0.0.1:8081") && loopback("[::1]:8081") && loopback("localhost:8081"));
+        assert!(!loopback("0.0.0.0:8081") && !loopback("10.0.0.2:8081") && !loopback("8081"));
+    }
+
+    #[test]
+    fn test_export() {
+        let state = State::new(Config::default());
+        handle("deposit,2,1,5\ndeposit,1,2,1.5\nwithdrawal,3,3,1\n".as_bytes(), std::io::sink(), &state).unwrap();
+        let response = respond(&get("/export/accounts.csv"), &state);
+        assert_eq!(response.status, "200 OK");
+        let mut output = Config::default().output;
+        output.sort = true;
+        output.decimals = 2;
+        output.empty_accounts = false;
+        let mut body = Vec::new();
+        export(response.export.as_ref().unwrap(), &output, Chunked(&mut body)).unwrap();
+        // the declined withdrawal's empty account left out, as from write_out
+        let csv = "client,available,held,total,locked\n1,1.50,0.00,1.50,false\n2,5.00,0.00,5.00,false\n";
+        assert_eq!(String::from_utf8(body).unwrap(), format!("{:x}\r\n{}\r\n0\r\n\r\n", csv.len(), csv));
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = Config { limits: Limits { max_amount: Some(dec!(100)), ..Limits::default() }, ..Config::default() };
+        for engine in [Config::default(), Config { actors: true, ..Config::default() }, Config { threads: Some(1), ..Config::default() }] {
+            let state = State::new(Config { actors: engine.actors, threads: engine.threads, ..config.clone() });
+            handle("deposit,1,1,10\ndeposit,1,2,5\ndispute,1,2,\ndeposit,2,3,1\ndispute,2,3,\nchargeback,2,3,\n".as_bytes(),
+                   std::io::sink(), &state).unwrap();
+            let before = state.engine.balances();
+
+            assert_eq!(validate("withdrawal,1,4,10\n", &state), r#"{"valid":true,"outcome":"ok"}"#);
+            assert_eq!(validate("withdrawal,1,4,10.5", &state), r#"{"valid":false,"outcome":"rejected","reason":"insufficient funds"}"#);
+            assert_eq!(validate("deposit,1,4,500", &state), r#"{"valid":false,"outcome":"rejected","reason":"amount over limit"}"#);
+            assert_eq!(validate("dispute,1,2,", &state), r#"{"valid":false,"outcome":"rejected","reason":"already disputed"}"#);
+            assert_eq!(validate("resolve,1,2,", &state), r#"{"valid":true,"outcome":"ok"}"#);
+            assert_eq!(validate("deposit,2,4,1", &state), r#"{"valid":false,"outcome":"rejected","reason":"account locked"}"#);
+            // a client with no account yet
+            assert_eq!(validate("deposit,9,5,1", &state), r#"{"valid":true,"outcome":"ok"}"#);
+            assert!(validate("deposit,1,x,1", &state).starts_with(r#"{"valid":false,"outcome":"malformatted","reason":"#));
+            // nothing was applied
+            assert_eq!(state.engine.balances(), before);
+        }
+        let state = State::new(Config::default());
+        assert_eq!(respond(&get("/validate"), &state).status, "405 Method Not Allowed");
+        assert_eq!(respond(&Request { method: "POST".into(), ..get("/accounts") }, &state).status, "405 Method Not Allowed");
+    }
+
+    #[test]
+    fn test_read_request() {
+        let request = "GET /accounts HTTP/1.1\r\nHost: localhost\r\nauthorization: Bearer foo \r\n\r\n";
+        assert_eq!(read_request(request.as_bytes()).unwrap().unwrap(),
+                   Request { method: "GET".into(), path: "/accounts".into(), bearer: Some("foo".into()), body: String::new() });
+        let request = "POST /validate HTTP/1.1\r\nContent-Length: 15\r\n\r\ndeposit,1,1,1.5";
+        assert_eq!(read_request(request.as_bytes()).unwrap().unwrap().body, "deposit,1,1,1.5");
+        let request = format!("POST /validate HTTP/1.1\r\nContent-Length: {}\r\n\r\n", 1 << 20);
+        assert_eq!(read_request(request.as_bytes()).unwrap().unwrap_err().status, "413 Payload Too Large");
+        assert_eq!(read_request("nonsense\r\n\r\n".as_bytes()).unwrap().unwrap_err().status, "400 Bad Request");
+        // however many headers, or however long the one
+        let many = format!("GET /accounts HTTP/1.1\r\n{}\r\n", "X-A: b\r\n".repeat(MAX_HEAD / 8));
+        let long = format!("GET /accounts HTTP/1.1\r\nX-A: {}\r\n\r\n", "b".repeat(MAX_HEAD));
+        for request in [many, long] {
+            assert_eq!(read_request(request.as_bytes()).unwrap().unwrap_err().status, "431 Request Header Fields Too Large");
+        }
+    }
+
+    #[test]
+    fn test_max_connections() {
+        let connections = Connections::default();
+        assert!((0..MAX_CONNECTIONS).all(|_| connections.open()));
+        assert!(!connections.open());
+        connections.close();
+        assert!(connections.open());
+    }
+
+    #[test]
+    fn test_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        // a byte every 20ms, each well within a read timeout
+        let trickle = std::thread::spawn(move || {
+            for _ in 0..50 {
+                if (&client).write_all(b"X").is_err() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        });
+        let socket = listener.accept().unwrap().0;
+        let mut reader = Deadline { inner: socket.try_clone().unwrap(), socket, until: Instant::now() + Duration::from_millis(200) };
+        let started = Instant::now();
+        let error = std::io::copy(&mut reader, &mut std::io::sink()).unwrap_err();
+        assert!(started.elapsed() < Duration::from_millis(500), "{:?}", started.elapsed());
+        assert!(matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock), "{}", error);
+        drop(reader);
+        trickle.join().unwrap();
+    }
+}
@@ -0,0 +1,329 @@
+//! arrow ipc (feather v2) input.
+//!
+//! expects the same columns as the csv header: `type` (any string-castable type, i.e. utf8 or dictionary),
+//! `client` and `tx` (any integer type, range checked) and a nullable `amount` (float, decimal or utf8).
+//! both the ipc file format and the ipc stream format are accepted.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::str::FromStr;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{ArrowPrimitiveType, Float64Type};
+use arrow_array::{Array, ArrayRef, RecordBatch};
+use arrow_cast::{cast_with_options, CastOptions};
+use arrow_ipc::reader::{FileReader, StreamReader};
+use arrow_schema::{ArrowError, DataType};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+
+use crate::source::{SourceError, TxnSource};
+use crate::id::{ClientArrow, TxnArrow};
+use crate::{ClientId, Precision, Txn, TxnId, TxnType};
+
+const FILE_MAGIC: &[u8; 6] = b"ARROW1";
+
+type Batches = Box<dyn Iterator<Item = Result<RecordBatch, ArrowError>>>;
+
+/// sniffs the ipc file magic to pick between the file & stream readers
+pub fn read_batches<R: Read + Seek + 'static>(mut reader: R) -> Result<Batches, ArrowError> {
+    let mut magic = [0u8; 6];
+    let is_file = reader.read_exact(&mut magic).is_ok() && &magic == FILE_MAGIC;
+    reader.seek(SeekFrom::Start(0))?;
+
+    if is_file {
+        Ok(Box::new(FileReader::try_new_buffered(reader, None)?))
+    } else {
+        Ok(Box::new(StreamReader::try_new_buffered(reader, None)?))
+    }
+}
+
+/// converts a record batch into transactions, in row order. the outer error fails the whole batch
+/// (missing or uncastable columns), the inner ones single rows, so the error policy can skip just those.
+pub fn batch_to_txns(batch: &RecordBatch, precision: impl Into<Precision>) -> Result<Vec<Result<Txn, ArrowError>>, ArrowError> {
+    let precision = precision.into();
+    let txntypes = Column::new(batch, "type", &DataType::Utf8)?;
+    let clients = Column::new(batch, "client", &ClientArrow::DATA_TYPE)?;
+    let txids = Column::new(batch, "tx", &TxnArrow::DATA_TYPE)?;
+    let amounts = match batch.column_by_name("amount") {
+        Some(column) => Some(Amounts::new(column)?),
+        None => None
+    };
+
+    let txns = (0..batch.num_rows()).map(|row| {
+        let txntype = txntypes.get(row, "type")?;
+        let txntype = parse_txntype(txntypes.cast.as_string::<i32>().value(txntype).trim())
+            .ok_or_else(|| row_error(row, "unknown transaction type"))?;
+        let client = clients.cast.as_primitive::<ClientArrow>().value(clients.get(row, "client")?);
+        let tx = txids.cast.as_primitive::<TxnArrow>().value(txids.get(row, "tx")?);
+        let amount = match &amounts {
+            Some(a) => a.get(row)?,
+            None => None
+        };
+        Txn::builder(txntype, ClientId(client), TxnId(tx)).amount(amount).precision(precision).build().map_err(|e| row_error(row, e.as_str()))
+    });
+    Ok(txns.collect())
+}
+
+/// the rows of every batch, a batch at a time, so memory is bounded by batch size
+pub struct ArrowSource {
+    batches: Batches,
+    txns: std::vec::IntoIter<Result<Txn, ArrowError>>,
+    precision: Precision
+}
+
+impl ArrowSource {
+    pub fn new<R: Read + Seek + 'static>(reader: R, precision: impl Into<Precision>) -> Result<Self, ArrowError> {
+        Ok(ArrowSource { batches: read_batches(reader)?, txns: Vec::new().into_iter(), precision: precision.into() })
+    }
+}
+
+/// an unreadable batch (i.e. a missing column) is one error, standing for every row it held
+impl TxnSource for ArrowSource {
+    fn next_txn(&mut self) -> Option<Result<Txn, SourceError>> {
+        loop {
+            if let Some(txn) = self.txns.next() {
+                return Some(txn.map_err(|e| SourceError::malformatted("row", e)));
+            }
+            let batch = match self.batches.next()? {
+                Ok(b) => b,
+                Err(e) => return Some(Err(SourceError::malformatted("batch", e)))
+            };
+            match batch_to_txns(&batch, self.precision) {
+                Ok(txns) => self.txns = txns.into_iter(),
+                Err(e) => return Some(Err(SourceError::malformatted("batch", e).rows(batch.num_rows().max(1) as u64)))
+            }
+        }
+    }
+}
+
+/// a column cast leniently: values that don't fit come out null, and are told apart from actual nulls per row
+struct Column {
+    original: ArrayRef,
+    cast: ArrayRef
+}
+
+impl Column {
+    fn new(batch: &RecordBatch, name: &str, to: &DataType) -> Result<Self, ArrowError> {
+        let original = batch.column_by_name(name)
+            .ok_or_else(|| ArrowError::SchemaError(format!("missing column '{}'", name)))?;
+        Column::cast(original, to)
+    }
+
+    fn cast(original: &ArrayRef, to: &DataType) -> Result<Self, ArrowError> {
+        let cast = cast_with_options(original, to, &CastOptions::default())?;
+        Ok(Column { original: original.clone(), cast })
+    }
+
+    /// the row, if it holds a valid value
+    fn get(&self, row: usize, name: &str) -> Result<usize, ArrowError> {
+        if self.original.is_null(row) {
+            Err(row_error(row, &format!("null {}", name)))
+        } else if self.cast.is_null(row) {
+            Err(row_error(row, &format!("invalid {}", name)))
+        } else {
+            Ok(row)
+        }
+    }
+
+    fn is_null(&self, row: usize, name: &str) -> Result<bool, ArrowError> {
+        match self.get(row, name) {
+            Ok(_) => Ok(false),
+            Err(_) if self.original.is_null(row) => Ok(true),
+            Err(e) => Err(e)
+        }
+    }
+}
+
+/// amount column, normalised to either floats (mirrors the csv serde-float path) or strings
+enum Amounts {
+    Float(Column),
+    Text(Column)
+}
+
+impl Amounts {
+    fn new(column: &ArrayRef) -> Result<Self, ArrowError> {
+        match column.data_type() {
+            DataType::Float16 | DataType::Float32 | DataType::Float64 => {
+                Ok(Amounts::Float(Column::cast(column, &DataType::Float64)?))
+            },
+            _ => Ok(Amounts::Text(Column::cast(column, &DataType::Utf8)?))
+        }
+    }
+
+    fn get(&self, row: usize) -> Result<Option<Decimal>, ArrowError> {
+        match self {
+            Amounts::Float(column) => {
+                if column.is_null(row, "amount")? {
+                    return Ok(None);
+                }
+                let array = column.cast.as_primitive::<Float64Type>();
+                Decimal::from_f64(array.value(row))
+                    .map(Some)
+                    .ok_or_else(|| row_error(row, "amount out of range"))
+            },
+            Amounts::Text(column) => {
+                if column.is_null(row, "amount")? {
+                    return Ok(None);
+                }
+                let array = column.cast.as_string::<i32>();
+                if array.value(row).trim().is_empty() {
+                    return Ok(None);
+                }
+                Decimal::from_str(array.value(row).trim())
+                    .map(Some)
+                    .map_err(|_| row_error(row, "invalid amount"))
+            }
+        }
+    }
+}
+
+/// mirrors serde's lowercase renaming used by the csv reader
+fn parse_txntype(s: &str) -> Option<TxnType> {
+    match s {
+        "deposit" => Some(TxnType::Deposit),
+        "withdrawal" => Some(TxnType::Withdrawal),
+        "dispute" => Some(TxnType::Dispute),
+        "resolve" => Some(TxnType::Resolve),
+        "chargeback" => Some(TxnType::Chargeback),
+        _ => None
+    }
+}
+
+fn row_error(row: usize, msg: &str) -> ArrowError {
+    ArrowError::InvalidArgumentError(format!("row {}: {}", row, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    use arrow_array::{Decimal128Array, Float64Array, Int64Array, RecordBatch, StringArray, UInt16Array, UInt32Array};
+    use arrow_ipc::writer::{FileWriter, StreamWriter};
+    use arrow_schema::{DataType, Field, Schema};
+    use rust_decimal_macros::dec;
+
+    use crate::{CURRENCY_PRECISION, Txn};
+
+    use super::{batch_to_txns, read_batches};
+
+    fn batch(amount: Arc<dyn arrow_array::Array>) -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new("type", DataType::Utf8, false),
+            Field::new("client", DataType::UInt16, false),
+            Field::new("tx", DataType::UInt32, false),
+            Field::new("amount", amount.data_type().clone(), true),
+        ]);
+        RecordBatch::try_new(Arc::new(schema), vec![
+            Arc::new(StringArray::from(vec!["deposit", " withdrawal", "dispute"])),
+            Arc::new(UInt16Array::from(vec![1, 1, 1])),
+            Arc::new(UInt32Array::from(vec![1, 2, 1])),
+            amount,
+        ]).unwrap()
+    }
+
+    fn txns(batch: &RecordBatch) -> Vec<Txn> {
+        batch_to_txns(batch, CURRENCY_PRECISION).unwrap().into_iter().map(Result::unwrap).collect()
+    }
+
+    fn expected() -> Vec<Txn> {
+        vec![
+            Txn::deposit(1, 1, dec!(1.2346)),
+            Txn::withdrawal(1, 2, dec!(1.5)),
+            Txn::dispute(1, 1),
+        ]
+    }
+
+    #[test]
+    fn test_float_amounts() {
+        let amounts = Float64Array::from(vec![Some(1.23456), Some(1.5), None]);
+        assert_eq!(txns(&batch(Arc::new(amounts))), expected());
+    }
+
+    #[test]
+    fn test_string_amounts() {
+        let amounts = StringArray::from(vec![Some("1.23456"), Some(" 1.5 "), Some("")]);
+        assert_eq!(txns(&batch(Arc::new(amounts))), expected());
+    }
+
+    #[test]
+    fn test_decimal_amounts() {
+        let amounts = Decimal128Array::from(vec![Some(123456), Some(150000), None])
+            .with_precision_and_scale(10, 5).unwrap();
+        assert_eq!(txns(&batch(Arc::new(amounts))), expected());
+    }
+
+    #[test]
+    fn test_client_overflow() {
+        let schema = Schema::new(vec![
+            Field::new("type", DataType::Utf8, false),
+            Field::new("client", DataType::Int64, false),
+            Field::new("tx", DataType::Int64, false),
+        ]);
+        let batch = RecordBatch::try_new(Arc::new(schema), vec![
+            Arc::new(StringArray::from(vec!["dispute"])),
+            Arc::new(Int64Array::from(vec![-1])),
+            Arc::new(Int64Array::from(vec![1])),
+        ]).unwrap();
+        let txns = batch_to_txns(&batch, CURRENCY_PRECISION).unwrap();
+        assert!(txns[0].as_ref().unwrap_err().to_string().contains("row 0: invalid client"));
+    }
+
+    #[test]
+    fn test_unknown_type() {
+        let schema = Schema::new(vec![
+            Field::new("type", DataType::Utf8, false),
+            Field::new("client", DataType::UInt16, false),
+            Field::new("tx", DataType::UInt32, false),
+        ]);
+        let batch = RecordBatch::try_new(Arc::new(schema), vec![
+            Arc::new(StringArray::from(vec!["refund"])),
+            Arc::new(UInt16Array::from(vec![1])),
+            Arc::new(UInt32Array::from(vec![1])),
+        ]).unwrap();
+        assert!(batch_to_txns(&batch, CURRENCY_PRECISION).unwrap()[0].is_err());
+    }
+
+    #[test]
+    fn test_bad_rows_fail_alone() {
+        let amounts = StringArray::from(vec![Some("1.23456"), Some("abc"), None]);
+        let txns = batch_to_txns(&batch(Arc::new(amounts)), CURRENCY_PRECISION).unwrap();
+        assert_eq!(txns.len(), 3);
+        assert_eq!(txns[0].as_ref().unwrap(), &expected()[0]);
+        assert!(txns[1].as_ref().unwrap_err().to_string().contains("row 1: invalid amount"));
+        assert_eq!(txns[2].as_ref().unwrap(), &expected()[2]);
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let schema = Schema::new(vec![Field::new("type", DataType::Utf8, false)]);
+        let batch = RecordBatch::try_new(Arc::new(schema), vec![
+            Arc::new(StringArray::from(vec!["dispute"])),
+        ]).unwrap();
+        assert!(batch_to_txns(&batch, CURRENCY_PRECISION).is_err());
+    }
+
+    #[test]
+    fn test_read_file_and_stream() {
+        let batch = batch(Arc::new(Float64Array::from(vec![Some(1.23456), Some(1.5), None])));
+
+        let mut file = Vec::new();
+        let mut writer = FileWriter::try_new(&mut file, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let mut stream = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut stream, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        for bytes in [file, stream] {
+            let txns: Vec<Txn> = read_batches(Cursor::new(bytes)).unwrap()
+                .flat_map(|b| txns(&b.unwrap()))
+                .collect();
+            assert_eq!(txns, expected());
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! `--auth-keys keys.txt`: api keys the server's connections authenticate with, and what each may do. the file lists
+//! the sha-256 digest of each key and its role, in `sha256sum`'s format so `printf %s "$KEY" | sha256sum` gives a
+//! line to finish with the role, and holds no key itself:
+//! ```text
+//! 2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  submitter
+//! fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9  admin
+//! ```
+//! - `submitter`: may send transactions
+//! - `admin`: may also run admin operations: `snapshot`, which writes the balances out there & then, `promote`,
+//!   which makes a standby a primary (see replica.rs), `adjust`, which corrects a balance, and `forget`, which
+//!   erases who a client is (see admin.rs)
+//!
+//! with keys set, a connection's first line must be `auth <key>`, answered `ok`. any other, or a key not listed,
+//! is answered `unauthorized: <why>` and the connection closed. an operation the role doesn't allow is answered
+//! `forbidden: <why>` and the connection carries on. without keys anyone who can open the socket is an admin, as the
+//! socket's file permissions allow, so a tcp listener won't start without them. over tcp without TLS the keys are
+//! sent in the clear. the file is read at startup, a changed one applies on restart.
+//!
+//! the query api (see api.rs) takes the same keys, as `Authorization: Bearer <key>`, any role reading the accounts
+//! & validating transactions, an admin's adjusting them, and a standby an admin's from the primary shipping to it
+//! (see replica.rs).
+//!
+//! a key is named in the audit log by its id, the first 8 hex digits of its digest as the file lists it.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::digest::hex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Role {
+    Submitter,
+    Admin
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Role::Submitter => "submitter",
+            Role::Admin => "admin"
+        })
+    }
+}
+
+/// roles by the digest of their key
+#[derive(Debug, Default)]
+pub(crate) struct Keys(HashMap<String, Role>);
+
+impl Keys {
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("auth keys {}: {}", path.display(), e))?;
+        Keys::parse(&text).map_err(|e| format!("auth keys {}: {}", path.display(), e))
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for (line, entry) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let (digest, role) = entry.split_once("  ")
+                .filter(|(d, _)| d.len() == 64 && d.bytes().all(|b| b.is_ascii_hexdigit()))
+                .ok_or_else(|| format!("line {}: expected a sha-256 digest & a role", line + 1))?;
+            let role = match role.trim() {
+                "submitter" => Role::Submitter,
+                "admin" => Role::Admin,
+                other => return Err(format!("line {}: unknown role '{}', expected submitter or admin", line + 1, other))
+            };
+            if keys.insert(digest.to_ascii_lowercase(), role).is_some() {
+                return Err(format!("line {}: key listed twice", line + 1));
+            }
+        }
+        Ok(Keys(keys))
+    }
+
+    /// the role a connection's first line, `auth <key>`, authenticates as
+    pub(crate) fn authenticate(&self, line: &str) -> Result<Role, &'static str> {
+        let key = line.trim().strip_prefix("auth ").ok_or("authenticate first, with auth <key>")?;
+        self.role(key).ok_or("unknown key")
+    }
+
+    /// the role `key` has, None if it isn't listed
+    pub(crate) fn role(&self, key: &str) -> Option<Role> {
+        self.0.get(&hex(Sha256::digest(key.trim().as_bytes()))).copied()
+    }
+}
+
+/// the key a client authenticates with, as `--auth-key-file` holds it
+pub(crate) fn read_key(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map(|key| key.trim().to_string()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// the id the audit log names `key` by, which doesn't give it away
+pub(crate) fn id(key: &str) -> String {
+    hex(Sha256::digest(key.trim().as_bytes()))[..8].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{id, Keys, Role};
+
+    const KEYS: &str = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  submitter\n\n\
+                        FCDE2B2EDBA56BF408601FB721FE9B5C338D10EE429EA04FAE5511B68FBF8FB9  admin\n";
+
+    #[test]
+    fn test_authenticate() {
+        let keys = Keys::parse(KEYS).unwrap();
+        // the digests of "foo" & "bar"
+        assert_eq!(keys.authenticate("auth foo"), Ok(Role::Submitter));
+        assert_eq!(keys.authenticate(" auth bar "), Ok(Role::Admin));
+        assert_eq!(keys.authenticate("auth baz"), Err("unknown key"));
+        assert!(keys.authenticate("deposit,1,1,1").is_err());
+        assert_eq!(id("foo"), "2c26b46b");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(Keys::parse("2c26  admin\n").is_err());
+        assert!(Keys::parse("2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  root\n").is_err());
+        let twice = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  admin\n".repeat(2);
+        assert_eq!(Keys::parse(&twice).unwrap_err(), "line 2: key listed twice");
+    }
+}
@@ -0,0 +1,223 @@
+//! avro object container file input.
+//!
+//! records are mapped onto `Txn` through the same serde impl as the csv reader, so field names match the csv header.
+//! `type` may be an avro string or enum, `client`/`tx` any int/long (range checked), `amount` a nullable
+//! double/float or `decimal` logical type, a decimal taken exactly rather than through a float.
+
+use std::convert::TryFrom;
+use std::io::Read;
+
+use apache_avro::schema::{RecordSchema, Schema};
+use apache_avro::types::Value;
+use apache_avro::Reader;
+use rust_decimal::Decimal;
+
+use crate::source::{SourceError, TxnSource};
+use crate::{Precision, RawRecord, Txn};
+
+pub(crate) struct AvroTxns<R> {
+    reader: Reader<'static, R>,
+    /// scale of the amount field if it is a `decimal` logical type
+    amount_scale: Option<u32>,
+    precision: Precision,
+    record: usize
+}
+
+pub(crate) fn read_txns<R: Read>(reader: R, precision: Precision) -> Result<AvroTxns<R>, apache_avro::Error> {
+    let reader = Reader::new(reader)?;
+    let amount_scale = decimal_scale(reader.writer_schema(), "amount");
+    Ok(AvroTxns { reader, amount_scale, precision, record: 0 })
+}
+
+impl<R: Read> Iterator for AvroTxns<R> {
+    type Item = Result<Txn, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = self.reader.next()?;
+        self.record += 1;
+        let txn = value.map_err(|e| e.to_string())
+            .and_then(|v| to_txn(v, self.amount_scale, self.precision))
+            .map_err(|e| format!("record {}: {}", self.record, e));
+        Some(txn)
+    }
+}
+
+impl<R: Read> TxnSource for AvroTxns<R> {
+    fn next_txn(&mut self) -> Option<Result<Txn, SourceError>> {
+        self.next().map(|txn| txn.map_err(|e| SourceError::malformatted("record", e)))
+    }
+}
+
+/// maps a record value onto a transaction, rounding the amount like `deserialize_record`
+fn to_txn(value: Value, amount_scale: Option<u32>, precision: Precision) -> Result<Txn, String> {
+    let fields = match value {
+        Value::Record(fields) => fields,
+        _ => return Err("expected a record".into())
+    };
+
+    // a decimal's taken as it is, rather than through the float serde reads amounts as
+    let mut decimal = None;
+    let fields = fields.into_iter()
+        .map(|(name, v)| match (name.as_str(), amount_scale) {
+            ("amount", Some(scale)) => {
+                decimal = decimal_amount(&v, scale)?;
+                Ok((name, Value::Union(0, Box::new(Value::Null))))
+            },
+            _ => Ok((name, v))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let raw = apache_avro::from_value::<RawRecord>(&Value::Record(fields)).map_err(|e| e.to_string())?;
+    RawRecord { amount: decimal.or(raw.amount), ..raw }
+        .into_txn(precision)
+        .map_err(|e| e.to_string())
+}
+
+/// a `decimal` amount exactly, from its unscaled big-endian two's complement bytes & the schema's scale. None if
+/// it's null
+fn decimal_amount(value: &Value, scale: u32) -> Result<Option<Decimal>, String> {
+    match value {
+        Value::Union(_, inner) => decimal_amount(inner, scale),
+        Value::Decimal(d) => {
+            let bytes: Vec<u8> = Vec::try_from(d).map_err(|e| e.to_string())?;
+            if bytes.len() > 16 {
+                return Err("decimal amount out of range".into());
+            }
+            // sign-extend big-endian two's complement into an i128
+            let mut unscaled = [if bytes.first().is_some_and(|b| b & 0x80 != 0) { 0xFF } else { 0 }; 16];
+            unscaled[16 - bytes.len()..].copy_from_slice(&bytes);
+            Decimal::try_from_i128_with_scale(i128::from_be_bytes(unscaled), scale)
+                .map(Some)
+                .map_err(|_| "decimal amount out of range".into())
+        },
+        Value::Null => Ok(None),
+        v => Err(format!("expected a decimal amount, got {:?}", v))
+    }
+}
+
+fn decimal_scale(schema: &Schema, field: &str) -> Option<u32> {
+    let fields = match schema {
+        Schema::Record(RecordSchema { fields, .. }) => fields,
+        _ => return None
+    };
+    let schema = &fields.iter().find(|f| f.name == field)?.schema;
+    let variants = match schema {
+        Schema::Union(union) => union.variants(),
+        s => std::slice::from_ref(s)
+    };
+    variants.iter().find_map(|s| match s {
+        Schema::Decimal(d) => Some(d.scale as u32),
+        _ => None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use apache_avro::types::{Record, Value};
+    use apache_avro::{Schema, Writer};
+    use rust_decimal_macros::dec;
+
+    use crate::{CURRENCY_PRECISION, Txn};
+
+    use super::read_txns;
+
+    fn write(schema: &str, records: Vec<Vec<(&str, Value)>>) -> Vec<u8> {
+        let schema = Schema::parse_str(schema).unwrap();
+        let mut writer = Writer::new(&schema, Vec::new()).unwrap();
+        for fields in records {
+            let mut record = Record::new(&schema).unwrap();
+            for (name, value) in fields {
+                record.put(name, value);
+            }
+            writer.append_value(record).unwrap();
+        }
+        writer.into_inner().unwrap()
+    }
+
+    fn read(bytes: Vec<u8>) -> Vec<Result<Txn, String>> {
+        read_txns(bytes.as_slice(), CURRENCY_PRECISION.into()).unwrap().collect()
+    }
+
+    const SCHEMA: &str = r#"{
+        "type": "record", "name": "txn",
+        "fields": [
+            {"name": "type", "type": {"type": "enum", "name": "txntype",
+                "symbols": ["deposit", "withdrawal", "dispute", "resolve", "chargeback"]}},
+            {"name": "client", "type": "int"},
+            {"name": "tx", "type": "long"},
+            {"name": "amount", "type": ["null", "double"], "default": null}
+        ]
+    }"#;
+
+    #[test]
+    fn test_read() {
+        let bytes = write(SCHEMA, vec![
+            vec![("type", Value::Enum(0, "deposit".into())), ("client", Value::Int(1)), ("tx", Value::Long(1)),
+                 ("amount", Value::Union(1, Box::new(Value::Double(1.23456))))],
+            vec![("type", Value::Enum(2, "dispute".into())), ("client", Value::Int(1)), ("tx", Value::Long(1)),
+                 ("amount", Value::Union(0, Box::new(Value::Null)))],
+        ]);
+        let txns: Vec<Txn> = read(bytes).into_iter().map(Result::unwrap).collect();
+        assert_eq!(txns, vec![Txn::deposit(1, 1, dec!(1.2346)), Txn::dispute(1, 1)]);
+    }
+
+    #[test]
+    fn test_read_string_type_and_decimal_amount() {
+        let schema = r#"{
+            "type": "record", "name": "txn",
+            "fields": [
+                {"name": "type", "type": "string"},
+                {"name": "client", "type": "int"},
+                {"name": "tx", "type": "int"},
+                {"name": "amount", "type": ["null", {"type": "bytes", "logicalType": "decimal", "precision": 10, "scale": 5}]}
+            ]
+        }"#;
+        let bytes = write(schema, vec![
+            vec![("type", Value::String("withdrawal".into())), ("client", Value::Int(2)), ("tx", Value::Int(7)),
+                 ("amount", Value::Union(1, Box::new(Value::Decimal(vec![0x01, 0xE2, 0x40].into()))))],
+            vec![("type", Value::String("deposit".into())), ("client", Value::Int(2)), ("tx", Value::Int(8)),
+                 ("amount", Value::Union(1, Box::new(Value::Decimal(vec![0xFE, 0x1D, 0xC0].into()))))],
+        ]);
+        let txns = read(bytes);
+        // 0x01E240 = 123456, 0xFE1DC0 = -123456, sign extended, so refused
+        assert_eq!(txns[0], Ok(Txn::withdrawal(2, 7, dec!(1.2346))));
+        assert_eq!(txns[1], Err("record 2: amount negative".to_string()));
+    }
+
+    #[test]
+    fn test_read_exact_decimal_amount() {
+        let schema = r#"{
+            "type": "record", "name": "txn",
+            "fields": [
+                {"name": "type", "type": "string"},
+                {"name": "client", "type": "int"},
+                {"name": "tx", "type": "int"},
+                {"name": "amount", "type": {"type": "bytes", "logicalType": "decimal", "precision": 20, "scale": 4}}
+            ]
+        }"#;
+        // 2^53 + 1 unscaled, which through a double would come out as 900719925474.0992
+        let bytes = write(schema, vec![
+            vec![("type", Value::String("deposit".into())), ("client", Value::Int(1)), ("tx", Value::Int(1)),
+                 ("amount", Value::Decimal(9_007_199_254_740_993_i64.to_be_bytes().to_vec().into()))],
+        ]);
+        assert_eq!(read(bytes), vec![Ok(Txn::deposit(1, 1, dec!(900719925474.0993)))]);
+    }
+
+    #[test]
+    fn test_read_invalid_ids() {
+        let bytes = write(SCHEMA, vec![
+            vec![("type", Value::Enum(0, "deposit".into())), ("client", Value::Int(-1)),
+                 ("tx", Value::Long(1)), ("amount", Value::Union(1, Box::new(Value::Double(1.0))))],
+            vec![("type", Value::Enum(0, "deposit".into())), ("client", Value::Int(1)),
+                 ("tx", Value::Long(-1)), ("amount", Value::Union(1, Box::new(Value::Double(1.0))))],
+        ]);
+        let txns = read(bytes);
+        assert!(txns[0].as_ref().unwrap_err().starts_with("record 1"));
+        assert!(txns[1].as_ref().unwrap_err().starts_with("record 2"));
+    }
+
+    #[test]
+    fn test_read_not_avro() {
+        assert!(read_txns(b"type,client,tx,amount".as_ref(), CURRENCY_PRECISION.into()).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! `TxnBuilder`: the one way a transaction is made from input, by every format, so they all hold transactions to the
+//! same rules: deposits & withdrawals carry an amount, disputes, resolves & chargebacks don't (their amount is the
+//! disputed transaction's), and an amount is rounded to the configured precision, must fit once it is, and mustn't
+//! be negative (a withdrawal is the way to take funds out).
+//!
+//! `RawRecord` is a transaction as formats read it, serde deserializable from a csv row or avro & json records.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{Amount, ClientId, CURRENCY_PRECISION, Currency, OutOfRange, Precision, Txn, TxnId, TxnType};
+
+/// why a transaction couldn't be built
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TxnError {
+    /// a deposit or withdrawal without an amount
+    MissingAmount,
+    /// a dispute, resolve or chargeback with an amount
+    UnexpectedAmount,
+    NegativeAmount,
+    OutOfRange,
+    /// a dispute, resolve or chargeback with a currency
+    UnexpectedCurrency
+}
+
+impl TxnError {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            TxnError::MissingAmount => "amount required",
+            TxnError::UnexpectedAmount => "amount not allowed",
+            TxnError::NegativeAmount => "amount negative",
+            TxnError::OutOfRange => "amount out of range",
+            TxnError::UnexpectedCurrency => "currency not allowed"
+        }
+    }
+}
+
+impl fmt::Display for TxnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::error::Error for TxnError {}
+
+impl From<OutOfRange> for TxnError {
+    fn from(_: OutOfRange) -> Self {
+        TxnError::OutOfRange
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TxnBuilder {
+    txntype: TxnType,
+    client: ClientId,
+    tx: TxnId,
+    amount: Option<Decimal>,
+    /// an amount read at the precision already, which isn't rounded again
+    parsed: Option<Amount>,
+    precision: Precision,
+    currency: Option<Currency>
+}
+
+impl TxnBuilder {
+    pub fn new(txntype: TxnType, client: ClientId, tx: TxnId) -> Self {
+        TxnBuilder { txntype, client, tx, amount: None, parsed: None, precision: CURRENCY_PRECISION.into(), currency: None }
+    }
+
+    /// an amount, or an `Option` of one as read
+    pub fn amount(mut self, amount: impl Into<Option<Decimal>>) -> Self {
+        self.amount = amount.into();
+        self
+    }
+
+    /// an amount `Amount::parse` read at the precision, in place of one to round
+    pub(crate) fn parsed_amount(mut self, amount: Amount) -> Self {
+        self.parsed = Some(amount);
+        self
+    }
+
+    /// decimal places the amount is rounded to, & how, `CURRENCY_PRECISION` half to even unless set
+    pub fn precision(mut self, precision: impl Into<Precision>) -> Self {
+        self.precision = precision.into();
+        self
+    }
+
+    /// the currency a deposit or withdrawal is in, checked against its account's if that has one
+    pub fn currency(mut self, currency: impl Into<Option<Currency>>) -> Self {
+        self.currency = currency.into();
+        self
+    }
+
+    pub fn build(self) -> Result<Txn, TxnError> {
+        let amount = match (&self.txntype, self.parsed, self.amount) {
+            (TxnType::Deposit | TxnType::Withdrawal, Some(a), _) => Some(a),
+            (TxnType::Deposit | TxnType::Withdrawal, None, Some(a)) => Some(Amount::from_decimal(a, self.precision).ok_or(OutOfRange)?),
+            (TxnType::Deposit | TxnType::Withdrawal, None, None) => return Err(TxnError::MissingAmount),
+            (_, Some(_), _) | (_, _, Some(_)) => return Err(TxnError::UnexpectedAmount),
+            (_, None, None) => None
+        };
+        // checked once rounded, so an amount rounding to zero (`-0.00001`) isn't refused
+        if amount.is_some_and(|a| a < Amount::ZERO) {
+            return Err(TxnError::NegativeAmount);
+        }
+        let currency = match (&self.txntype, self.currency) {
+            (TxnType::Deposit | TxnType::Withdrawal, currency) => currency,
+            (_, Some(_)) => return Err(TxnError::UnexpectedCurrency),
+            (_, None) => None
+        };
+        Ok(Txn { currency, ..Txn::new(self.txntype, self.client, self.tx, amount) })
+    }
+}
+
+/// a transaction as read, before it's checked & its amount rounded
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RawRecord {
+    #[serde(rename = "type")]
+    pub txntype: TxnType,
+    pub client: ClientId,
+    pub tx: TxnId,
+    pub amount: Option<Decimal>
+}
+
+impl RawRecord {
+    pub fn into_txn(self, precision: impl Into<Precision>) -> Result<Txn, TxnError> {
+        TxnBuilder::new(self.txntype, self.client, self.tx).amount(self.amount).precision(precision).build()
+    }
+}
+
+/// at `CURRENCY_PRECISION`
+impl TryFrom<RawRecord> for Txn {
+    type Error = TxnError;
+
+    fn try_from(record: RawRecord) -> Result<Self, TxnError> {
+        record.into_txn(CURRENCY_PRECISION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use rust_decimal_macros::dec;
+
+    use crate::{ClientId, Txn, TxnId, TxnType};
+
+    use super::{RawRecord, TxnBuilder, TxnError};
+
+    #[test]
+    fn test_amount_rules() {
+        let build = |txntype: TxnType, amount: Option<rust_decimal::Decimal>| TxnBuilder::new(txntype, ClientId(1), TxnId(2)).amount(amount).build();
+        assert_eq!(build(TxnType::Deposit, Some(dec!(1.23456))), Ok(Txn::deposit(1, 2, dec!(1.2346))));
+        assert_eq!(build(TxnType::Withdrawal, None), Err(TxnError::MissingAmount));
+        assert_eq!(build(TxnType::Dispute, None), Ok(Txn::dispute(1, 2)));
+        for txntype in [TxnType::Dispute, TxnType::Resolve, TxnType::Chargeback] {
+            assert_eq!(build(txntype, Some(dec!(1))), Err(TxnError::UnexpectedAmount));
+        }
+        assert_eq!(build(TxnType::Withdrawal, Some(dec!(-1))), Err(TxnError::NegativeAmount));
+        let usd = "USD".parse::<crate::Currency>().unwrap();
+        assert_eq!(TxnBuilder::new(TxnType::Dispute, ClientId(1), TxnId(2)).currency(usd).build(), Err(TxnError::UnexpectedCurrency));
+        assert!(build(TxnType::Deposit, Some(dec!(-0.00001))).is_ok());
+        let rounded = TxnBuilder::new(TxnType::Deposit, ClientId(1), TxnId(2)).amount(dec!(1.25)).precision(1).build().unwrap();
+        assert_eq!(rounded.amount.unwrap(), dec!(1.2));
+        let rounded = TxnBuilder::new(TxnType::Deposit, ClientId(1), TxnId(2)).amount(dec!(1.25))
+            .precision(crate::Precision::new(1, crate::Rounding::HalfUp)).build().unwrap();
+        assert_eq!(rounded.amount.unwrap(), dec!(1.3));
+    }
+
+    #[test]
+    fn test_raw_record() {
+        let record = RawRecord { txntype: TxnType::Deposit, client: ClientId(1), tx: TxnId(2), amount: Some(dec!(5)) };
+        assert_eq!(Txn::try_from(record.clone()), Ok(Txn::deposit(1, 2, dec!(5))));
+        assert_eq!(Txn::try_from(RawRecord { amount: None, ..record }), Err(TxnError::MissingAmount));
+    }
+}
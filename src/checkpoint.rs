@@ -0,0 +1,561 @@
+//! checkpointing: `--checkpoint-every N` snapshots engine state, along with how far into the input it got,
+//! to `<checkpoint.dir>/checkpoint.json` every N rows. `--resume` restores the snapshot and carries on reading
+//! from that byte offset, so a crash part way through a huge file doesn't mean starting over.
+//!
+//! the file is written to a temporary name then renamed into place, so a crash mid-write leaves the previous
+//! checkpoint intact. amounts are stored as decimal strings, never floats.
+//!
+//! the json is followed by a `crc32 <hex>` footer line, checked on restore, so a corrupted checkpoint is refused
+//! rather than resumed from. with `checkpoint.key` or `checkpoint.key_file` set (`--features encryption`) the file
+//! is AES-256-GCM encrypted instead, its tag standing in for the footer: a heading naming the format, a random
+//! nonce, then the sealed json. an encrypted checkpoint only restores with its key, and with a key set a plain one
+//! isn't restored at all.
+//!
+//! the json's `version` says what fields its accounts have. one written by an older build is brought up to this
+//! one's by `MIGRATIONS`, a step per version, before it's restored; one from a newer build is refused. adding an
+//! account field means bumping `VERSION` and adding the step that fills it in.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::CheckpointOptions;
+use crate::report::Report;
+use crate::retention::PrunedTxs;
+use crate::txnlog::TxnLog;
+use crate::{Account, AccountKind, Accounts, Amount, Balance, ClientId, Currency, Set, Settling, Txn, TxnId, TxnType};
+
+const FILE_NAME: &str = "checkpoint.json";
+/// what an encrypted checkpoint starts with, and authenticates along with the json
+const SEALED: &[u8] = b"txn checkpoint, aes-256-gcm\n";
+/// what a plain checkpoint's json is followed by, then its crc32 in hex
+const FOOTER: &[u8] = b"\ncrc32 ";
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+
+/// the checkpoint format this build writes
+const VERSION: u32 = 4;
+/// brings a checkpoint's json up a version
+type Migration = fn(&mut Value) -> Result<(), String>;
+/// a migration for each older version, `MIGRATIONS[v]` taking version v to v + 1
+const MIGRATIONS: [Migration; VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4];
+
+/// the AES-256 key checkpoints are encrypted with
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub(crate) struct Key([u8; 32]);
+
+impl Key {
+    /// the key `checkpoint.key` or `checkpoint.key_file` gives, 64 hex digits, if either is set
+    pub(crate) fn read(options: &CheckpointOptions) -> Result<Option<Self>, String> {
+        let hex = match (&options.key, &options.key_file) {
+            (Some(key), _) => key.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+            (None, None) => return Ok(None)
+        };
+        if cfg!(not(feature = "encryption")) {
+            return Err("Checkpoint encryption requires building with the `encryption` feature".into());
+        }
+        let hex = hex.trim();
+        let bytes: Option<Vec<u8>> = (0..hex.len()).step_by(2)
+            .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+            .collect();
+        match bytes.and_then(|b| <[u8; 32]>::try_from(b).ok()) {
+            Some(key) => Ok(Some(Key(key))),
+            None => Err("checkpoint key must be 64 hex digits, an AES-256 key".into())
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Checkpoint {
+    /// missing from checkpoints taken before they were versioned, version 0
+    #[serde(default)]
+    version: u32,
+    /// the input the checkpoint was taken from
+    pub(crate) input: PathBuf,
+    /// byte offset of the first row not yet applied
+    pub(crate) offset: u64,
+    pub(crate) report: Report,
+    accounts: Vec<AccountState>
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct AccountState {
+    client: ClientId,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+    funded: bool,
+    disputes: Vec<TxnId>,
+    resolved: Vec<TxnId>,
+    charged_back: Vec<TxnId>,
+    settling: Vec<SettlingState>,
+    kind: AccountKind,
+    reserve: String,
+    name: Option<String>,
+    currency: Option<Currency>,
+    chargeback_loss: String,
+    adjusted: String,
+    forgotten: bool,
+    pruned: u64,
+    pruned_txs: PrunedTxs,
+    txnlog: Vec<TxnState>
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SettlingState {
+    tx: TxnId,
+    amount: String,
+    since: u64
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct TxnState {
+    #[serde(rename = "type")]
+    txntype: TxnType,
+    tx: TxnId,
+    amount: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>
+}
+
+impl Checkpoint {
+    pub(crate) fn new(input: &Path, offset: u64, accounts: &Accounts, report: &Report) -> Self {
+        let mut accounts: Vec<AccountState> = accounts.iter().map(|(client, account)| {
+            let ids = |set: &Set<TxnId>| {
+                let mut ids: Vec<TxnId> = set.iter().copied().collect();
+                ids.sort_unstable();
+                ids
+            };
+            let txnlog: Vec<TxnState> = account.txnlog.values().map(|t| TxnState {
+                txntype: t.txntype.clone(),
+                tx: t.tx,
+                amount: t.amount.map(|a| a.to_string()),
+                currency: t.currency
+            }).collect();
+            AccountState {
+                client: *client,
+                available: account.balance.available.to_string(),
+                held: account.balance.held.to_string(),
+                total: account.balance.total.to_string(),
+                locked: account.locked,
+                funded: account.funded,
+                disputes: ids(&account.disputes),
+                resolved: ids(&account.resolved),
+                charged_back: ids(&account.charged_back),
+                settling: account.settling.iter()
+                    .map(|s| SettlingState { tx: s.tx, amount: s.amount.to_string(), since: s.since })
+                    .collect(),
+                kind: account.kind,
+                reserve: account.reserve.to_string(),
+                name: account.name.clone(),
+                currency: account.currency,
+                chargeback_loss: account.chargeback_loss.to_string(),
+                adjusted: account.adjusted.to_string(),
+                forgotten: account.forgotten,
+                pruned: account.pruned,
+                pruned_txs: account.pruned_txs.clone(),
+                txnlog
+            }
+        }).collect();
+        accounts.sort_unstable_by_key(|a| a.client);
+
+        Checkpoint { version: VERSION, input: input.to_path_buf(), offset, report: report.clone(), accounts }
+    }
+
+    pub(crate) fn accounts(&self) -> Result<Accounts, String> {
+        // a checkpoint taken by a build with a wider amount type may not fit this one
+        let decimal = |s: &str| Amount::read(s).ok_or_else(|| format!("invalid amount '{}' in checkpoint", s));
+        let mut accounts = Accounts::default();
+        for state in &self.accounts {
+            let mut txnlog = TxnLog::default();
+            for t in &state.txnlog {
+                let amount = match &t.amount {
+                    Some(a) => Some(decimal(a)?),
+                    None => None
+                };
+                txnlog.insert(Txn { currency: t.currency, ..Txn::new(t.txntype.clone(), state.client, t.tx, amount) });
+            }
+            let mut settling = Vec::with_capacity(state.settling.len());
+            for s in &state.settling {
+                settling.push(Settling { tx: s.tx, amount: decimal(&s.amount)?, since: s.since });
+            }
+            accounts.insert(state.client, Account {
+                balance: Balance {
+                    available: decimal(&state.available)?,
+                    held: decimal(&state.held)?,
+                    total: decimal(&state.total)?
+                },
+                disputes: state.disputes.iter().copied().collect(),
+                resolved: state.resolved.iter().copied().collect(),
+                charged_back: state.charged_back.iter().copied().collect(),
+                txnlog,
+                locked: state.locked,
+                funded: state.funded,
+                settling,
+                kind: state.kind,
+                reserve: decimal(&state.reserve)?,
+                name: state.name.clone(),
+                currency: state.currency,
+                chargeback_loss: decimal(&state.chargeback_loss)?,
+                adjusted: decimal(&state.adjusted)?,
+                forgotten: state.forgotten,
+                pruned: state.pruned,
+                pruned_txs: state.pruned_txs.clone()
+            });
+        }
+        Ok(accounts)
+    }
+
+    pub(crate) fn save(&self, dir: &Path, key: Option<&Key>) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(dir)?;
+        let tmp = dir.join(format!("{}.tmp", FILE_NAME));
+        std::fs::write(&tmp, seal(serde_json::to_vec(self)?, key)?)?;
+        std::fs::rename(&tmp, dir.join(FILE_NAME))?;
+        Ok(())
+    }
+
+    pub(crate) fn remove(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        match std::fs::remove_file(dir.join(FILE_NAME)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(())
+        }
+    }
+
+    /// None if there's no checkpoint to resume from
+    pub(crate) fn load(dir: &Path, key: Option<&Key>) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let path = dir.join(FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::read(&path, key).map(Some)
+    }
+
+    /// the checkpoint at `path`, wherever it's been copied to, as a snapshot of the accounts to serve
+    pub(crate) fn read(path: &Path, key: Option<&Key>) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let json = unseal(&content, key).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&json).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// the checkpoint the json holds, migrated from the version it was written at
+    fn parse(json: &[u8]) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct Versioned {
+            #[serde(default)]
+            version: u32
+        }
+
+        let version = serde_json::from_slice::<Versioned>(json).map_err(|e| e.to_string())?.version;
+        if version == VERSION {
+            return serde_json::from_slice(json).map_err(|e| e.to_string());
+        }
+        if version > VERSION {
+            return Err(format!("checkpoint is version {}, from a newer build, this one restores up to {}", version, VERSION));
+        }
+        let mut checkpoint: Value = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+        for migrate in &MIGRATIONS[version as usize..] {
+            migrate(&mut checkpoint)?;
+        }
+        checkpoint["version"] = VERSION.into();
+        serde_json::from_value(checkpoint).map_err(|e| e.to_string())
+    }
+}
+
+/// version 0, from before checkpoints were versioned, may be missing any account field added after the first:
+/// those are filled in as for an account that never used them, and `funded` from whether a deposit is logged
+fn v0_to_v1(checkpoint: &mut Value) -> Result<(), String> {
+    let accounts = checkpoint.get_mut("accounts").and_then(Value::as_array_mut).ok_or("checkpoint has no accounts")?;
+    for account in accounts {
+        let account = account.as_object_mut().ok_or("checkpoint account isn't an object")?;
+        let deposited = account.get("txnlog").and_then(Value::as_array)
+            .is_some_and(|log| log.iter().any(|t| t.get("type").and_then(Value::as_str) == Some("deposit")));
+        let funded = account.get("funded").and_then(Value::as_bool).unwrap_or(false) || deposited;
+        account.insert("funded".into(), funded.into());
+        let defaults = [("resolved", Value::Array(Vec::new())), ("charged_back", Value::Array(Vec::new())),
+                        ("settling", Value::Array(Vec::new())), ("kind", "customer".into()), ("reserve", "0".into()),
+                        ("name", Value::Null), ("currency", Value::Null), ("chargeback_loss", "0".into())];
+        for (field, default) in defaults {
+            match account.get(field) {
+                Some(value) if !value.is_null() => (),
+                _ => {
+                    account.insert(field.into(), default);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// version 1, from before admins' adjustments, has had none
+fn v1_to_v2(checkpoint: &mut Value) -> Result<(), String> {
+    let accounts = checkpoint.get_mut("accounts").and_then(Value::as_array_mut).ok_or("checkpoint has no accounts")?;
+    for account in accounts {
+        let account = account.as_object_mut().ok_or("checkpoint account isn't an object")?;
+        account.insert("adjusted".into(), "0".into());
+    }
+    Ok(())
+}
+
+/// version 2, from before admins could have a client forgotten, has forgotten none
+fn v2_to_v3(checkpoint: &mut Value) -> Result<(), String> {
+    let accounts = checkpoint.get_mut("accounts").and_then(Value::as_array_mut).ok_or("checkpoint has no accounts")?;
+    for account in accounts {
+        let account = account.as_object_mut().ok_or("checkpoint account isn't an object")?;
+        account.insert("forgotten".into(), false.into());
+    }
+    Ok(())
+}
+
+/// version 3, from before transactions were pruned, has pruned none
+fn v3_to_v4(checkpoint: &mut Value) -> Result<(), String> {
+    let accounts = checkpoint.get_mut("accounts").and_then(Value::as_array_mut).ok_or("checkpoint has no accounts")?;
+    for account in accounts {
+        let account = account.as_object_mut().ok_or("checkpoint account isn't an object")?;
+        account.insert("pruned".into(), 0.into());
+        account.insert("pruned_txs".into(), Value::Array(Vec::new()));
+    }
+    Ok(())
+}
+
+/// the json as it's written: encrypted with the key, or followed by its footer
+fn seal(mut json: Vec<u8>, key: Option<&Key>) -> Result<Vec<u8>, String> {
+    match key {
+        Some(key) => encrypt(&json, key),
+        None => {
+            let crc = crc32fast::hash(&json);
+            json.extend_from_slice(FOOTER);
+            json.extend_from_slice(format!("{:08x}\n", crc).as_bytes());
+            Ok(json)
+        }
+    }
+}
+
+/// the json a checkpoint file holds, decrypted or checked against its footer
+fn unseal<'a>(content: &'a [u8], key: Option<&Key>) -> Result<Cow<'a, [u8]>, String> {
+    match (content.strip_prefix(SEALED), key) {
+        (Some(sealed), Some(key)) => decrypt(sealed, key).map(Cow::Owned),
+        (Some(_), None) => Err("checkpoint is encrypted, set checkpoint.key or checkpoint.key_file to restore it".into()),
+        (None, Some(_)) => Err("checkpoint isn't encrypted, though a key is set".into()),
+        (None, None) => checked(content).map(Cow::Borrowed)
+    }
+}
+
+/// the json ahead of the footer, if its crc32 matches. compact json has no newlines, so the footer's the last
+/// one, and a checkpoint without one (cut short before it was written) is refused
+fn checked(content: &[u8]) -> Result<&[u8], String> {
+    let at = content.windows(FOOTER.len()).rposition(|w| w == FOOTER)
+        .ok_or("checkpoint is corrupt, it has no crc32 footer")?;
+    let (json, footer) = (&content[..at], &content[at + FOOTER.len()..]);
+    let expected = std::str::from_utf8(footer).ok()
+        .and_then(|f| f.strip_suffix('\n'))
+        .filter(|f| f.len() == 8)
+        .and_then(|f| u32::from_str_radix(f, 16).ok())
+        .ok_or("checkpoint is corrupt, its crc32 footer is unreadable")?;
+    match crc32fast::hash(json) {
+        crc if crc == expected => Ok(json),
+        crc => Err(format!("checkpoint is corrupt, its crc32 is {:08x} where the footer says {:08x}", crc, expected))
+    }
+}
+
+#[cfg(feature = "encryption")]
+fn encrypt(json: &[u8], key: &Key) -> Result<Vec<u8>, String> {
+    use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+
+    let cipher = aes_gcm::Aes256Gcm::new(&key.0.into());
+    let nonce = aes_gcm::Aes256Gcm::generate_nonce(&mut OsRng);
+    let sealed = cipher.encrypt(&nonce, Payload { msg: json, aad: SEALED }).map_err(|_| "checkpoint encryption failed")?;
+    Ok([SEALED, nonce.as_slice(), &sealed].concat())
+}
+
+#[cfg(feature = "encryption")]
+fn decrypt(sealed: &[u8], key: &Key) -> Result<Vec<u8>, String> {
+    use aes_gcm::aead::{Aead, KeyInit, Payload};
+
+    if sealed.len() < NONCE_LEN {
+        return Err("checkpoint is corrupt, it's cut short".into());
+    }
+    let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+    let cipher = aes_gcm::Aes256Gcm::new(&key.0.into());
+    cipher.decrypt(aes_gcm::Nonce::from_slice(nonce), Payload { msg: sealed, aad: SEALED })
+        .map_err(|_| "checkpoint doesn't decrypt, the key is wrong or the file is corrupt".into())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn encrypt(_json: &[u8], _key: &Key) -> Result<Vec<u8>, String> {
+    Err("Checkpoint encryption requires building with the `encryption` feature".into())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn decrypt(_sealed: &[u8], _key: &Key) -> Result<Vec<u8>, String> {
+    Err("Checkpoint encryption requires building with the `encryption` feature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::report::Report;
+    use crate::{Accounts, ClientId, execute, process_csv, Rejection, Txn};
+
+    use super::{Checkpoint, seal, unseal};
+
+    #[test]
+    fn test_roundtrip() {
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(1, 1, dec!(0.1)));
+        execute(&mut accounts, Txn::deposit(1, 2, dec!(2.0001)));
+        execute(&mut accounts, Txn::dispute(1, 2));
+        execute(&mut accounts, Txn::deposit(2, 3, dec!(5)));
+        execute(&mut accounts, Txn::dispute(2, 3));
+        execute(&mut accounts, Txn::chargeback(2, 3));
+        let mut report = Report::default();
+        report.record(Ok(()));
+        report.record(Err(Rejection::NotDisputed));
+
+        let dir = std::env::temp_dir().join(format!("txn-checkpoint-test-{}", std::process::id()));
+        Checkpoint::new(Path::new("in.csv"), 1234, &accounts, &report).save(&dir, None).unwrap();
+        let checkpoint = Checkpoint::load(&dir, None).unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(checkpoint.input, Path::new("in.csv"));
+        assert_eq!(checkpoint.offset, 1234);
+        assert_eq!(checkpoint.report, report);
+        assert_eq!(checkpoint.accounts().unwrap(), accounts);
+    }
+
+    #[test]
+    fn test_resume_after_abort() {
+        let dir = std::env::temp_dir().join(format!("txn-resume-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let rows = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\ndispute,1,2,\nwithdrawal,1,3,0.5\n";
+        let mut config = Config::default();
+        config.checkpoint.every = 2;
+        config.checkpoint.dir = dir.join("ckpt");
+
+        // stops short at the bogus row, leaving the checkpoint taken after the second row
+        std::fs::write(&input, format!("{}bogus\n", rows)).unwrap();
+        let (mut accounts, mut report) = (Accounts::default(), Report::default());
+        assert!(process_csv(&mut accounts, &input, &config, &mut report).is_err());
+
+        std::fs::write(&input, rows).unwrap();
+        config.checkpoint.resume = true;
+        let (mut resumed, mut report) = (Accounts::default(), Report::default());
+        process_csv(&mut resumed, &input, &config, &mut report).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut expected = Accounts::default();
+        for txn in [Txn::deposit(1, 1, dec!(1)), Txn::deposit(1, 2, dec!(2)), Txn::dispute(1, 2), Txn::withdrawal(1, 3, dec!(0.5))] {
+            execute(&mut expected, txn);
+        }
+        assert_eq!(resumed, expected);
+        assert_eq!(report.applied, 4);
+    }
+
+    #[test]
+    fn test_replay_tolerant() {
+        let dir = std::env::temp_dir().join(format!("txn-replay-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let rows = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\ndispute,1,2,\nresolve,1,2,\n";
+        let mut config = Config::default();
+        config.checkpoint.every = 4;
+        config.checkpoint.dir = dir.join("ckpt");
+        std::fs::write(&input, format!("{}bogus\n", rows)).unwrap();
+        assert!(process_csv(&mut Accounts::default(), &input, &config, &mut Report::default()).is_err());
+
+        // the input as it's resubmitted, rewritten & grown since the checkpoint
+        std::fs::write(&input, format!("type,client,tx,amount\ndeposit,1,2,2.0\n{}withdrawal,1,3,0.5\n", &rows[22..])).unwrap();
+        config.checkpoint.resume = true;
+        config.checkpoint.replay_tolerant = true;
+        let (mut resumed, mut report) = (Accounts::default(), Report::default());
+        process_csv(&mut resumed, &input, &config, &mut report).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut expected = Accounts::default();
+        for txn in [Txn::deposit(1, 1, dec!(1)), Txn::deposit(1, 2, dec!(2)), Txn::dispute(1, 2), Txn::resolve(1, 2),
+                    Txn::withdrawal(1, 3, dec!(0.5))] {
+            execute(&mut expected, txn);
+        }
+        assert_eq!(resumed, expected);
+        assert_eq!((report.replayed, report.applied, report.rejected_total()), (5, 1, 0));
+    }
+
+    #[test]
+    fn test_load_missing() {
+        assert!(Checkpoint::load(Path::new("/nonexistent/ckpt"), None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_migrate_v0() {
+        // as the first checkpoints were written, before versions and most account fields
+        let mut report = Report::default();
+        report.record(Ok(()));
+        let json = format!(concat!(r#"{{"input":"in.csv","offset":40,"report":{},"accounts":[{{"client":1,"#,
+                                   r#""available":"0.5","held":"2","total":"2.5","locked":false,"disputes":[2],"#,
+                                   r#""txnlog":[{{"type":"deposit","tx":1,"amount":"0.5"}},{{"type":"deposit","tx":2,"amount":"2"}}]}},"#,
+                                   r#"{{"client":2,"available":"0","held":"0","total":"0","locked":false,"disputes":[],"txnlog":[]}}]}}"#),
+                           serde_json::to_string(&report).unwrap());
+        let checkpoint = Checkpoint::parse(json.as_bytes()).unwrap();
+
+        let mut expected = Accounts::default();
+        execute(&mut expected, Txn::deposit(1, 1, dec!(0.5)));
+        execute(&mut expected, Txn::deposit(1, 2, dec!(2)));
+        execute(&mut expected, Txn::dispute(1, 2));
+        // as a declined withdrawal opens one, before those were logged
+        expected.entry(ClientId::from(2u16)).or_default();
+        assert_eq!(checkpoint.accounts().unwrap(), expected);
+        assert_eq!((checkpoint.offset, checkpoint.report), (40, report));
+
+        let newer = Checkpoint::parse(br#"{"version":99}"#);
+        assert!(newer.err().unwrap().contains("version 99"));
+    }
+
+    #[test]
+    fn test_integrity() {
+        let json = br#"{"input":"in.csv","offset":3}"#.to_vec();
+        let sealed = seal(json.clone(), None).unwrap();
+        assert_eq!(String::from_utf8(sealed[json.len()..].to_vec()).unwrap(), format!("\ncrc32 {:08x}\n", crc32fast::hash(&json)));
+        assert_eq!(&*unseal(&sealed, None).unwrap(), &json[..]);
+        // cut short before the footer
+        assert!(unseal(&json, None).unwrap_err().contains("no crc32 footer"));
+
+        let mut corrupt = sealed.clone();
+        corrupt[12] = b'4';
+        assert!(unseal(&corrupt, None).unwrap_err().contains("is corrupt"));
+        let cut = &sealed[..sealed.len() - 3];
+        assert!(unseal(cut, None).unwrap_err().contains("is corrupt"));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted() {
+        use crate::config::CheckpointOptions;
+
+        use super::Key;
+
+        let key = |hex: &str| Key::read(&CheckpointOptions { key: Some(hex.into()), ..CheckpointOptions::default() });
+        let (right, wrong) = (key(&"ab".repeat(32)).unwrap().unwrap(), key(&"cd".repeat(32)).unwrap().unwrap());
+        assert!(key("abcd").is_err());
+
+        let json = br#"{"input":"in.csv","offset":3}"#.to_vec();
+        let sealed = seal(json.clone(), Some(&right)).unwrap();
+        assert!(!sealed.windows(6).any(|w| w == b"in.csv"));
+        assert_eq!(&*unseal(&sealed, Some(&right)).unwrap(), &json[..]);
+        assert!(unseal(&sealed, Some(&wrong)).unwrap_err().contains("doesn't decrypt"));
+        assert!(unseal(&sealed, None).unwrap_err().contains("is encrypted"));
+        assert!(unseal(&seal(json, None).unwrap(), Some(&right)).unwrap_err().contains("isn't encrypted"));
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(unseal(&tampered, Some(&right)).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+//! command line parsing.
+//!
+//! usage: txn [process|tail|query|history|analyze|disputes|verify] [options] <file>
+//!        txn merge-output [--output <file>] <part>...
+//!        txn fuzz [--seed <n>] [--runs <n>] [--rows <n>]
+//!        txn admin adjust --client <id> --amount <amount> --reason <text> --listen <address> [--auth-key-file <file>]
+//!        txn admin forget --client <id> --listen <address> [--auth-key-file <file>]
+//!
+//! `process` (the default) runs the file once, `tail` follows it as it grows, `query` reconstructs one client's
+//! balance part way through it, or a page of every client's (see query.rs), `history` lists one client's transactions (see history.rs),
+//! `analyze` aggregates over it (see analyze.rs), `disputes` lists its disputes by age (see aging.rs) and `verify`
+//! diffs the engine's balances against a reference implementation's (see reference.rs).
+//! `merge-output` combines the parts of sharded output (see shard.rs), and `fuzz` runs generated transactions
+//! through the engine rather than a file (see simulation.rs). `admin adjust` sends a running server an admin's
+//! correction of a balance, and `admin forget` its erasure of who a client is (see admin.rs).
+//! the file can be given as `--input <file>` too.
+//! the file is left out when listening on a socket instead (`--listen`).
+//! flags map onto config keys (see config.rs) and override the config file. `--flag value` & `--flag=value` both work.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage: txn [process|tail|query|history|analyze|disputes|verify] [--config <file>] [--input <file>] [--precision <dp>] [--rounding <half_even|half_up|half_down|down|up>] [--amount-locale <strict|comma|dot|auto>] [--amount-policy <round|truncate|reject>] [--on-error <abort|skip|quarantine>] [--storage <memory>] [--parse-threads <n>] [--threads <n>] [--fast-parse] [--mmap] [--columnar] \
+[--dispute-withdrawals <true|false>] [--redisputes <true|false>] [--settlement-delay <n>] [--keep-last <n>] [--keep-days <days>] [--max-amount <amount>] [--max-memory <size>] [--output <file>] [--output-buffer-size <size>] [--output-shards <n>] [--stream-output] [--sort] [--empty-accounts <true|false>] [--enriched] [--losses] [--held-breakdown] [--output-decimals <dp>] [--columns +disputes,+txn_count] [--statement-client <id>] [--dry-run] [--stats] [--poll-ms <ms>] [--reorder-lateness <n>] [--clients <file>] [--schedule <file>] [--digests <file>] [--duplicates <refuse|warn>] [--manifest <file>] [--client <id>] [--at-tx <rows>] [--all] [--filter-locked <true|false>] [--min-balance <amount>] [--after <client>] [--limit <n>] [--top <n>] [--open] [--as-of <timestamp>] [--reference <naive>] [--otel-endpoint <url>] [--checkpoint-every <rows>] [--checkpoint-dir <dir>] [--checkpoint-key-file <file>] [--resume] [--replay-tolerant] [--listen unix:<path>|tcp:<host:port>] [--actors] [--health-listen <host:port>] [--rate-limit <txns/s>] [--global-rate-limit <txns/s>] [--rate-policy <reject|wait>] [--auth-keys <file>] [--replicate-to <host:port,...>] [--standby-listen <host:port>] [--replication-horizon <n>] [--tls-cert <pem>] [--tls-key <pem>] [--tls-client-ca <pem>] [--tls-ca <pem>] [--api-listen <host:port>] [--read-only] [--snapshot <checkpoint>] [--lease-dir <dir>] [--lease-ttl-ms <ms>] [--wait-for-lease] [--audit-log <file>] [--dedup-index <file>] [--dedup-expected <n>] [--tui] [--tenants] [<file>]
+       txn merge-output [--output <file>] <part>...
+       txn fuzz [--seed <n>] [--runs <n>] [--rows <n>]
+       txn admin adjust --client <id> --amount <amount> --reason <text> --listen unix:<path>|tcp:<host:port> [--auth-key-file <file>]
+       txn admin forget --client <id> --listen unix:<path>|tcp:<host:port> [--auth-key-file <file>]";
+
+/// flag -> config key
+const OPTIONS: &[(&str, &str)] = &[
+    ("--precision", "precision"),
+    ("--rounding", "rounding"),
+    ("--amount-locale", "amount_locale"),
+    ("--amount-policy", "amount_policy"),
+    ("--on-error", "on_error"),
+    ("--storage", "storage"),
+    ("--parse-threads", "parse_threads"),
+    ("--threads", "threads"),
+    ("--dispute-withdrawals", "disputes.withdrawals"),
+    ("--redisputes", "disputes.redisputes"),
+    ("--settlement-delay", "settlement.delay"),
+    ("--keep-last", "retention.keep_last"),
+    ("--keep-days", "retention.keep_days"),
+    ("--max-amount", "limits.max_amount"),
+    ("--max-memory", "limits.max_memory"),
+    ("--output", "output.path"),
+    ("--output-buffer-size", "output.buffer_size"),
+    ("--output-shards", "output.shards"),
+    ("--output-decimals", "output.decimals"),
+    ("--columns", "output.columns"),
+    ("--empty-accounts", "output.empty_accounts"),
+    ("--statement-client", "statement.client"),
+    ("--poll-ms", "tail.poll_ms"),
+    ("--reorder-lateness", "reorder.lateness"),
+    ("--clients", "clients.path"),
+    ("--schedule", "schedule.path"),
+    ("--digests", "digests.path"),
+    ("--duplicates", "digests.duplicates"),
+    ("--manifest", "manifest.path"),
+    ("--client", "query.client"),
+    ("--at-tx", "query.at_tx"),
+    ("--filter-locked", "query.filter_locked"),
+    ("--min-balance", "query.min_balance"),
+    ("--after", "query.after"),
+    ("--limit", "query.limit"),
+    ("--top", "analyze.top"),
+    ("--seed", "fuzz.seed"),
+    ("--runs", "fuzz.runs"),
+    ("--rows", "fuzz.rows"),
+    ("--reference", "verify.reference"),
+    ("--as-of", "aging.as_of"),
+    ("--otel-endpoint", "otel.endpoint"),
+    ("--checkpoint-every", "checkpoint.every"),
+    ("--checkpoint-dir", "checkpoint.dir"),
+    ("--checkpoint-key-file", "checkpoint.key_file"),
+    ("--listen", "listen"),
+    ("--health-listen", "health.listen"),
+    ("--rate-limit", "rate.per_connection"),
+    ("--global-rate-limit", "rate.global"),
+    ("--rate-policy", "rate.policy"),
+    ("--auth-keys", "auth.keys_file"),
+    ("--replicate-to", "replication.to"),
+    ("--standby-listen", "replication.listen"),
+    ("--replication-horizon", "replication.horizon"),
+    ("--tls-cert", "tls.cert"),
+    ("--tls-key", "tls.key"),
+    ("--tls-client-ca", "tls.client_ca"),
+    ("--tls-ca", "tls.ca"),
+    ("--api-listen", "api.listen"),
+    ("--snapshot", "api.snapshot"),
+    ("--lease-dir", "lease.dir"),
+    ("--lease-ttl-ms", "lease.ttl_ms"),
+    ("--audit-log", "audit.path"),
+    ("--dedup-index", "dedup.index"),
+    ("--dedup-expected", "dedup.expected"),
+    ("--amount", "admin.amount"),
+    ("--reason", "admin.reason"),
+    ("--auth-key-file", "admin.key_file")
+];
+
+/// valueless flag -> config key set to true
+const SWITCHES: &[(&str, &str)] = &[
+    ("--sort", "output.sort"),
+    ("--enriched", "output.enriched"),
+    ("--losses", "output.losses"),
+    ("--held-breakdown", "output.held_breakdown"),
+    ("--stream-output", "output.streaming"),
+    ("--dry-run", "dry_run"),
+    ("--stats", "stats"),
+    ("--fast-parse", "fast_parse"),
+    ("--mmap", "mmap"),
+    ("--columnar", "columnar"),
+    ("--open", "aging.open"),
+    ("--all", "query.all"),
+    ("--resume", "checkpoint.resume"),
+    ("--replay-tolerant", "checkpoint.replay_tolerant"),
+    ("--actors", "actors"),
+    ("--tui", "tui"),
+    ("--tenants", "tenants"),
+    ("--read-only", "api.read_only"),
+    ("--wait-for-lease", "lease.wait")
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Command {
+    Process,
+    Tail,
+    Query,
+    History,
+    Analyze,
+    Disputes,
+    Verify,
+    MergeOutput,
+    Fuzz,
+    /// `admin adjust`
+    Adjust,
+    /// `admin forget`
+    Forget
+}
+
+impl Command {
+    fn named(name: &OsString) -> Option<Self> {
+        match name.to_str()? {
+            "process" => Some(Command::Process),
+            "tail" => Some(Command::Tail),
+            "query" => Some(Command::Query),
+            "history" => Some(Command::History),
+            "analyze" => Some(Command::Analyze),
+            "disputes" => Some(Command::Disputes),
+            "verify" => Some(Command::Verify),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub config: Option<PathBuf>,
+    /// (config key, value), in command line order
+    pub overrides: Vec<(&'static str, String)>,
+    pub input: Option<PathBuf>,
+    /// the files `merge-output` merges
+    pub parts: Vec<PathBuf>
+}
+
+pub fn parse<I: IntoIterator<Item = OsString>>(args: I) -> Result<Cli, String> {
+    let mut config = None;
+    let mut input = None;
+    let mut overrides = Vec::new();
+    let mut positional = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let arg = match arg.to_str() {
+            Some(a) if a.starts_with("--") => a.to_string(),
+            _ => {
+                positional.push(arg);
+                continue;
+            }
+        };
+
+        let (flag, inline) = match arg.find('=') {
+            Some(i) => (&arg[..i], Some(arg[i + 1..].to_string())),
+            None => (arg.as_str(), None)
+        };
+
+        if let Some((_, key)) = SWITCHES.iter().find(|(f, _)| *f == flag) {
+            overrides.push((*key, inline.unwrap_or_else(|| "true".into())));
+            continue;
+        }
+
+        let mut value = || -> Result<String, String> {
+            match inline.clone() {
+                Some(v) => Ok(v),
+                None => args.next()
+                    .and_then(|v| v.into_string().ok())
+                    .ok_or_else(|| format!("{} expects a value", flag))
+            }
+        };
+
+        if flag == "--config" {
+            config = Some(PathBuf::from(value()?));
+        } else if flag == "--input" {
+            input = Some(OsString::from(value()?));
+        } else if let Some((_, key)) = OPTIONS.iter().find(|(f, _)| *f == flag) {
+            overrides.push((*key, value()?));
+        } else {
+            return Err(format!("unknown option {}. {}", flag, USAGE));
+        }
+    }
+
+    // merge-output takes any number of parts
+    if positional.first().and_then(|c| c.to_str()) == Some("merge-output") && (positional.len() > 1 || input.is_some()) {
+        let parts = positional.into_iter().skip(1).chain(input).map(PathBuf::from).collect();
+        return Ok(Cli { command: Command::MergeOutput, config, overrides, input: None, parts });
+    }
+    // as does fuzz no file
+    if positional.len() == 1 && positional[0].to_str() == Some("fuzz") && input.is_none() {
+        return Ok(Cli { command: Command::Fuzz, config, overrides, input: None, parts: Vec::new() });
+    }
+    // and admin the operation it sends
+    if positional.first().and_then(|c| c.to_str()) == Some("admin") && input.is_none() {
+        return match positional.get(1).and_then(|o| o.to_str()) {
+            Some("adjust") if positional.len() == 2 => Ok(Cli { command: Command::Adjust, config, overrides, input: None, parts: Vec::new() }),
+            Some("forget") if positional.len() == 2 => Ok(Cli { command: Command::Forget, config, overrides, input: None, parts: Vec::new() }),
+            _ => Err(USAGE.into())
+        };
+    }
+    let mut positional = positional.into_iter();
+    let (command, input) = match (positional.next(), positional.next(), positional.next(), input) {
+        (None, _, _, input) => (Command::Process, input),
+        (Some(command), None, None, Some(input)) => (Command::named(&command).ok_or(USAGE)?, Some(input)),
+        (Some(input), None, None, None) => (Command::Process, Some(input)),
+        (Some(command), Some(input), None, None) => (Command::named(&command).ok_or(USAGE)?, Some(input)),
+        _ => return Err(USAGE.into())
+    };
+    Ok(Cli { command, config, overrides, input: input.map(PathBuf::from), parts: Vec::new() })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+
+    use super::{Command, parse};
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_input_only() {
+        let cli = parse(args(&["transactions.csv"])).unwrap();
+        assert_eq!(cli.input, Some(PathBuf::from("transactions.csv")));
+        assert_eq!(cli.command, Command::Process);
+        assert_eq!(cli.config, None);
+        assert!(cli.overrides.is_empty());
+    }
+
+    #[test]
+    fn test_options() {
+        let cli = parse(args(&["--config", "txn.toml", "--precision=2", "--sort", "in.csv", "--on-error", "skip"])).unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("txn.toml")));
+        assert_eq!(cli.input, Some(PathBuf::from("in.csv")));
+        assert_eq!(cli.overrides, vec![
+            ("precision", "2".to_string()),
+            ("output.sort", "true".to_string()),
+            ("on_error", "skip".to_string()),
+        ]);
+        let cli = parse(args(&["--columns", "+disputes,+txn_count", "--output-decimals", "2", "in.csv"])).unwrap();
+        assert_eq!(cli.input, Some(PathBuf::from("in.csv")));
+        assert_eq!(cli.overrides, vec![
+            ("output.columns", "+disputes,+txn_count".to_string()),
+            ("output.decimals", "2".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(parse(args(&["process", "a.csv"])).unwrap().command, Command::Process);
+        let cli = parse(args(&["tail", "--poll-ms", "50", "a.csv"])).unwrap();
+        assert_eq!(cli.command, Command::Tail);
+        assert_eq!(cli.input, Some(PathBuf::from("a.csv")));
+        assert_eq!(cli.overrides, vec![("tail.poll_ms", "50".to_string())]);
+        let cli = parse(args(&["query", "--client", "3", "--at-tx=1500000", "a.csv"])).unwrap();
+        assert_eq!(cli.command, Command::Query);
+        assert_eq!(cli.overrides, vec![("query.client", "3".to_string()), ("query.at_tx", "1500000".to_string())]);
+        let cli = parse(args(&["history", "--client", "9", "--input", "txns.csv"])).unwrap();
+        assert_eq!(cli.command, Command::History);
+        assert_eq!(cli.input, Some(PathBuf::from("txns.csv")));
+        let cli = parse(args(&["analyze", "--top=3", "a.csv"])).unwrap();
+        assert_eq!(cli.command, Command::Analyze);
+        assert_eq!(cli.overrides, vec![("analyze.top", "3".to_string())]);
+        let cli = parse(args(&["disputes", "--open", "a.csv"])).unwrap();
+        assert_eq!(cli.command, Command::Disputes);
+        assert_eq!(cli.overrides, vec![("aging.open", "true".to_string())]);
+        let cli = parse(args(&["verify", "--reference", "naive", "a.csv"])).unwrap();
+        assert_eq!(cli.command, Command::Verify);
+        assert_eq!(cli.overrides, vec![("verify.reference", "naive".to_string())]);
+        // a file named after a command is still an input
+        assert_eq!(parse(args(&["tail"])).unwrap().input, Some(PathBuf::from("tail")));
+        let cli = parse(args(&["merge-output", "--output", "all.csv", "part-0.csv", "part-1.csv"])).unwrap();
+        assert_eq!(cli.command, Command::MergeOutput);
+        assert_eq!(cli.parts, vec![PathBuf::from("part-0.csv"), PathBuf::from("part-1.csv")]);
+        assert_eq!(cli.input, None);
+        let cli = parse(args(&["fuzz", "--seed", "42", "--runs=1"])).unwrap();
+        assert_eq!((cli.command, cli.input), (Command::Fuzz, None));
+        assert_eq!(cli.overrides, vec![("fuzz.seed", "42".to_string()), ("fuzz.runs", "1".to_string())]);
+        let cli = parse(args(&["admin", "adjust", "--client", "1", "--amount", "-2.5", "--reason", "a correction"])).unwrap();
+        assert_eq!((cli.command, cli.input), (Command::Adjust, None));
+        assert_eq!(cli.overrides, vec![("query.client", "1".to_string()), ("admin.amount", "-2.5".to_string()),
+                                       ("admin.reason", "a correction".to_string())]);
+        let cli = parse(args(&["admin", "forget", "--client", "123"])).unwrap();
+        assert_eq!((cli.command, cli.overrides), (Command::Forget, vec![("query.client", "123".to_string())]));
+    }
+
+    #[test]
+    fn test_invalid() {
+        // no input is left to main, as it's fine when listening
+        assert_eq!(parse(args(&["--listen", "unix:/tmp/txn.sock"])).unwrap().input, None);
+        assert!(parse(args(&["a.csv", "b.csv"])).is_err());
+        assert!(parse(args(&["--precision"])).is_err());
+        assert!(parse(args(&["--unknown", "a.csv"])).is_err());
+        assert!(parse(args(&["follow", "a.csv"])).is_err());
+        assert!(parse(args(&["admin", "erase"])).is_err());
+        assert!(parse(args(&["admin", "adjust", "a.csv"])).is_err());
+        assert!(parse(args(&["admin", "forget", "123"])).is_err());
+        assert!(parse(args(&["--input", "a.csv", "b.csv"])).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! `Clock`: where the time is read from, for what's timed: the server's ingestion lag (`/healthz`) & rate limits, and
+//! a run manifest's timings. the engine reads none itself, settlement waits being counted in transactions and reordering &
+//! dispute aging going by the input's timestamps, so balances never depend on when a run happens.
+//!
+//! `SystemClock` is the wall clock. `MockClock` stands still until it's set or advanced by hand, so tests & replays
+//! time the same every run.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// since `earlier`, zero if the clock's gone back past it
+    fn since(&self, earlier: SystemTime) -> Duration {
+        self.now().duration_since(earlier).unwrap_or_default()
+    }
+
+    /// blocks the thread for `duration`, as the clock tells it
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<SystemTime>
+}
+
+impl MockClock {
+    pub fn new(now: SystemTime) -> Self {
+        MockClock { now: Mutex::new(now) }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+
+    /// passes at once, the clock advancing by `duration`
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{Clock, MockClock};
+
+    #[test]
+    fn test_mock() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_718_000_000);
+        let clock = MockClock::new(start);
+        assert_eq!(clock.now(), start);
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(clock.since(start), Duration::from_millis(1500));
+        clock.sleep(Duration::from_millis(500));
+        assert_eq!(clock.since(start), Duration::from_secs(2));
+        clock.set(UNIX_EPOCH);
+        assert_eq!(clock.since(start), Duration::ZERO);
+    }
+}
@@ -0,0 +1,244 @@
+//! `--columnar`: for analytical replays of big files, transactions are executed a batch of `BATCH` at a time rather
+//! than row by row. a batch is held as columns, as an arrow record batch is (`Columns`), and executed client by
+//! client: the client column is sorted, each client's rows are taken in file order, and a run of a client's deposits
+//! with nothing else of theirs between is credited as one sum, a single balance update however many it holds.
+//!
+//! only the order across clients is given up, which nothing a transaction does depends on, so the balances come out
+//! as row by row. where summing a run wouldn't, its deposits are executed one at a time:
+//! - a merchant's, whose reserve is a share of each deposit, rounded
+//! - under `settlement.delay` or `retention.keep_last`, which count each transaction as it's logged
+//! - a run the sum of which would overflow, so the one that would is the one rejected
+//!
+//! each deposit is still checked & logged on its own, to be disputed. what does change is the order accounts are
+//! opened in, so an unsorted output may list them differently, and the events raised: a run's credits are one
+//! `FundsDeposited` under its last tx.
+
+use std::iter::FromIterator;
+
+use crate::config::Config;
+use crate::event::{Event, Sink};
+use crate::{Accounts, Amount, AccountKind, ClientId, Currency, emit, execute_recorded, Map, open_account, precheck, Rejection,
+            Txn, TxnId, TxnType};
+
+/// rows per batch
+pub(crate) const BATCH: usize = 64 * 1024;
+
+/// a batch of transactions, a column per field
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Columns {
+    types: Vec<TxnType>,
+    clients: Vec<ClientId>,
+    txs: Vec<TxnId>,
+    amounts: Vec<Option<Amount>>,
+    currencies: Vec<Option<Currency>>
+}
+
+impl Columns {
+    pub fn push(&mut self, txn: Txn) {
+        self.types.push(txn.txntype);
+        self.clients.push(txn.client);
+        self.txs.push(txn.tx);
+        self.amounts.push(txn.amount);
+        self.currencies.push(txn.currency);
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.types.clear();
+        self.clients.clear();
+        self.txs.clear();
+        self.amounts.clear();
+        self.currencies.clear();
+    }
+
+    pub(crate) fn types(&self) -> &[TxnType] {
+        &self.types
+    }
+
+    pub(crate) fn clients(&self) -> &[ClientId] {
+        &self.clients
+    }
+
+    /// row `i` as a transaction
+    fn txn(&self, i: usize) -> Txn {
+        Txn { currency: self.currencies[i], ..Txn::new(self.types[i].clone(), self.clients[i], self.txs[i], self.amounts[i]) }
+    }
+}
+
+impl FromIterator<Txn> for Columns {
+    fn from_iter<I: IntoIterator<Item = Txn>>(txns: I) -> Self {
+        let mut columns = Columns::default();
+        for txn in txns {
+            columns.push(txn);
+        }
+        columns
+    }
+}
+
+/// executes the batch, client by client, as `execute_with` would each row: what each came to, in row order
+pub fn execute_columns(accounts: &mut Accounts, columns: &Columns, config: &Config) -> Vec<Result<(), Rejection>> {
+    execute_columns_recorded(accounts, columns, config, &mut ())
+}
+
+/// as `execute_columns`, handing the events raised to the sink
+pub(crate) fn execute_columns_recorded<S: Sink>(accounts: &mut Accounts, columns: &Columns, config: &Config, sink: &mut S)
+                                                -> Vec<Result<(), Rejection>> {
+    let mut results = vec![Ok(()); columns.len()];
+    let order = group(&columns.clients);
+    let summable = config.settlement.delay == 0 && config.retention.keep_last.is_none();
+    let mut rows = &order[..];
+    while let Some(&first) = rows.first() {
+        let (first, client) = (first as usize, columns.clients[first as usize]);
+        let run = match columns.types[first] {
+            TxnType::Deposit => rows.iter().take_while(|&&i| columns.clients[i as usize] == client
+                                                      && columns.types[i as usize] == TxnType::Deposit).count(),
+            _ => 1
+        };
+        let (taken, rest) = rows.split_at(run);
+        if run == 1 || !summable || !deposit_run(accounts, columns, taken, config, &mut results, sink) {
+            for &i in taken {
+                results[i as usize] = execute_recorded(accounts, columns.txn(i as usize), config, sink);
+            }
+        }
+        rows = rest;
+    }
+    results
+}
+
+/// the rows, each client's together & in row order, the clients in the order they're first seen: they're numbered
+/// as they are, then the rows counting sorted by number
+fn group(clients: &[ClientId]) -> Vec<u32> {
+    let mut numbers: Map<ClientId, u32> = Map::default();
+    let (mut numbered, mut starts) = (Vec::with_capacity(clients.len()), Vec::new());
+    for client in clients {
+        let number = *numbers.entry(*client).or_insert_with(|| {
+            starts.push(0);
+            starts.len() as u32 - 1
+        });
+        starts[number as usize] += 1;
+        numbered.push(number);
+    }
+    // each number's count, to where its rows start
+    let mut start = 0;
+    for count in starts.iter_mut() {
+        start += std::mem::replace(count, start);
+    }
+    let mut order = vec![0; clients.len()];
+    for (row, number) in numbered.into_iter().enumerate() {
+        let at = &mut starts[number as usize];
+        order[*at as usize] = row as u32;
+        *at += 1;
+    }
+    order
+}
+
+/// credits a client's run of deposits as one sum, false if that wouldn't come to what crediting each would. each is
+/// checked as it would be alone, the account not changing between them but for what they credit
+fn deposit_run<S: Sink>(accounts: &mut Accounts, columns: &Columns, run: &[u32], config: &Config,
+                        results: &mut [Result<(), Rejection>], sink: &mut S) -> bool {
+    let client = columns.clients[run[0] as usize];
+    let account = accounts.get(&client);
+    let kind = account.map_or_else(|| config.kinds.kind(client), |a| a.kind);
+    if kind == AccountKind::Merchant || account.is_some_and(|a| !a.settling.is_empty()) {
+        return false;
+    }
+    let (mut credited, mut sum) = (Vec::with_capacity(run.len()), Amount::ZERO);
+    for i in run.iter().map(|&i| i as usize) {
+        let txn = columns.txn(i);
+        let result = precheck(account, &txn, config).and_then(|()| match account {
+            Some(a) if a.disputes.contains(&txn.tx) => Err(Rejection::AlreadyDisputed),
+            _ => Ok(())
+        });
+        if result.is_ok() {
+            // credited in any order, so long as none takes anything away
+            let amount = txn.amount();
+            sum = match sum.checked_add(amount).filter(|_| amount >= Amount::ZERO) {
+                Some(sum) => sum,
+                None => return false
+            };
+            credited.push(txn);
+        }
+        results[i] = result;
+    }
+    let balance = account.map(|a| a.balance).unwrap_or_default();
+    let last = match credited.last() {
+        Some(txn) => txn.tx,
+        None => return true
+    };
+    if balance.available.checked_add(sum).is_none() || balance.total.checked_add(sum).is_none() {
+        return false;
+    }
+    let account = accounts.entry(client).or_insert_with(|| open_account(client, config, sink));
+    emit(account, client, Event::FundsDeposited { tx: last, amount: sum }, sink).expect("the sum fits");
+    for txn in credited {
+        emit(account, client, Event::TransactionLogged(txn), sink).expect("logging always applies");
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::{Accounts, execute_with, Rejection, Txn};
+
+    use super::{Columns, execute_columns};
+
+    /// the batch executed both ways: the outcomes & accounts must agree
+    fn both_ways(txns: &[Txn], config: &Config) -> Vec<Result<(), Rejection>> {
+        let mut rows = Accounts::default();
+        let expected: Vec<_> = txns.iter().map(|t| execute_with(&mut rows, t.clone(), config)).collect();
+        let mut columnar = Accounts::default();
+        let results = execute_columns(&mut columnar, &txns.iter().cloned().collect::<Columns>(), config);
+        assert_eq!(results, expected);
+        assert_eq!(columnar, rows);
+        results
+    }
+
+    #[test]
+    fn test_matches_rows() {
+        let txns = [
+            Txn::deposit(1, 1, dec!(1.5)), Txn::deposit(2, 2, dec!(2)), Txn::deposit(1, 3, dec!(2.5)),
+            Txn::withdrawal(2, 4, dec!(3)), Txn::deposit(2, 5, dec!(1)), Txn::deposit(2, 6, dec!(1)),
+            Txn::dispute(1, 3), Txn::deposit(1, 3, dec!(9)), Txn::deposit(1, 7, dec!(1)), Txn::chargeback(1, 3),
+            Txn::deposit(1, 8, dec!(1)), Txn::deposit(3, 9, dec!(0)), Txn::dispute(3, 9)
+        ];
+        let results = both_ways(&txns, &Config::default());
+        assert_eq!(results[7], Err(Rejection::AlreadyDisputed));
+        assert_eq!(results[10], Err(Rejection::Locked));
+
+        // rejected alone, a deposit over the limit doesn't open an account
+        let mut config = Config::default();
+        config.limits.max_amount = Some(dec!(2));
+        let results = both_ways(&[Txn::deposit(4, 1, dec!(5)), Txn::deposit(4, 2, dec!(5)), Txn::deposit(1, 3, dec!(1))], &config);
+        assert_eq!(results[..2], [Err(Rejection::OverLimit), Err(Rejection::OverLimit)]);
+    }
+
+    #[test]
+    fn test_unsummable() {
+        // a merchant's reserve is taken deposit by deposit
+        let mut config = Config::from_toml("[kinds]\nmerchants = \"1\"\nreserve = 0.1").unwrap();
+        both_ways(&[Txn::deposit(1, 1, dec!(0.0005)), Txn::deposit(1, 2, dec!(0.0005)), Txn::deposit(2, 3, dec!(1))], &config);
+
+        // the deposit that overflows is the one rejected
+        #[cfg(not(feature = "fixed-point"))]
+        let max = rust_decimal::Decimal::MAX;
+        #[cfg(feature = "fixed-point")]
+        let max = dec!(922337203685477);
+        let results = both_ways(&[Txn::deposit(1, 1, dec!(1)), Txn::deposit(1, 2, max), Txn::deposit(1, 3, dec!(1))], &Config::default());
+        assert_eq!(results[1], Err(Rejection::Overflow));
+
+        config = Config::default();
+        config.retention.keep_last = Some(4);
+        let txns: Vec<Txn> = (0..7).map(|tx| Txn::deposit(1, tx, dec!(1))).collect();
+        both_ways(&txns, &config);
+    }
+}
@@ -0,0 +1,232 @@
+//! accounts shared between threads, as the server's connections are. every transaction touches only its own
+//! client's account, so accounts sit in shards, each a map behind its own lock: transactions for clients in
+//! different shards apply in parallel, and those for the same client are serialized by the shard's lock, in the
+//! order they take it.
+
+use std::hash::BuildHasher;
+
+use crate::config::Config;
+use crate::sync::RwLock;
+use crate::{Account, Accounts, adjust_with, Amount, apply, ClientId, forget_with, Hasher, locked_out, open_account, precheck, prune_with, Rejection, Txn,
+            TxnId, validate_with};
+
+/// enough that connections seldom wait on each other's clients
+const SHARDS: usize = 64;
+
+pub struct ConcurrentEngine {
+    shards: Box<[RwLock<Accounts>]>
+}
+
+impl Default for ConcurrentEngine {
+    fn default() -> Self {
+        ConcurrentEngine { shards: (0..SHARDS).map(|_| RwLock::new(Accounts::default())).collect() }
+    }
+}
+
+impl ConcurrentEngine {
+    /// as `execute_with`
+    pub fn execute(&self, txn: Txn, config: &Config) -> Result<(), Rejection> {
+        let shard = self.shard(txn.client);
+        // the shard's read lock is let go of before its write lock is taken
+        precheck(shard.read().unwrap().get(&txn.client), &txn, config)?;
+        let mut accounts = shard.write().unwrap();
+        let account = accounts.entry(txn.client).or_insert_with(|| open_account(txn.client, config, &mut ()));
+        // locked by another thread since the check
+        if locked_out(account, &txn, &config.locked) {
+            return Err(Rejection::Locked);
+        }
+        apply(account, txn, config)
+    }
+
+    /// what `execute` would come to for `txn`, applying nothing
+    pub fn validate(&self, txn: Txn, config: &Config) -> Result<(), Rejection> {
+        let account = self.shard(txn.client).read().unwrap().get(&txn.client).cloned();
+        validate_with(account.as_ref(), txn, config)
+    }
+
+    /// as `adjust_with`
+    pub fn adjust(&self, client: ClientId, amount: Amount, config: &Config) -> Result<(), Rejection> {
+        adjust_with(&mut self.shard(client).write().unwrap(), client, amount, config)
+    }
+
+    /// as `prune_with`, a shard at a time
+    pub fn prune(&self, through: TxnId) {
+        for shard in &self.shards {
+            prune_with(&mut shard.write().unwrap(), through);
+        }
+    }
+
+    /// as `forget_with`
+    pub fn forget(&self, client: ClientId) -> Result<(), String> {
+        forget_with(&mut self.shard(client).write().unwrap(), client)
+    }
+
+    /// balances & locks as they stand, without the transaction logs (which are only needed for disputes)
+    pub fn balances(&self) -> Accounts {
+        self.shards.iter()
+            .flat_map(|shard| {
+                let accounts = shard.read().unwrap();
+                accounts.iter().map(|(client, a)| (*client, a.summary())).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// the client's account as `balances` has it, along with the transactions it has under dispute
+    pub fn account(&self, client: ClientId) -> Option<Account> {
+        self.shard(client).read().unwrap().get(&client).map(Account::with_disputes)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.read().unwrap().is_empty())
+    }
+
+    fn shard(&self, client: ClientId) -> &RwLock<Accounts> {
+        &self.shards[Hasher::default().hash_one(client) as usize % SHARDS]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::{Accounts, ClientId, execute_with, Rejection, Txn};
+
+    use super::ConcurrentEngine;
+
+    /// a deposit, withdrawal, dispute & chargeback per client, ending with the account locked
+    fn client_txns(client: u16) -> Vec<Txn> {
+        let tx = client as u32 * 10;
+        vec![
+            Txn::deposit(client, tx, dec!(10)),
+            Txn::withdrawal(client, tx + 1, dec!(4)),
+            Txn::deposit(client, tx + 2, dec!(2.5)),
+            Txn::dispute(client, tx + 2),
+            Txn::chargeback(client, tx + 2),
+            Txn::deposit(client, tx + 3, dec!(1))
+        ]
+    }
+
+    #[test]
+    fn test_matches_sequential() {
+        let config = Config::default();
+        let engine = ConcurrentEngine::default();
+        let threads = 8;
+        let barrier = Barrier::new(threads);
+        // every thread works through its own clients, interleaved with every other thread's
+        std::thread::scope(|s| {
+            for thread in 0..threads {
+                let (engine, config, barrier) = (&engine, &config, &barrier);
+                s.spawn(move || {
+                    barrier.wait();
+                    for client in (thread..200).step_by(threads) {
+                        let results: Vec<_> = client_txns(client as u16).into_iter().map(|t| engine.execute(t, config)).collect();
+                        assert_eq!(results.last(), Some(&Err(Rejection::Locked)));
+                    }
+                });
+            }
+        });
+
+        let mut expected = Accounts::default();
+        for client in 0..200 {
+            for txn in client_txns(client) {
+                let _ = execute_with(&mut expected, txn, &config);
+            }
+        }
+        let balances = engine.balances();
+        assert_eq!(balances.len(), expected.len());
+        for (client, account) in &expected {
+            assert_eq!(balances[client].balance, account.balance);
+            assert!(balances[client].locked);
+        }
+    }
+
+    #[test]
+    fn test_same_client_serialized() {
+        let config = Config::default();
+        let engine = ConcurrentEngine::default();
+        let threads = 8;
+        let per_thread = 1000;
+        // every thread deposits to & withdraws from the one account, so no update may be lost
+        std::thread::scope(|s| {
+            for thread in 0..threads {
+                let (engine, config) = (&engine, &config);
+                s.spawn(move || {
+                    for i in 0..per_thread {
+                        let tx = (thread * per_thread + i) as u32 * 2;
+                        engine.execute(Txn::deposit(1, tx, dec!(1.5)), config).unwrap();
+                        engine.execute(Txn::withdrawal(1, tx + 1, dec!(0.5)), config).unwrap();
+                    }
+                });
+            }
+        });
+
+        let balance = engine.balances()[&ClientId(1)].balance;
+        assert_eq!(balance.total, dec!(8000));
+        assert_eq!(balance.available, dec!(8000));
+    }
+
+    #[test]
+    fn test_declined_opens_no_account() {
+        let mut config = Config::default();
+        config.limits.max_amount = Some(dec!(1));
+        let engine = ConcurrentEngine::default();
+        assert_eq!(engine.execute(Txn::deposit(1, 1, dec!(2)), &config), Err(Rejection::OverLimit));
+        assert!(engine.is_empty());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use std::sync::Arc;
+
+    use loom::thread;
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::{ClientId, Rejection, Txn};
+
+    use super::ConcurrentEngine;
+
+    #[test]
+    fn loom_account_opened_once() {
+        // two first deposits racing to open the account both land in it
+        loom::model(|| {
+            let (engine, config) = (Arc::new(ConcurrentEngine::default()), Arc::new(Config::default()));
+            let deposits: Vec<_> = [1, 2].iter().map(|&tx| {
+                let (engine, config) = (Arc::clone(&engine), Arc::clone(&config));
+                thread::spawn(move || engine.execute(Txn::deposit(1, tx, dec!(1)), &config))
+            }).collect();
+            for deposit in deposits {
+                assert_eq!(deposit.join().unwrap(), Ok(()));
+            }
+            assert_eq!(engine.balances()[&ClientId(1)].balance.total, dec!(2));
+        });
+    }
+
+    #[test]
+    fn loom_lock_rechecked() {
+        // a resolve racing the chargeback of the same dispute: one applies, and a resolve after the chargeback's
+        // locked the account is declined for the lock, even when it was checked before it
+        loom::model(|| {
+            let (engine, config) = (Arc::new(ConcurrentEngine::default()), Arc::new(Config::default()));
+            engine.execute(Txn::deposit(1, 1, dec!(10)), &config).unwrap();
+            engine.execute(Txn::dispute(1, 1), &config).unwrap();
+            let chargeback = {
+                let (engine, config) = (Arc::clone(&engine), Arc::clone(&config));
+                thread::spawn(move || engine.execute(Txn::chargeback(1, 1), &config))
+            };
+            let resolve = engine.execute(Txn::resolve(1, 1), &config);
+            let chargeback = chargeback.join().unwrap();
+
+            let account = &engine.balances()[&ClientId(1)];
+            match (resolve, chargeback) {
+                (Ok(()), Err(Rejection::NotDisputed)) => assert_eq!((account.balance.total.to_decimal(), account.locked), (dec!(10), false)),
+                (Err(Rejection::Locked), Ok(())) => assert_eq!((account.balance.total.to_decimal(), account.locked), (dec!(0), true)),
+                outcomes => panic!("{:?}", outcomes)
+            }
+        });
+    }
+}
@@ -0,0 +1,1394 @@
+//! run configuration. every key has a default matching the engine's historic behaviour, so an empty file changes nothing.
+//!
+//! layers, lowest precedence first:
+//! 1. defaults
+//! 2. toml file (`--config txn.toml`, or `TXN_CONFIG`)
+//! 3. `TXN_*` environment variables, the key upper cased with dots as underscores (`TXN_LIMITS_MAX_AMOUNT`)
+//! 4. command line flags
+//!
+//! ```toml
+//! precision = 4          # decimal places amounts are rounded to on read
+//! rounding = "half_even"  # "half_up", "half_down", "down" or "up": how they're rounded, see Rounding
+//! amount_locale = "strict"  # "comma" (1.234,56), "dot" (1,234.56) or "auto": separators csv amounts may have
+//! # amount_policy = "reject"  # "round" or "truncate": csv amounts in scientific notation or past precision, logged
+//! on_error = "abort"     # "skip" or "quarantine": what to do with malformatted rows
+//! storage = "memory"     # only backend so far
+//! parse_threads = 1      # csv parser threads, more than 1 runs the parallel pipeline
+//! # threads = 1          # a server's connections served at once, a thread each: 1 executes on the one thread,
+//!                        # deterministically, taking connections in turn
+//! fast_parse = false     # parse csv rows by hand rather than through serde
+//! mmap = false           # map csv files into memory & parse chunks of them in parallel
+//! columnar = false       # execute a file's transactions a batch at a time, client by client, see columnar.rs
+//! # listen = "unix:/var/run/txn.sock"  # serve newline-delimited transactions instead of reading a file, or tcp:<host:port>
+//! actors = false         # when serving, run an actor per client rather than sharing one map
+//! tui = false            # when serving, show a live dashboard in the terminal (`--features tui`)
+//! tenants = false        # rows carry a fifth `tenant` column, each tenant's accounts kept & written apart
+//!
+//! [disputes]
+//! withdrawals = true     # whether withdrawals may be disputed
+//! redisputes = true      # whether a resolved dispute may be disputed again. a charged back one never can
+//!
+//! [locked]               # what a locked account still accepts, nothing by default
+//! deposits = false
+//! withdrawals = false
+//! disputes = false
+//! resolves = false
+//! chargebacks = false
+//!
+//! [settlement]
+//! delay = 0              # withdrawals stay held for this many more deposits & withdrawals, 0 pays out at once
+//!
+//! [retention]            # prune old, undisputed transactions from the accounts' logs, see retention.rs
+//! # keep_last = 1000     # the newest this many per account
+//! # keep_days = 90       # when serving, those logged in the last this many days
+//!
+//! [kinds]                # clients whose accounts open as merchants or escrow, the rest are customers
+//! merchants = "1000-1999"  # see AccountKind
+//! escrow = "9000-9099,9500"
+//! reserve = 0.1          # share of each merchant deposit held in reserve against chargebacks
+//!
+//! [limits]
+//! max_amount = 10000     # deposits & withdrawals above this are ignored
+//! max_memory = "4G"      # abort once accounts & transaction logs take roughly this much
+//!
+//! [output]
+//! path = "accounts.csv"  # defaults to stdout
+//! sort = false           # order rows by client id
+//! empty_accounts = true  # list accounts never funded & still at zero, i.e. opened by a declined withdrawal
+//! enriched = false       # add name, currency & kind columns to csv & json output
+//! losses = false         # add a chargeback_loss column to csv & json output
+//! held_breakdown = false # add a held_breakdown column to csv & json output, the disputes making up held
+//! decimals = 4           # decimal places every csv amount is written with, 10.0000
+//! columns = []           # "disputes" & "txn_count": columns added to csv & json output, see Column
+//! buffer_size = "1M"     # bytes written out at a time
+//! shards = 1             # files written at once, path naming them with {shard}, see shard.rs
+//! streaming = false      # write each account as soon as input sorted by client is past it, see stream.rs
+//!
+//! [http]
+//! bearer_token = "..."   # for https:// input, better set as TXN_HTTP_BEARER_TOKEN
+//!
+//! [object_store]
+//! chunk_size = 8388608   # bytes per ranged read of s3:// & gs:// input
+//!
+//! [statement]
+//! client = 1             # client ofx/qif statements are booked against
+//!
+//! [tail]
+//! poll_ms = 1000         # how often `txn tail` checks the file for new rows
+//!
+//! [health]
+//! # listen = "127.0.0.1:8080"  # when serving, answer /healthz & /readyz over http
+//!
+//! [rate]                 # when serving, transactions a second taken, see rate.rs
+//! # per_connection = 100 # from each connection
+//! # global = 1000        # from all of them together
+//! policy = "reject"      # or "wait": what's done with a line over either, see RatePolicy
+//!
+//! [auth]
+//! keys_file = "keys.txt" # when serving, api keys connections authenticate with & their roles, see auth.rs
+//!
+//! [replication]          # when serving, see replica.rs
+//! to = ["10.0.0.2:7100"] # as a primary, standbys every transaction is shipped to
+//! # listen = "10.0.0.2:7100"  # as a standby, where a primary ships to
+//! horizon = 1000000      # as a primary, the newest transactions kept for standbys to catch up from
+//!
+//! [tls]                  # when serving over tcp, terminate TLS (`--features tls`), see tls.rs
+//! cert = "server.pem"    # the certificate chain, PEM
+//! key = "server.key"     # its private key, PEM
+//! client_ca = "ca.pem"   # only take clients with a certificate these CAs signed
+//! # ca = "ca.pem"        # connecting to a server, `txn admin` or a primary, only through TLS to one these CAs signed
+//!
+//! [api]                  # answer queries of the accounts over http, see api.rs
+//! listen = "127.0.0.1:8081"
+//! # read_only = true     # serve a snapshot's accounts alone, taking no transactions
+//! # snapshot = "ckpt/checkpoint.json"  # the checkpoint read-only mode serves
+//!
+//! [lease]                # one instance applying transactions at a time, see lease.rs
+//! dir = "/shared/txn.lease"  # where the instances sharing an output take turns
+//! ttl_ms = 10000         # how long a lease lasts unrenewed
+//! wait = false           # wait for a held lease rather than refuse to start
+//!
+//! [audit]
+//! path = "audit.jsonl"   # when serving, where admins' adjustments are recorded, see admin.rs
+//!
+//! [dedup]                # when serving, reject a deposit or withdrawal reusing any earlier tx, see dedup.rs
+//! index = "txids.idx"    # the file every tx is kept in
+//! expected = 100000000   # how many transactions the index is made for
+//!
+//! [admin]                # what `txn admin adjust` sends the server, see admin.rs
+//! amount = "-2.5"        # credited, or debited when negative
+//! reason = "refund of a duplicated fee"
+//! key_file = "admin.key" # the api key it authenticates with, if the server has keys
+//!
+//! [reorder]
+//! lateness = 1000        # execute rows in timestamp order (a fifth csv column), see reorder.rs
+//!
+//! [clients]
+//! path = "clients.csv"   # names, currencies & kinds of clients, whose accounts open up front, see registry.rs
+//!
+//! [schedule]
+//! path = "schedule.csv"  # recurring deposits & withdrawals among timestamped rows, see schedule.rs
+//!
+//! [digests]
+//! path = "digests.txt"   # digests of inputs already processed, to refuse one twice, see digest.rs
+//! duplicates = "refuse"  # or "warn" & process it again
+//!
+//! [manifest]
+//! path = "run.json"      # what a run processed & how it went, as json for orchestration, see manifest.rs
+//!
+//! [query]
+//! client = 3             # the client `txn query` reconstructs & `txn history` lists
+//! at_tx = 1500000        # after this many input rows
+//! # all = true           # `txn query` lists every client's, those the rest pick out
+//! # filter_locked = true # only the locked accounts, or with false only those that aren't
+//! # min_balance = "100"  # only those with at least this total
+//! # after = 500          # only the clients after this one, as the last page ended
+//! # limit = 1000         # at most this many
+//!
+//! [analyze]
+//! top = 10               # clients `txn analyze` lists as the top by volume & by dispute rate
+//!
+//! [fuzz]
+//! seed = 1               # the first seed `txn fuzz` simulates (`--features simulation`)
+//! runs = 100             # seeds simulated, one after another
+//! rows = 10000           # transactions generated from each
+//!
+//! [verify]
+//! reference = "naive"    # what `txn verify` runs the engine against, the only reference so far
+//!
+//! [aging]
+//! open = false           # `txn disputes` lists only the disputes still open
+//! as_of = 1000           # the timestamp open disputes are aged to, the input's latest by default
+//!
+//! [otel]
+//! endpoint = "http://localhost:4318"  # export traces & metrics over OTLP/http (`--features otel`)
+//!
+//! [checkpoint]
+//! every = 0              # snapshot state every n csv rows, 0 disables
+//! dir = "ckpt"           # where checkpoint.json is kept
+//! resume = false         # pick up from the checkpoint in dir, if there is one
+//! replay_tolerant = false  # resuming, re-read the input whole, passing over what the checkpoint has applied
+//! key_file = "ckpt.key"  # encrypt checkpoints with the AES-256 key it holds in hex (`--features encryption`)
+//! key = "..."            # or the key itself, better set as TXN_CHECKPOINT_KEY
+//! ```
+//!
+//! `dry_run = true` (`--dry-run`) processes the input and prints the run report in place of the output.
+//! `stats = true` (`--stats`) counts each client's transactions as they're executed and prints what they came to
+//! on stderr once the file's processed: the hottest clients, transaction log sizes, shard spread & map collisions,
+//! see stats.rs.
+
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::memory::parse_size;
+use crate::sink::Column;
+use crate::statement::STATEMENT_CLIENT;
+use crate::{AccountKind, ClientId, ClientRepr, CURRENCY_PRECISION, Precision, Rounding, TxnType};
+
+/// rust_decimal's maximum scale
+#[cfg(not(feature = "fixed-point"))]
+const MAX_PRECISION: u32 = 28;
+/// what a fixed-point amount holds
+#[cfg(feature = "fixed-point")]
+const MAX_PRECISION: u32 = crate::amount::SCALE;
+
+/// what output amounts can be written with from either build, rust_decimal's maximum scale
+const MAX_DECIMALS: u32 = 28;
+
+/// every key accepted by `Config::set`
+pub const KEYS: &[&str] = &[
+    "precision",
+    "rounding",
+    "amount_locale",
+    "amount_policy",
+    "on_error",
+    "storage",
+    "parse_threads",
+    "threads",
+    "fast_parse",
+    "mmap",
+    "columnar",
+    "disputes.withdrawals",
+    "disputes.redisputes",
+    "locked.deposits",
+    "locked.withdrawals",
+    "locked.disputes",
+    "locked.resolves",
+    "locked.chargebacks",
+    "settlement.delay",
+    "retention.keep_last",
+    "retention.keep_days",
+    "kinds.merchants",
+    "kinds.escrow",
+    "kinds.reserve",
+    "limits.max_amount",
+    "limits.max_memory",
+    "output.path",
+    "output.sort",
+    "output.empty_accounts",
+    "output.enriched",
+    "output.losses",
+    "output.held_breakdown",
+    "output.decimals",
+    "output.columns",
+    "output.buffer_size",
+    "output.shards",
+    "output.streaming",
+    "http.bearer_token",
+    "object_store.chunk_size",
+    "statement.client",
+    "tail.poll_ms",
+    "reorder.lateness",
+    "clients.path",
+    "schedule.path",
+    "digests.path",
+    "digests.duplicates",
+    "manifest.path",
+    "query.client",
+    "query.at_tx",
+    "query.all",
+    "query.filter_locked",
+    "query.min_balance",
+    "query.after",
+    "query.limit",
+    "analyze.top",
+    "fuzz.seed",
+    "fuzz.runs",
+    "fuzz.rows",
+    "verify.reference",
+    "aging.open",
+    "aging.as_of",
+    "otel.endpoint",
+    "checkpoint.every",
+    "checkpoint.dir",
+    "checkpoint.resume",
+    "checkpoint.replay_tolerant",
+    "checkpoint.key",
+    "checkpoint.key_file",
+    "listen",
+    "actors",
+    "tui",
+    "tenants",
+    "health.listen",
+    "rate.per_connection",
+    "rate.global",
+    "rate.policy",
+    "auth.keys_file",
+    "replication.to",
+    "replication.listen",
+    "replication.horizon",
+    "tls.cert",
+    "tls.key",
+    "tls.client_ca",
+    "tls.ca",
+    "api.listen",
+    "api.read_only",
+    "api.snapshot",
+    "lease.dir",
+    "lease.ttl_ms",
+    "lease.wait",
+    "audit.path",
+    "dedup.index",
+    "dedup.expected",
+    "admin.amount",
+    "admin.reason",
+    "admin.key_file",
+    "stats",
+    "dry_run"
+];
+
+const ENV_PREFIX: &str = "TXN_";
+/// names the config file rather than a key
+const ENV_CONFIG: &str = "TXN_CONFIG";
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub precision: u32,
+    pub rounding: Rounding,
+    pub amount_locale: AmountLocale,
+    /// what's done with amounts in scientific notation or past `precision`, see excess.rs. unset, they're rounded silently
+    pub amount_policy: Option<AmountPolicy>,
+    pub on_error: ErrorPolicy,
+    pub storage: Storage,
+    pub parse_threads: usize,
+    /// connections a server serves at once, 1 executing on the one thread, for debugging what concurrency changes.
+    /// unset, up to `server::MAX_CONNECTIONS`
+    pub threads: Option<usize>,
+    pub fast_parse: bool,
+    pub mmap: bool,
+    /// execute in batches, each client's deposits summed, see columnar.rs
+    pub columnar: bool,
+    pub disputes: DisputePolicy,
+    pub locked: LockPolicy,
+    pub settlement: SettlementOptions,
+    pub retention: RetentionOptions,
+    pub kinds: KindOptions,
+    pub limits: Limits,
+    pub output: OutputOptions,
+    pub http: HttpOptions,
+    pub object_store: ObjectStoreOptions,
+    pub statement: StatementOptions,
+    pub tail: TailOptions,
+    pub reorder: ReorderOptions,
+    pub clients: ClientsOptions,
+    pub schedule: ScheduleOptions,
+    pub digests: DigestOptions,
+    pub manifest: ManifestOptions,
+    pub query: QueryOptions,
+    pub analyze: AnalyzeOptions,
+    pub fuzz: FuzzOptions,
+    pub verify: VerifyOptions,
+    pub aging: AgingOptions,
+    pub otel: OtelOptions,
+    pub checkpoint: CheckpointOptions,
+    /// socket address to serve on, i.e. `unix:/var/run/txn.sock`
+    pub listen: Option<String>,
+    /// serve through an actor per client
+    pub actors: bool,
+    /// show the server's dashboard, see tui.rs
+    pub tui: bool,
+    /// key accounts by a `tenant` column too, see tenant.rs
+    pub tenants: bool,
+    pub health: HealthOptions,
+    pub rate: RateOptions,
+    pub auth: AuthOptions,
+    pub replication: ReplicationOptions,
+    pub tls: TlsOptions,
+    pub api: ApiOptions,
+    pub lease: LeaseOptions,
+    pub audit: AuditOptions,
+    pub dedup: DedupOptions,
+    pub admin: AdminOptions,
+    /// count each client's transactions, printing the stats after, see stats.rs
+    pub stats: bool,
+    /// process & report, but write no output
+    pub dry_run: bool
+}
+
+/// the separators a csv amount may be written with, see locale.rs
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AmountLocale {
+    /// a plain decimal, `1234.56`
+    #[default]
+    Strict,
+    /// `1.234,56`
+    Comma,
+    /// `1,234.56`
+    Dot,
+    /// either, by the separators the amount has
+    Auto
+}
+
+/// what's done with a csv amount the precision doesn't hold, or in scientific notation, see excess.rs
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AmountPolicy {
+    Round,
+    Truncate,
+    Reject
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorPolicy {
+    /// stop at the first malformatted row
+    Abort,
+    /// report malformatted rows on stderr and carry on
+    Skip,
+    /// as skip, and write the rows to a `.bad` file beside the input, see quarantine.rs
+    Quarantine
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    Memory
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisputePolicy {
+    pub withdrawals: bool,
+    pub redisputes: bool
+}
+
+/// the transaction types a locked account still accepts, i.e. deposits into a frozen account
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockPolicy {
+    pub deposits: bool,
+    pub withdrawals: bool,
+    pub disputes: bool,
+    pub resolves: bool,
+    pub chargebacks: bool
+}
+
+impl LockPolicy {
+    pub fn accepts(&self, txntype: &TxnType) -> bool {
+        match txntype {
+            TxnType::Deposit => self.deposits,
+            TxnType::Withdrawal => self.withdrawals,
+            TxnType::Dispute => self.disputes,
+            TxnType::Resolve => self.resolves,
+            TxnType::Chargeback => self.chargebacks
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub max_amount: Option<Decimal>,
+    /// bytes, see memory.rs
+    #[serde(deserialize_with = "deserialize_optional_size")]
+    pub max_memory: Option<u64>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputOptions {
+    pub path: Option<PathBuf>,
+    pub sort: bool,
+    /// list accounts that were never funded and stand at zero, i.e. opened by a declined withdrawal
+    pub empty_accounts: bool,
+    /// output accounts' names, currencies & kinds as well, in csv & json
+    pub enriched: bool,
+    /// output what chargebacks have taken from each account as well, in csv & json
+    pub losses: bool,
+    /// output the disputed transactions & amounts making up each account's held as well, in csv & json
+    pub held_breakdown: bool,
+    /// decimal places every csv & parquet amount is written with, whatever it was read with
+    pub decimals: u32,
+    /// columns added after all the others in csv & json, in the order given
+    pub columns: Vec<Column>,
+    /// bytes written out at a time
+    #[serde(deserialize_with = "deserialize_size")]
+    pub buffer_size: u64,
+    /// files written out at once, a thread each
+    pub shards: usize,
+    /// accounts written & dropped as the input, sorted by client, moves past them
+    pub streaming: bool
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpOptions {
+    pub bearer_token: Option<String>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObjectStoreOptions {
+    pub chunk_size: u64
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatementOptions {
+    pub client: ClientId
+}
+
+/// withdrawals settle like ach transfers: funds move from available to held, and leave the account once the
+/// account has seen `delay` more deposits & withdrawals
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SettlementOptions {
+    pub delay: u64
+}
+
+/// how long deposits & withdrawals are kept in their account's log to be disputed, for as long as the account lasts
+/// by default. a transaction's age goes by its tx, ids being given out in increasing order
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionOptions {
+    /// the newest transactions kept per account, older ones pruned unless they're disputed or settling
+    pub keep_last: Option<u64>,
+    /// when serving, transactions older than this many days are pruned unless they're disputed or settling
+    pub keep_days: Option<u64>
+}
+
+/// the clients whose accounts open as merchants or escrow, every other is a customer
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct KindOptions {
+    pub merchants: ClientRanges,
+    pub escrow: ClientRanges,
+    /// the share of a merchant's deposits kept in reserve, from 0 to 1
+    pub reserve: Decimal
+}
+
+impl KindOptions {
+    pub fn kind(&self, client: ClientId) -> AccountKind {
+        if self.merchants.contains(client) {
+            AccountKind::Merchant
+        } else if self.escrow.contains(client) {
+            AccountKind::Escrow
+        } else {
+            AccountKind::Customer
+        }
+    }
+}
+
+/// client ids as ranges and single ids, comma separated: `1000-1999,42`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(try_from = "String")]
+pub struct ClientRanges(Vec<(ClientRepr, ClientRepr)>);
+
+impl ClientRanges {
+    pub fn contains(&self, client: ClientId) -> bool {
+        self.0.iter().any(|(first, last)| (*first..=*last).contains(&client.0))
+    }
+
+    fn overlaps(&self, other: &ClientRanges) -> bool {
+        self.0.iter().any(|(first, last)| other.0.iter().any(|(f, l)| first <= l && f <= last))
+    }
+}
+
+impl FromStr for ClientRanges {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut ranges = Vec::new();
+        for range in s.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            let invalid = || format!("invalid client range '{}'", range);
+            let (first, last) = range.split_once('-').unwrap_or((range, range));
+            let (first, last): (ClientRepr, ClientRepr) = (first.trim().parse().map_err(|_| invalid())?, last.trim().parse().map_err(|_| invalid())?);
+            if first > last {
+                return Err(invalid());
+            }
+            ranges.push((first, last));
+        }
+        Ok(ClientRanges(ranges))
+    }
+}
+
+impl TryFrom<String> for ClientRanges {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TailOptions {
+    pub poll_ms: u64
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReorderOptions {
+    /// how far behind the newest timestamp a row may arrive, None to execute in file order
+    pub lateness: Option<u64>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientsOptions {
+    /// client metadata, see registry.rs
+    pub path: Option<PathBuf>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleOptions {
+    /// recurring transactions, see schedule.rs
+    pub path: Option<PathBuf>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DigestOptions {
+    /// the digests of inputs processed so far, see digest.rs
+    pub path: Option<PathBuf>,
+    pub duplicates: DuplicatePolicy
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManifestOptions {
+    /// where a run's manifest is written, see manifest.rs
+    pub path: Option<PathBuf>
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+    /// an input with a digest already listed is an error
+    #[default]
+    Refuse,
+    /// say so on stderr, and process it again
+    Warn
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryOptions {
+    pub client: Option<ClientId>,
+    /// input rows, counting from 1
+    pub at_tx: Option<u64>,
+    /// list every client's account rather than `client`'s, see query.rs
+    pub all: bool,
+    /// only the locked accounts, or only those that aren't: not `locked`, which names the [locked] section
+    pub filter_locked: Option<bool>,
+    pub min_balance: Option<Decimal>,
+    pub after: Option<ClientId>,
+    pub limit: Option<usize>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyzeOptions {
+    pub top: usize
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FuzzOptions {
+    pub seed: u64,
+    pub runs: u64,
+    /// transactions generated from each seed
+    pub rows: usize
+}
+
+impl Default for FuzzOptions {
+    fn default() -> Self {
+        Self { seed: 1, runs: 100, rows: 10_000 }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Reference {
+    /// see reference.rs
+    Naive
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerifyOptions {
+    pub reference: Reference
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self { reference: Reference::Naive }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgingOptions {
+    pub open: bool,
+    pub as_of: Option<u64>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthOptions {
+    /// tcp address to answer probes on, see health.rs
+    pub listen: Option<String>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateOptions {
+    /// transactions a second each connection may send, None for no limit
+    pub per_connection: Option<u32>,
+    /// transactions a second all connections together may send
+    pub global: Option<u32>,
+    pub policy: RatePolicy
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RatePolicy {
+    /// answer a line over the rate `throttled: ...` without executing it
+    #[default]
+    Reject,
+    /// stop reading the connection until the line is within the rate
+    Wait
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthOptions {
+    /// digests of the keys connections may authenticate with & their roles, None to take any connection
+    pub keys_file: Option<PathBuf>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplicationOptions {
+    /// `host:port` of each standby a primary ships to
+    pub to: Vec<String>,
+    /// tcp address a standby takes a primary's transactions on, None for a server that isn't one
+    pub listen: Option<String>,
+    /// the newest transactions a primary keeps in its log, a standby further behind can't catch up
+    pub horizon: usize
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsOptions {
+    /// the server's certificate chain, leaf first, None to serve without TLS
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// CAs a client's certificate must chain to, None to ask clients for none
+    pub client_ca: Option<PathBuf>,
+    /// CAs the certificate of a server connected to must chain to, None to connect without TLS
+    pub ca: Option<PathBuf>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiOptions {
+    /// tcp address to answer queries on, see api.rs
+    pub listen: Option<String>,
+    /// serve `snapshot`'s accounts alone, with no transaction socket
+    pub read_only: bool,
+    /// a checkpoint file
+    pub snapshot: Option<PathBuf>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaseOptions {
+    /// directory the instances sharing an output take a lease in, None to take none
+    pub dir: Option<PathBuf>,
+    pub ttl_ms: u64,
+    /// wait for a lease another instance holds, rather than refuse to start
+    pub wait: bool
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditOptions {
+    /// file admins' adjustments are appended to, a json line each, None to take no adjustments
+    pub path: Option<PathBuf>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DedupOptions {
+    /// file every tx the server takes is kept in, None to detect no duplicates
+    pub index: Option<PathBuf>,
+    /// the transactions a new index is sized for, its bloom filter taking ~1.2 bytes each
+    pub expected: u64
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminOptions {
+    /// what `txn admin adjust` credits the client, or debits when negative
+    pub amount: Option<Decimal>,
+    /// why, recorded in the server's audit log
+    pub reason: Option<String>,
+    /// a file holding the api key to authenticate with
+    pub key_file: Option<PathBuf>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct OtelOptions {
+    /// OTLP/http collector, None to export nothing
+    pub endpoint: Option<String>
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckpointOptions {
+    /// rows between checkpoints, 0 for none
+    pub every: u64,
+    pub dir: PathBuf,
+    pub resume: bool,
+    /// resuming, read the input from its start rather than the checkpoint's offset, passing over transactions the
+    /// checkpoint has applied (see `replayed` in lib.rs)
+    pub replay_tolerant: bool,
+    /// a hex AES-256 key checkpoints are encrypted with, or the file holding one
+    pub key: Option<String>,
+    pub key_file: Option<PathBuf>
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            precision: CURRENCY_PRECISION,
+            rounding: Rounding::HalfEven,
+            amount_locale: AmountLocale::Strict,
+            amount_policy: None,
+            on_error: ErrorPolicy::Abort,
+            storage: Storage::Memory,
+            parse_threads: 1,
+            threads: None,
+            fast_parse: false,
+            mmap: false,
+            columnar: false,
+            disputes: DisputePolicy::default(),
+            locked: LockPolicy::default(),
+            settlement: SettlementOptions::default(),
+            retention: RetentionOptions::default(),
+            kinds: KindOptions::default(),
+            limits: Limits::default(),
+            output: OutputOptions::default(),
+            http: HttpOptions::default(),
+            object_store: ObjectStoreOptions::default(),
+            statement: StatementOptions::default(),
+            tail: TailOptions::default(),
+            reorder: ReorderOptions::default(),
+            clients: ClientsOptions::default(),
+            schedule: ScheduleOptions::default(),
+            digests: DigestOptions::default(),
+            manifest: ManifestOptions::default(),
+            query: QueryOptions::default(),
+            analyze: AnalyzeOptions::default(),
+            fuzz: FuzzOptions::default(),
+            verify: VerifyOptions::default(),
+            aging: AgingOptions::default(),
+            otel: OtelOptions::default(),
+            checkpoint: CheckpointOptions::default(),
+            listen: None,
+            actors: false,
+            tui: false,
+            tenants: false,
+            health: HealthOptions::default(),
+            rate: RateOptions::default(),
+            auth: AuthOptions::default(),
+            replication: ReplicationOptions::default(),
+            tls: TlsOptions::default(),
+            api: ApiOptions::default(),
+            lease: LeaseOptions::default(),
+            audit: AuditOptions::default(),
+            dedup: DedupOptions::default(),
+            admin: AdminOptions::default(),
+            stats: false,
+            dry_run: false
+        }
+    }
+}
+
+impl Default for DisputePolicy {
+    fn default() -> Self {
+        Self { withdrawals: true, redisputes: true }
+    }
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self { path: None, sort: false, empty_accounts: true, enriched: false, losses: false, held_breakdown: false, decimals: CURRENCY_PRECISION,
+               columns: Vec::new(), buffer_size: 1024 * 1024, shards: 1, streaming: false }
+    }
+}
+
+impl Default for ReplicationOptions {
+    fn default() -> Self {
+        Self { to: Vec::new(), listen: None, horizon: 1_000_000 }
+    }
+}
+
+impl Default for AnalyzeOptions {
+    fn default() -> Self {
+        Self { top: 10 }
+    }
+}
+
+impl Default for ObjectStoreOptions {
+    fn default() -> Self {
+        Self { chunk_size: 8 * 1024 * 1024 }
+    }
+}
+
+impl Default for StatementOptions {
+    fn default() -> Self {
+        Self { client: STATEMENT_CLIENT }
+    }
+}
+
+impl Default for TailOptions {
+    fn default() -> Self {
+        Self { poll_ms: 1000 }
+    }
+}
+
+impl Default for CheckpointOptions {
+    fn default() -> Self {
+        Self { every: 0, dir: PathBuf::from("ckpt"), resume: false, replay_tolerant: false, key: None, key_file: None }
+    }
+}
+
+impl Default for LeaseOptions {
+    fn default() -> Self {
+        Self { dir: None, ttl_ms: 10_000, wait: false }
+    }
+}
+
+impl Default for DedupOptions {
+    fn default() -> Self {
+        Self { index: None, expected: 100_000_000 }
+    }
+}
+
+impl Config {
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        let config: Config = toml::from_str(content).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// layers the config file, environment and command line overrides over the defaults, validated once they're all
+    /// laid. a `file` given on the command line takes precedence over `TXN_CONFIG`
+    pub fn resolve<E>(file: Option<&Path>, env: E, overrides: &[(&str, String)]) -> Result<Self, String>
+        where E: IntoIterator<Item = (String, String)>
+    {
+        let env: Vec<(String, String)> = env.into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+
+        let mut config = match Config::file(file, &env) {
+            Some(path) => Config::read(&path)?,
+            None => Config::default()
+        };
+
+        // the prefix is shared with variables that aren't config (TXN_BENCH_ROWS), so an unknown one is only warned of
+        for (name, value) in env.iter().filter(|(name, _)| name != ENV_CONFIG) {
+            match KEYS.iter().find(|k| env_var(k) == *name) {
+                Some(key) => config.set(key, value).map_err(|e| format!("{}: {}", name, e))?,
+                None => eprintln!("warning: ignoring {}, not a config variable", name)
+            }
+        }
+        for (key, value) in overrides {
+            config.set(key, value)?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// the config file `resolve` reads, if any: `file`, else `TXN_CONFIG`
+    pub fn file(file: Option<&Path>, env: &[(String, String)]) -> Option<PathBuf> {
+        let env_file = env.iter().find(|(name, _)| name == ENV_CONFIG).map(|(_, v)| PathBuf::from(v));
+        file.map(Path::to_path_buf).or(env_file)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let config = Config::read(path)?;
+        config.validate().map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    /// the file's config, unvalidated, for `resolve` to lay more over
+    fn read(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// `precision` & `rounding`, as amounts are rounded
+    pub fn amount_precision(&self) -> Precision {
+        Precision::new(self.precision, self.rounding)
+    }
+
+    /// overrides a single key, named as in the toml file with sections dot separated (i.e. `limits.max_amount`)
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("invalid value '{}' for {}", value, key);
+        match key {
+            "precision" => self.precision = value.parse().map_err(|_| invalid())?,
+            "rounding" => self.rounding = match value {
+                "half_even" => Rounding::HalfEven,
+                "half_up" => Rounding::HalfUp,
+                "half_down" => Rounding::HalfDown,
+                "down" => Rounding::Down,
+                "up" => Rounding::Up,
+                _ => return Err(invalid())
+            },
+            "amount_locale" => self.amount_locale = match value {
+                "strict" => AmountLocale::Strict,
+                "comma" => AmountLocale::Comma,
+                "dot" => AmountLocale::Dot,
+                "auto" => AmountLocale::Auto,
+                _ => return Err(invalid())
+            },
+            "amount_policy" => self.amount_policy = Some(match value {
+                "round" => AmountPolicy::Round,
+                "truncate" => AmountPolicy::Truncate,
+                "reject" => AmountPolicy::Reject,
+                _ => return Err(invalid())
+            }),
+            "on_error" => self.on_error = match value {
+                "abort" => ErrorPolicy::Abort,
+                "skip" => ErrorPolicy::Skip,
+                "quarantine" => ErrorPolicy::Quarantine,
+                _ => return Err(invalid())
+            },
+            "storage" => self.storage = match value {
+                "memory" => Storage::Memory,
+                _ => return Err(invalid())
+            },
+            "parse_threads" => self.parse_threads = value.parse().map_err(|_| invalid())?,
+            "threads" => self.threads = Some(value.parse().map_err(|_| invalid())?),
+            "fast_parse" => self.fast_parse = value.parse().map_err(|_| invalid())?,
+            "mmap" => self.mmap = value.parse().map_err(|_| invalid())?,
+            "columnar" => self.columnar = value.parse().map_err(|_| invalid())?,
+            "disputes.withdrawals" => self.disputes.withdrawals = value.parse().map_err(|_| invalid())?,
+            "disputes.redisputes" => self.disputes.redisputes = value.parse().map_err(|_| invalid())?,
+            "locked.deposits" => self.locked.deposits = value.parse().map_err(|_| invalid())?,
+            "locked.withdrawals" => self.locked.withdrawals = value.parse().map_err(|_| invalid())?,
+            "locked.disputes" => self.locked.disputes = value.parse().map_err(|_| invalid())?,
+            "locked.resolves" => self.locked.resolves = value.parse().map_err(|_| invalid())?,
+            "locked.chargebacks" => self.locked.chargebacks = value.parse().map_err(|_| invalid())?,
+            "settlement.delay" => self.settlement.delay = value.parse().map_err(|_| invalid())?,
+            "retention.keep_last" => self.retention.keep_last = Some(value.parse().map_err(|_| invalid())?),
+            "retention.keep_days" => self.retention.keep_days = Some(value.parse().map_err(|_| invalid())?),
+            "kinds.merchants" => self.kinds.merchants = value.parse()?,
+            "kinds.escrow" => self.kinds.escrow = value.parse()?,
+            "kinds.reserve" => self.kinds.reserve = Decimal::from_str(value).map_err(|_| invalid())?,
+            "limits.max_amount" => self.limits.max_amount = Some(Decimal::from_str(value).map_err(|_| invalid())?),
+            "limits.max_memory" => self.limits.max_memory = Some(parse_size(value).ok_or_else(invalid)?),
+            "output.path" => self.output.path = Some(PathBuf::from(value)),
+            "output.sort" => self.output.sort = value.parse().map_err(|_| invalid())?,
+            "output.empty_accounts" => self.output.empty_accounts = value.parse().map_err(|_| invalid())?,
+            "output.enriched" => self.output.enriched = value.parse().map_err(|_| invalid())?,
+            "output.losses" => self.output.losses = value.parse().map_err(|_| invalid())?,
+            "output.held_breakdown" => self.output.held_breakdown = value.parse().map_err(|_| invalid())?,
+            "output.decimals" => self.output.decimals = value.parse().map_err(|_| invalid())?,
+            // `+disputes,+txn_count`, each added to the output's columns
+            "output.columns" => self.output.columns = value.split(',')
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(|c| c.strip_prefix('+').unwrap_or(c).parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid())?,
+            "output.buffer_size" => self.output.buffer_size = parse_size(value).ok_or_else(invalid)?,
+            "output.shards" => self.output.shards = value.parse().map_err(|_| invalid())?,
+            "output.streaming" => self.output.streaming = value.parse().map_err(|_| invalid())?,
+            "http.bearer_token" => self.http.bearer_token = Some(value.to_string()),
+            "object_store.chunk_size" => self.object_store.chunk_size = value.parse().map_err(|_| invalid())?,
+            "statement.client" => self.statement.client = value.parse().map_err(|_| invalid())?,
+            "tail.poll_ms" => self.tail.poll_ms = value.parse().map_err(|_| invalid())?,
+            "reorder.lateness" => self.reorder.lateness = Some(value.parse().map_err(|_| invalid())?),
+            "clients.path" => self.clients.path = Some(PathBuf::from(value)),
+            "schedule.path" => self.schedule.path = Some(PathBuf::from(value)),
+            "digests.path" => self.digests.path = Some(PathBuf::from(value)),
+            "digests.duplicates" => self.digests.duplicates = match value {
+                "refuse" => DuplicatePolicy::Refuse,
+                "warn" => DuplicatePolicy::Warn,
+                _ => return Err(invalid())
+            },
+            "manifest.path" => self.manifest.path = Some(PathBuf::from(value)),
+            "query.client" => self.query.client = Some(value.parse().map_err(|_| invalid())?),
+            "query.at_tx" => self.query.at_tx = Some(value.parse().map_err(|_| invalid())?),
+            "query.all" => self.query.all = value.parse().map_err(|_| invalid())?,
+            "query.filter_locked" => self.query.filter_locked = Some(value.parse().map_err(|_| invalid())?),
+            "query.min_balance" => self.query.min_balance = Some(Decimal::from_str(value).map_err(|_| invalid())?),
+            "query.after" => self.query.after = Some(value.parse().map_err(|_| invalid())?),
+            "query.limit" => self.query.limit = Some(value.parse().map_err(|_| invalid())?),
+            "analyze.top" => self.analyze.top = value.parse().map_err(|_| invalid())?,
+            "fuzz.seed" => self.fuzz.seed = value.parse().map_err(|_| invalid())?,
+            "fuzz.runs" => self.fuzz.runs = value.parse().map_err(|_| invalid())?,
+            "fuzz.rows" => self.fuzz.rows = value.parse().map_err(|_| invalid())?,
+            "verify.reference" => self.verify.reference = match value {
+                "naive" => Reference::Naive,
+                _ => return Err(invalid())
+            },
+            "aging.open" => self.aging.open = value.parse().map_err(|_| invalid())?,
+            "aging.as_of" => self.aging.as_of = Some(value.parse().map_err(|_| invalid())?),
+            "otel.endpoint" => self.otel.endpoint = Some(value.to_string()),
+            "checkpoint.every" => self.checkpoint.every = value.parse().map_err(|_| invalid())?,
+            "checkpoint.dir" => self.checkpoint.dir = PathBuf::from(value),
+            "checkpoint.resume" => self.checkpoint.resume = value.parse().map_err(|_| invalid())?,
+            "checkpoint.replay_tolerant" => self.checkpoint.replay_tolerant = value.parse().map_err(|_| invalid())?,
+            "checkpoint.key" => self.checkpoint.key = Some(value.to_string()),
+            "checkpoint.key_file" => self.checkpoint.key_file = Some(PathBuf::from(value)),
+            "listen" => self.listen = Some(value.to_string()),
+            "actors" => self.actors = value.parse().map_err(|_| invalid())?,
+            "tui" => self.tui = value.parse().map_err(|_| invalid())?,
+            "tenants" => self.tenants = value.parse().map_err(|_| invalid())?,
+            "health.listen" => self.health.listen = Some(value.to_string()),
+            "rate.per_connection" => self.rate.per_connection = Some(value.parse().map_err(|_| invalid())?),
+            "rate.global" => self.rate.global = Some(value.parse().map_err(|_| invalid())?),
+            "rate.policy" => self.rate.policy = match value {
+                "reject" => RatePolicy::Reject,
+                "wait" => RatePolicy::Wait,
+                _ => return Err(invalid())
+            },
+            "auth.keys_file" => self.auth.keys_file = Some(PathBuf::from(value)),
+            "replication.to" => self.replication.to = value.split(',')
+                .map(str::trim)
+                .filter(|a| !a.is_empty())
+                .map(str::to_string)
+                .collect(),
+            "replication.listen" => self.replication.listen = Some(value.to_string()),
+            "replication.horizon" => self.replication.horizon = value.parse().map_err(|_| invalid())?,
+            "tls.cert" => self.tls.cert = Some(PathBuf::from(value)),
+            "tls.key" => self.tls.key = Some(PathBuf::from(value)),
+            "tls.client_ca" => self.tls.client_ca = Some(PathBuf::from(value)),
+            "tls.ca" => self.tls.ca = Some(PathBuf::from(value)),
+            "api.listen" => self.api.listen = Some(value.to_string()),
+            "api.read_only" => self.api.read_only = value.parse().map_err(|_| invalid())?,
+            "api.snapshot" => self.api.snapshot = Some(PathBuf::from(value)),
+            "lease.dir" => self.lease.dir = Some(PathBuf::from(value)),
+            "lease.ttl_ms" => self.lease.ttl_ms = value.parse().map_err(|_| invalid())?,
+            "lease.wait" => self.lease.wait = value.parse().map_err(|_| invalid())?,
+            "audit.path" => self.audit.path = Some(PathBuf::from(value)),
+            "dedup.index" => self.dedup.index = Some(PathBuf::from(value)),
+            "dedup.expected" => self.dedup.expected = value.parse().map_err(|_| invalid())?,
+            "admin.amount" => self.admin.amount = Some(value.parse().map_err(|_| invalid())?),
+            "admin.reason" => self.admin.reason = Some(value.to_string()),
+            "admin.key_file" => self.admin.key_file = Some(PathBuf::from(value)),
+            "stats" => self.stats = value.parse().map_err(|_| invalid())?,
+            "dry_run" => self.dry_run = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("unknown config key '{}'", key))
+        }
+        self.check(key)
+    }
+
+    /// the rule for `key` alone, as it's set
+    fn check(&self, key: &str) -> Result<(), String> {
+        match key {
+            "precision" if self.precision > MAX_PRECISION => Err(format!("precision must be at most {}", MAX_PRECISION)),
+            "output.decimals" if self.output.decimals > MAX_DECIMALS => Err(format!("output.decimals must be at most {}", MAX_DECIMALS)),
+            "parse_threads" if self.parse_threads == 0 => Err("parse_threads must be positive".into()),
+            "threads" if self.threads == Some(0) => Err("threads must be positive".into()),
+            "output.buffer_size" if self.output.buffer_size == 0 => Err("output.buffer_size must be positive".into()),
+            "query.limit" if self.query.limit == Some(0) => Err("query.limit must be positive".into()),
+            "analyze.top" if self.analyze.top == 0 => Err("analyze.top must be positive".into()),
+            "fuzz.runs" if self.fuzz.runs == 0 => Err("fuzz.runs must be positive".into()),
+            "output.shards" if self.output.shards == 0 => Err("output.shards must be positive".into()),
+            "object_store.chunk_size" if self.object_store.chunk_size == 0 => Err("object_store.chunk_size must be positive".into()),
+            "tail.poll_ms" if self.tail.poll_ms == 0 => Err("tail.poll_ms must be positive".into()),
+            "limits.max_amount" if self.limits.max_amount.is_some_and(|m| m.is_sign_negative()) => {
+                Err("limits.max_amount must not be negative".into())
+            },
+            "kinds.reserve" if self.kinds.reserve < Decimal::ZERO || self.kinds.reserve > Decimal::ONE => {
+                Err("kinds.reserve must be from 0 to 1".into())
+            },
+            "retention.keep_last" | "retention.keep_days" if self.retention.keep_last == Some(0) || self.retention.keep_days == Some(0) => {
+                Err("retention.keep_last & retention.keep_days must be positive".into())
+            },
+            "dedup.expected" if self.dedup.expected == 0 => Err("dedup.expected must be positive".into()),
+            "replication.horizon" if self.replication.horizon == 0 => Err("replication.horizon must be positive".into()),
+            "limits.max_memory" if self.limits.max_memory == Some(0) => Err("limits.max_memory must be positive".into()),
+            "rate.per_connection" | "rate.global" if self.rate.per_connection == Some(0) || self.rate.global == Some(0) => {
+                Err("rate.per_connection & rate.global must be positive".into())
+            },
+            "lease.ttl_ms" if self.lease.ttl_ms < 3 => Err("lease.ttl_ms must be at least 3, renewed every third of it".into()),
+            "admin.amount" if self.admin.amount.is_some_and(|a| a.is_zero()) => Err("admin.amount must not be zero".into()),
+            "admin.reason" if self.admin.reason.as_deref().is_some_and(|r| r.trim().is_empty() || r.contains('\n')) => {
+                Err("admin.reason must be a line of text".into())
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// every key's rule, then those between keys, once the config's whole: a layer may set a key another relies on
+    fn validate(&self) -> Result<(), String> {
+        KEYS.iter().try_for_each(|key| self.check(key))?;
+        if self.threads == Some(1) && (self.parse_threads > 1 || self.mmap || self.actors) {
+            return Err("threads = 1 executes on the one thread, not with parse_threads above 1, mmap or actors".into());
+        }
+        if self.kinds.merchants.overlaps(&self.kinds.escrow) {
+            return Err("kinds.merchants & kinds.escrow overlap".into());
+        }
+        if self.checkpoint.key.is_some() && self.checkpoint.key_file.is_some() {
+            return Err("set one of checkpoint.key & checkpoint.key_file".into());
+        }
+        Ok(())
+    }
+}
+
+/// a byte count, or a size string as for `--max-memory`
+fn deserialize_size<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String)
+    }
+
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Ok(bytes),
+        Size::Text(text) => parse_size(&text).ok_or_else(|| serde::de::Error::custom(format!("invalid size '{}'", text)))
+    }
+}
+
+fn deserialize_optional_size<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    deserialize_size(deserializer).map(Some)
+}
+
+/// environment variable for a key, i.e. `limits.max_amount` -> `TXN_LIMITS_MAX_AMOUNT`
+pub fn env_var(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.to_ascii_uppercase().replace('.', "_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rust_decimal_macros::dec;
+
+    use crate::{AccountKind, ClientId, ClientRepr, Column, TxnType};
+
+    use super::{ClientRanges, Config, ErrorPolicy, KEYS, env_var};
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_empty_is_default() {
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(r#"
+            precision = 2
+            on_error = "skip"
+            storage = "memory"
+
+            [disputes]
+            withdrawals = false
+            redisputes = false
+
+            [locked]
+            deposits = true
+
+            [limits]
+            max_amount = 100.5
+            max_memory = "512M"
+
+            [output]
+            path = "out.csv"
+            sort = true
+            buffer_size = "4M"
+        "#).unwrap();
+        assert_eq!(config.precision, 2);
+        assert_eq!(config.on_error, ErrorPolicy::Skip);
+        assert!(!config.disputes.withdrawals);
+        assert!(!config.disputes.redisputes);
+        assert!(config.locked.accepts(&TxnType::Deposit) && !config.locked.accepts(&TxnType::Withdrawal));
+        assert_eq!(config.limits.max_amount, Some(dec!(100.5)));
+        assert_eq!(config.limits.max_memory, Some(512 << 20));
+        assert_eq!(config.output.path, Some(PathBuf::from("out.csv")));
+        assert!(config.output.sort);
+        assert_eq!(config.output.buffer_size, 4 << 20);
+    }
+
+    #[test]
+    fn test_from_toml_invalid() {
+        assert!(Config::from_toml("precison = 2").is_err());
+        assert!(Config::from_toml("precision = 29").is_err());
+        #[cfg(feature = "fixed-point")]
+        assert!(Config::from_toml("precision = 5").is_err());
+        assert!(Config::from_toml("on_error = \"ignore\"").is_err());
+        assert!(Config::from_toml("storage = \"rocksdb\"").is_err());
+        assert!(Config::from_toml("threads = 0").is_err());
+        assert_eq!(Config::from_toml("threads = 2\nparse_threads = 4").unwrap().threads, Some(2));
+        assert!(Config::from_toml("threads = 1\nparse_threads = 4").is_err());
+        assert!(Config::from_toml("threads = 1\nactors = true").is_err());
+        assert!(Config::from_toml("[limits]\nmax_amount = -1").is_err());
+        assert!(Config::from_toml("[limits]\nmax_memory = \"4 lots\"").is_err());
+        assert!(Config::from_toml("[limits]\nmax_memory = 0").is_err());
+        assert_eq!(Config::from_toml("[limits]\nmax_memory = 1024").unwrap().limits.max_memory, Some(1024));
+    }
+
+    #[test]
+    fn test_set() {
+        let mut config = Config::from_toml("precision = 2\n[limits]\nmax_amount = 5").unwrap();
+        config.set("precision", "3").unwrap();
+        config.set("limits.max_amount", "7.5").unwrap();
+        config.set("output.sort", "true").unwrap();
+        assert_eq!(config.precision, 3);
+        assert_eq!(config.limits.max_amount, Some(dec!(7.5)));
+        assert!(config.output.sort);
+        config.set("output.columns", "+txn_count, +disputes").unwrap();
+        assert_eq!(config.output.columns, [Column::TxnCount, Column::Disputes]);
+        assert_eq!(Config::from_toml("[output]\ncolumns = [\"disputes\"]").unwrap().output.columns, [Column::Disputes]);
+
+        assert!(config.set("precision", "two").is_err());
+        assert!(config.set("output.columns", "+balance").is_err());
+        assert!(config.set("disputes.withdrawals", "maybe").is_err());
+        assert!(config.set("nonexistent", "1").is_err());
+    }
+
+    #[test]
+    fn test_env_var() {
+        assert_eq!(env_var("precision"), "TXN_PRECISION");
+        assert_eq!(env_var("limits.max_amount"), "TXN_LIMITS_MAX_AMOUNT");
+    }
+
+    #[test]
+    fn test_client_ranges() {
+        let ranges: ClientRanges = " 1000-1999, 42 ,".parse().unwrap();
+        assert!(ranges.contains(ClientId(1000)) && ranges.contains(ClientId(1999)) && ranges.contains(ClientId(42)));
+        assert!(!ranges.contains(ClientId(43)) && !ranges.contains(ClientId(2000)));
+        assert!("5-1".parse::<ClientRanges>().is_err());
+        assert!(format!("1-{}", ClientRepr::MAX as u128 + 1).parse::<ClientRanges>().is_err());
+
+        let mut config = Config::default();
+        config.set("kinds.merchants", "1-10").unwrap();
+        config.set("kinds.escrow", "10").unwrap();
+        assert_eq!(config.validate(), Err("kinds.merchants & kinds.escrow overlap".to_string()));
+        config.set("kinds.escrow", "9000").unwrap();
+        assert!(config.set("kinds.reserve", "1.5").is_err());
+        assert_eq!(config.kinds.kind(ClientId(3)), AccountKind::Merchant);
+    }
+
+    #[test]
+    fn test_keys_are_settable() {
+        let defaults = [("precision", "4"), ("rounding", "half_up"), ("amount_locale", "auto"), ("amount_policy", "truncate"), ("on_error", "abort"), ("storage", "memory"), ("parse_threads", "4"), ("threads", "1"), ("fast_parse", "true"), ("mmap", "true"), ("disputes.withdrawals", "true"), ("disputes.redisputes", "false"), ("locked.deposits", "true"), ("locked.withdrawals", "true"), ("locked.disputes", "true"), ("locked.resolves", "true"), ("locked.chargebacks", "true"), ("settlement.delay", "3"), ("retention.keep_last", "1000"), ("retention.keep_days", "90"), ("kinds.merchants", "1000-1999"), ("kinds.escrow", "9000"), ("kinds.reserve", "0.1"),
+            ("limits.max_amount", "1"), ("limits.max_memory", "4G"), ("output.path", "out.csv"), ("output.sort", "false"), ("output.empty_accounts", "false"), ("output.enriched", "true"), ("output.losses", "true"), ("output.held_breakdown", "true"), ("output.decimals", "2"), ("output.columns", "+disputes,+txn_count"), ("output.buffer_size", "8M"), ("output.shards", "4"), ("output.streaming", "true"), ("http.bearer_token", "t"), ("object_store.chunk_size", "1024"), ("statement.client", "9"), ("tail.poll_ms", "100"), ("reorder.lateness", "1000"), ("clients.path", "clients.csv"), ("schedule.path", "schedule.csv"), ("digests.path", "digests.txt"), ("digests.duplicates", "warn"), ("manifest.path", "run.json"), ("query.client", "3"), ("query.at_tx", "1500000"), ("query.all", "true"), ("query.filter_locked", "true"), ("query.min_balance", "100"), ("query.after", "500"), ("query.limit", "1000"), ("analyze.top", "5"), ("fuzz.seed", "42"), ("fuzz.runs", "1"), ("fuzz.rows", "500"), ("verify.reference", "naive"), ("aging.open", "true"), ("aging.as_of", "1000"), ("otel.endpoint", "http://localhost:4318"), ("checkpoint.every", "1000"), ("checkpoint.dir", "ckpt"), ("checkpoint.resume", "true"), ("checkpoint.replay_tolerant", "true"), ("checkpoint.key", "00"), ("checkpoint.key_file", "ckpt.key"), ("listen", "unix:txn.sock"), ("actors", "true"), ("tui", "true"), ("tenants", "true"), ("health.listen", "127.0.0.1:8080"), ("rate.per_connection", "100"), ("rate.global", "1000"), ("rate.policy", "wait"), ("auth.keys_file", "keys.txt"), ("replication.to", "10.0.0.2:7100,10.0.0.3:7100"), ("replication.listen", "0.0.0.0:7100"), ("replication.horizon", "1000"), ("tls.client_ca", "ca.pem"), ("tls.ca", "ca.pem"), ("tls.cert", "server.pem"), ("tls.key", "server.key"), ("api.listen", "127.0.0.1:8081"), ("api.read_only", "true"), ("api.snapshot", "latest.snap"), ("lease.dir", "/shared/txn.lease"), ("lease.ttl_ms", "5000"), ("lease.wait", "true"), ("audit.path", "audit.jsonl"), ("dedup.index", "txids.idx"), ("dedup.expected", "1000"), ("admin.amount", "-2.5"), ("admin.reason", "a correction"), ("admin.key_file", "admin.key"), ("columnar", "true"), ("stats", "true"), ("dry_run", "true")];
+        assert_eq!(KEYS.len(), defaults.len());
+        for (key, value) in defaults.iter() {
+            assert!(KEYS.contains(key));
+            Config::default().set(key, value).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let path = std::env::temp_dir().join(format!("txn-config-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "precision = 2\non_error = \"skip\"\n[output]\nsort = true").unwrap();
+
+        let vars = env(&[("TXN_PRECISION", "3"), ("TXN_LIMITS_MAX_AMOUNT", "50"), ("PATH", "/bin"), ("TXN_CONFIG", "missing.toml")]);
+        let config = Config::resolve(Some(&path), vars, &[("limits.max_amount", "60".to_string())]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // file
+        assert_eq!(config.on_error, ErrorPolicy::Skip);
+        assert!(config.output.sort);
+        // env over file
+        assert_eq!(config.precision, 3);
+        // cli over env
+        assert_eq!(config.limits.max_amount, Some(dec!(60)));
+    }
+
+    #[test]
+    fn test_resolve_validates_once() {
+        let path = std::env::temp_dir().join(format!("txn-config-once-{}.toml", std::process::id()));
+        std::fs::write(&path, "parse_threads = 4").unwrap();
+
+        let config = Config::resolve(Some(&path), env(&[("TXN_THREADS", "1")]), &[("parse_threads", "1".to_string())]);
+        let clash = Config::resolve(Some(&path), env(&[("TXN_THREADS", "1")]), &[]);
+        std::fs::remove_file(&path).unwrap();
+
+        // the file's parse_threads is only checked against threads once the cli has had its say
+        let config = config.unwrap();
+        assert_eq!((config.threads, config.parse_threads), (Some(1), 1));
+        assert!(clash.is_err());
+    }
+
+    #[test]
+    fn test_resolve_env_invalid() {
+        assert_eq!(Config::resolve(None, env(&[("TXN_PRECISON", "3"), ("TXN_BENCH_ROWS", "100")]), &[]).unwrap(), Config::default());
+        assert!(Config::resolve(None, env(&[("TXN_PRECISION", "x")]), &[]).is_err());
+        assert!(Config::resolve(None, env(&[("TXN_CONFIG", "/nonexistent/txn.toml")]), &[]).is_err());
+        assert_eq!(Config::resolve(None, env(&[("HOME", "/root")]), &[]).unwrap(), Config::default());
+    }
+}
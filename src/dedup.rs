@@ -0,0 +1,263 @@
+//! `--dedup-index txids.idx`: global duplicate detection for the server. a deposit or withdrawal whose tx has been
+//! seen before, for any client, is rejected as `duplicate transaction`, rather than taking over its account's log
+//! entry or sitting beside another client's. a tx is taken before its transaction executes, so another connection
+//! can't slip the same one in meanwhile, and given back if the engine rejects it without logging it (a locked
+//! account, an amount over the limit, ...), so it can be sent again. declined withdrawals keep theirs, as they're
+//! logged.
+//!
+//! every tx is kept in the index file rather than in memory, which only holds a bloom filter over them, ~1.2 bytes
+//! a tx sized by `dedup.expected` (120 MiB for the default 100 million), and a count per bucket. the filter says for
+//! certain that a tx is new, so the file's only read on its probable hits: duplicates, and about 1 in 100 new ids
+//! once it holds as many as expected, more past that.
+//!
+//! - the file is 4 KiB buckets, a tx going in the one its hash picks: a count, then up to 511 ids. there are enough
+//!   for twice `dedup.expected`, sparse on disk until they're written, and a bucket filling up stops the server
+//!   taking deposits & withdrawals (`failed: ...`) until it's restarted with a new index sized for more
+//! - an index is kept across restarts, read through once at startup to refill the filter. it's written through the
+//!   page cache without syncing each tx, so a crash can lose the latest
+//! - a standby adds the transactions it's shipped to its own index, so it carries on detecting once promoted.
+//!   `POST /validate` checks the index too, adding nothing
+
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::TxnId;
+
+const BUCKET_BYTES: u64 = 4096;
+/// ids a bucket holds after its count
+const BUCKET_IDS: u64 = BUCKET_BYTES / 8 - 1;
+/// bloom filter bits per expected tx, and hashes per tx, for a false positive rate of ~1%
+const BITS_PER_TX: u64 = 10;
+const HASHES: u64 = 7;
+
+pub(crate) struct Dedup(Mutex<Index>);
+
+struct Index {
+    path: PathBuf,
+    file: File,
+    /// ids in each bucket
+    counts: Vec<u16>,
+    bloom: Bloom
+}
+
+struct Bloom(Vec<u64>);
+
+/// splitmix64's finalizer, spreading an id's bits over the hash
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+impl Bloom {
+    fn new(expected: u64) -> Self {
+        Bloom(vec![0; expected.saturating_mul(BITS_PER_TX).div_ceil(64).max(1) as usize])
+    }
+
+    /// the bits the id sets, by double hashing
+    fn bits(&self, id: u64) -> impl Iterator<Item = usize> {
+        let (h1, h2) = (mix(id), mix(id ^ 0x9e37_79b9_7f4a_7c15) | 1);
+        let len = self.0.len() as u64 * 64;
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    fn insert(&mut self, id: u64) {
+        for bit in self.bits(id).collect::<Vec<_>>() {
+            self.0[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    fn may_contain(&self, id: u64) -> bool {
+        self.bits(id).all(|bit| self.0[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+impl Dedup {
+    /// the index at `path`, made for `expected` transactions if it doesn't exist yet
+    pub(crate) fn open(path: &Path, expected: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let len = file.metadata()?.len();
+        let buckets = match len {
+            0 => {
+                let buckets = expected.saturating_mul(2).div_ceil(BUCKET_IDS).max(1);
+                file.set_len(buckets * BUCKET_BYTES)?;
+                buckets
+            },
+            len if len % BUCKET_BYTES == 0 => len / BUCKET_BYTES,
+            len => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} bytes isn't a whole number of buckets", len)))
+        };
+        let mut index = Index { path: path.to_path_buf(), file, counts: vec![0; buckets as usize], bloom: Bloom::new(expected) };
+        if len > 0 {
+            index.load()?;
+        }
+        Ok(Dedup(Mutex::new(index)))
+    }
+
+    /// takes the tx, Ok(false) if it's been seen before
+    pub(crate) fn admit(&self, tx: TxnId) -> io::Result<bool> {
+        let mut index = self.0.lock().unwrap();
+        if index.seen(tx.as_u64())? {
+            return Ok(false);
+        }
+        index.insert(tx.as_u64())?;
+        Ok(true)
+    }
+
+    /// gives back a tx `admit` took, as if it'd never been seen
+    pub(crate) fn release(&self, tx: TxnId) -> io::Result<()> {
+        self.0.lock().unwrap().remove(tx.as_u64())
+    }
+
+    /// whether the tx has been seen before, taking nothing
+    pub(crate) fn seen(&self, tx: TxnId) -> io::Result<bool> {
+        self.0.lock().unwrap().seen(tx.as_u64())
+    }
+}
+
+impl Index {
+    /// refills the counts & filter from the file
+    fn load(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::with_capacity(64 * BUCKET_BYTES as usize, &self.file);
+        let mut bucket = [0u8; BUCKET_BYTES as usize];
+        for count in self.counts.iter_mut() {
+            reader.read_exact(&mut bucket)?;
+            let mut ids = ids(&bucket);
+            let held = ids.next().unwrap_or(0).min(BUCKET_IDS);
+            for id in ids.take(held as usize) {
+                self.bloom.insert(id);
+            }
+            *count = held as u16;
+        }
+        Ok(())
+    }
+
+    fn bucket(&self, id: u64) -> usize {
+        (mix(id) % self.counts.len() as u64) as usize
+    }
+
+    fn seen(&mut self, id: u64) -> io::Result<bool> {
+        if !self.bloom.may_contain(id) {
+            return Ok(false);
+        }
+        let bucket = self.bucket(id);
+        let mut bytes = vec![0u8; (self.counts[bucket] as usize + 1) * 8];
+        self.file.seek(SeekFrom::Start(bucket as u64 * BUCKET_BYTES))?;
+        self.file.read_exact(&mut bytes)?;
+        let seen = ids(&bytes).skip(1).any(|held| held == id);
+        Ok(seen)
+    }
+
+    fn insert(&mut self, id: u64) -> io::Result<()> {
+        let bucket = self.bucket(id);
+        let count = self.counts[bucket] as u64;
+        if count == BUCKET_IDS {
+            return Err(io::Error::other(format!("{} is full, start a new one sized for more with dedup.expected", self.path.display())));
+        }
+        // the id, then the count that takes it in
+        let start = bucket as u64 * BUCKET_BYTES;
+        self.file.seek(SeekFrom::Start(start + (count + 1) * 8))?;
+        self.file.write_all(&id.to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file.write_all(&(count + 1).to_le_bytes())?;
+        self.counts[bucket] += 1;
+        self.bloom.insert(id);
+        Ok(())
+    }
+
+    /// takes the id out of its bucket, the bucket's last id moving into its place. it stays in the filter, costing
+    /// a read of the bucket when it's seen again
+    fn remove(&mut self, id: u64) -> io::Result<()> {
+        let bucket = self.bucket(id);
+        let count = self.counts[bucket] as usize;
+        let start = bucket as u64 * BUCKET_BYTES;
+        let mut bytes = vec![0u8; (count + 1) * 8];
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file.read_exact(&mut bytes)?;
+        let held: Vec<u64> = ids(&bytes).skip(1).collect();
+        let at = match held.iter().position(|&h| h == id) {
+            Some(at) => at,
+            None => return Ok(())
+        };
+        // the last id, then the count that drops the one it's replaced
+        self.file.seek(SeekFrom::Start(start + (at as u64 + 1) * 8))?;
+        self.file.write_all(&held[count - 1].to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(start))?;
+        self.file.write_all(&(count as u64 - 1).to_le_bytes())?;
+        self.counts[bucket] -= 1;
+        Ok(())
+    }
+}
+
+/// the little endian u64s in `bytes`
+fn ids(bytes: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    bytes.chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().expect("8 bytes")))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TxnId;
+
+    use super::{Bloom, Dedup};
+
+    #[test]
+    fn test_admit() {
+        let path = std::env::temp_dir().join(format!("txn-dedup-test-{}.idx", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let dedup = Dedup::open(&path, 1000).unwrap();
+        assert!(dedup.admit(TxnId(1)).unwrap());
+        assert!(dedup.admit(TxnId(2)).unwrap());
+        assert!(!dedup.admit(TxnId(1)).unwrap());
+        assert!(dedup.seen(TxnId(2)).unwrap());
+        assert!(!dedup.seen(TxnId(3)).unwrap());
+        for tx in 3..1000 {
+            assert!(dedup.admit(TxnId(tx)).unwrap());
+        }
+        drop(dedup);
+
+        // reopened, whatever the expected count now, it's as it was
+        let dedup = Dedup::open(&path, 10).unwrap();
+        assert_eq!(dedup.0.lock().unwrap().counts.len(), 4);
+        assert!((0..1000).all(|tx| dedup.seen(TxnId(tx)).unwrap() == (tx > 0)));
+        assert!(!dedup.admit(TxnId(999)).unwrap());
+        assert!(dedup.admit(TxnId(0)).unwrap());
+
+        // given back, a tx is new again, and the rest of its bucket's kept
+        dedup.release(TxnId(500)).unwrap();
+        dedup.release(TxnId(5000)).unwrap();
+        assert!((0..1000).all(|tx| dedup.seen(TxnId(tx)).unwrap() == (tx != 500)));
+        drop(dedup);
+        let dedup = Dedup::open(&path, 10).unwrap();
+        assert!((0..1000).all(|tx| dedup.seen(TxnId(tx)).unwrap() == (tx != 500)));
+        assert!(dedup.admit(TxnId(500)).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_full() {
+        let path = std::env::temp_dir().join(format!("txn-dedup-full-test-{}.idx", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // the one bucket
+        let dedup = Dedup::open(&path, 1).unwrap();
+        for tx in 0..511 {
+            assert!(dedup.admit(TxnId(tx)).unwrap());
+        }
+        assert!(dedup.admit(TxnId(511)).unwrap_err().to_string().ends_with("is full, start a new one sized for more with dedup.expected"));
+        assert!(!dedup.admit(TxnId(510)).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_bloom() {
+        let mut bloom = Bloom::new(10_000);
+        for id in 0..10_000 {
+            bloom.insert(id * 7);
+        }
+        assert!((0..10_000).all(|id| bloom.may_contain(id * 7)));
+        let false_positives = (0..10_000).filter(|id| bloom.may_contain(1_000_000 + id)).count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+    }
+}
@@ -0,0 +1,151 @@
+//! `--digests digests.txt`: a file of the sha-256 digests of inputs processed so far, so that the same batch submitted
+//! twice, under whatever name, isn't posted twice. it's in `sha256sum`'s format, a digest and the input's path a line:
+//! ```text
+//! 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08  in/2024-06-01.csv
+//! ```
+//! an input is digested before it's read. one already listed is refused, or with `--duplicates warn` reported on
+//! stderr and processed again. an input is listed once it's been processed through & its output written, not on a dry
+//! run or a run that failed, so a failed input can be submitted again as it is.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::config::{DigestOptions, DuplicatePolicy};
+
+/// the digests listed, as they're found in the file
+struct Digests {
+    path: PathBuf,
+    listed: Vec<(String, String)>
+}
+
+impl Digests {
+    /// a file that doesn't exist yet lists none
+    fn load(path: &Path) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("digests {}: {}", path.display(), e))
+        };
+        Digests::parse(path, &text)
+    }
+
+    fn parse(path: &Path, text: &str) -> Result<Self, String> {
+        let mut listed = Vec::new();
+        for (line, entry) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let (digest, input) = entry.split_once("  ")
+                .filter(|(d, _)| d.len() == 64 && d.bytes().all(|b| b.is_ascii_hexdigit()))
+                .ok_or_else(|| format!("digests {}: line {}: expected a sha-256 digest & a path", path.display(), line + 1))?;
+            listed.push((digest.to_ascii_lowercase(), input.to_string()));
+        }
+        Ok(Digests { path: path.to_path_buf(), listed })
+    }
+
+    /// the path the input with `digest` was listed under, if it has been
+    fn listed(&self, digest: &str) -> Option<&str> {
+        self.listed.iter().find(|(d, _)| d == digest).map(|(_, input)| input.as_str())
+    }
+
+    /// appends the input to the file
+    fn list(&self, digest: &str, input: &Path) -> Result<(), String> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)
+            .map_err(|e| format!("digests {}: {}", self.path.display(), e))?;
+        writeln!(file, "{}  {}", digest, input.display()).map_err(|e| format!("digests {}: {}", self.path.display(), e))
+    }
+}
+
+/// hex sha-256 of everything `reader` has
+pub(crate) fn digest<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match reader.read(&mut buffer)? {
+            0 => break,
+            n => hasher.update(&buffer[..n])
+        }
+    }
+    Ok(hex(hasher.finalize()))
+}
+
+/// a digest as lower case hex
+pub(crate) fn hex(digest: impl AsRef<[u8]>) -> String {
+    digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// an input not yet listed, to list once it's processed
+pub(crate) struct Unlisted {
+    digests: Digests,
+    digest: String
+}
+
+impl Unlisted {
+    pub(crate) fn list(&self, input: &Path) -> Result<(), String> {
+        self.digests.list(&self.digest, input)
+    }
+}
+
+/// digests the input and checks it against those listed. None if there's no digests file, or the input is listed
+/// already and processed again anyway
+pub(crate) fn check(options: &DigestOptions, input: &Path) -> Result<Option<Unlisted>, String> {
+    let path = match &options.path {
+        Some(path) => path,
+        None => return Ok(None)
+    };
+    let digests = Digests::load(path)?;
+    let file = File::open(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+    let digest = digest(io::BufReader::new(file)).map_err(|e| format!("{}: {}", input.display(), e))?;
+    let listed = match digests.listed(&digest) {
+        Some(listed) => listed,
+        None => return Ok(Some(Unlisted { digests, digest }))
+    };
+    let duplicate = format!("{} has been processed before, as {} (sha-256 {})", input.display(), listed, digest);
+    match options.duplicates {
+        DuplicatePolicy::Refuse => Err(format!("{}, refusing it: --duplicates warn processes it again", duplicate)),
+        DuplicatePolicy::Warn => {
+            eprintln!("warning: {}", duplicate);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::config::{DigestOptions, DuplicatePolicy};
+
+    use super::{check, digest, Digests};
+
+    #[test]
+    fn test_digest() {
+        assert_eq!(digest("test".as_bytes()).unwrap(), "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08");
+        let digests = Digests::parse(Path::new("d"), "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08  in/a b.csv\n\n").unwrap();
+        assert_eq!(digests.listed("9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"), Some("in/a b.csv"));
+        assert!(Digests::parse(Path::new("d"), "9f86d0  in.csv\n").is_err());
+        assert!(Digests::parse(Path::new("d"), "in.csv\n").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("txn-digest-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.csv"), dir.join("b.csv"));
+        std::fs::write(&a, "type,client,tx,amount\ndeposit,1,1,1\n").unwrap();
+        std::fs::write(&b, "type,client,tx,amount\ndeposit,1,1,1\n").unwrap();
+        let mut options = DigestOptions { path: Some(dir.join("digests.txt")), duplicates: DuplicatePolicy::Refuse };
+
+        let unlisted = check(&options, &a).unwrap().unwrap();
+        // not listed until it's been processed
+        assert!(check(&options, &a).unwrap().is_some());
+        unlisted.list(&a).unwrap();
+        // the same content under another name
+        let refused = check(&options, &b).err().unwrap();
+        options.duplicates = DuplicatePolicy::Warn;
+        let warned = check(&options, &b).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(refused.contains(&a.display().to_string()), "{}", refused);
+        assert!(warned.is_none());
+    }
+}
@@ -0,0 +1,385 @@
+//! `Engine`: accounts & the config they're run under, for embedding txn as a library rather than running the cli.
+//!
+//! `apply_batch` applies a group of transactions atomically, i.e. the legs of one operation: they're applied in
+//! order against an undo log, and if any is declined the log is replayed backwards so the batch leaves no trace.
+//! every transaction only touches its own client's balance & lock and its own id's log & dispute entries, so
+//! that's all the log has to hold.
+//!
+//! `savepoint` does the same across calls, for speculative runs (what would this file do to these accounts?): while
+//! any savepoint is held every transaction is logged, `rollback_to` undoes back to one and `release` lets it go.
+//! savepoints nest as they do in sql: rolling back to or releasing one drops every savepoint taken after it.
+//!
+//! `process` runs every transaction from a `TxnSource` through `execute`, as the cli runs a file, and counts what it
+//! did; `process_csv` does so for csv from any reader (a socket, a decompressor, a buffer).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+
+use crate::config::{AmountLocale, AmountPolicy, Config, ErrorPolicy};
+use crate::source::{CsvSource, TxnSource};
+use crate::{Accounts, Amount, Balance, ClientId, execute_with, Rejection, Settling, Txn, TxnId};
+
+#[derive(Default)]
+pub struct Engine {
+    accounts: Accounts,
+    config: Config,
+    /// held savepoints, oldest first, as (id, position in the undo log)
+    savepoints: Vec<(u64, usize)>,
+    next_savepoint: u64,
+    /// kept only while a savepoint is held
+    undo: Vec<Undo>
+}
+
+/// a point `Engine::rollback_to` can return to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Savepoint(u64);
+
+/// why a batch was rolled back
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BatchError {
+    /// position of the declined transaction in the batch
+    pub index: usize,
+    pub rejection: Rejection
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "transaction {} of the batch rejected: {}", self.index, self.rejection)
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// how `Engine::process_csv` reads its input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// whether the first row is a header, as in a csv the cli reads. true by default
+    pub has_headers: bool,
+    pub delimiter: u8,
+    /// the hand-written row parser, as `fast_parse` in the config
+    pub fast_parse: bool,
+    /// the separators amounts may have, as `amount_locale` in the config. plain decimals by default
+    pub amount_locale: AmountLocale,
+    /// what's done with amounts in scientific notation or past the precision, as `amount_policy` in the config
+    pub amount_policy: Option<AmountPolicy>
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions { has_headers: true, delimiter: b',', fast_parse: false, amount_locale: AmountLocale::Strict,
+                     amount_policy: None }
+    }
+}
+
+/// what `Engine::process_csv` did with its input
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProcessReport {
+    pub applied: u64,
+    pub rejected: BTreeMap<Rejection, u64>,
+    /// input that wasn't a transaction, as (row, why), rows counted from 1 after any header. under
+    /// `on_error = "abort"` the first one ends the run, as does a fatal one under either policy
+    pub malformatted: Vec<(u64, String)>
+}
+
+impl ProcessReport {
+    pub fn rejected_total(&self) -> u64 {
+        self.rejected.values().sum()
+    }
+}
+
+impl Engine {
+    pub fn new(config: Config) -> Self {
+        Engine { config, ..Engine::default() }
+    }
+
+    pub fn accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    pub fn into_accounts(self) -> Accounts {
+        self.accounts
+    }
+
+    pub fn execute(&mut self, txn: Txn) -> Result<(), Rejection> {
+        if !self.savepoints.is_empty() {
+            self.undo.push(Undo::capture(&self.accounts, &txn));
+        }
+        execute_with(&mut self.accounts, txn, &self.config)
+    }
+
+    /// executes every row of csv read from `reader`, with the engine's precision & error policy
+    pub fn process_csv<R: Read>(&mut self, reader: R, options: CsvOptions) -> ProcessReport {
+        let precision = self.config.amount_precision();
+        self.process(&mut CsvSource::new(reader, options, precision))
+    }
+
+    /// executes every transaction the source gives, with the engine's error policy
+    pub fn process<S: TxnSource + ?Sized>(&mut self, source: &mut S) -> ProcessReport {
+        let mut report = ProcessReport::default();
+        let mut row = 0;
+        while let Some(txn) = source.next_txn() {
+            let error = match txn {
+                Ok(txn) => {
+                    row += 1;
+                    match self.execute(txn) {
+                        Ok(()) => report.applied += 1,
+                        Err(r) => *report.rejected.entry(r).or_insert(0) += 1
+                    }
+                    continue;
+                },
+                Err(e) => e
+            };
+            row += error.row_count();
+            let fatal = error.is_fatal();
+            report.malformatted.push((row, error.to_string()));
+            if fatal || self.config.on_error == ErrorPolicy::Abort {
+                break;
+            }
+        }
+        report
+    }
+
+    /// applies every transaction, or if one is declined, none of them
+    pub fn apply_batch(&mut self, txns: &[Txn]) -> Result<(), BatchError> {
+        let mut undo = Vec::with_capacity(txns.len());
+        for (index, txn) in txns.iter().enumerate() {
+            undo.push(Undo::capture(&self.accounts, txn));
+            if let Err(rejection) = execute_with(&mut self.accounts, txn.clone(), &self.config) {
+                for step in undo.into_iter().rev() {
+                    step.restore(&mut self.accounts);
+                }
+                return Err(BatchError { index, rejection });
+            }
+        }
+        if !self.savepoints.is_empty() {
+            self.undo.extend(undo);
+        }
+        Ok(())
+    }
+
+    pub fn savepoint(&mut self) -> Savepoint {
+        let id = self.next_savepoint;
+        self.next_savepoint += 1;
+        self.savepoints.push((id, self.undo.len()));
+        Savepoint(id)
+    }
+
+    /// undoes every transaction since the savepoint, which stays held; the savepoints taken after it are dropped
+    ///
+    /// # Panics
+    /// if the savepoint was released or dropped
+    pub fn rollback_to(&mut self, savepoint: Savepoint) {
+        let held = self.held(savepoint);
+        let position = self.savepoints[held].1;
+        self.savepoints.truncate(held + 1);
+        for step in self.undo.drain(position..).rev() {
+            step.restore(&mut self.accounts);
+        }
+    }
+
+    /// keeps every transaction since the savepoint, dropping it & the savepoints taken after it
+    ///
+    /// # Panics
+    /// if the savepoint was released or dropped
+    pub fn release(&mut self, savepoint: Savepoint) {
+        let held = self.held(savepoint);
+        self.savepoints.truncate(held);
+        if self.savepoints.is_empty() {
+            self.undo = Vec::new();
+        }
+    }
+
+    fn held(&self, savepoint: Savepoint) -> usize {
+        self.savepoints.iter()
+            .position(|(id, _)| *id == savepoint.0)
+            .expect("savepoint no longer held")
+    }
+}
+
+/// what a transaction may change, as it was before
+struct Undo {
+    client: ClientId,
+    tx: TxnId,
+    /// None if the transaction opened the account
+    prior: Option<Prior>
+}
+
+struct Prior {
+    balance: Balance,
+    locked: bool,
+    funded: bool,
+    settling: Vec<Settling>,
+    reserve: Amount,
+    chargeback_loss: Amount,
+    logged: Option<Txn>,
+    disputed: bool,
+    resolved: bool,
+    charged_back: bool
+}
+
+impl Undo {
+    fn capture(accounts: &Accounts, txn: &Txn) -> Self {
+        let prior = accounts.get(&txn.client).map(|account| Prior {
+            balance: account.balance,
+            locked: account.locked,
+            funded: account.funded,
+            settling: account.settling.clone(),
+            reserve: account.reserve,
+            chargeback_loss: account.chargeback_loss,
+            logged: account.txnlog.get(&txn.tx).cloned(),
+            disputed: account.disputes.contains(&txn.tx),
+            resolved: account.resolved.contains(&txn.tx),
+            charged_back: account.charged_back.contains(&txn.tx)
+        });
+        Undo { client: txn.client, tx: txn.tx, prior }
+    }
+
+    fn restore(self, accounts: &mut Accounts) {
+        let prior = match self.prior {
+            Some(p) => p,
+            None => {
+                accounts.remove(&self.client);
+                return;
+            }
+        };
+        let account = accounts.get_mut(&self.client).expect("accounts are never removed outside of a rollback");
+        account.balance = prior.balance;
+        account.locked = prior.locked;
+        account.funded = prior.funded;
+        account.settling = prior.settling;
+        account.reserve = prior.reserve;
+        account.chargeback_loss = prior.chargeback_loss;
+        match prior.logged {
+            Some(txn) => account.txnlog.insert(txn),
+            None => account.txnlog.remove(&self.tx)
+        };
+        for (set, was) in [(&mut account.disputes, prior.disputed), (&mut account.resolved, prior.resolved),
+                           (&mut account.charged_back, prior.charged_back)] {
+            if was {
+                set.insert(self.tx);
+            } else {
+                set.remove(&self.tx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::{AmountLocale, Config, ErrorPolicy};
+    use crate::{get_balance, Rejection, Txn};
+
+    use super::{BatchError, CsvOptions, Engine};
+
+    fn seeded() -> Engine {
+        let mut engine = Engine::new(Config::default());
+        engine.execute(Txn::deposit(1, 1, dec!(10))).unwrap();
+        engine.execute(Txn::deposit(2, 2, dec!(5))).unwrap();
+        engine.execute(Txn::dispute(2, 2)).unwrap();
+        engine
+    }
+
+    #[test]
+    fn test_batch_applied() {
+        let mut engine = seeded();
+        // a transfer: out of one account, into another
+        engine.apply_batch(&[Txn::withdrawal(1, 3, dec!(4)), Txn::deposit(3, 4, dec!(4))]).unwrap();
+        assert_eq!(get_balance(engine.accounts(), 1).available, dec!(6));
+        assert_eq!(get_balance(engine.accounts(), 3).available, dec!(4));
+    }
+
+    #[test]
+    fn test_batch_rolled_back() {
+        let mut engine = seeded();
+        let before = seeded().into_accounts();
+        let batch = [
+            Txn::deposit(1, 3, dec!(1)),
+            Txn::dispute(1, 1),
+            Txn::resolve(2, 2),
+            Txn::deposit(4, 5, dec!(2)),
+            Txn::chargeback(1, 1),
+            Txn::withdrawal(1, 6, dec!(100))
+        ];
+        assert_eq!(engine.apply_batch(&batch), Err(BatchError { index: 5, rejection: Rejection::Locked }));
+        // balances, locks, logs, disputes & opened accounts all undone
+        assert_eq!(engine.accounts(), &before);
+
+        let overdraw = [Txn::deposit(1, 3, dec!(1)), Txn::withdrawal(1, 3, dec!(100))];
+        assert_eq!(engine.apply_batch(&overdraw), Err(BatchError { index: 1, rejection: Rejection::InsufficientFunds }));
+        // the withdrawal reused the deposit's id, the deposit's log entry is gone with it
+        assert_eq!(engine.accounts(), &before);
+    }
+
+    #[test]
+    fn test_savepoints() {
+        let mut engine = seeded();
+        let before = seeded().into_accounts();
+        let outer = engine.savepoint();
+        engine.execute(Txn::withdrawal(1, 3, dec!(4))).unwrap();
+        engine.execute(Txn::resolve(2, 2)).unwrap();
+        let mut after_withdrawal = seeded();
+        after_withdrawal.execute(Txn::withdrawal(1, 3, dec!(4))).unwrap();
+        after_withdrawal.execute(Txn::resolve(2, 2)).unwrap();
+        let after_withdrawal = after_withdrawal.into_accounts();
+
+        let inner = engine.savepoint();
+        engine.apply_batch(&[Txn::dispute(1, 1), Txn::chargeback(1, 1), Txn::deposit(5, 4, dec!(1))]).unwrap();
+        assert_eq!(engine.execute(Txn::deposit(1, 5, dec!(1))), Err(Rejection::Locked));
+        engine.rollback_to(inner);
+        assert_eq!(engine.accounts(), &after_withdrawal);
+
+        // still held, so it can be rolled back to again
+        engine.execute(Txn::deposit(6, 6, dec!(1))).unwrap();
+        engine.rollback_to(inner);
+        assert_eq!(engine.accounts(), &after_withdrawal);
+
+        engine.rollback_to(outer);
+        assert_eq!(engine.accounts(), &before);
+
+        // released, the transactions since stay
+        engine.execute(Txn::deposit(1, 7, dec!(1))).unwrap();
+        engine.release(outer);
+        assert_eq!(get_balance(engine.accounts(), 1).available, dec!(11));
+    }
+
+    #[test]
+    fn test_process_csv() {
+        let csv = "type,client,tx,amount\ndeposit,1,1,10\nwithdrawal,1,2,50\ndeposit,1\ndispute,1,1,\n";
+        for fast_parse in [false, true] {
+            let mut engine = Engine::new(Config { on_error: ErrorPolicy::Skip, ..Config::default() });
+            let report = engine.process_csv(csv.as_bytes(), CsvOptions { fast_parse, ..CsvOptions::default() });
+            assert_eq!(report.applied, 2);
+            assert_eq!(report.rejected_total(), 1);
+            assert_eq!(report.rejected[&Rejection::InsufficientFunds], 1);
+            assert_eq!(report.malformatted.iter().map(|(row, _)| *row).collect::<Vec<_>>(), vec![3]);
+            assert_eq!(get_balance(engine.accounts(), 1).held, dec!(10));
+        }
+
+        // aborts at the malformatted row, with the rows before it applied
+        let mut engine = Engine::new(Config::default());
+        let options = CsvOptions { has_headers: false, delimiter: b';', ..CsvOptions::default() };
+        let report = engine.process_csv("deposit;1;1;10\nbogus;1;2;1\ndeposit;1;3;1\n".as_bytes(), options.clone());
+        assert_eq!((report.applied, report.malformatted.len()), (1, 1));
+        assert_eq!(get_balance(engine.accounts(), 1).total, dec!(10));
+
+        // decimal commas, as semicolon separated files tend to have
+        let mut engine = Engine::new(Config::default());
+        let options = CsvOptions { amount_locale: AmountLocale::Comma, ..options };
+        let report = engine.process_csv("deposit;1;1;1.234,5\nwithdrawal;1;2;0,5\n".as_bytes(), options);
+        assert_eq!(report.applied, 2);
+        assert_eq!(get_balance(engine.accounts(), 1).total, dec!(1234));
+    }
+
+    #[test]
+    #[should_panic(expected = "savepoint no longer held")]
+    fn test_dropped_savepoint() {
+        let mut engine = seeded();
+        let outer = engine.savepoint();
+        let inner = engine.savepoint();
+        engine.rollback_to(outer);
+        engine.rollback_to(inner);
+    }
+}
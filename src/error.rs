@@ -0,0 +1,225 @@
+//! what a run can end with, by kind, so code embedding the crate can match on why rather than on a message, and the
+//! cli can say precisely what went wrong, and where:
+//! ```text
+//! Error: Malformatted row: line 7: amount '1.2.3' is not a valid decimal
+//! Error: /tmp/in.csv: No such file or directory (os error 2)
+//! ```
+//! `TxnCliError` is a run's error: bad usage or config, malformatted input (on the line it was read from, where
+//! there's one), io, the storage balances go to, or the engine's own `EngineError`. each kind has its exit code.
+//! the internals still pass `Box<dyn Error>` about, and `From` recovers the kind at the boundary.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::engine::BatchError;
+use crate::pipeline::RowError;
+use crate::source::SourceError;
+use crate::Rejection;
+
+#[derive(Debug)]
+pub enum TxnCliError {
+    /// the command line or config: unknown options & keys, bad values, options that don't go together
+    Validation(String),
+    /// input that isn't transactions, under `on_error = "abort"` or when the input can't be read past it. `what`
+    /// names the unit, "row", "line", "batch"..., and `line` the line of the input it's on, where known
+    Parse { what: String, line: Option<u64>, detail: String },
+    /// reading input or writing output, and the file, where it's one
+    Io { path: Option<PathBuf>, source: io::Error },
+    /// a store balances are written to: parquet, sqlite or postgres
+    Storage(Box<dyn Error>),
+    Engine(EngineError),
+    /// anything else, from a dependency without a kind of its own
+    Other(Box<dyn Error>)
+}
+
+/// the engine's errors: those its api declines transactions with, and the checks a run's balances have to pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    Rejected(Rejection),
+    Batch(BatchError),
+    /// balances failed the invariant check, so none were written
+    Invariant(String),
+    /// the estimated memory went past `limits.max_memory`
+    Memory(String)
+}
+
+impl TxnCliError {
+    /// malformatted `what`, the line taken from `detail` where it knows it
+    pub(crate) fn parse(what: &str, detail: &RowError) -> Self {
+        TxnCliError::Parse { what: what.to_string(), line: line_of(&**detail), detail: detail.to_string() }
+    }
+
+    pub(crate) fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        TxnCliError::Io { path: Some(path.into()), source }
+    }
+
+    /// kept as it is if it has a kind already, i.e. the io error of a file a sink writes to
+    #[cfg(any(feature = "parquet", feature = "sqlite", feature = "postgres"))]
+    pub(crate) fn storage(e: impl Into<Box<dyn Error>>) -> Self {
+        match TxnCliError::from(e.into()) {
+            TxnCliError::Other(e) => TxnCliError::Storage(e),
+            e => e
+        }
+    }
+
+    /// the process exit code it ends a run with
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            TxnCliError::Parse { .. } => crate::exit::MALFORMATTED,
+            TxnCliError::Engine(EngineError::Invariant(_)) => crate::exit::INVARIANT,
+            _ => crate::exit::ERROR
+        }
+    }
+}
+
+impl fmt::Display for TxnCliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxnCliError::Validation(what) => f.write_str(what),
+            TxnCliError::Parse { what, detail, .. } => write!(f, "Malformatted {}: {}", what, detail),
+            TxnCliError::Io { path: Some(path), source } => write!(f, "{}: {}", path.display(), source),
+            TxnCliError::Io { path: None, source } => write!(f, "{}", source),
+            TxnCliError::Storage(e) => write!(f, "{}", e),
+            TxnCliError::Engine(e) => write!(f, "{}", e),
+            TxnCliError::Other(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl Error for TxnCliError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TxnCliError::Io { source, .. } => Some(source),
+            TxnCliError::Storage(e) => e.source(),
+            TxnCliError::Engine(e) => Some(e),
+            TxnCliError::Other(e) => e.source(),
+            TxnCliError::Validation(_) | TxnCliError::Parse { .. } => None
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::Rejected(rejection) => write!(f, "Rejected: {}", rejection),
+            EngineError::Batch(e) => write!(f, "{}", e),
+            EngineError::Invariant(detail) => write!(f, "Invariant violated: {}", detail),
+            EngineError::Memory(detail) => f.write_str(detail)
+        }
+    }
+}
+
+impl Error for EngineError {}
+
+impl From<EngineError> for TxnCliError {
+    fn from(e: EngineError) -> Self {
+        TxnCliError::Engine(e)
+    }
+}
+
+impl From<Rejection> for EngineError {
+    fn from(rejection: Rejection) -> Self {
+        EngineError::Rejected(rejection)
+    }
+}
+
+impl From<BatchError> for EngineError {
+    fn from(e: BatchError) -> Self {
+        EngineError::Batch(e)
+    }
+}
+
+impl From<io::Error> for TxnCliError {
+    fn from(source: io::Error) -> Self {
+        TxnCliError::Io { path: None, source }
+    }
+}
+
+/// a message without a kind, from the internals that only have one
+impl From<String> for TxnCliError {
+    fn from(e: String) -> Self {
+        TxnCliError::Other(e.into())
+    }
+}
+
+impl From<Box<dyn Error>> for TxnCliError {
+    fn from(e: Box<dyn Error>) -> Self {
+        let e = match e.downcast::<TxnCliError>() {
+            Ok(e) => return *e,
+            Err(e) => e
+        };
+        let e = match e.downcast::<EngineError>() {
+            Ok(e) => return TxnCliError::Engine(*e),
+            Err(e) => e
+        };
+        match e.downcast::<io::Error>() {
+            Ok(e) => TxnCliError::Io { path: None, source: *e },
+            Err(e) => TxnCliError::Other(e)
+        }
+    }
+}
+
+/// a row's error and the line it's on, as `on_line` has it
+#[derive(Debug)]
+pub(crate) struct OnLine {
+    pub(crate) line: u64,
+    pub(crate) detail: RowError
+}
+
+impl fmt::Display for OnLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.detail)
+    }
+}
+
+impl Error for OnLine {}
+
+/// the line of input an error's about, where it says
+pub(crate) fn line_of(e: &(dyn Error + 'static)) -> Option<u64> {
+    if let Some(e) = e.downcast_ref::<OnLine>() {
+        return Some(e.line);
+    }
+    if let Some(e) = e.downcast_ref::<SourceError>() {
+        return e.line();
+    }
+    e.downcast_ref::<csv::Error>().and_then(csv::Error::position).map(csv::Position::line)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::pipeline::RowError;
+    use crate::{EngineError, Rejection, TxnCliError};
+
+    use super::OnLine;
+
+    #[test]
+    fn test_kinds_recovered() {
+        let boxed: Box<dyn Error> = Box::new(EngineError::Invariant("client 1 has negative held funds".into()));
+        let e = TxnCliError::from(boxed);
+        assert!(matches!(e, TxnCliError::Engine(EngineError::Invariant(_))));
+        assert_eq!(e.to_string(), "Invariant violated: client 1 has negative held funds");
+        assert_eq!(e.exit_code(), 4);
+
+        let boxed: Box<dyn Error> = Box::new(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        assert!(matches!(TxnCliError::from(boxed), TxnCliError::Io { path: None, .. }));
+        let boxed: Box<dyn Error> = "unknown key".into();
+        assert_eq!(TxnCliError::from(boxed).exit_code(), 1);
+        assert_eq!(TxnCliError::from(EngineError::from(Rejection::Locked)).to_string(), "Rejected: account locked");
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let detail: RowError = Box::new(OnLine { line: 7, detail: "amount '1.2.3' is not a valid decimal".into() });
+        let e = TxnCliError::parse("row", &detail);
+        assert!(matches!(e, TxnCliError::Parse { line: Some(7), .. }));
+        assert_eq!(e.to_string(), "Malformatted row: line 7: amount '1.2.3' is not a valid decimal");
+        assert_eq!(e.exit_code(), 3);
+
+        let detail: RowError = "bogus".into();
+        assert!(matches!(TxnCliError::parse("row", &detail), TxnCliError::Parse { line: None, .. }));
+    }
+}
@@ -0,0 +1,466 @@
+//! the engine as events: a transaction is checked against its account, and what it does is expressed as domain
+//! events (`FundsDeposited`, `FundsHeld`, `AccountLocked`...) which are the only way an account's state changes.
+//! run through an `EventLog`, every event raised is kept in order under the sequence number of the transaction
+//! that raised it, so state can be rebuilt by replaying the log, up to any point, or projected some other way.
+//!
+//! a log is persisted as csv (`seq,client,event,tx,type,amount,kind,name,currency`), amounts as decimal strings, never floats.
+//! running without a log records nothing, the events are applied and dropped.
+
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::{Account, AccountKind, Accounts, Amount, Balance, ClientId, Currency, execute_recorded, Rejection, Settling, Txn, TxnId, TxnType};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Event {
+    /// a transaction was seen for a client with no account
+    AccountOpened,
+    /// a merchant or escrow account, as opened
+    AccountClassified(AccountKind),
+    /// who the client is, from the clients file
+    AccountRegistered { name: Option<String>, currency: Option<Currency> },
+    /// a deposit or withdrawal, kept so it can be disputed
+    TransactionLogged(Txn),
+    FundsDeposited { tx: TxnId, amount: Amount },
+    FundsWithdrawn { tx: TxnId, amount: Amount },
+    /// a withdrawal under a settlement delay, available to held until it settles
+    WithdrawalHeld { tx: TxnId, amount: Amount },
+    /// a held withdrawal paid out, leaving held & total
+    WithdrawalSettled { tx: TxnId, amount: Amount },
+    /// a dispute, available to held
+    FundsHeld { tx: TxnId, amount: Amount },
+    /// a resolve, held back to available
+    FundsReleased { tx: TxnId, amount: Amount },
+    FundsChargedBack { tx: TxnId, amount: Amount },
+    /// a merchant's share of a deposit, available to held
+    ReserveHeld { tx: TxnId, amount: Amount },
+    /// a merchant's chargeback covered by its reserve: the disputed funds go back to available, the reserve leaves
+    ReserveChargedBack { tx: TxnId, amount: Amount },
+    AccountLocked,
+    /// an admin's correction, credited to available or debited from it when negative, see admin.rs
+    BalanceAdjusted { amount: Amount },
+    /// an admin's erasure of who the client is: its name & transactions, see admin.rs
+    AccountForgotten,
+    /// an old transaction dropped from the log under `[retention]`, no longer to be disputed
+    TransactionPruned { tx: TxnId }
+}
+
+/// an event, who it happened to, and the transaction that raised it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Entry {
+    /// the transaction's position among those run through the log, from 1
+    pub seq: u64,
+    pub client: ClientId,
+    pub event: Event
+}
+
+/// where the events raised by a transaction go, once applied
+pub(crate) trait Sink {
+    fn record(&mut self, client: ClientId, event: Event);
+}
+
+/// running without a log
+impl Sink for () {
+    fn record(&mut self, _client: ClientId, _event: Event) {}
+}
+
+impl Account {
+    /// applies the event, or if it would overflow a balance, leaves the account as it was
+    pub(crate) fn apply(&mut self, event: &Event) -> Result<(), Rejection> {
+        let balance = &mut self.balance;
+        match event {
+            Event::AccountOpened => {},
+            Event::AccountClassified(kind) => {
+                self.kind = *kind;
+            },
+            Event::AccountRegistered { name, currency } => {
+                self.name = name.clone();
+                self.currency = *currency;
+            },
+            Event::TransactionLogged(txn) => {
+                self.txnlog.insert(txn.clone());
+            },
+            Event::FundsDeposited { amount, .. } => {
+                let available = balance.available.checked_add(*amount).ok_or(Rejection::Overflow)?;
+                let total = balance.total.checked_add(*amount).ok_or(Rejection::Overflow)?;
+                balance.available = available;
+                balance.total = total;
+                self.funded = true;
+            },
+            Event::FundsWithdrawn { amount, .. } => {
+                let available = balance.available.checked_sub(*amount).ok_or(Rejection::Overflow)?;
+                let total = balance.total.checked_sub(*amount).ok_or(Rejection::Overflow)?;
+                balance.available = available;
+                balance.total = total;
+            },
+            Event::WithdrawalHeld { tx, amount } => {
+                hold(balance, *amount)?;
+                let since = self.logged();
+                self.settling.push(Settling { tx: *tx, amount: *amount, since });
+            },
+            Event::WithdrawalSettled { tx, amount } => {
+                let held = balance.held.checked_sub(*amount).ok_or(Rejection::Overflow)?;
+                let total = balance.total.checked_sub(*amount).ok_or(Rejection::Overflow)?;
+                balance.held = held;
+                balance.total = total;
+                self.settling.retain(|s| s.tx != *tx);
+            },
+            Event::FundsHeld { tx, amount } => {
+                hold(balance, *amount)?;
+                self.disputes.insert(*tx);
+            },
+            Event::FundsReleased { tx, amount } => {
+                let release = Amount::ZERO.checked_sub(*amount).ok_or(Rejection::Overflow)?;
+                hold(balance, release)?;
+                self.disputes.remove(tx);
+                self.resolved.insert(*tx);
+            },
+            Event::FundsChargedBack { tx, amount } => {
+                let held = balance.held.checked_sub(*amount).ok_or(Rejection::Overflow)?;
+                let total = balance.total.checked_sub(*amount).ok_or(Rejection::Overflow)?;
+                let loss = self.chargeback_loss.checked_add(*amount).ok_or(Rejection::Overflow)?;
+                balance.held = held;
+                balance.total = total;
+                self.chargeback_loss = loss;
+                self.disputes.remove(tx);
+                self.resolved.remove(tx);
+                self.charged_back.insert(*tx);
+            },
+            Event::ReserveHeld { amount, .. } => {
+                let reserve = self.reserve.checked_add(*amount).ok_or(Rejection::Overflow)?;
+                hold(balance, *amount)?;
+                self.reserve = reserve;
+            },
+            Event::ReserveChargedBack { tx, amount } => {
+                let reserve = self.reserve.checked_sub(*amount).ok_or(Rejection::Overflow)?;
+                // the dispute's hold is released, and the reserve's leaves held & total
+                let total = balance.total.checked_sub(*amount).ok_or(Rejection::Overflow)?;
+                let available = balance.available.checked_add(*amount).ok_or(Rejection::Overflow)?;
+                let held = balance.held.checked_sub(*amount).and_then(|h| h.checked_sub(*amount)).ok_or(Rejection::Overflow)?;
+                let loss = self.chargeback_loss.checked_add(*amount).ok_or(Rejection::Overflow)?;
+                *balance = Balance { available, held, total };
+                self.reserve = reserve;
+                self.chargeback_loss = loss;
+                self.disputes.remove(tx);
+                self.resolved.remove(tx);
+                self.charged_back.insert(*tx);
+            },
+            Event::AccountLocked => {
+                self.locked = true;
+            },
+            Event::BalanceAdjusted { amount } => {
+                let available = balance.available.checked_add(*amount).ok_or(Rejection::Overflow)?;
+                if available < Amount::ZERO {
+                    return Err(Rejection::InsufficientFunds);
+                }
+                let total = balance.total.checked_add(*amount).ok_or(Rejection::Overflow)?;
+                let adjusted = self.adjusted.checked_add(*amount).ok_or(Rejection::Overflow)?;
+                balance.available = available;
+                balance.total = total;
+                self.adjusted = adjusted;
+            },
+            Event::AccountForgotten => {
+                self.name = None;
+                self.txnlog.clear();
+                self.resolved.clear();
+                self.charged_back.clear();
+                self.pruned_txs.clear();
+                self.forgotten = true;
+            },
+            Event::TransactionPruned { tx } => {
+                self.txnlog.remove(tx);
+                self.resolved.remove(tx);
+                self.charged_back.remove(tx);
+                self.pruned += 1;
+                self.pruned_txs.insert(*tx);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// moves `amount` from available to held, or back when negative. balances are only touched if both sides fit
+fn hold(balance: &mut Balance, amount: Amount) -> Result<(), Rejection> {
+    let available = balance.available.checked_sub(amount).ok_or(Rejection::Overflow)?;
+    let held = balance.held.checked_add(amount).ok_or(Rejection::Overflow)?;
+    balance.available = available;
+    balance.held = held;
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct EventLog {
+    entries: Vec<Entry>,
+    /// transactions run through the log
+    seq: u64
+}
+
+impl Sink for EventLog {
+    fn record(&mut self, client: ClientId, event: Event) {
+        self.entries.push(Entry { seq: self.seq, client, event });
+    }
+}
+
+impl EventLog {
+    /// as `execute_with`, keeping the events the transaction raised
+    pub fn execute(&mut self, accounts: &mut Accounts, txn: Txn, config: &Config) -> Result<(), Rejection> {
+        self.seq += 1;
+        execute_recorded(accounts, txn, config, self)
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// rebuilds the accounts from the events
+    pub fn replay(&self) -> Result<Accounts, String> {
+        self.replay_until(u64::MAX)
+    }
+
+    /// rebuilds the accounts as they stood after transaction `seq`
+    pub fn replay_until(&self, seq: u64) -> Result<Accounts, String> {
+        let mut accounts = Accounts::default();
+        for entry in self.entries.iter().take_while(|e| e.seq <= seq) {
+            accounts.entry(entry.client).or_default().apply(&entry.event)
+                .map_err(|e| format!("event of transaction {} doesn't apply to client {}: {}", entry.seq, entry.client, e))?;
+        }
+        Ok(accounts)
+    }
+
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), csv::Error> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for entry in &self.entries {
+            writer.serialize(Record::from(entry))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_csv<R: Read>(reader: R) -> Result<Self, String> {
+        let mut log = EventLog::default();
+        for (line, record) in csv::Reader::from_reader(reader).deserialize::<Record>().enumerate() {
+            // 1 for the header, 1 for counting from 1
+            let entry = record.map_err(|e| e.to_string())?
+                .into_entry()
+                .map_err(|e| format!("event log line {}: {}", line + 2, e))?;
+            log.seq = entry.seq;
+            log.entries.push(entry);
+        }
+        Ok(log)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    AccountOpened,
+    AccountClassified,
+    AccountRegistered,
+    TransactionLogged,
+    FundsDeposited,
+    FundsWithdrawn,
+    WithdrawalHeld,
+    WithdrawalSettled,
+    FundsHeld,
+    FundsReleased,
+    FundsChargedBack,
+    ReserveHeld,
+    ReserveChargedBack,
+    AccountLocked,
+    BalanceAdjusted,
+    AccountForgotten,
+    TransactionPruned
+}
+
+/// an entry as persisted
+#[derive(Serialize, Deserialize)]
+struct Record {
+    seq: u64,
+    client: ClientId,
+    event: Kind,
+    tx: Option<TxnId>,
+    #[serde(rename = "type")]
+    txntype: Option<TxnType>,
+    amount: Option<String>,
+    /// missing from logs written before kinds & metadata were kept
+    #[serde(default)]
+    kind: Option<AccountKind>,
+    #[serde(default)]
+    name: Option<String>,
+    /// a registered account's, or a logged transaction's
+    #[serde(default)]
+    currency: Option<Currency>
+}
+
+impl From<&Entry> for Record {
+    fn from(entry: &Entry) -> Self {
+        let (event, tx, txntype, amount) = match &entry.event {
+            Event::AccountOpened => (Kind::AccountOpened, None, None, None),
+            Event::AccountClassified(_) => (Kind::AccountClassified, None, None, None),
+            Event::AccountRegistered { .. } => (Kind::AccountRegistered, None, None, None),
+            Event::TransactionLogged(txn) => (Kind::TransactionLogged, Some(txn.tx), Some(txn.txntype.clone()), txn.amount),
+            Event::FundsDeposited { tx, amount } => (Kind::FundsDeposited, Some(*tx), None, Some(*amount)),
+            Event::FundsWithdrawn { tx, amount } => (Kind::FundsWithdrawn, Some(*tx), None, Some(*amount)),
+            Event::WithdrawalHeld { tx, amount } => (Kind::WithdrawalHeld, Some(*tx), None, Some(*amount)),
+            Event::WithdrawalSettled { tx, amount } => (Kind::WithdrawalSettled, Some(*tx), None, Some(*amount)),
+            Event::FundsHeld { tx, amount } => (Kind::FundsHeld, Some(*tx), None, Some(*amount)),
+            Event::FundsReleased { tx, amount } => (Kind::FundsReleased, Some(*tx), None, Some(*amount)),
+            Event::FundsChargedBack { tx, amount } => (Kind::FundsChargedBack, Some(*tx), None, Some(*amount)),
+            Event::ReserveHeld { tx, amount } => (Kind::ReserveHeld, Some(*tx), None, Some(*amount)),
+            Event::ReserveChargedBack { tx, amount } => (Kind::ReserveChargedBack, Some(*tx), None, Some(*amount)),
+            Event::AccountLocked => (Kind::AccountLocked, None, None, None),
+            Event::BalanceAdjusted { amount } => (Kind::BalanceAdjusted, None, None, Some(*amount)),
+            Event::AccountForgotten => (Kind::AccountForgotten, None, None, None),
+            Event::TransactionPruned { tx } => (Kind::TransactionPruned, Some(*tx), None, None)
+        };
+        let (kind, name, currency) = match &entry.event {
+            Event::AccountClassified(kind) => (Some(*kind), None, None),
+            Event::AccountRegistered { name, currency } => (None, name.clone(), *currency),
+            Event::TransactionLogged(txn) => (None, None, txn.currency),
+            _ => (None, None, None)
+        };
+        Record { seq: entry.seq, client: entry.client, event, tx, txntype, amount: amount.map(|a| a.to_string()), kind, name, currency }
+    }
+}
+
+impl Record {
+    fn into_entry(self) -> Result<Entry, String> {
+        let kind = self.event;
+        let tx = || self.tx.ok_or_else(|| format!("{:?} without a tx", kind));
+        // a log written by a build with a wider amount type may not fit this one
+        let amount = match &self.amount {
+            Some(s) => Some(Amount::read(s).ok_or_else(|| format!("invalid amount '{}'", s))?),
+            None => None
+        };
+        let required = || amount.ok_or_else(|| format!("{:?} without an amount", kind));
+        let event = match kind {
+            Kind::AccountOpened => Event::AccountOpened,
+            Kind::AccountClassified => Event::AccountClassified(self.kind.ok_or_else(|| format!("{:?} without a kind", kind))?),
+            Kind::AccountRegistered => Event::AccountRegistered { name: self.name.clone(), currency: self.currency },
+            Kind::TransactionLogged => {
+                let txntype = self.txntype.clone().ok_or_else(|| format!("{:?} without a type", kind))?;
+                Event::TransactionLogged(Txn { currency: self.currency, ..Txn::new(txntype, self.client, tx()?, amount) })
+            },
+            Kind::FundsDeposited => Event::FundsDeposited { tx: tx()?, amount: required()? },
+            Kind::FundsWithdrawn => Event::FundsWithdrawn { tx: tx()?, amount: required()? },
+            Kind::WithdrawalHeld => Event::WithdrawalHeld { tx: tx()?, amount: required()? },
+            Kind::WithdrawalSettled => Event::WithdrawalSettled { tx: tx()?, amount: required()? },
+            Kind::FundsHeld => Event::FundsHeld { tx: tx()?, amount: required()? },
+            Kind::FundsReleased => Event::FundsReleased { tx: tx()?, amount: required()? },
+            Kind::FundsChargedBack => Event::FundsChargedBack { tx: tx()?, amount: required()? },
+            Kind::ReserveHeld => Event::ReserveHeld { tx: tx()?, amount: required()? },
+            Kind::ReserveChargedBack => Event::ReserveChargedBack { tx: tx()?, amount: required()? },
+            Kind::AccountLocked => Event::AccountLocked,
+            Kind::BalanceAdjusted => Event::BalanceAdjusted { amount: required()? },
+            Kind::AccountForgotten => Event::AccountForgotten,
+            Kind::TransactionPruned => Event::TransactionPruned { tx: tx()? }
+        };
+        Ok(Entry { seq: self.seq, client: self.client, event })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::{AccountKind, Accounts, amount, ClientId, execute_with, get_balance, Txn, TxnId};
+
+    use super::{Event, EventLog};
+
+    fn txns() -> Vec<Txn> {
+        vec![
+            Txn::deposit(1, 1, dec!(10)),
+            Txn::withdrawal(1, 2, dec!(3.5)),
+            // declined, but logged
+            Txn::withdrawal(1, 3, dec!(100)),
+            Txn::deposit(2, 4, dec!(2)),
+            Txn::dispute(2, 4),
+            Txn::resolve(2, 4),
+            Txn::dispute(1, 1),
+            Txn::chargeback(1, 1),
+            // declined before the account is opened
+            Txn::deposit(1, 5, dec!(1)),
+            // declined, there being no account to dispute anything of
+            Txn::dispute(3, 99)
+        ]
+    }
+
+    fn logged() -> (EventLog, Accounts) {
+        let (mut log, mut accounts) = (EventLog::default(), Accounts::default());
+        for txn in txns() {
+            let _ = log.execute(&mut accounts, txn, &Config::default());
+        }
+        (log, accounts)
+    }
+
+    #[test]
+    fn test_replay() {
+        let (log, accounts) = logged();
+        let mut expected = Accounts::default();
+        for txn in txns() {
+            let _ = execute_with(&mut expected, txn, &Config::default());
+        }
+        assert_eq!(accounts, expected);
+        assert_eq!(log.replay().unwrap(), expected);
+        assert!(!expected.contains_key(&ClientId(3)));
+
+        // as the accounts were just before the dispute of 1
+        let earlier = log.replay_until(6).unwrap();
+        assert_eq!(get_balance(&earlier, 1).available, dec!(6.5));
+        assert_eq!(get_balance(&earlier, 2).held, dec!(0));
+        assert!(!earlier.contains_key(&ClientId(3)));
+
+        let charged_back: Vec<&Event> = log.entries().iter().filter(|e| e.seq == 8).map(|e| &e.event).collect();
+        assert_eq!(charged_back, vec![&Event::FundsChargedBack { tx: TxnId(1), amount: amount(dec!(10)) }, &Event::AccountLocked]);
+    }
+
+    #[test]
+    fn test_settlement_replay() {
+        let mut config = Config::default();
+        config.settlement.delay = 1;
+        let (mut log, mut accounts) = (EventLog::default(), Accounts::default());
+        for txn in [Txn::deposit(1, 1, dec!(5)), Txn::withdrawal(1, 2, dec!(2)), Txn::deposit(1, 3, dec!(1)), Txn::withdrawal(1, 4, dec!(1))] {
+            log.execute(&mut accounts, txn, &config).unwrap();
+        }
+        assert_eq!(log.entries().iter().filter(|e| matches!(e.event, Event::WithdrawalSettled { .. })).count(), 1);
+        let mut csv = Vec::new();
+        log.write_csv(&mut csv).unwrap();
+        let replayed = EventLog::read_csv(csv.as_slice()).unwrap().replay().unwrap();
+        assert_eq!(replayed, accounts);
+        assert_eq!(get_balance(&replayed, 1).held, dec!(1));
+    }
+
+    #[test]
+    fn test_kinds_replay() {
+        let mut config = Config::default();
+        config.kinds.merchants = "1".parse().unwrap();
+        config.kinds.reserve = dec!(0.5);
+        let (mut log, mut accounts) = (EventLog::default(), Accounts::default());
+        for txn in [Txn::deposit(1, 1, dec!(4)), Txn::deposit(1, 2, dec!(1)), Txn::dispute(1, 2), Txn::chargeback(1, 2)] {
+            log.execute(&mut accounts, txn, &config).unwrap();
+        }
+        assert_eq!(log.entries()[1].event, Event::AccountClassified(AccountKind::Merchant));
+        let mut csv = Vec::new();
+        log.write_csv(&mut csv).unwrap();
+        let replayed = EventLog::read_csv(csv.as_slice()).unwrap().replay().unwrap();
+        assert_eq!(replayed, accounts);
+        assert_eq!(get_balance(&replayed, 1).held, dec!(1.5));
+    }
+
+    #[test]
+    fn test_csv_roundtrip() {
+        let (log, accounts) = logged();
+        let mut csv = Vec::new();
+        log.write_csv(&mut csv).unwrap();
+        let read = EventLog::read_csv(csv.as_slice()).unwrap();
+        // trailing transactions that raised nothing leave no trace of their seq
+        assert_eq!(read.entries(), log.entries());
+        assert_eq!(read.replay().unwrap(), accounts);
+
+        let bogus = "seq,client,event,tx,type,amount\n1,1,account_opened,,,\n2,1,funds_held,1,,\n";
+        assert_eq!(EventLog::read_csv(bogus.as_bytes()), Err("event log line 3: FundsHeld without an amount".to_string()));
+    }
+}
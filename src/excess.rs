@@ -0,0 +1,93 @@
+//! `--amount-policy`: what's done with a csv amount in scientific notation (`1e10`), or with more decimal places than
+//! `precision` (`1.23456`, a 30 digit value), rather than leaving it to the parsers to round silently:
+//! - `round`: read, rounded to `precision` as it would have been (by `rounding`), and logged
+//! - `truncate`: read, its excess digits cut off, and logged
+//! - `reject`: malformatted, so the error policy decides
+//!
+//! every decision is logged on stderr, on the row's line: `line 7: amount '1.23456' truncated to 1.2345`. a
+//! scientific amount is read as its plain decimal under `round` & `truncate`, and then held to the precision as any
+//! other. unset, nothing's logged and amounts are read as they always were: serde rounds, and takes scientific
+//! notation where `--fast-parse` doesn't.
+
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use crate::config::AmountPolicy;
+use crate::Precision;
+
+/// the amount as the parsers are to read it, and what was decided about it if anything was, or why it's rejected
+pub(crate) fn apply(amount: Cow<'_, str>, policy: Option<AmountPolicy>, precision: Precision)
+                    -> Result<(Cow<'_, str>, Option<String>), String> {
+    let policy = match policy {
+        Some(policy) => policy,
+        None => return Ok((amount, None))
+    };
+    let scientific = amount.contains(['e', 'E']);
+    let plain = match scientific {
+        true if policy == AmountPolicy::Reject => return Err(format!("amount '{}' is in scientific notation", amount)),
+        true => Cow::Owned(Decimal::from_scientific(&amount)
+            .map_err(|_| format!("amount '{}' is not a valid decimal", amount))?.normalize().to_string()),
+        false => amount.clone()
+    };
+    let decimals = plain.split_once('.').map_or(0, |(_, fraction)| fraction.len());
+    let excess = decimals.saturating_sub(precision.places as usize);
+    if excess == 0 {
+        let decision = scientific.then(|| format!("amount '{}' read as {}", amount, plain));
+        return Ok((plain, decision));
+    }
+    match policy {
+        AmountPolicy::Reject => Err(format!("amount '{}' has more than {} decimal places", amount, precision.places)),
+        AmountPolicy::Truncate => {
+            // the point goes too when no decimals are kept
+            let kept = plain.len() - excess - usize::from(precision.places == 0);
+            let truncated = plain[..kept].to_string();
+            let decision = format!("amount '{}' truncated to {}", amount, truncated);
+            Ok((Cow::Owned(truncated), Some(decision)))
+        },
+        AmountPolicy::Round => {
+            let rounded = precision.round(Decimal::from_str(&plain)
+                .map_err(|_| format!("amount '{}' is not a valid decimal", amount))?);
+            let decision = format!("amount '{}' rounded to {}", amount, rounded);
+            Ok((Cow::Owned(rounded.to_string()), Some(decision)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::config::AmountPolicy;
+
+    use super::apply;
+
+    fn applied(amount: &str, policy: AmountPolicy, precision: u32) -> Result<(String, Option<String>), String> {
+        apply(Cow::Borrowed(amount), Some(policy), precision.into()).map(|(a, decision)| (a.into_owned(), decision))
+    }
+
+    #[test]
+    fn test_policies() {
+        let long = "0.123456789012345678901234567890";
+        assert_eq!(applied("1.23456", AmountPolicy::Round, 4), Ok(("1.2346".into(), Some("amount '1.23456' rounded to 1.2346".into()))));
+        assert_eq!(applied("1.23456", AmountPolicy::Truncate, 4), Ok(("1.2345".into(), Some("amount '1.23456' truncated to 1.2345".into()))));
+        assert_eq!(applied("-1.99", AmountPolicy::Truncate, 0), Ok(("-1".into(), Some("amount '-1.99' truncated to -1".into()))));
+        assert_eq!(applied(long, AmountPolicy::Truncate, 4).unwrap().0, "0.1234");
+        assert_eq!(applied("1.23456", AmountPolicy::Reject, 4), Err("amount '1.23456' has more than 4 decimal places".into()));
+        assert_eq!(applied(long, AmountPolicy::Reject, 28).unwrap_err(), format!("amount '{}' has more than 28 decimal places", long));
+        // within the precision, nothing to decide
+        assert_eq!(applied("1.2", AmountPolicy::Reject, 4), Ok(("1.2".into(), None)));
+        let half_up = crate::Precision::new(4, crate::Rounding::HalfUp);
+        assert_eq!(apply(Cow::Borrowed("0.00125"), Some(AmountPolicy::Round), half_up).unwrap().0, "0.0013");
+        assert_eq!(apply(Cow::Borrowed("1.23456"), None, 4.into()).unwrap(), (Cow::Borrowed("1.23456"), None));
+    }
+
+    #[test]
+    fn test_scientific() {
+        assert_eq!(applied("1e10", AmountPolicy::Round, 4), Ok(("10000000000".into(), Some("amount '1e10' read as 10000000000".into()))));
+        assert_eq!(applied("1.5E-5", AmountPolicy::Truncate, 4).unwrap().0, "0.0000");
+        assert_eq!(applied("1e10", AmountPolicy::Reject, 4), Err("amount '1e10' is in scientific notation".into()));
+        assert!(applied("1e", AmountPolicy::Round, 4).is_err());
+    }
+}
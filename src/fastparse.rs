@@ -0,0 +1,193 @@
+//! `--fast-parse`: reads transactions straight out of a `csv::ByteRecord`, skipping utf-8 validation, serde and the
+//! float round trip serde-float makes amounts go through. fields are parsed in place, nothing is allocated per row.
+//!
+//! amounts are plain decimals (`-1.5`, `.25`, `3.`), read exactly rather than via f64, so a value on a rounding tie
+//! can come out a unit apart from the serde path. exponents, `inf` & `nan` aren't accepted. one with no more places
+//! than the precision, nor 4, is read straight into an amount (see amount.rs), others into a `Decimal` to round.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use rust_decimal::Decimal;
+
+use crate::{Amount, ClientId, ClientRepr, Precision, Txn, TxnId, TxnRepr, TxnType};
+
+/// rust_decimal's maximum scale
+const MAX_SCALE: u32 = 28;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct FieldError {
+    field: &'static str,
+    reason: &'static str,
+    /// the field's, when it's the value that's wrong, for `reason` to say it isn't a valid one of
+    value: Option<String>,
+    /// the record's, when it was read from a file
+    line: Option<u64>
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        match &self.value {
+            Some(value) => write!(f, "{} '{}' is not a valid {}", self.field, value, self.reason),
+            None => write!(f, "{}: {}", self.field, self.reason)
+        }
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+fn error(field: &'static str, reason: &'static str) -> FieldError {
+    FieldError { field, reason, value: None, line: None }
+}
+
+/// `value` isn't a valid `kind`
+fn invalid(field: &'static str, kind: &'static str, value: &[u8]) -> FieldError {
+    FieldError { value: Some(String::from_utf8_lossy(value).into_owned()), ..error(field, kind) }
+}
+
+/// `type,client,tx,amount`, each field trimmed of ascii whitespace
+pub(crate) fn parse_record(record: &csv::ByteRecord, precision: Precision) -> Result<Txn, FieldError> {
+    parse_fields(record, precision).map_err(|e| FieldError { line: record.position().map(csv::Position::line), ..e })
+}
+
+fn parse_fields(record: &csv::ByteRecord, precision: Precision) -> Result<Txn, FieldError> {
+    if record.len() != 4 {
+        return Err(error("record", "expected 4 fields"));
+    }
+    let txntype = match record[0].trim_ascii() {
+        b"deposit" => TxnType::Deposit,
+        b"withdrawal" => TxnType::Withdrawal,
+        b"dispute" => TxnType::Dispute,
+        b"resolve" => TxnType::Resolve,
+        b"chargeback" => TxnType::Chargeback,
+        field => return Err(invalid("type", "transaction type", field))
+    };
+    let (client, tx) = (record[1].trim_ascii(), record[2].trim_ascii());
+    let client: ClientRepr = parse_uint(client).ok_or_else(|| invalid("client", "client id", client))?;
+    let tx: TxnRepr = parse_uint(tx).ok_or_else(|| invalid("tx", "transaction id", tx))?;
+    let builder = Txn::builder(txntype, ClientId(client), TxnId(tx)).precision(precision);
+    let builder = match record[3].trim_ascii() {
+        b"" => builder,
+        field => match Amount::parse(field, precision.places) {
+            Some(amount) => builder.parsed_amount(amount),
+            None => builder.amount(parse_decimal(field).ok_or_else(|| invalid("amount", "decimal", field))?)
+        }
+    };
+    builder.build().map_err(|e| error("amount", e.as_str()))
+}
+
+/// unsigned decimal integer that fits a `T`, with an optional leading `+`
+fn parse_uint<T: TryFrom<u64>>(field: &[u8]) -> Option<T> {
+    let digits = field.strip_prefix(b"+").unwrap_or(field);
+    if digits.is_empty() {
+        return None;
+    }
+    let mut n: u64 = 0;
+    for &b in digits {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        n = n.checked_mul(10)?.checked_add((b - b'0') as u64)?;
+    }
+    T::try_from(n).ok()
+}
+
+/// `[+-]digits[.digits]`, where either side of the point may be empty but not both
+fn parse_decimal(field: &[u8]) -> Option<Decimal> {
+    let (negative, rest) = match field.split_first() {
+        Some((b'-', rest)) => (true, rest),
+        Some((b'+', rest)) => (false, rest),
+        _ => (false, field)
+    };
+
+    let mut mantissa: i128 = 0;
+    let mut scale: Option<u32> = None;
+    let mut digits = 0;
+    for &b in rest {
+        match b {
+            b'.' if scale.is_none() => scale = Some(0),
+            b'0'..=b'9' => {
+                mantissa = mantissa.checked_mul(10)?.checked_add((b - b'0') as i128)?;
+                digits += 1;
+                if let Some(s) = scale.as_mut() {
+                    *s += 1;
+                }
+            },
+            _ => return None
+        }
+    }
+    let scale = scale.unwrap_or(0);
+    if digits == 0 || scale > MAX_SCALE {
+        return None;
+    }
+    if negative {
+        mantissa = -mantissa;
+    }
+    Decimal::try_from_i128_with_scale(mantissa, scale).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use crate::{ClientRepr, CURRENCY_PRECISION, deserialize_record, Txn, TxnRepr};
+
+    use super::{parse_decimal, parse_record};
+
+    fn parse(fields: &[&str]) -> Result<Txn, super::FieldError> {
+        parse_record(&csv::ByteRecord::from(fields.to_vec()), CURRENCY_PRECISION.into())
+    }
+
+    #[test]
+    fn test_matches_serde_path() {
+        let rows: &[&[&str]] = &[
+            &["deposit", "1", "2", "3.1459265"],
+            &["  withdrawal", " 1", " 2 ", "3   "],
+            &["dispute", "1", "2", ""],
+            &["resolve", "65535", "4294967295", ""],
+            &["chargeback", "+1", "2", ""],
+            &["deposit", "1", "2", "0.0001"],
+        ];
+        for row in rows {
+            let expected = deserialize_record(&mut csv::StringRecord::from(row.to_vec()), CURRENCY_PRECISION).unwrap();
+            assert_eq!(parse(row).unwrap(), expected, "{:?}", row);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse(&["refund", "1", "2", "1.0"]).is_err());
+        assert!(parse(&["Deposit", "1", "2", "1.0"]).is_err());
+        let (client, tx) = ((ClientRepr::MAX as u128 + 1).to_string(), (TxnRepr::MAX as u128 + 1).to_string());
+        assert!(parse(&["deposit", &client, "2", "1.0"]).is_err());
+        assert!(parse(&["deposit", "-1", "2", "1.0"]).is_err());
+        assert!(parse(&["deposit", "1", &tx, "1.0"]).is_err());
+        assert!(parse(&["deposit", "1", "", "1.0"]).is_err());
+        assert!(parse(&["deposit", "1", "2", "1.0.0"]).is_err());
+        assert!(parse(&["dispute", "1", "2"]).is_err());
+        assert_eq!(parse(&["dispute", "1", "2", "1.0"]).unwrap_err().to_string(), "amount: amount not allowed");
+        assert_eq!(parse(&["withdrawal", "1", "2", ""]).unwrap_err().to_string(), "amount: amount required");
+        // as the serde path words them
+        for row in [["deposit", "1", "2", "12,50"], ["refund", "1", "2", "1"], ["deposit", "-1", "2", "1"]] {
+            let serde = deserialize_record(&mut csv::StringRecord::from(row.to_vec()), CURRENCY_PRECISION).unwrap_err();
+            assert_eq!(parse(&row).unwrap_err().to_string(), serde.to_string());
+        }
+        assert_eq!(parse(&["deposit", "1", "2", "12,50"]).unwrap_err().to_string(), "amount '12,50' is not a valid decimal");
+    }
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!(parse_decimal(b"-1.5"), Some(dec!(-1.5)));
+        assert_eq!(parse_decimal(b".25"), Some(dec!(0.25)));
+        assert_eq!(parse_decimal(b"3."), Some(dec!(3)));
+        assert_eq!(parse_decimal(b"79228162514264337593543950335"), Some(Decimal::MAX));
+        assert_eq!(parse_decimal(b"79228162514264337593543950336"), None);
+        assert_eq!(parse_decimal(b"."), None);
+        assert_eq!(parse_decimal(b"-"), None);
+        assert_eq!(parse_decimal(b"1e5"), None);
+    }
+}
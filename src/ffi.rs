@@ -0,0 +1,217 @@
+//! a C ABI over `Engine` (`--features ffi`), for embedding the engine in C & C++ settlement systems. the header,
+//! include/txn.h, is generated from this file: `cbindgen --config cbindgen.toml -o include/txn.h src/ffi.rs`.
+//! build the library with `cargo rustc --release --lib --features ffi --crate-type cdylib` (or `staticlib`).
+//!
+//! amounts cross the boundary as int64 ten-thousandths (`15000` is 1.5), as the fixed-point build holds them. every
+//! call returns `TXN_OK`, a `TXN_REJECTED_*` code for a transaction the engine declined, or a negative `TXN_ERR_*`.
+//! no panic unwinds into C: a call that panics returns `TXN_ERR_PANIC` (or null), and the engine is best freed.
+//! an engine isn't thread safe, calls on one must not overlap.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+use crate::config::Config;
+use crate::{Amount, ClientId, CURRENCY_PRECISION, Engine, Rejection, Txn, TxnId, TxnType};
+
+pub const TXN_DEPOSIT: u8 = 0;
+pub const TXN_WITHDRAWAL: u8 = 1;
+pub const TXN_DISPUTE: u8 = 2;
+pub const TXN_RESOLVE: u8 = 3;
+pub const TXN_CHARGEBACK: u8 = 4;
+
+pub const TXN_OK: i32 = 0;
+pub const TXN_REJECTED_LOCKED: i32 = 1;
+pub const TXN_REJECTED_OVER_LIMIT: i32 = 2;
+pub const TXN_REJECTED_OVERFLOW: i32 = 3;
+pub const TXN_REJECTED_INSUFFICIENT_FUNDS: i32 = 4;
+pub const TXN_REJECTED_UNKNOWN_TXN: i32 = 5;
+pub const TXN_REJECTED_ALREADY_DISPUTED: i32 = 6;
+pub const TXN_REJECTED_WITHDRAWAL_DISPUTE: i32 = 7;
+pub const TXN_REJECTED_NOT_DISPUTED: i32 = 8;
+pub const TXN_REJECTED_LATE: i32 = 9;
+pub const TXN_REJECTED_CHARGED_BACK: i32 = 10;
+pub const TXN_REJECTED_REDISPUTE: i32 = 11;
+pub const TXN_REJECTED_WRONG_CLIENT: i32 = 12;
+pub const TXN_REJECTED_ESCROW_DISPUTE: i32 = 13;
+pub const TXN_REJECTED_CURRENCY_MISMATCH: i32 = 14;
+pub const TXN_REJECTED_DECLINED: i32 = 15;
+pub const TXN_REJECTED_PRUNED: i32 = 16;
+pub const TXN_REJECTED_DUPLICATE: i32 = 17;
+/// a null engine or out pointer
+pub const TXN_ERR_NULL: i32 = -1;
+/// an unknown transaction type
+pub const TXN_ERR_INVALID_TYPE: i32 = -2;
+/// a negative amount, or an amount or balance being read that doesn't fit
+pub const TXN_ERR_OUT_OF_RANGE: i32 = -3;
+/// the client has no account
+pub const TXN_ERR_NO_ACCOUNT: i32 = -4;
+pub const TXN_ERR_PANIC: i32 = -5;
+
+/// ten-thousandths per unit
+const SCALE: u32 = CURRENCY_PRECISION;
+
+/// an engine with the default config, opaque to C
+pub struct TxnEngine(Engine);
+
+/// a client's balance, amounts in ten-thousandths
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct TxnBalance {
+    pub available: i64,
+    pub held: i64,
+    pub total: i64,
+    pub locked: bool
+}
+
+fn rejection_code(rejection: Rejection) -> i32 {
+    match rejection {
+        Rejection::Locked => TXN_REJECTED_LOCKED,
+        Rejection::OverLimit => TXN_REJECTED_OVER_LIMIT,
+        Rejection::Overflow => TXN_REJECTED_OVERFLOW,
+        Rejection::InsufficientFunds => TXN_REJECTED_INSUFFICIENT_FUNDS,
+        Rejection::UnknownTxn => TXN_REJECTED_UNKNOWN_TXN,
+        Rejection::AlreadyDisputed => TXN_REJECTED_ALREADY_DISPUTED,
+        Rejection::WithdrawalDispute => TXN_REJECTED_WITHDRAWAL_DISPUTE,
+        Rejection::NotDisputed => TXN_REJECTED_NOT_DISPUTED,
+        Rejection::Late => TXN_REJECTED_LATE,
+        Rejection::ChargedBack => TXN_REJECTED_CHARGED_BACK,
+        Rejection::Redispute => TXN_REJECTED_REDISPUTE,
+        Rejection::WrongClient => TXN_REJECTED_WRONG_CLIENT,
+        Rejection::EscrowDispute => TXN_REJECTED_ESCROW_DISPUTE,
+        Rejection::CurrencyMismatch => TXN_REJECTED_CURRENCY_MISMATCH,
+        Rejection::Declined => TXN_REJECTED_DECLINED,
+        Rejection::Pruned => TXN_REJECTED_PRUNED,
+        Rejection::Duplicate => TXN_REJECTED_DUPLICATE
+    }
+}
+
+fn scaled(amount: Amount) -> Option<i64> {
+    amount.to_decimal().checked_mul(Decimal::from(10i64.pow(SCALE)))?.to_i64()
+}
+
+/// None for a panic
+fn guard<T, F: FnOnce() -> T>(f: F) -> Option<T> {
+    catch_unwind(AssertUnwindSafe(f)).ok()
+}
+
+/// a new engine, to be freed with `txn_engine_free`. null if it couldn't be made
+#[no_mangle]
+pub extern "C" fn txn_engine_new() -> *mut TxnEngine {
+    guard(|| Box::into_raw(Box::new(TxnEngine(Engine::new(Config::default())))))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// executes a transaction of `txntype` (`TXN_DEPOSIT`...). `amount`, in ten-thousandths, is only read for deposits
+/// & withdrawals
+///
+/// # Safety
+/// `engine` must be null or from `txn_engine_new`, not yet freed
+#[no_mangle]
+pub unsafe extern "C" fn txn_engine_execute(engine: *mut TxnEngine, txntype: u8, client: u16, tx: u32, amount: i64)
+                                            -> i32 {
+    let engine = match engine.as_mut() {
+        Some(e) => e,
+        None => return TXN_ERR_NULL
+    };
+    let txntype = match txntype {
+        TXN_DEPOSIT => TxnType::Deposit,
+        TXN_WITHDRAWAL => TxnType::Withdrawal,
+        TXN_DISPUTE => TxnType::Dispute,
+        TXN_RESOLVE => TxnType::Resolve,
+        TXN_CHARGEBACK => TxnType::Chargeback,
+        _ => return TXN_ERR_INVALID_TYPE
+    };
+    let amount = match txntype {
+        TxnType::Deposit | TxnType::Withdrawal => Some(Decimal::new(amount, SCALE)),
+        _ => None
+    };
+    guard(|| {
+        let txn = match Txn::builder(txntype, ClientId::from(client), TxnId::from(tx)).amount(amount).precision(SCALE).build() {
+            Ok(t) => t,
+            Err(_) => return TXN_ERR_OUT_OF_RANGE
+        };
+        match engine.0.execute(txn) {
+            Ok(()) => TXN_OK,
+            Err(r) => rejection_code(r)
+        }
+    }).unwrap_or(TXN_ERR_PANIC)
+}
+
+/// writes the client's balance to `out`
+///
+/// # Safety
+/// `engine` must be null or from `txn_engine_new`, not yet freed, and `out` null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn txn_engine_balance(engine: *const TxnEngine, client: u16, out: *mut TxnBalance) -> i32 {
+    let (engine, out) = match (engine.as_ref(), out.as_mut()) {
+        (Some(e), Some(o)) => (e, o),
+        _ => return TXN_ERR_NULL
+    };
+    guard(|| {
+        let account = match engine.0.accounts().get(&ClientId::from(client)) {
+            Some(a) => a,
+            None => return TXN_ERR_NO_ACCOUNT
+        };
+        let b = account.balance;
+        match (scaled(b.available), scaled(b.held), scaled(b.total)) {
+            (Some(available), Some(held), Some(total)) => {
+                *out = TxnBalance { available, held, total, locked: account.locked };
+                TXN_OK
+            },
+            _ => TXN_ERR_OUT_OF_RANGE
+        }
+    }).unwrap_or(TXN_ERR_PANIC)
+}
+
+/// frees an engine from `txn_engine_new`. null is ignored
+///
+/// # Safety
+/// `engine` must be null or from `txn_engine_new`, and not freed before
+#[no_mangle]
+pub unsafe extern "C" fn txn_engine_free(engine: *mut TxnEngine) {
+    if !engine.is_null() {
+        // dropping the accounts can't panic short of a bug in std, and there'd be nothing to tell C
+        let _ = guard(|| drop(Box::from_raw(engine)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TXN_CHARGEBACK, TXN_DEPOSIT, TXN_DISPUTE, txn_engine_balance, txn_engine_execute, txn_engine_free,
+                txn_engine_new, TXN_ERR_INVALID_TYPE, TXN_ERR_NO_ACCOUNT, TXN_ERR_NULL, TXN_OK,
+                TXN_REJECTED_INSUFFICIENT_FUNDS, TXN_WITHDRAWAL, TxnBalance};
+
+    #[test]
+    fn test_engine() {
+        unsafe {
+            let engine = txn_engine_new();
+            assert_eq!(txn_engine_execute(engine, TXN_DEPOSIT, 1, 1, 25_000), TXN_OK);
+            assert_eq!(txn_engine_execute(engine, TXN_WITHDRAWAL, 1, 2, 50_000), TXN_REJECTED_INSUFFICIENT_FUNDS);
+            // the amount is ignored
+            assert_eq!(txn_engine_execute(engine, TXN_DISPUTE, 1, 1, 7), TXN_OK);
+            assert_eq!(txn_engine_execute(engine, 9, 1, 3, 0), TXN_ERR_INVALID_TYPE);
+
+            let mut balance = TxnBalance::default();
+            assert_eq!(txn_engine_balance(engine, 1, &mut balance), TXN_OK);
+            assert_eq!(balance, TxnBalance { available: 0, held: 25_000, total: 25_000, locked: false });
+            assert_eq!(txn_engine_execute(engine, TXN_CHARGEBACK, 1, 1, 0), TXN_OK);
+            assert_eq!(txn_engine_balance(engine, 1, &mut balance), TXN_OK);
+            assert!(balance.locked);
+            assert_eq!(txn_engine_balance(engine, 2, &mut balance), TXN_ERR_NO_ACCOUNT);
+            txn_engine_free(engine);
+        }
+    }
+
+    #[test]
+    fn test_null() {
+        unsafe {
+            assert_eq!(txn_engine_execute(std::ptr::null_mut(), TXN_DEPOSIT, 1, 1, 1), TXN_ERR_NULL);
+            let engine = txn_engine_new();
+            assert_eq!(txn_engine_balance(engine, 1, std::ptr::null_mut()), TXN_ERR_NULL);
+            txn_engine_free(engine);
+            txn_engine_free(std::ptr::null_mut());
+        }
+    }
+}
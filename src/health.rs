@@ -0,0 +1,140 @@
+//! `--health-listen 127.0.0.1:8080`: liveness & readiness probes for server mode, over plain http.
+//! - `GET /healthz` answers 200 while the server is up, with what it's doing:
+//!   `{"status":"ok","storage":"memory","ingestion_lag_ms":12,"transactions":1500,"last_checkpoint":null}`.
+//!   `ingestion_lag_ms` is the time since the last transaction was executed, null before the first.
+//!   the server doesn't checkpoint (checkpoints are for files), so `last_checkpoint` is always null for now.
+//!   `txnlog` is the pages the transaction logs are kept in (see txnlog.rs):
+//!   `"txnlog":{"pages":1024,"free":12,"bytes":327680}`, free pages being those threads' pools hold for reuse.
+//! - `GET /readyz` answers 200 `{"ready":true}` once the transaction socket is accepting, 503 until then, and while
+//!   the server's a standby not yet promoted (see replica.rs).
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::config::Storage;
+use crate::server::State;
+use crate::txnlog;
+
+/// a probe that hasn't sent its request by now isn't waited on
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    storage: &'static str,
+    ingestion_lag_ms: Option<u64>,
+    transactions: u64,
+    last_checkpoint: Option<String>,
+    txnlog: txnlog::Stats
+}
+
+#[derive(Serialize)]
+struct Ready {
+    ready: bool
+}
+
+/// binds, then answers probes on a thread of its own
+pub(crate) fn serve(address: &str, state: Arc<State>, storage: Storage) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(address).map_err(|e| format!("Error listening on {}: {}", address, e))?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            // probes are small & infrequent, one at a time will do
+            if let Err(e) = stream.and_then(|s| answer(s, &state, storage)) {
+                eprintln!("health check error: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn answer(stream: TcpStream, state: &State, storage: Storage) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // the headers aren't needed, but are read so the client doesn't see its request cut off
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = match request.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", path, _] => Some(path),
+        _ => None
+    };
+    let (status, body) = match path {
+        Some(path) => respond(path, state, storage),
+        None => ("405 Method Not Allowed", String::new())
+    };
+    let mut stream = &stream;
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, body.len(), body)?;
+    stream.flush()
+}
+
+/// the status line & body for a GET of `path`
+fn respond(path: &str, state: &State, storage: Storage) -> (&'static str, String) {
+    match path {
+        "/healthz" => {
+            let health = Health {
+                status: "ok",
+                storage: match storage {
+                    Storage::Memory => "memory"
+                },
+                ingestion_lag_ms: state.last_executed().map(|at| state.clock.since(at).as_millis() as u64),
+                transactions: state.executed.load(Ordering::Relaxed),
+                last_checkpoint: None,
+                txnlog: txnlog::stats()
+            };
+            ("200 OK", serde_json::to_string(&health).unwrap())
+        },
+        "/readyz" => {
+            let ready = state.ready.load(Ordering::Acquire) && !state.standby.load(Ordering::Acquire);
+            let status = if ready { "200 OK" } else { "503 Service Unavailable" };
+            (status, serde_json::to_string(&Ready { ready }).unwrap())
+        },
+        _ => ("404 Not Found", String::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::clock::MockClock;
+    use crate::config::{Config, Storage};
+    use crate::server::{handle, State};
+
+    use super::respond;
+
+    #[test]
+    fn test_respond() {
+        let clock = Arc::new(MockClock::new(UNIX_EPOCH));
+        let state = State::with_clock(Config::default(), clock.clone());
+        assert_eq!(respond("/readyz", &state, Storage::Memory), ("503 Service Unavailable", r#"{"ready":false}"#.to_string()));
+        // the pages are counted over every other test's logs too
+        let mut health: serde_json::Value = serde_json::from_str(&respond("/healthz", &state, Storage::Memory).1).unwrap();
+        let pool = health.as_object_mut().unwrap().remove("txnlog").unwrap();
+        let expected = r#"{"status":"ok","storage":"memory","ingestion_lag_ms":null,"transactions":0,"last_checkpoint":null}"#;
+        assert_eq!(health, serde_json::from_str::<serde_json::Value>(expected).unwrap());
+        assert!(pool["free"].as_u64() <= pool["pages"].as_u64());
+
+        state.ready.store(true, Ordering::Release);
+        clock.advance(Duration::from_millis(40));
+        handle("deposit,1,1,1\nwithdrawal,1,2,5\n".as_bytes(), std::io::sink(), &state).unwrap();
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(respond("/readyz", &state, Storage::Memory).0, "200 OK");
+        let health: serde_json::Value = serde_json::from_str(&respond("/healthz", &state, Storage::Memory).1).unwrap();
+        assert_eq!(health["transactions"], 2);
+        assert_eq!(health["ingestion_lag_ms"], 250);
+
+        assert_eq!(respond("/metrics", &state, Storage::Memory).0, "404 Not Found");
+    }
+}
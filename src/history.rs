@@ -0,0 +1,149 @@
+//! `txn history --client C <file>`: every transaction in a csv input that affects the client, with its outcome and,
+//! for deposits & withdrawals, where their dispute ended up, laid out as a table rather than left to grep:
+//! ```text
+//! row  type        tx  amount  outcome             dispute
+//!   1  deposit      1      10  ok                  charged back
+//!   3  withdrawal   3     100  insufficient funds
+//!   4  dispute      1          ok
+//!   5  chargeback   1          ok
+//! ```
+//! only the client's rows are executed, as `txn query` does. rows are counted from 1, malformatted ones included.
+
+use std::fmt;
+use std::io::Read;
+
+use crate::config::Config;
+use crate::report::Report;
+use crate::{Accounts, ClientId, execute_with, malformatted, Map, read_record, Rejection, Txn, TxnId, TxnType};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Dispute {
+    Disputed,
+    Resolved,
+    ChargedBack
+}
+
+impl fmt::Display for Dispute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Dispute::Disputed => "disputed",
+            Dispute::Resolved => "resolved",
+            Dispute::ChargedBack => "charged back"
+        })
+    }
+}
+
+struct Line {
+    row: u64,
+    txn: Txn,
+    outcome: Result<(), Rejection>
+}
+
+pub(crate) struct History {
+    lines: Vec<Line>,
+    /// the latest applied dispute, resolve or chargeback of each transaction
+    disputes: Map<TxnId, Dispute>
+}
+
+impl History {
+    pub(crate) fn read<R: Read>(reader: R, client: ClientId, config: &Config, report: &mut Report)
+                                -> Result<Self, Box<dyn std::error::Error>> {
+        let mut accounts = Accounts::default();
+        let mut history = History { lines: Vec::new(), disputes: Map::default() };
+        for (row, record) in csv::Reader::from_reader(reader).into_records().enumerate() {
+            let txn = record.map_err(crate::pipeline::RowError::from)
+                .and_then(|mut r| read_record(&mut r, config));
+            let txn = match txn {
+                Ok(t) if t.client == client => t,
+                Ok(_) => continue,
+                Err(e) => {
+                    malformatted(config, report, "row", e)?;
+                    continue;
+                }
+            };
+            let outcome = execute_with(&mut accounts, txn.clone(), config);
+            report.record(outcome);
+            if outcome.is_ok() {
+                let status = match txn.txntype {
+                    TxnType::Dispute => Some(Dispute::Disputed),
+                    TxnType::Resolve => Some(Dispute::Resolved),
+                    TxnType::Chargeback => Some(Dispute::ChargedBack),
+                    TxnType::Deposit | TxnType::Withdrawal => None
+                };
+                if let Some(status) = status {
+                    history.disputes.insert(txn.tx, status);
+                }
+            }
+            history.lines.push(Line { row: row as u64 + 1, txn, outcome });
+        }
+        Ok(history)
+    }
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cells: Vec<[String; 6]> = self.lines.iter().map(|line| {
+            let txn = &line.txn;
+            let dispute = match txn.txntype {
+                TxnType::Deposit | TxnType::Withdrawal => self.disputes.get(&txn.tx).map(|d| d.to_string()),
+                _ => None
+            };
+            [
+                line.row.to_string(),
+                format!("{:?}", txn.txntype).to_lowercase(),
+                txn.tx.to_string(),
+                txn.amount.map(|a| a.to_decimal().normalize().to_string()).unwrap_or_default(),
+                match line.outcome {
+                    Ok(()) => "ok".to_string(),
+                    Err(r) => r.to_string()
+                },
+                dispute.unwrap_or_default()
+            ]
+        }).collect();
+
+        let header = ["row", "type", "tx", "amount", "outcome", "dispute"].map(String::from);
+        let mut widths = [0; 6];
+        for row in std::iter::once(&header).chain(&cells) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in std::iter::once(&header).chain(&cells) {
+            // numbers right aligned, words left
+            let line = format!("{:>w0$}  {:<w1$}  {:>w2$}  {:>w3$}  {:<w4$}  {}", row[0], row[1], row[2], row[3], row[4],
+                               row[5], w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3], w4 = widths[4]);
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, ErrorPolicy};
+    use crate::report::Report;
+    use crate::ClientId;
+
+    use super::History;
+
+    #[test]
+    fn test_history() {
+        let csv = "type,client,tx,amount\ndeposit,9,1,10\ndeposit,2,2,5\nwithdrawal,9,3,100\nbogus\ndeposit,9,4,2.5\n\
+                   dispute,9,1,\ndispute,9,4,\nresolve,9,4,\nchargeback,9,1,\ndispute,2,2,\n";
+        let config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        let mut report = Report::default();
+        let history = History::read(csv.as_bytes(), ClientId(9), &config, &mut report).unwrap();
+
+        assert_eq!(history.to_string(), "\
+row  type        tx  amount  outcome             dispute
+  1  deposit      1      10  ok                  charged back
+  3  withdrawal   3     100  insufficient funds
+  5  deposit      4     2.5  ok                  resolved
+  6  dispute      1          ok
+  7  dispute      4          ok
+  8  resolve      4          ok
+  9  chargeback   1          ok
+");
+        assert_eq!(report.skipped, 1);
+    }
+}
@@ -0,0 +1,68 @@
+//! http(s) url input: `txn process https://example.com/export.csv` streams the response body through the csv reader.
+//! `http.bearer_token` (best set as `TXN_HTTP_BEARER_TOKEN`) is sent as `Authorization: Bearer <token>`.
+//! any non-2xx response is an error.
+
+pub(crate) fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+#[cfg(feature = "http")]
+pub(crate) fn open(url: &str, bearer_token: Option<&str>) -> Result<impl std::io::Read, Box<dyn std::error::Error>> {
+    let mut request = ureq::get(url);
+    if let Some(token) = bearer_token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    match request.call() {
+        Ok(response) => Ok(response.into_body().into_reader()),
+        Err(e) => Err(format!("{}: {}", url, e).into())
+    }
+}
+
+#[cfg(not(feature = "http"))]
+pub(crate) fn open(_url: &str, _bearer_token: Option<&str>) -> Result<std::io::Empty, Box<dyn std::error::Error>> {
+    Err("URL input requires building with the `http` feature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_url;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/export.csv"));
+        assert!(is_url("http://localhost:8080/x"));
+        assert!(!is_url("export.csv"));
+        assert!(!is_url("s3://bucket/key"));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_open() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/export.csv", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                headers.push(line.trim().to_ascii_lowercase());
+            }
+            let body = "type,client,tx,amount\ndeposit,1,1,1.0\n";
+            write!(&stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body).unwrap();
+            headers
+        });
+
+        let mut body = String::new();
+        super::open(&url, Some("secret")).unwrap().read_to_string(&mut body).unwrap();
+        assert_eq!(body, "type,client,tx,amount\ndeposit,1,1,1.0\n");
+        assert!(server.join().unwrap().contains(&"authorization: bearer secret".to_string()));
+    }
+}
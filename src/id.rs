@@ -0,0 +1,121 @@
+//! client & transaction ids. any u16 is a client and any u32 a transaction, the wrappers are there so one can't be
+//! passed for the other, or an amount for either. both (de)serialize, display & parse as the bare number.
+//!
+//! built with `--features wide-ids` both are u64, for upstream ids past those widths. rows, files & checkpoints are
+//! alike either way, only the ids they'll take differ; the c abi keeps u16 & u32 ids.
+
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// what a client id holds
+#[cfg(not(feature = "wide-ids"))]
+pub type ClientRepr = u16;
+#[cfg(feature = "wide-ids")]
+pub type ClientRepr = u64;
+
+/// what a transaction id holds
+#[cfg(not(feature = "wide-ids"))]
+pub type TxnRepr = u32;
+#[cfg(feature = "wide-ids")]
+pub type TxnRepr = u64;
+
+/// arrow's types for the ids, for columns read into them & written from them
+#[cfg(all(any(feature = "arrow", feature = "parquet"), not(feature = "wide-ids")))]
+pub(crate) type ClientArrow = arrow_array::types::UInt16Type;
+#[cfg(all(any(feature = "arrow", feature = "parquet"), feature = "wide-ids"))]
+pub(crate) type ClientArrow = arrow_array::types::UInt64Type;
+#[cfg(all(feature = "arrow", not(feature = "wide-ids")))]
+pub(crate) type TxnArrow = arrow_array::types::UInt32Type;
+#[cfg(all(feature = "arrow", feature = "wide-ids"))]
+pub(crate) type TxnArrow = arrow_array::types::UInt64Type;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(transparent)]
+pub struct ClientId(pub ClientRepr);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(transparent)]
+pub struct TxnId(pub TxnRepr);
+
+macro_rules! id {
+    ($id:ident, $repr:ty) => {
+        impl From<$repr> for $id {
+            fn from(id: $repr) -> Self {
+                $id(id)
+            }
+        }
+
+        impl From<$id> for $repr {
+            fn from(id: $id) -> Self {
+                id.0
+            }
+        }
+
+        impl fmt::Display for $id {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl FromStr for $id {
+            type Err = ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map($id)
+            }
+        }
+    };
+}
+
+id!(ClientId, ClientRepr);
+id!(TxnId, TxnRepr);
+
+impl TxnId {
+    /// the id widened, whatever it holds
+    pub(crate) fn as_u64(self) -> u64 {
+        #[cfg(not(feature = "wide-ids"))]
+        return u64::from(self.0);
+        #[cfg(feature = "wide-ids")]
+        return self.0;
+    }
+}
+
+// the narrow ids still convert, for what's u16 & u32 either way (the c abi, statement ids)
+#[cfg(feature = "wide-ids")]
+impl From<u16> for ClientId {
+    fn from(id: u16) -> Self {
+        ClientId(id.into())
+    }
+}
+
+#[cfg(feature = "wide-ids")]
+impl From<u32> for TxnId {
+    fn from(id: u32) -> Self {
+        TxnId(id.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientId, ClientRepr, TxnId};
+
+    #[test]
+    fn test_id() {
+        assert_eq!("7".parse::<ClientId>(), Ok(ClientId(7)));
+        assert!((ClientRepr::MAX as u128 + 1).to_string().parse::<ClientId>().is_err());
+        assert_eq!(TxnId(70_000).to_string(), "70000");
+        assert_eq!(serde_json::to_string(&ClientId(7)).unwrap(), "7");
+        assert_eq!(serde_json::from_str::<TxnId>("9").unwrap(), TxnId(9));
+    }
+
+    #[cfg(feature = "wide-ids")]
+    #[test]
+    fn test_wide() {
+        assert_eq!("70000".parse::<ClientId>(), Ok(ClientId(70_000)));
+        assert_eq!(serde_json::from_str::<TxnId>("5000000000").unwrap(), TxnId(5_000_000_000));
+        assert_eq!(ClientId::from(7u16), ClientId(7));
+    }
+}
@@ -0,0 +1,339 @@
+//! iso 20022 xml ingestion: customer credit transfer initiation (pain.001) & bank to customer statements (camt.053).
+//!
+//! pain.001 payments are withdrawals from the debtor account, camt.053 booked entries are deposits (CRDT)
+//! or withdrawals (DBIT). the client is the account's `Othr/Id`, which must be a numeric client id - iban-only
+//! accounts can't be mapped onto the engine's ids, and are rejected as such. txn ids are synthesized from the message
+//! references, a transfer's EndToEndId or else its InstrId.
+//!
+//! balances carry no currency, so every amount booked against a client must be in the same one: the account's `Ccy`
+//! if given, otherwise that of the client's first amount. anything else is rejected.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+use rust_decimal::Decimal;
+
+use crate::statement::TxnIds;
+use crate::{ClientId, Precision, Txn, TxnType};
+
+pub fn parse(xml: &str, precision: impl Into<Precision>) -> Result<Vec<Txn>, String> {
+    let precision = precision.into();
+    let document = parse_tree(xml)?;
+    if let Some(initiation) = document.child("CstmrCdtTrfInitn") {
+        parse_pain001(initiation, precision)
+    } else if let Some(statement) = document.child("BkToCstmrStmt") {
+        parse_camt053(statement, precision)
+    } else {
+        Err("expected a pain.001 (CstmrCdtTrfInitn) or camt.053 (BkToCstmrStmt) document".into())
+    }
+}
+
+fn parse_pain001(initiation: &Element, precision: Precision) -> Result<Vec<Txn>, String> {
+    let mut ids = TxnIds::default();
+    let mut currencies = Currencies::default();
+    let mut txns = Vec::new();
+    for payment in initiation.children("PmtInf") {
+        let client = client_id(payment.path(&["DbtrAcct", "Id"]))?;
+        let account_currency = payment.text(&["DbtrAcct", "Ccy"]);
+        for transfer in payment.children("CdtTrfTxInf") {
+            let reference = transfer.text(&["PmtId", "EndToEndId"])
+                .or_else(|| transfer.text(&["PmtId", "InstrId"]))
+                .ok_or("CdtTrfTxInf without EndToEndId or InstrId")?;
+            let amount = transfer.path(&["Amt", "InstdAmt"])
+                .or_else(|| transfer.path(&["Amt", "EqvtAmt", "Amt"]))
+                .ok_or_else(|| format!("{}: missing amount", reference))?;
+            currencies.check(client, reference, account_currency, amount.attr("Ccy"))?;
+            let amount = parse_amount(reference, amount.text.trim())?;
+            txns.push(Txn::builder(TxnType::Withdrawal, client, ids.next(reference)).amount(amount).precision(precision).build()
+                .map_err(|e| format!("{}: {}", reference, e))?);
+        }
+    }
+    Ok(txns)
+}
+
+fn parse_camt053(statement: &Element, precision: Precision) -> Result<Vec<Txn>, String> {
+    let mut ids = TxnIds::default();
+    let mut currencies = Currencies::default();
+    let mut txns = Vec::new();
+    for stmt in statement.children("Stmt") {
+        let client = client_id(stmt.path(&["Acct", "Id"]))?;
+        let account_currency = stmt.text(&["Acct", "Ccy"]);
+        for entry in stmt.children("Ntry") {
+            // status is a plain code pre camt.053.001.08, a `Cd` child after
+            let status = entry.text(&["Sts", "Cd"]).or_else(|| entry.text(&["Sts"]));
+            if status.is_some() && status != Some("BOOK") {
+                continue;
+            }
+
+            let reference = entry.text(&["AcctSvcrRef"])
+                .or_else(|| entry.text(&["NtryRef"]))
+                .or_else(|| entry.text(&["NtryDtls", "TxDtls", "Refs", "EndToEndId"]))
+                .ok_or("Ntry without AcctSvcrRef, NtryRef or EndToEndId")?;
+            let amount = entry.child("Amt").ok_or_else(|| format!("{}: missing Amt", reference))?;
+            currencies.check(client, reference, account_currency, amount.attr("Ccy"))?;
+            let amount = parse_amount(reference, amount.text.trim())?;
+            // the direction of this entry, reversals (`RvslInd`) included: a reversed debit is reported as CRDT
+            let credit = match entry.text(&["CdtDbtInd"]) {
+                Some("CRDT") => true,
+                Some("DBIT") => false,
+                _ => return Err(format!("{}: invalid CdtDbtInd", reference))
+            };
+
+            let txntype = if credit { TxnType::Deposit } else { TxnType::Withdrawal };
+            txns.push(Txn::builder(txntype, client, ids.next(reference)).amount(amount).precision(precision).build()
+                .map_err(|e| format!("{}: {}", reference, e))?);
+        }
+    }
+    Ok(txns)
+}
+
+fn client_id(account: Option<&Element>) -> Result<ClientId, String> {
+    let account = account.ok_or("missing account")?;
+    let id = match (account.text(&["Othr", "Id"]), account.text(&["IBAN"])) {
+        (Some(id), _) => id,
+        (None, Some(iban)) => return Err(format!("iban-only account {}, without an Othr/Id client id", iban)),
+        (None, None) => return Err("missing account id".into())
+    };
+    ClientId::from_str(id).map_err(|_| format!("account '{}' is not a numeric client id", id))
+}
+
+fn parse_amount(reference: &str, amount: &str) -> Result<Decimal, String> {
+    match Decimal::from_str(amount) {
+        Ok(a) if a.is_sign_positive() => Ok(a),
+        _ => Err(format!("{}: invalid amount '{}'", reference, amount))
+    }
+}
+
+/// currency of each client's amounts so far
+#[derive(Default)]
+struct Currencies {
+    seen: HashMap<ClientId, String>
+}
+
+impl Currencies {
+    fn check(&mut self, client: ClientId, reference: &str, account: Option<&str>, amount: Option<&str>) -> Result<(), String> {
+        let amount = amount.ok_or_else(|| format!("{}: amount without Ccy", reference))?;
+        let expected = self.seen.entry(client)
+            .or_insert_with(|| account.unwrap_or(amount).to_string());
+        if amount != expected || account.is_some_and(|a| a != expected) {
+            return Err(format!("{}: {} amount on a {} account", reference, amount, expected));
+        }
+        Ok(())
+    }
+}
+
+/// just enough of a dom for path lookups. namespace prefixes are dropped
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: String,
+    children: Vec<Element>
+}
+
+impl Element {
+    fn new(start: &BytesStart) -> Result<Self, String> {
+        let mut attributes = Vec::new();
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(|e| e.to_string())?;
+            let value = attribute.normalized_value(XmlVersion::Implicit1_0).map_err(|e| e.to_string())?;
+            attributes.push((attribute.key.local_name().into_inner().to_string(), value.into_owned()));
+        }
+        Ok(Element { name: start.local_name().into_inner().to_string(), attributes, ..Default::default() })
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn path(&self, path: &[&str]) -> Option<&Element> {
+        path.iter().try_fold(self, |e, name| e.child(name))
+    }
+
+    fn text(&self, path: &[&str]) -> Option<&str> {
+        let text = self.path(path)?.text.trim();
+        if text.is_empty() { None } else { Some(text) }
+    }
+}
+
+/// returns the document element
+fn parse_tree(xml: &str) -> Result<Element, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut stack = vec![Element::default()];
+    loop {
+        let event = reader.read_event()
+            .map_err(|e| format!("xml error at byte {}: {}", reader.buffer_position(), e))?;
+        match event {
+            Event::Start(e) => stack.push(Element::new(&e)?),
+            Event::Empty(e) => {
+                let element = Element::new(&e)?;
+                stack.last_mut().unwrap().children.push(element);
+            },
+            Event::End(_) => {
+                let element = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Err("unbalanced closing tag".into())
+                }
+            },
+            Event::Text(e) => stack.last_mut().unwrap().text.push_str(&e.xml10_content()),
+            Event::CData(e) => stack.last_mut().unwrap().text.push_str(e.as_ref()),
+            Event::GeneralRef(e) => {
+                let resolved = match e.into_inner().as_ref() {
+                    "amp" => '&',
+                    "lt" => '<',
+                    "gt" => '>',
+                    "quot" => '"',
+                    "apos" => '\'',
+                    r => char_ref(r).ok_or_else(|| format!("unknown entity '&{};'", r))?
+                };
+                stack.last_mut().unwrap().text.push(resolved);
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    match stack.pop() {
+        Some(mut root) if stack.is_empty() && root.children.len() == 1 => Ok(root.children.remove(0)),
+        _ => Err("expected a single document element".into())
+    }
+}
+
+/// `#123` / `#x7B`
+fn char_ref(r: &str) -> Option<char> {
+    let code = match r.strip_prefix("#x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => r.strip_prefix('#')?.parse().ok()?
+    };
+    std::char::from_u32(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use crate::statement::TxnIds;
+    use crate::{ClientId, CURRENCY_PRECISION, Txn, TxnId, TxnType};
+
+    use super::parse;
+
+    fn txn(txntype: TxnType, client: u16, tx: TxnId, amount: Decimal) -> Txn {
+        Txn::builder(txntype, ClientId::from(client), tx).amount(amount).build().unwrap()
+    }
+
+    const PAIN001: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.001.001.09">
+  <CstmrCdtTrfInitn>
+    <GrpHdr><MsgId>MSG1</MsgId><NbOfTxs>2</NbOfTxs></GrpHdr>
+    <PmtInf>
+      <PmtInfId>PMT1</PmtInfId>
+      <DbtrAcct><Id><Othr><Id>42</Id></Othr></Id></DbtrAcct>
+      <CdtTrfTxInf>
+        <PmtId><EndToEndId>E2E-1</EndToEndId></PmtId>
+        <Amt><InstdAmt Ccy="EUR">10.12346</InstdAmt></Amt>
+      </CdtTrfTxInf>
+      <CdtTrfTxInf>
+        <PmtId><EndToEndId>E2E-2 &amp; co</EndToEndId></PmtId>
+        <Amt><InstdAmt Ccy="EUR">2.50</InstdAmt></Amt>
+      </CdtTrfTxInf>
+    </PmtInf>
+  </CstmrCdtTrfInitn>
+</Document>"#;
+
+    const CAMT053: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<camt:Document xmlns:camt="urn:iso:std:iso:20022:tech:xsd:camt.053.001.08">
+  <camt:BkToCstmrStmt>
+    <camt:Stmt>
+      <camt:Acct><camt:Id><camt:Othr><camt:Id>7</camt:Id></camt:Othr></camt:Id></camt:Acct>
+      <camt:Ntry>
+        <camt:NtryRef>N1</camt:NtryRef>
+        <camt:Amt Ccy="EUR">100.00</camt:Amt>
+        <camt:CdtDbtInd>CRDT</camt:CdtDbtInd>
+        <camt:Sts><camt:Cd>BOOK</camt:Cd></camt:Sts>
+      </camt:Ntry>
+      <camt:Ntry>
+        <camt:AcctSvcrRef>A2</camt:AcctSvcrRef>
+        <camt:Amt Ccy="EUR">30.00</camt:Amt>
+        <camt:CdtDbtInd>DBIT</camt:CdtDbtInd>
+        <camt:Sts><camt:Cd>BOOK</camt:Cd></camt:Sts>
+      </camt:Ntry>
+      <camt:Ntry>
+        <camt:NtryRef>N3</camt:NtryRef>
+        <camt:Amt Ccy="EUR">5.00</camt:Amt>
+        <camt:CdtDbtInd>CRDT</camt:CdtDbtInd>
+        <camt:RvslInd>true</camt:RvslInd>
+        <camt:Sts>BOOK</camt:Sts>
+      </camt:Ntry>
+      <camt:Ntry>
+        <camt:NtryRef>N4</camt:NtryRef>
+        <camt:Amt Ccy="EUR">999.00</camt:Amt>
+        <camt:CdtDbtInd>CRDT</camt:CdtDbtInd>
+        <camt:Sts><camt:Cd>PDNG</camt:Cd></camt:Sts>
+      </camt:Ntry>
+    </camt:Stmt>
+  </camt:BkToCstmrStmt>
+</camt:Document>"#;
+
+    #[test]
+    fn test_pain001() {
+        let mut ids = TxnIds::default();
+        assert_eq!(parse(PAIN001, CURRENCY_PRECISION).unwrap(), vec![
+            txn(TxnType::Withdrawal, 42, ids.next("E2E-1"), dec!(10.1235)),
+            txn(TxnType::Withdrawal, 42, ids.next("E2E-2 & co"), dec!(2.50)),
+        ]);
+    }
+
+    #[test]
+    fn test_camt053() {
+        let mut ids = TxnIds::default();
+        assert_eq!(parse(CAMT053, CURRENCY_PRECISION).unwrap(), vec![
+            txn(TxnType::Deposit, 7, ids.next("N1"), dec!(100)),
+            txn(TxnType::Withdrawal, 7, ids.next("A2"), dec!(30)),
+            // reversal of a debit, booked as the credit it's reported as
+            txn(TxnType::Deposit, 7, ids.next("N3"), dec!(5)),
+        ]);
+    }
+
+    #[test]
+    fn test_iban_account_rejected() {
+        let xml = PAIN001.replace("<Othr><Id>42</Id></Othr>", "<IBAN>DE89370400440532013000</IBAN>");
+        assert_eq!(parse(&xml, CURRENCY_PRECISION).unwrap_err(), "iban-only account DE89370400440532013000, without an Othr/Id client id");
+    }
+
+    #[test]
+    fn test_mixed_currencies_rejected() {
+        let usd = CAMT053.replace(r#"<camt:Amt Ccy="EUR">30.00"#, r#"<camt:Amt Ccy="USD">30.00"#);
+        assert!(parse(&usd, CURRENCY_PRECISION).unwrap_err().contains("USD amount on a EUR account"));
+
+        let account = CAMT053.replace("</camt:Id></camt:Acct>", "</camt:Id><camt:Ccy>USD</camt:Ccy></camt:Acct>");
+        assert!(parse(&account, CURRENCY_PRECISION).unwrap_err().contains("EUR amount on a USD account"));
+
+        let missing = PAIN001.replace(r#"<InstdAmt Ccy="EUR">2.50"#, "<InstdAmt>2.50");
+        assert!(parse(&missing, CURRENCY_PRECISION).is_err());
+    }
+
+    #[test]
+    fn test_invalid_documents() {
+        assert!(parse("<Document><Unknown/></Document>", CURRENCY_PRECISION).is_err());
+        assert!(parse("<Document><CstmrCdtTrfInitn>", CURRENCY_PRECISION).is_err());
+        assert!(parse(&PAIN001.replace("2.50", "-2.50"), CURRENCY_PRECISION).is_err());
+        assert!(parse(&CAMT053.replace("DBIT", "XXXX"), CURRENCY_PRECISION).is_err());
+        let unreferenced = PAIN001.replace("<PmtId><EndToEndId>E2E-1</EndToEndId></PmtId>", "<PmtId/>");
+        assert_eq!(parse(&unreferenced, CURRENCY_PRECISION).unwrap_err(), "CdtTrfTxInf without EndToEndId or InstrId");
+        // an InstrId stands in for a missing EndToEndId
+        let instructed = PAIN001.replace("<EndToEndId>E2E-1</EndToEndId>", "<InstrId>I-1</InstrId>");
+        assert_eq!(parse(&instructed, CURRENCY_PRECISION).unwrap().len(), 2);
+    }
+}
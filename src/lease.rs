@@ -0,0 +1,222 @@
+//! `--lease-dir /shared/txn.lease`: a single-writer guard for instances pointed at the same output, checkpoints &
+//! digests. before it applies a transaction, `txn process`, `txn tail` or the server takes the lease in the
+//! directory and holds it until it exits, renewing it every third of `lease.ttl_ms`. an instance finding it held
+//! refuses to start, or with `--wait-for-lease` waits to take it over, as a cold standby. a dry run writes nothing,
+//! and takes no lease.
+//!
+//! a lease is a file per generation, `lease.7`, holding its owner & when it expires:
+//! `{"owner":"host-a:4242","expires_at_ms":1718000010000}`. it's taken by creating the next generation's file,
+//! which only one instance can, once the latest has expired or been given up. so an instance that stalls past its
+//! lease (paused, or cut off from the directory) finds a newer generation when it next renews, and stops at once
+//! with exit code 5 rather than carry on writing beside its successor. expiry goes by each instance's wall clock,
+//! which should agree to well within the ttl. the lease is given up, its file removed, when the run ends.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::config::LeaseOptions;
+use crate::TxnCliError;
+
+const PREFIX: &str = "lease.";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Record {
+    owner: String,
+    expires_at_ms: u64
+}
+
+pub(crate) struct Lease {
+    dir: PathBuf,
+    generation: u64,
+    owner: String,
+    ttl: Duration,
+    expires: SystemTime,
+    released: bool
+}
+
+fn path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("{}{}", PREFIX, generation))
+}
+
+fn unix_ms(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// the latest generation and its record, None if it's being written
+fn latest(dir: &Path) -> io::Result<Option<(u64, Option<Record>)>> {
+    let mut latest = None;
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        if let Some(generation) = name.to_str().and_then(|n| n.strip_prefix(PREFIX)).and_then(|g| g.parse::<u64>().ok()) {
+            latest = latest.max(Some(generation));
+        }
+    }
+    Ok(latest.map(|generation| {
+        let record = std::fs::read(path(dir, generation)).ok().and_then(|r| serde_json::from_slice(&r).ok());
+        (generation, record)
+    }))
+}
+
+impl Lease {
+    /// takes the lease if it's free, Ok(Err(holder)) if another instance holds it
+    fn try_acquire(dir: &Path, ttl: Duration, owner: &str, clock: &dyn Clock) -> io::Result<Result<Lease, String>> {
+        std::fs::create_dir_all(dir)?;
+        let now = clock.now();
+        let generation = match latest(dir)? {
+            None => 1,
+            Some((generation, Some(record))) if record.expires_at_ms <= unix_ms(now) => generation + 1,
+            Some((_, Some(record))) => {
+                return Ok(Err(format!("{}, for another {} ms", record.owner, record.expires_at_ms - unix_ms(now))));
+            },
+            Some((_, None)) => return Ok(Err("an instance taking it".into()))
+        };
+        let lease = Lease { dir: dir.to_path_buf(), generation, owner: owner.to_string(), ttl, expires: now + ttl, released: false };
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(path(dir, generation)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(Err("an instance that took it first".into())),
+            Err(e) => return Err(e)
+        };
+        file.write_all(&lease.record())?;
+        file.sync_all()?;
+        // generations before are spent, whoever held them
+        for spent in 1..generation {
+            let _ = std::fs::remove_file(path(dir, spent));
+        }
+        Ok(Ok(lease))
+    }
+
+    fn record(&self) -> Vec<u8> {
+        serde_json::to_vec(&Record { owner: self.owner.clone(), expires_at_ms: unix_ms(self.expires) }).expect("a record serializes")
+    }
+
+    /// extends the lease by its ttl from now, Err if it's been lost to another instance
+    fn renew(&mut self, clock: &dyn Clock) -> Result<(), String> {
+        if self.released {
+            return Ok(());
+        }
+        let now = clock.now();
+        let renewed = match latest(&self.dir) {
+            Ok(Some((generation, _))) if generation == self.generation => {
+                let expires = now + self.ttl;
+                let temp = self.dir.join(format!("{}{}.{}.tmp", PREFIX, self.generation, std::process::id()));
+                let record = Record { owner: self.owner.clone(), expires_at_ms: unix_ms(expires) };
+                std::fs::write(&temp, serde_json::to_vec(&record).expect("a record serializes"))
+                    .and_then(|()| std::fs::rename(&temp, path(&self.dir, self.generation)))
+                    .map(|()| expires)
+                    .map_err(|e| e.to_string())
+            },
+            Ok(Some((generation, _))) if generation > self.generation => return Err(format!("taken over as generation {}", generation)),
+            Ok(_) => return Err("its file is gone".into()),
+            Err(e) => Err(e.to_string())
+        };
+        match renewed {
+            Ok(expires) => {
+                self.expires = expires;
+                Ok(())
+            },
+            // i.e. the directory's briefly unreachable, tried again until the lease runs out
+            Err(e) if now < self.expires => {
+                eprintln!("lease renewal failed, retrying: {}", e);
+                Ok(())
+            },
+            Err(e) => Err(e)
+        }
+    }
+
+    fn release(&mut self) {
+        self.released = true;
+        let _ = std::fs::remove_file(path(&self.dir, self.generation));
+    }
+}
+
+/// the lease held, renewed on a thread of its own & given up when dropped
+pub(crate) struct Held(Arc<Mutex<Lease>>);
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().release();
+    }
+}
+
+/// takes the lease `options` names, if any, waiting for it under `wait`
+pub(crate) fn hold(options: &LeaseOptions, clock: Arc<dyn Clock>) -> Result<Option<Held>, TxnCliError> {
+    let dir = match &options.dir {
+        Some(dir) => dir,
+        None => return Ok(None)
+    };
+    let ttl = Duration::from_millis(options.ttl_ms);
+    let owner = format!("{}:{}", std::env::var("HOSTNAME").unwrap_or_else(|_| "localhost".into()), std::process::id());
+    let mut waiting = false;
+    let lease = loop {
+        match Lease::try_acquire(dir, ttl, &owner, &*clock).map_err(|e| TxnCliError::io(dir, e))? {
+            Ok(lease) => break lease,
+            Err(holder) if options.wait => {
+                if !waiting {
+                    eprintln!("waiting for the lease {} held by {}", dir.display(), holder);
+                    waiting = true;
+                }
+                clock.sleep(ttl / 3);
+            },
+            Err(holder) => return Err(TxnCliError::Validation(format!("the lease {} is held by {}", dir.display(), holder)))
+        }
+    };
+    let lease = Arc::new(Mutex::new(lease));
+    let renewing = Arc::clone(&lease);
+    std::thread::spawn(move || loop {
+        clock.sleep(ttl / 3);
+        let mut lease = renewing.lock().unwrap();
+        if lease.released {
+            return;
+        }
+        if let Err(e) = lease.renew(&*clock) {
+            // whoever holds it now is applying transactions, this instance mustn't
+            eprintln!("Error: lost the lease {}: {}", lease.dir.display(), e);
+            std::process::exit(crate::exit::FENCED);
+        }
+    });
+    Ok(Some(Held(lease)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::clock::MockClock;
+
+    use super::{latest, Lease};
+
+    #[test]
+    fn test_lease() {
+        let dir = std::env::temp_dir().join(format!("txn-lease-test-{}", std::process::id()));
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1_718_000_000));
+        let ttl = Duration::from_secs(10);
+
+        let mut a = Lease::try_acquire(&dir, ttl, "a", &clock).unwrap().unwrap();
+        assert_eq!(Lease::try_acquire(&dir, ttl, "b", &clock).unwrap().err().unwrap(), "a, for another 10000 ms");
+        // renewed, it's held past its first ttl
+        clock.advance(Duration::from_secs(8));
+        a.renew(&clock).unwrap();
+        clock.advance(Duration::from_secs(8));
+        assert!(Lease::try_acquire(&dir, ttl, "b", &clock).unwrap().is_err());
+
+        // a stalls past its lease, b takes it over, and a finds out when it next renews
+        clock.advance(Duration::from_secs(10));
+        let mut b = Lease::try_acquire(&dir, ttl, "b", &clock).unwrap().unwrap();
+        assert_eq!(b.generation, 2);
+        assert_eq!(a.renew(&clock).unwrap_err(), "taken over as generation 2");
+        b.renew(&clock).unwrap();
+
+        // given up, it's free at once
+        b.release();
+        assert!(latest(&dir).unwrap().is_none());
+        let c = Lease::try_acquire(&dir, ttl, "c", &clock).unwrap().unwrap();
+        assert_eq!(c.generation, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,2415 @@
+use std::collections::hash_map::Entry as MapEntry;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::hash::BuildHasherDefault;
+use std::io::Write;
+use std::path::Path;
+
+use rust_decimal::Decimal;
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Command;
+use crate::config::{Config, DisputePolicy, ErrorPolicy, Limits, LockPolicy, OutputOptions, RetentionOptions, SettlementOptions};
+use crate::event::Sink;
+use crate::report::Report;
+use crate::retention::PrunedTxs;
+use crate::txnlog::TxnLog;
+
+pub use crate::amount::{Amount, OutOfRange, Precision, Rounding};
+pub use crate::builder::{RawRecord, TxnBuilder, TxnError};
+pub use crate::clock::{Clock, MockClock, SystemClock};
+pub use crate::columnar::{Columns, execute_columns};
+pub use crate::concurrent::ConcurrentEngine;
+pub use crate::engine::{BatchError, CsvOptions, Engine, ProcessReport, Savepoint};
+pub use crate::error::{EngineError, TxnCliError};
+pub use crate::event::{Entry, Event, EventLog};
+pub use crate::sink::{AccountRow, AccountSink, Column, CsvSink, JsonSink, write_accounts};
+#[cfg(feature = "parquet")]
+pub use crate::sink::ParquetSink;
+#[cfg(feature = "sqlite")]
+pub use crate::sink::SqliteSink;
+#[cfg(feature = "postgres")]
+pub use crate::sink::PostgresSink;
+pub use crate::source::{CsvSource, Generator, JsonSource, SourceError, TxnSource};
+pub use crate::id::{ClientId, ClientRepr, TxnId, TxnRepr};
+pub use crate::registry::Currency;
+
+mod actor;
+mod admin;
+mod aging;
+mod amount;
+mod analyze;
+mod api;
+mod auth;
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "avro")]
+mod avro;
+mod builder;
+mod checkpoint;
+mod cli;
+mod columnar;
+mod clock;
+mod concurrent;
+pub mod config;
+mod dedup;
+mod digest;
+mod engine;
+mod error;
+mod event;
+mod excess;
+mod fastparse;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod health;
+mod history;
+mod http;
+mod id;
+#[cfg(feature = "iso20022")]
+mod iso20022;
+mod lease;
+mod locale;
+mod manifest;
+mod memory;
+mod mmap;
+#[cfg(feature = "node")]
+mod node;
+mod object;
+mod pipeline;
+#[cfg(feature = "python")]
+mod python;
+mod quarantine;
+mod query;
+mod rate;
+mod reference;
+mod reload;
+mod registry;
+mod reorder;
+mod replica;
+mod report;
+mod retention;
+#[cfg(feature = "simd")]
+pub mod scan;
+mod schedule;
+mod server;
+mod shard;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+mod sink;
+mod source;
+mod statement;
+mod stats;
+mod stream;
+mod sync;
+mod tail;
+mod telemetry;
+mod tenant;
+#[cfg(feature = "testing")]
+mod testing;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tui")]
+mod tui;
+mod txnlog;
+#[cfg(feature = "io-uring")]
+pub mod uring;
+
+pub const CURRENCY_PRECISION: u32 = 4;
+
+/// hasher for the account & transaction log maps. the keys are small integers, where SipHash's resistance to
+/// crafted collisions costs far more than it buys, FxHash is a multiply per key (see benches/hashers.rs)
+type Hasher = BuildHasherDefault<FxHasher>;
+type Map<K, V> = HashMap<K, V, Hasher>;
+type Set<K> = HashSet<K, Hasher>;
+
+pub type Accounts = Map<ClientId, Account>;
+
+/// serialized with its disputes & transaction log as lists in id order, so the same account always serializes
+/// the same: `{"balance":{..},"disputes":[1],"resolved":[],"charged_back":[],
+/// "txnlog":[{"type":"deposit","client":1,"tx":1,"amount":"2.5"}],"locked":false,"funded":true,"settling":[],
+/// "kind":"customer","reserve":"0","name":null,"currency":null,"chargeback_loss":"0","adjusted":"0","forgotten":false,
+/// "pruned":0,"pruned_txs":[]}`
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Default, Clone)]
+pub struct Account {
+    balance: Balance,
+    #[serde(serialize_with = "serialize_ids")]
+    disputes: Set<TxnId>,
+    /// transactions whose last dispute was resolved, they may be disputed again if the policy allows
+    #[serde(default, serialize_with = "serialize_ids")]
+    resolved: Set<TxnId>,
+    /// transactions whose dispute ended in a chargeback, which is final
+    #[serde(default, serialize_with = "serialize_ids")]
+    charged_back: Set<TxnId>,
+    #[serde(serialize_with = "serialize_txnlog", deserialize_with = "deserialize_txnlog")]
+    txnlog: TxnLog,
+    locked: bool,
+    /// a deposit has been applied to it
+    #[serde(default)]
+    funded: bool,
+    /// withdrawals held until they settle, oldest first
+    #[serde(default)]
+    settling: Vec<Settling>,
+    #[serde(default)]
+    kind: AccountKind,
+    /// the part of held a merchant keeps in escrow against chargebacks
+    #[serde(default)]
+    reserve: Amount,
+    /// as given by the clients file, see registry.rs
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    currency: Option<Currency>,
+    /// what chargebacks have taken from it, all told
+    #[serde(default)]
+    chargeback_loss: Amount,
+    /// what admins' adjustments have come to, all told, negative if they've debited more than credited
+    #[serde(default)]
+    adjusted: Amount,
+    /// an admin had it forgotten: its name & transactions are gone, its balances left as a tombstone, see admin.rs
+    #[serde(default)]
+    forgotten: bool,
+    /// transactions pruned from its log under `[retention]`, so it's logged this many more than it holds
+    #[serde(default)]
+    pruned: u64,
+    /// their txs, as runs, a dispute of one being rejected as of a pruned transaction rather than an unknown one
+    #[serde(default)]
+    pruned_txs: PrunedTxs
+}
+
+/// what rules an account's transactions follow, given by client id under `[kinds]`
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Default, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountKind {
+    #[default]
+    Customer,
+    /// reserves a share of each deposit, and chargebacks the reserve covers are debited from it without locking
+    Merchant,
+    /// holds funds in trust, its transactions can't be disputed
+    Escrow
+}
+
+/// a withdrawal awaiting settlement
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+struct Settling {
+    tx: TxnId,
+    amount: Amount,
+    /// deposits & withdrawals logged against the account before it
+    since: u64
+}
+
+impl Account {
+    /// never funded and with nothing to show for it, as an account opened by a declined withdrawal is
+    fn is_empty(&self) -> bool {
+        !self.funded && !self.locked && self.balance == Balance::default()
+    }
+
+    /// the deposits & withdrawals it's logged, pruned or not
+    fn logged(&self) -> u64 {
+        self.txnlog.len() as u64 + self.pruned
+    }
+
+    /// its balance & lock as they stand, without the logs, as a snapshot of the balances takes it
+    fn summary(&self) -> Account {
+        Account { balance: self.balance, locked: self.locked, funded: self.funded, kind: self.kind,
+                  chargeback_loss: self.chargeback_loss, adjusted: self.adjusted, forgotten: self.forgotten, ..Account::default() }
+    }
+
+    /// its summary along with what it has under dispute, the transactions logged being only those
+    fn with_disputes(&self) -> Account {
+        Account {
+            disputes: self.disputes.clone(),
+            txnlog: self.disputes.iter().filter_map(|tx| self.txnlog.get(tx).cloned()).collect(),
+            ..self.summary()
+        }
+    }
+
+    /// the transactions it has under dispute, in id order
+    fn disputed(&self) -> Vec<&Txn> {
+        let mut disputed: Vec<&Txn> = self.disputes.iter().filter_map(|tx| self.txnlog.get(tx)).collect();
+        disputed.sort_unstable_by_key(|t| t.tx);
+        disputed
+    }
+}
+
+fn serialize_ids<S: serde::Serializer>(ids: &Set<TxnId>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut ids: Vec<&TxnId> = ids.iter().collect();
+    ids.sort_unstable();
+    serializer.collect_seq(ids)
+}
+
+fn serialize_txnlog<S: serde::Serializer>(txnlog: &TxnLog, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(txnlog.values())
+}
+
+fn deserialize_txnlog<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<TxnLog, D::Error> {
+    let mut txnlog = TxnLog::default();
+    for txn in Vec::<Txn>::deserialize(deserializer)? {
+        let tx = txn.tx;
+        if txnlog.insert(txn).is_some() {
+            return Err(serde::de::Error::custom(format!("transaction {} logged twice", tx)));
+        }
+    }
+    Ok(txnlog)
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum TxnType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback
+}
+
+/// serialized as a csv row reads: `{"type":"deposit","client":1,"tx":1,"amount":"2.5"}`
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct Txn {
+    #[serde(rename = "type")]
+    txntype: TxnType,
+    client: ClientId,
+    tx: TxnId,
+    amount: Option<Amount>,
+    /// left out when serialized if it has none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>
+}
+
+/// serialized as `{"available":"1.5","held":"0","total":"1.5"}`, a total that isn't available + held is refused
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Default, Copy, Clone)]
+#[serde(try_from = "BalanceFields")]
+pub struct Balance {
+    /// total - held
+    available: Amount,
+    /// total - available
+    held: Amount,
+    /// available + held
+    total: Amount
+}
+
+#[derive(Deserialize)]
+struct BalanceFields {
+    available: Amount,
+    held: Amount,
+    total: Amount
+}
+
+impl TryFrom<BalanceFields> for Balance {
+    type Error = String;
+
+    fn try_from(b: BalanceFields) -> Result<Self, String> {
+        if b.available.checked_add(b.held) != Some(b.total) {
+            return Err(format!("total {} isn't available {} + held {}", b.total, b.available, b.held));
+        }
+        Ok(Balance { available: b.available, held: b.held, total: b.total })
+    }
+}
+
+/// why the engine declined a transaction
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+pub enum Rejection {
+    Locked,
+    OverLimit,
+    Overflow,
+    InsufficientFunds,
+    UnknownTxn,
+    AlreadyDisputed,
+    WithdrawalDispute,
+    NotDisputed,
+    Late,
+    ChargedBack,
+    Redispute,
+    WrongClient,
+    EscrowDispute,
+    CurrencyMismatch,
+    /// a declined withdrawal, which moved nothing to dispute
+    Declined,
+    /// a dispute of a transaction pruned from the log under `[retention]`
+    Pruned,
+    /// a deposit or withdrawal reusing a tx the server's `--dedup-index` has seen
+    Duplicate
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Rejection::Locked => "account locked",
+            Rejection::OverLimit => "amount over limit",
+            Rejection::Overflow => "balance overflow",
+            Rejection::InsufficientFunds => "insufficient funds",
+            Rejection::UnknownTxn => "unknown transaction",
+            Rejection::AlreadyDisputed => "already disputed",
+            Rejection::WithdrawalDispute => "withdrawal disputes disabled",
+            Rejection::NotDisputed => "not disputed",
+            Rejection::Late => "late arrival",
+            Rejection::ChargedBack => "already charged back",
+            Rejection::Redispute => "re-disputes disabled",
+            Rejection::WrongClient => "another client's transaction",
+            Rejection::EscrowDispute => "escrow dispute",
+            Rejection::CurrencyMismatch => "currency mismatch",
+            Rejection::Declined => "declined transaction",
+            Rejection::Pruned => "pruned transaction",
+            Rejection::Duplicate => "duplicate transaction"
+        })
+    }
+}
+
+impl Txn {
+    /// amount is taken as is, see `rounded`
+    pub fn new(txntype: TxnType, client: ClientId, tx: TxnId, amount: Option<Amount>) -> Self {
+        Self { txntype, client, tx, amount, currency: None }
+    }
+
+    /// a builder checking the amount against the type, which input should be made through
+    pub fn builder(txntype: TxnType, client: ClientId, tx: TxnId) -> TxnBuilder {
+        TxnBuilder::new(txntype, client, tx)
+    }
+
+    #[cfg(test)]
+    fn deposit(client: u16, tx: u32, amount: Decimal) -> Self {
+        Txn::builder(TxnType::Deposit, ClientId::from(client), TxnId::from(tx)).amount(amount).build().unwrap()
+    }
+
+    #[cfg(test)]
+    fn withdrawal(client: u16, tx: u32, amount: Decimal) -> Self {
+        Txn::builder(TxnType::Withdrawal, ClientId::from(client), TxnId::from(tx)).amount(amount).build().unwrap()
+    }
+
+    #[cfg(test)]
+    fn dispute(client: u16, tx: u32) -> Self {
+        Txn::new(TxnType::Dispute, ClientId::from(client), TxnId::from(tx), None)
+    }
+
+    #[cfg(test)]
+    fn resolve(client: u16, tx: u32) -> Self {
+        Txn::new(TxnType::Resolve, ClientId::from(client), TxnId::from(tx), None)
+    }
+
+    #[cfg(test)]
+    fn chargeback(client: u16, tx: u32) -> Self {
+        Txn::new(TxnType::Chargeback, ClientId::from(client), TxnId::from(tx), None)
+    }
+
+    fn amount(&self) -> Amount {
+        self.amount.unwrap_or(Amount::ZERO)
+    }
+}
+
+/// safe. creates if it doesn't exist.
+#[cfg(test)]
+fn get_account_mut(accounts: &mut Accounts, client: u16) -> &mut Account {
+    accounts.entry(ClientId::from(client)).or_default()
+}
+
+#[cfg(test)]
+fn amount(amount: Decimal) -> Amount {
+    Amount::from_decimal(amount, CURRENCY_PRECISION).unwrap()
+}
+
+/// safe. returns default empty balance if account does not exist.
+#[cfg(test)]
+fn get_balance(accounts: &Accounts, client: u16) -> Balance {
+    match accounts.get(&ClientId::from(client)) {
+        Some(acc) => acc.balance,
+        None => Balance::default()
+    }
+}
+
+#[cfg(test)]
+fn is_locked(accounts: &Accounts, client: ClientId) -> bool {
+    accounts.get(&client).is_some_and(|a| a.locked)
+}
+
+fn deposit<S: Sink>(account: &mut Account, client: ClientId, tx: TxnId, amount: Amount, sink: &mut S)
+                   -> Result<(), Rejection> {
+    emit(account, client, Event::FundsDeposited { tx, amount }, sink)
+}
+
+fn withdraw<S: Sink>(account: &mut Account, client: ClientId, tx: TxnId, amount: Amount, settlement: &SettlementOptions,
+                    sink: &mut S) -> Result<(), Rejection> {
+    if account.balance.available < amount {
+        return Err(Rejection::InsufficientFunds);
+    }
+    match settlement.delay {
+        0 => emit(account, client, Event::FundsWithdrawn { tx, amount }, sink),
+        _ => emit(account, client, Event::WithdrawalHeld { tx, amount }, sink)
+    }
+}
+
+/// pays out the held withdrawals that have waited long enough
+fn settle<S: Sink>(account: &mut Account, client: ClientId, settlement: &SettlementOptions, sink: &mut S)
+                  -> Result<(), Rejection> {
+    let logged = account.logged();
+    // its own logging is the first transaction since
+    while let Some(held) = account.settling.first().filter(|s| s.since.saturating_add(settlement.delay) < logged) {
+        let (tx, amount) = (held.tx, held.amount);
+        emit(account, client, Event::WithdrawalSettled { tx, amount }, sink)?;
+    }
+    Ok(())
+}
+
+/// prunes the log back to the newest `keep_last` transactions once it's grown a quarter past them, so it's pruned
+/// once every so many rather than on every one. newest goes by tx, as `keep_days` does, not by when they were logged:
+/// a tx logged late, lower than those before it, is among the first pruned. disputed & settling transactions are
+/// kept whatever their age, and don't count towards the quarter, or enough of them would have it pruning on every one
+fn retain<S: Sink>(account: &mut Account, client: ClientId, retention: &RetentionOptions, sink: &mut S) -> Result<(), Rejection> {
+    let pinned = account.disputes.len() + account.settling.len();
+    let prunable = account.txnlog.len().saturating_sub(pinned) as u64;
+    let keep = match retention.keep_last {
+        Some(keep) if prunable > keep + keep / 4 => keep as usize,
+        _ => return Ok(())
+    };
+    let mut ids: Vec<TxnId> = account.txnlog.keys().copied().collect();
+    ids.truncate(ids.len() - keep);
+    prune(account, client, &ids, sink)
+}
+
+/// prunes those of `ids` that aren't disputed or settling
+fn prune<S: Sink>(account: &mut Account, client: ClientId, ids: &[TxnId], sink: &mut S) -> Result<(), Rejection> {
+    for tx in ids {
+        if !account.disputes.contains(tx) && !account.settling.iter().any(|s| s.tx == *tx) {
+            emit(account, client, Event::TransactionPruned { tx: *tx }, sink)?;
+        }
+    }
+    Ok(())
+}
+
+/// prunes every account's transactions up to `through` that aren't disputed or settling, as `[retention]
+/// keep_days` does on a server
+fn prune_with(accounts: &mut Accounts, through: TxnId) {
+    for (client, account) in accounts.iter_mut() {
+        let ids: Vec<TxnId> = account.txnlog.keys().copied().filter(|tx| *tx <= through).collect();
+        prune(account, *client, &ids, &mut ()).expect("pruning always applies");
+    }
+}
+
+fn dispute<S: Sink>(account: &mut Account, client: ClientId, tx: TxnId, policy: &DisputePolicy, sink: &mut S)
+                   -> Result<(), Rejection> {
+    let txn = owned_txn(account, client, tx)?;
+    if account.kind == AccountKind::Escrow {
+        return Err(Rejection::EscrowDispute);
+    }
+    if txn.amount.is_none() {
+        return Err(Rejection::Declined);
+    }
+    if txn.txntype == TxnType::Withdrawal && !policy.withdrawals {
+        return Err(Rejection::WithdrawalDispute);
+    }
+    if account.disputes.contains(&tx) {
+        // do not deduct available
+        return Err(Rejection::AlreadyDisputed);
+    }
+    if account.charged_back.contains(&tx) {
+        // refunded already, even if the account's since been unlocked
+        return Err(Rejection::ChargedBack);
+    }
+    if account.resolved.contains(&tx) && !policy.redisputes {
+        return Err(Rejection::Redispute);
+    }
+
+    let amount = txn.amount();
+    emit(account, client, Event::FundsHeld { tx, amount }, sink)
+}
+
+fn resolve<S: Sink>(account: &mut Account, client: ClientId, tx: TxnId, sink: &mut S) -> Result<(), Rejection> {
+    if !account.disputes.contains(&tx) {
+        // transaction is not under dispute
+        return Err(Rejection::NotDisputed);
+    }
+
+    let amount = owned_txn(account, client, tx)?.amount();
+    emit(account, client, Event::FundsReleased { tx, amount }, sink)
+}
+
+fn chargeback<S: Sink>(account: &mut Account, client: ClientId, tx: TxnId, sink: &mut S) -> Result<(), Rejection> {
+    let disputed = account.disputes.contains(&tx);
+    if !disputed {
+        // cannot chargeback an undisputed transaction?
+        return Err(Rejection::NotDisputed);
+    }
+
+    let amount = owned_txn(account, client, tx)?.amount();
+    if account.kind == AccountKind::Merchant && account.reserve >= amount {
+        // the merchant keeps trading
+        return emit(account, client, Event::ReserveChargedBack { tx, amount }, sink);
+    }
+    emit(account, client, Event::FundsChargedBack { tx, amount }, sink)?;
+    emit(account, client, Event::AccountLocked, sink)
+}
+
+/// a merchant's share of a deposit, to be moved into its reserve once the deposit's applied. worked out before,
+/// so a share the reserve or held can't take rejects the deposit with the account as it was
+fn reserve_share(account: &Account, amount: Amount, config: &Config) -> Result<Option<Amount>, Rejection> {
+    if account.kind != AccountKind::Merchant || config.kinds.reserve.is_zero() {
+        return Ok(None);
+    }
+    let share = amount.to_decimal().checked_mul(config.kinds.reserve)
+        .and_then(|d| Amount::from_decimal(d, config.amount_precision()))
+        .ok_or(Rejection::Overflow)?;
+    // available takes it from the deposit, which is no less
+    account.reserve.checked_add(share).and(account.balance.held.checked_add(share)).ok_or(Rejection::Overflow)?;
+    Ok(Some(share))
+}
+
+/// the logged transaction a dispute, resolve or chargeback refers to, which must be the disputing client's own
+fn owned_txn(account: &Account, client: ClientId, tx: TxnId) -> Result<&Txn, Rejection> {
+    let txn = account.txnlog.get(&tx).ok_or(match account.pruned_txs.contains(tx) {
+        true => Rejection::Pruned,
+        false => Rejection::UnknownTxn
+    })?;
+    if txn.client != client {
+        // an account restored from outside the engine could hold another client's transaction
+        return Err(Rejection::WrongClient);
+    }
+    Ok(txn)
+}
+
+/// applies the event, and records it if it applied
+fn emit<S: Sink>(account: &mut Account, client: ClientId, event: Event, sink: &mut S) -> Result<(), Rejection> {
+    account.apply(&event)?;
+    sink.record(client, event);
+    Ok(())
+}
+
+/// true if the transaction moves more than the configured maximum
+fn exceeds_limits(txn: &Txn, limits: &Limits) -> bool {
+    match limits.max_amount {
+        Some(max) => txn.amount().to_decimal() > max,
+        None => false
+    }
+}
+
+/// executes under the default config, ignoring the outcome
+#[cfg(test)]
+fn execute(accounts: &mut Accounts, txn: Txn) {
+    let _ = execute_with(accounts, txn, &Config::default());
+}
+
+pub fn execute_with(accounts: &mut Accounts, txn: Txn, config: &Config) -> Result<(), Rejection> {
+    execute_recorded(accounts, txn, config, &mut ())
+}
+
+/// what `execute_with` would come to for `txn`, tried on a copy of its account so nothing's applied
+fn validate_with(account: Option<&Account>, txn: Txn, config: &Config) -> Result<(), Rejection> {
+    let mut scratch = Accounts::default();
+    if let Some(account) = account {
+        scratch.insert(txn.client, account.clone());
+    }
+    execute_with(&mut scratch, txn, config)
+}
+
+/// credits `amount` to the client's account, or debits it when negative, as an admin's correction rather than a
+/// transaction: it's applied to a locked account too, and isn't logged to be disputed. a debit is declined if it'd
+/// take more than is available, without opening an account
+fn adjust_with(accounts: &mut Accounts, client: ClientId, amount: Amount, config: &Config) -> Result<(), Rejection> {
+    let opened = !accounts.contains_key(&client);
+    if opened && amount < Amount::ZERO {
+        return Err(Rejection::InsufficientFunds);
+    }
+    let account = accounts.entry(client).or_insert_with(|| open_account(client, config, &mut ()));
+    let result = emit(account, client, Event::BalanceAdjusted { amount }, &mut ());
+    if result.is_err() && opened {
+        accounts.remove(&client);
+    }
+    result
+}
+
+/// erases what ties the client's account to a person: its name, and the transactions logged against it along with
+/// which were disputed, leaving its balances, lock & kind as a tombstone. refused while a dispute's open or a
+/// withdrawal's settling, as those need their transactions
+fn forget_with(accounts: &mut Accounts, client: ClientId) -> Result<(), String> {
+    let account = accounts.get_mut(&client).ok_or_else(|| format!("client {} has no account", client))?;
+    if !account.disputes.is_empty() {
+        return Err(format!("client {} has disputes open", client));
+    }
+    if !account.settling.is_empty() {
+        return Err(format!("client {} has withdrawals settling", client));
+    }
+    emit(account, client, Event::AccountForgotten, &mut ()).expect("forgetting always applies");
+    Ok(())
+}
+
+/// as `execute_with`, handing the events raised to the sink
+fn execute_recorded<S: Sink>(accounts: &mut Accounts, txn: Txn, config: &Config, sink: &mut S) -> Result<(), Rejection> {
+    precheck(accounts.get(&txn.client), &txn, config)?;
+    let account = match accounts.entry(txn.client) {
+        MapEntry::Occupied(e) => e.into_mut(),
+        MapEntry::Vacant(e) => e.insert(open_account(txn.client, config, sink))
+    };
+    apply_recorded(account, txn, config, sink)
+}
+
+/// a new account, of the kind the config gives the client
+fn open_account<S: Sink>(client: ClientId, config: &Config, sink: &mut S) -> Account {
+    let mut account = Account::default();
+    sink.record(client, Event::AccountOpened);
+    let kind = config.kinds.kind(client);
+    if kind != AccountKind::default() {
+        emit(&mut account, client, Event::AccountClassified(kind), sink).expect("a kind always applies");
+    }
+    account
+}
+
+/// the checks made before the account is looked up, so a declined transaction doesn't open one
+fn precheck(account: Option<&Account>, txn: &Txn, config: &Config) -> Result<(), Rejection> {
+    match (account, &txn.txntype) {
+        (Some(a), _) if locked_out(a, txn, &config.locked) => return Err(Rejection::Locked),
+        (Some(a), _) if txn.currency.is_some() && a.currency.is_some() && txn.currency != a.currency => {
+            return Err(Rejection::CurrencyMismatch)
+        },
+        // a client without an account has nothing to dispute, whoever's transaction the tx is, as apply would find
+        (None, TxnType::Dispute) => return Err(Rejection::UnknownTxn),
+        (None, TxnType::Resolve) | (None, TxnType::Chargeback) => return Err(Rejection::NotDisputed),
+        _ => {}
+    }
+    if exceeds_limits(txn, &config.limits) {
+        return Err(Rejection::OverLimit);
+    }
+    Ok(())
+}
+
+/// a transaction the accounts show has been applied already: a deposit or withdrawal whose tx is logged, or a
+/// dispute, resolve or chargeback whose transaction is already past it. a resolved transaction disputed again is
+/// taken for the first dispute replayed
+fn replayed(accounts: &Accounts, txn: &Txn) -> bool {
+    let account = match accounts.get(&txn.client) {
+        Some(a) => a,
+        None => return false
+    };
+    let tx = &txn.tx;
+    match txn.txntype {
+        TxnType::Deposit | TxnType::Withdrawal => account.txnlog.contains_key(tx),
+        TxnType::Dispute => account.disputes.contains(tx) || account.resolved.contains(tx) || account.charged_back.contains(tx),
+        TxnType::Resolve => account.resolved.contains(tx) && !account.disputes.contains(tx),
+        TxnType::Chargeback => account.charged_back.contains(tx)
+    }
+}
+
+/// true if the account's locked to transactions of this type
+fn locked_out(account: &Account, txn: &Txn, policy: &LockPolicy) -> bool {
+    account.locked && !policy.accepts(&txn.txntype)
+}
+
+/// executes against the transaction's own account, every transaction touches just the one
+fn apply(account: &mut Account, txn: Txn, config: &Config) -> Result<(), Rejection> {
+    apply_recorded(account, txn, config, &mut ())
+}
+
+/// checks the transaction against the account, then applies what it does as events
+fn apply_recorded<S: Sink>(account: &mut Account, txn: Txn, config: &Config, sink: &mut S) -> Result<(), Rejection> {
+    let (client, tx) = (txn.client, txn.tx);
+    // a reused id would take the log entry over, leaving what the dispute holds to be released at the new amount
+    if matches!(txn.txntype, TxnType::Deposit | TxnType::Withdrawal) && account.disputes.contains(&tx) {
+        return Err(Rejection::AlreadyDisputed);
+    }
+    match txn.txntype {
+        TxnType::Deposit => {
+            // an overflowing deposit isn't logged, so it can't be disputed
+            let amount = txn.amount();
+            let share = reserve_share(account, amount, config)?;
+            deposit(account, client, tx, amount, sink)?;
+            emit(account, client, Event::TransactionLogged(txn), sink)?;
+            if let Some(share) = share {
+                emit(account, client, Event::ReserveHeld { tx, amount: share }, sink)?;
+            }
+            settle(account, client, &config.settlement, sink)?;
+            retain(account, client, &config.retention, sink)
+        },
+        TxnType::Withdrawal => {
+            // logged even when declined, so its id's taken, but without the amount it never moved
+            let result = withdraw(account, client, tx, txn.amount(), &config.settlement, sink);
+            let txn = match result {
+                Ok(()) => txn,
+                Err(_) => Txn { amount: None, ..txn }
+            };
+            emit(account, client, Event::TransactionLogged(txn), sink)?;
+            settle(account, client, &config.settlement, sink)?;
+            retain(account, client, &config.retention, sink)?;
+            result
+        },
+        TxnType::Dispute => {
+            dispute(account, client, tx, &config.disputes, sink)
+        },
+        TxnType::Resolve => {
+            resolve(account, client, tx, sink)
+        },
+        TxnType::Chargeback => {
+            chargeback(account, client, tx, sink)
+        }
+    }
+}
+
+/// trims, deserializes & rounds the amount. a field that isn't what it should be is named in the error, with its
+/// value & line: `line 48210: amount '12,50' is not a valid decimal`
+pub fn deserialize_record(record: &mut csv::StringRecord, precision: impl Into<Precision>) -> Result<Txn, pipeline::RowError> {
+    record.trim();
+    let raw = record.deserialize::<RawRecord>(Option::None)
+        .map_err(|e| field_error(e, record.position(), |i| record.get(i).map(str::to_string)))?;
+    raw.into_txn(precision).map_err(|e| on_line(record.position(), e))
+}
+
+/// as `deserialize_record`, the amount read in `config.amount_locale` & under `config.amount_policy` first (see
+/// locale.rs)
+fn read_record(record: &mut csv::StringRecord, config: &Config) -> Result<Txn, pipeline::RowError> {
+    locale::localize(record, locale::AmountFormat::of(config))?;
+    deserialize_record(record, config.amount_precision())
+}
+
+/// as `deserialize_record`, for the pipeline
+fn deserialize_byte_record(record: &mut csv::ByteRecord, precision: Precision) -> Result<Txn, pipeline::RowError> {
+    record.trim();
+    let raw = record.deserialize::<RawRecord>(Option::None)
+        .map_err(|e| field_error(e, record.position(), |i| record.get(i).map(|f| String::from_utf8_lossy(f).into_owned())))?;
+    raw.into_txn(precision).map_err(|e| on_line(record.position(), e))
+}
+
+/// whether a value reads as a field's type on its own
+type Reads = fn(&str) -> bool;
+
+/// the columns `RawRecord` reads, what each holds, and whether a value reads as one
+const FIELDS: [(&str, &str, Reads); 4] = [
+    ("type", "transaction type", reads::<TxnType>),
+    ("client", "client id", reads::<ClientId>),
+    ("tx", "transaction id", reads::<TxnId>),
+    ("amount", "decimal", reads::<Option<Decimal>>)
+];
+
+fn reads<T: serde::de::DeserializeOwned>(value: &str) -> bool {
+    csv::StringRecord::from(vec![value]).deserialize::<(T,)>(None).is_ok()
+}
+
+/// a row serde couldn't read, by the field it couldn't and that field's `value`. csv's own error, which says where,
+/// if it isn't a field's. serde only says which field for the errors csv raises itself, i.e. an id that isn't a
+/// number, so otherwise it's the first field that doesn't read on its own
+fn field_error(e: csv::Error, position: Option<&csv::Position>, value: impl Fn(usize) -> Option<String>) -> pipeline::RowError {
+    let field = match e.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => err.field().and_then(|i| usize::try_from(i).ok())
+            .or_else(|| FIELDS.iter().enumerate().position(|(i, (_, _, reads))| value(i).is_some_and(|v| !reads(&v)))),
+        _ => None
+    };
+    match field.and_then(|i| Some((FIELDS.get(i)?, value(i)?))) {
+        Some(((name, kind, _), value)) => on_line(position, format!("{} '{}' is not a valid {}", name, value, kind)),
+        None => e.into()
+    }
+}
+
+/// a row's error, saying which line the row's on when it was read from a file. csv's own errors say already
+fn on_line(position: Option<&csv::Position>, e: impl Into<pipeline::RowError>) -> pipeline::RowError {
+    match position {
+        Some(position) => Box::new(error::OnLine { line: position.line(), detail: e.into() }),
+        None => e.into()
+    }
+}
+
+/// balances to `options.path` (stdout if none) in the format its extension names, csv unless it names another.
+/// buffered `options.buffer_size` bytes at a time, and split across `options.shards` files (see shard.rs). any write
+/// error, i.e. a closed pipe, is returned
+pub fn write_out(accounts: &Accounts, options: &OutputOptions) -> Result<(), TxnCliError> {
+    if options.shards > 1 {
+        return Ok(shard::write_out(accounts, options)?);
+    }
+    Ok(write_part(accounts, options, &|_| true)?)
+}
+
+/// a csv sink writing to `out` as `options` say
+fn csv_sink<W: Write>(out: W, options: &OutputOptions) -> CsvSink<W> {
+    let buffer_size = usize::try_from(options.buffer_size).unwrap_or(usize::MAX);
+    CsvSink::new(out, buffer_size).decimals(options.decimals).enriched(options.enriched).losses(options.losses)
+        .held_breakdown(options.held_breakdown).columns(&options.columns)
+}
+
+/// as `write_out`, for the accounts of clients `pick` picks out
+fn write_part(accounts: &Accounts, options: &OutputOptions, pick: &dyn Fn(&ClientId) -> bool)
+              -> Result<(), Box<dyn std::error::Error>> {
+    let buffer_size = usize::try_from(options.buffer_size).unwrap_or(usize::MAX);
+    let path = match &options.path {
+        Some(path) => path,
+        None => {
+            let mut sink = csv_sink(std::io::stdout().lock(), options);
+            return write_listed(accounts, options, pick, &mut sink);
+        }
+    };
+    let create = || std::fs::File::create(path).map_err(|e| TxnCliError::io(path, e));
+    match OutputFormat::from_path(path) {
+        OutputFormat::Csv => {
+            let mut sink = csv_sink(create()?, options);
+            write_listed(accounts, options, pick, &mut sink)
+        },
+        OutputFormat::Json => {
+            let mut sink = JsonSink::new(create()?, buffer_size).enriched(options.enriched).losses(options.losses)
+                .held_breakdown(options.held_breakdown).columns(&options.columns);
+            write_listed(accounts, options, pick, &mut sink)
+        },
+        OutputFormat::Parquet => write_parquet(accounts, options, pick, create()?),
+        OutputFormat::Sqlite => write_sqlite(accounts, options, pick, path),
+        OutputFormat::Postgres => write_postgres(accounts, options, pick, path)
+    }
+}
+
+/// the accounts the options list & `pick` picks, to the sink
+fn write_listed<S: AccountSink + ?Sized>(accounts: &Accounts, options: &OutputOptions, pick: &dyn Fn(&ClientId) -> bool,
+                                        sink: &mut S) -> Result<(), Box<dyn std::error::Error>> {
+    let listed = accounts.iter().filter(|(c, a)| (options.empty_accounts || !a.is_empty()) && pick(c));
+    sink::write_some(listed, options.sort, sink)
+}
+
+enum OutputFormat {
+    Csv,
+    Json,
+    Parquet,
+    Sqlite,
+    /// a `postgres://` or `postgresql://` url
+    Postgres
+}
+
+impl OutputFormat {
+    /// recognised by extension as inputs are, or a postgres url, anything else is written as csv
+    fn from_path(file_path: &Path) -> Self {
+        let url = file_path.to_str().unwrap_or_default();
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            return OutputFormat::Postgres;
+        }
+        let ext = file_path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("json") | Some("jsonl") | Some("ndjson") => OutputFormat::Json,
+            Some("parquet") => OutputFormat::Parquet,
+            Some("db") | Some("sqlite") | Some("sqlite3") => OutputFormat::Sqlite,
+            _ => OutputFormat::Csv
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(accounts: &Accounts, options: &OutputOptions, pick: &dyn Fn(&ClientId) -> bool, file: std::fs::File)
+                 -> Result<(), Box<dyn std::error::Error>> {
+    write_listed(accounts, options, pick, &mut sink::ParquetSink::new(file, options.decimals)?).map_err(TxnCliError::storage)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_accounts: &Accounts, _options: &OutputOptions, _pick: &dyn Fn(&ClientId) -> bool, _file: std::fs::File)
+                 -> Result<(), Box<dyn std::error::Error>> {
+    Err("Parquet output requires building with the `parquet` feature".into())
+}
+
+#[cfg(feature = "sqlite")]
+fn write_sqlite(accounts: &Accounts, options: &OutputOptions, pick: &dyn Fn(&ClientId) -> bool, path: &Path)
+                -> Result<(), Box<dyn std::error::Error>> {
+    write_listed(accounts, options, pick, &mut sink::SqliteSink::new(path)?).map_err(TxnCliError::storage)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn write_sqlite(_accounts: &Accounts, _options: &OutputOptions, _pick: &dyn Fn(&ClientId) -> bool, _path: &Path)
+                -> Result<(), Box<dyn std::error::Error>> {
+    Err("SQLite output requires building with the `sqlite` feature".into())
+}
+
+#[cfg(feature = "postgres")]
+fn write_postgres(accounts: &Accounts, options: &OutputOptions, pick: &dyn Fn(&ClientId) -> bool, url: &Path)
+                  -> Result<(), Box<dyn std::error::Error>> {
+    let url = url.to_str().expect("a postgres url is text");
+    write_listed(accounts, options, pick, &mut sink::PostgresSink::new(url)?).map_err(TxnCliError::storage)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "postgres"))]
+fn write_postgres(_accounts: &Accounts, _options: &OutputOptions, _pick: &dyn Fn(&ClientId) -> bool, _url: &Path)
+                  -> Result<(), Box<dyn std::error::Error>> {
+    Err("Postgres output requires building with the `postgres` feature".into())
+}
+
+#[cfg(feature = "simulation")]
+fn fuzz(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    simulation::fuzz(config)
+}
+
+#[cfg(not(feature = "simulation"))]
+fn fuzz(_config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    Err("txn fuzz requires building with the `simulation` feature".into())
+}
+
+/// process exit codes
+pub(crate) mod exit {
+    pub const CLEAN: i32 = 0;
+    /// usage, config or io errors
+    pub const ERROR: i32 = 1;
+    /// completed, but rows were skipped or rejected
+    pub const INCOMPLETE: i32 = 2;
+    /// stopped at malformatted input
+    pub const MALFORMATTED: i32 = 3;
+    /// balances failed the post-run invariant check, no output written
+    pub const INVARIANT: i32 = 4;
+    /// lost the lease to another instance, stopped mid-run
+    pub const FENCED: i32 = 5;
+}
+
+/// held funds are never negative and always account for the difference between total and available
+fn check_invariants(accounts: &Accounts) -> Result<(), EngineError> {
+    accounts.iter().try_for_each(|(client, account)| check_account(client, account))
+}
+
+/// as `check_invariants`, for one account
+fn check_account(client: &ClientId, account: &Account) -> Result<(), EngineError> {
+    let balance = account.balance;
+    if balance.held < Amount::ZERO {
+        return Err(EngineError::Invariant(format!("client {} has negative held funds", client)));
+    }
+    if balance.available.checked_add(balance.held) != Some(balance.total) {
+        return Err(EngineError::Invariant(format!("client {} available + held != total", client)));
+    }
+    Ok(())
+}
+
+/// runs the command line, returning the process exit code
+pub fn cli() -> i32 {
+    let result = run();
+    telemetry::shutdown();
+    match result {
+        Ok(report) if report.skipped > 0 || report.rejected_total() > 0 => exit::INCOMPLETE,
+        Ok(_) => exit::CLEAN,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            e.exit_code()
+        }
+    }
+}
+
+/// whether an option's on
+type Flag = fn(&Config) -> bool;
+
+/// the one pass modes & the options they can't be combined with, by flag: what turns each on
+const ONE_PASS: [(&str, Flag); 6] = [
+    ("--reorder-lateness", |c| c.reorder.lateness.is_some()),
+    ("--tenants", |c| c.tenants),
+    ("--on-error quarantine", |c| c.on_error == ErrorPolicy::Quarantine),
+    ("--stream-output", |c| c.output.streaming),
+    ("--clients", |c| c.clients.path.is_some()),
+    ("--output-shards", |c| c.output.shards > 1)
+];
+
+/// the one pass modes, whether each is for process alone (else any command that reads a file but tail), and the
+/// other options it can't be combined with. process reads a local file, the rest a local, http or object store one
+const INCOMPATIBLE: [(&str, bool, &[&str]); 4] = [
+    ("--reorder-lateness", false, &[]),
+    ("--tenants", true, &["--reorder-lateness", "--clients"]),
+    ("--on-error quarantine", true, &["--reorder-lateness", "--tenants"]),
+    ("--stream-output", true, &["--reorder-lateness", "--tenants", "--on-error quarantine", "--clients", "--output-shards"])
+];
+
+/// fails naming the first one pass mode that's on with what it isn't supported by: a non csv input, the server,
+/// parallel or fast parsing, memory mapping, checkpoints, a command it's not for or an incompatible option
+fn single_pass(config: &Config, cli: &cli::Cli) -> Result<(), String> {
+    let on = |flag: &str| ONE_PASS.iter().any(|(f, on)| *f == flag && on(config));
+    let one_pass = config.parse_threads == 1 && !config.fast_parse && !config.mmap && config.listen.is_none()
+        && config.checkpoint.every == 0 && !config.checkpoint.resume;
+    for (mode, process, incompatible) in INCOMPATIBLE.iter().filter(|(mode, ..)| on(mode)) {
+        let csv = cli.input.as_deref().is_some_and(|p| matches!(InputFormat::from_path(p), InputFormat::Csv)
+            && !(*process && p.to_str().is_none_or(is_remote)));
+        let command = match process {
+            true => cli.command == Command::Process,
+            false => cli.command != Command::Tail
+        };
+        if !csv || !command || !one_pass || incompatible.iter().any(|flag| on(flag)) {
+            let (file, commands) = match process {
+                true => ("processing a local", "tail, query, history"),
+                false => ("reading a", "tail")
+            };
+            let others: String = incompatible.iter().map(|flag| format!(", {}", flag)).collect();
+            return Err(format!("{} is only supported {} csv file in one pass: not with {}, the server, parallel or fast parsing, \
+                                memory mapping, checkpoints{}", mode, file, commands, others));
+        }
+    }
+    Ok(())
+}
+
+fn run() -> Result<Report, TxnCliError> {
+    let clock = SystemClock;
+    let started = clock.now();
+    let mut accounts = Accounts::default();
+    let mut report = Report::default();
+
+    let cli = cli::parse(std::env::args_os().skip(1)).map_err(TxnCliError::Validation)?;
+    let env: Vec<(String, String)> = std::env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .collect();
+    let config = Config::resolve(cli.config.as_deref(), env.clone(), &cli.overrides).map_err(TxnCliError::Validation)?;
+    if let Some(endpoint) = &config.otel.endpoint {
+        telemetry::init(endpoint)?;
+    }
+    if cli.command == Command::MergeOutput {
+        shard::merge(&cli.parts, &config.output)?;
+        return Ok(report);
+    }
+    if cli.command == Command::Fuzz {
+        fuzz(&config)?;
+        return Ok(report);
+    }
+    if cli.command == Command::Adjust {
+        if config.listen.is_none() || config.query.client.is_none() || config.admin.amount.is_none() || config.admin.reason.is_none() {
+            return Err(TxnCliError::Validation("txn admin adjust sends the server on --listen an adjustment: it needs --client, \
+                        --amount & --reason".into()));
+        }
+        println!("{}", admin::send(cli.command, &config)?);
+        return Ok(report);
+    }
+    if cli.command == Command::Forget {
+        if config.listen.is_none() || config.query.client.is_none() {
+            return Err(TxnCliError::Validation("txn admin forget sends the server on --listen a client to forget: it needs --client".into()));
+        }
+        if config.admin.amount.is_some() || config.admin.reason.is_some() {
+            return Err(TxnCliError::Validation("--amount & --reason are for txn admin adjust".into()));
+        }
+        println!("{}", admin::send(cli.command, &config)?);
+        return Ok(report);
+    }
+    if config.admin.amount.is_some() || config.admin.reason.is_some() || config.admin.key_file.is_some() {
+        return Err(TxnCliError::Validation("--amount, --reason & --auth-key-file are for txn admin adjust & forget".into()));
+    }
+
+    if let Err(e) = single_pass(&config, &cli) {
+        return Err(TxnCliError::Validation(e));
+    }
+    if config.columnar {
+        let checkpointing = config.checkpoint.every > 0 || config.checkpoint.resume;
+        if cli.command != Command::Process || config.listen.is_some() || checkpointing || config.reorder.lateness.is_some()
+            || config.tenants || config.output.streaming || config.on_error == ErrorPolicy::Quarantine {
+            return Err(TxnCliError::Validation("--columnar executes a file's transactions a batch at a time: it's for process, not with \
+                        the server, checkpoints, reordering, tenants, streamed output or quarantining".into()));
+        }
+    }
+    if config.stats && (cli.command != Command::Process || config.listen.is_some() || config.tenants || config.output.streaming) {
+        return Err(TxnCliError::Validation("--stats counts what processing a file executes: not with tail, query, history, the server, \
+                    tenants or streamed output".into()));
+    }
+    report.operations = config.stats.then(stats::Operations::default);
+    if config.digests.path.is_some() {
+        let local = cli.input.as_deref().is_some_and(|p| p.to_str().is_some_and(|p| !is_remote(p)));
+        if !local || config.listen.is_some() || cli.command != Command::Process {
+            return Err(TxnCliError::Validation("digests are only kept of local files processed whole: not with tail, query, history or the server".into()));
+        }
+    }
+    if config.manifest.path.is_some()
+        && (config.listen.is_some() || cli.command != Command::Process || config.tenants || config.output.streaming) {
+        return Err(TxnCliError::Validation("manifests are only written processing an input whole: not with tail, query, history, the server, \
+                    tenants or streamed output".into()));
+    }
+    if config.checkpoint.replay_tolerant && !config.checkpoint.resume {
+        return Err(TxnCliError::Validation("--replay-tolerant re-reads the input over a checkpoint, it needs --resume".into()));
+    }
+    if config.schedule.path.is_some() && config.reorder.lateness.is_none() {
+        return Err(TxnCliError::Validation("--schedule places recurring transactions among timestamped rows, it needs --reorder-lateness".into()));
+    }
+    if config.clients.path.is_some() && config.listen.is_some() {
+        return Err(TxnCliError::Validation("--clients opens accounts ahead of reading a file, it isn't supported by the server".into()));
+    }
+    if (config.rate.per_connection.is_some() || config.rate.global.is_some()) && config.listen.is_none() {
+        return Err(TxnCliError::Validation("--rate-limit & --global-rate-limit limit the server's connections, they need --listen".into()));
+    }
+    if config.auth.keys_file.is_some() && config.listen.is_none() && !config.api.read_only {
+        return Err(TxnCliError::Validation("--auth-keys authenticates the server's connections, it needs --listen or --read-only".into()));
+    }
+    if config.retention.keep_days.is_some() && config.listen.is_none() {
+        return Err(TxnCliError::Validation("--keep-days prunes by the server's clock, it needs --listen".into()));
+    }
+    if config.audit.path.is_some() && config.listen.is_none() {
+        return Err(TxnCliError::Validation("--audit-log records the server's adjustments & erasures, it needs --listen".into()));
+    }
+    if config.dedup.index.is_some() && config.listen.is_none() {
+        return Err(TxnCliError::Validation("--dedup-index keeps the server's transaction ids, it needs --listen".into()));
+    }
+    if (!config.replication.to.is_empty() || config.replication.listen.is_some()) && config.listen.is_none() {
+        return Err(TxnCliError::Validation("--replicate-to & --standby-listen replicate a server, they need --listen".into()));
+    }
+    if !config.replication.to.is_empty() && config.replication.listen.is_some() {
+        return Err(TxnCliError::Validation("a standby ships to no standbys of its own, set one of --replicate-to & --standby-listen".into()));
+    }
+    if config.replication.listen.is_some() && config.auth.keys_file.is_none() {
+        return Err(TxnCliError::Validation("--standby-listen needs --auth-keys, without them anyone who can reach it can ship to the standby".into()));
+    }
+    if !config.replication.to.is_empty() && config.admin.key_file.is_none() {
+        return Err(TxnCliError::Validation("--replicate-to needs --auth-key-file, the admin's key the standbys authenticate the primary by".into()));
+    }
+    if config.tls.cert.is_some() != config.tls.key.is_some() {
+        return Err(TxnCliError::Validation("--tls-cert & --tls-key go together".into()));
+    }
+    if config.tls.client_ca.is_some() && config.tls.cert.is_none() {
+        return Err(TxnCliError::Validation("--tls-client-ca verifies clients of a TLS server, it needs --tls-cert".into()));
+    }
+    if config.tls.cert.is_some() && !config.listen.as_deref().is_some_and(|l| l.starts_with("tcp:")) && config.api.listen.is_none()
+        && config.replication.listen.is_none() {
+        return Err(TxnCliError::Validation("--tls-cert terminates TLS on a tcp listener, it needs --listen tcp:<host:port>, --api-listen \
+                    or --standby-listen".into()));
+    }
+    if config.auth.keys_file.is_some() && config.tls.cert.is_none()
+        && config.api.listen.as_deref().is_some_and(|address| !crate::api::loopback(address)) {
+        return Err(TxnCliError::Validation("--api-listen off loopback needs --tls-cert with --auth-keys, or api keys cross the network in the clear".into()));
+    }
+    if config.listen.as_deref().is_some_and(|l| l.starts_with("tcp:")) && config.auth.keys_file.is_none() {
+        return Err(TxnCliError::Validation("a tcp listener needs --auth-keys, without them anyone who can reach it is an admin".into()));
+    }
+    if config.health.listen.is_some() && config.listen.is_none() && !config.api.read_only {
+        return Err(TxnCliError::Validation("--health-listen answers probes for the server, it needs --listen or --read-only".into()));
+    }
+    let filtered = config.query.filter_locked.is_some() || config.query.min_balance.is_some() || config.query.after.is_some()
+        || config.query.limit.is_some();
+    if (config.query.all || filtered) && (cli.command != Command::Query || !config.query.all || config.query.client.is_some()) {
+        return Err(TxnCliError::Validation("--all lists every client's account, a page at a time with --filter-locked, --min-balance, \
+                    --after & --limit: it's for txn query, not with --client".into()));
+    }
+    if config.api.read_only && (config.api.snapshot.is_none() || config.api.listen.is_none() || config.listen.is_some()
+                                || cli.input.is_some() || cli.command != Command::Process) {
+        return Err(TxnCliError::Validation("--read-only serves a --snapshot's accounts on --api-listen, taking no transactions: \
+                    not with --listen, an input or a command".into()));
+    }
+    if config.api.snapshot.is_some() && !config.api.read_only {
+        return Err(TxnCliError::Validation("--snapshot is what --read-only serves, it needs --read-only".into()));
+    }
+    if config.api.listen.is_some() && config.listen.is_none() && !config.api.read_only {
+        return Err(TxnCliError::Validation("--api-listen answers queries of the server's accounts, it needs --listen or --read-only".into()));
+    }
+    if config.tui && (config.listen.is_none() || !cfg!(feature = "tui")) {
+        return Err(TxnCliError::Validation("--tui is the server's dashboard: it needs --listen, and building with the `tui` feature".into()));
+    }
+    if config.lease.dir.is_some() && (!matches!(cli.command, Command::Process | Command::Tail) || config.api.read_only) {
+        return Err(TxnCliError::Validation("--lease-dir guards applying transactions to an output, it's for process, tail or the server".into()));
+    }
+    if config.api.read_only {
+        api::read_only(config)?;
+        return Ok(report);
+    }
+    // held until the run's over, its output written
+    let _lease = match config.dry_run {
+        true => None,
+        false => lease::hold(&config.lease, std::sync::Arc::new(clock))?
+    };
+
+    let file_path = match (&config.listen, &cli.input) {
+        (Some(address), None) => {
+            let watch = Config::file(cli.config.as_deref(), &env)
+                .map(|path| reload::Watch::new(path, env, cli.overrides.clone()));
+            server::serve(&server::Address::parse(address).map_err(TxnCliError::Validation)?, config, watch)?;
+            return Ok(report);
+        },
+        (None, Some(input)) => input.as_path(),
+        _ => return Err(TxnCliError::Validation(cli::USAGE.into()))
+    };
+    let checkpointing = config.checkpoint.every > 0 || config.checkpoint.resume;
+    if checkpointing && (cli.command == Command::Tail || file_path.to_str().is_none_or(is_remote)
+                         || !matches!(InputFormat::from_path(file_path), InputFormat::Csv)) {
+        return Err(TxnCliError::Validation("checkpoints are only supported when processing a local csv file".into()));
+    }
+    let unlisted = digest::check(&config.digests, file_path)?;
+    if config.tenants {
+        tenant::run(open_local_csv(file_path, "tenants")?, &config, &mut report)?;
+        if let (Some(unlisted), false) = (&unlisted, config.dry_run) {
+            unlisted.list(file_path)?;
+        }
+        return Ok(report);
+    }
+    if config.output.streaming {
+        stream::run(open_local_csv(file_path, "streamed outputs")?, &config, &mut report)?;
+        if let (Some(unlisted), false) = (&unlisted, config.dry_run) {
+            unlisted.list(file_path)?;
+        }
+        return Ok(report);
+    }
+    if cli.command == Command::Query {
+        query(file_path, &config, &mut report)?;
+        return Ok(report);
+    }
+    if cli.command == Command::History {
+        history(file_path, &config, &mut report)?;
+        return Ok(report);
+    }
+    if cli.command == Command::Analyze {
+        let analysis = analyze::Analysis::read(open_local_csv(file_path, "analyses")?, &config, &mut report)?;
+        write_text(&analysis, &config.output)?;
+        return Ok(report);
+    }
+    if cli.command == Command::Disputes {
+        let aging = aging::Aging::read(open_local_csv(file_path, "dispute reports")?, &config, &mut report)?;
+        write_text(&aging, &config.output)?;
+        return Ok(report);
+    }
+    if cli.command == Command::Verify {
+        let verification = reference::Verification::read(open_local_csv(file_path, "verifications")?, &config, &mut report)?;
+        write_text(&verification, &config.output)?;
+        if !verification.agrees() {
+            return Err(EngineError::Invariant(verification.disagreement()).into());
+        }
+        return Ok(report);
+    }
+    if let Some(path) = &config.clients.path {
+        registry::open(&mut accounts, registry::load(path)?, &config, &mut ());
+    }
+    if cli.command == Command::Tail {
+        tail_csv(&mut accounts, file_path, &config, &mut report)?;
+        return Ok(report);
+    }
+
+    if let Some(url) = file_path.to_str().filter(|p| http::is_url(p)) {
+        let reader = http::open(url, config.http.bearer_token.as_deref())?;
+        process_csv_reader(&mut accounts, reader, &config, &mut report)?;
+    } else if let Some(location) = file_path.to_str().and_then(object::Location::parse) {
+        let reader = object::open(&location?, config.object_store.chunk_size)?;
+        process_csv_reader(&mut accounts, reader, &config, &mut report)?;
+    } else {
+        match InputFormat::from_path(file_path) {
+            InputFormat::Csv => process_csv(&mut accounts, file_path, &config, &mut report)?,
+            InputFormat::Arrow => process_arrow(&mut accounts, file_path, &config, &mut report)?,
+            InputFormat::Avro => process_avro(&mut accounts, file_path, &config, &mut report)?,
+            InputFormat::Ofx => process_statement(&mut accounts, file_path, &config, &mut report,
+                                                  |c| statement::parse_ofx(c, config.statement.client, config.amount_precision()))?,
+            InputFormat::Qif => process_statement(&mut accounts, file_path, &config, &mut report,
+                                                  |c| statement::parse_qif(c, config.statement.client, config.amount_precision()))?,
+            InputFormat::Iso20022 => process_iso20022(&mut accounts, file_path, &config, &mut report)?,
+            InputFormat::Json => process_json(&mut accounts, file_path, &config, &mut report)?
+        }
+    }
+
+    finish(&accounts, &config, &report)?;
+    if let Some(operations) = &report.operations {
+        eprint!("{}", stats::Stats { operations, accounts: &accounts });
+    }
+    manifest::write(file_path, &accounts, &config, &report, started, &clock)?;
+    if checkpointing {
+        // the run completed, nothing is left to resume
+        checkpoint::Checkpoint::remove(&config.checkpoint.dir)?;
+    }
+    if let (Some(unlisted), false) = (&unlisted, config.dry_run) {
+        unlisted.list(file_path)?;
+    }
+    Ok(report)
+}
+
+/// writes out the balance `txn query` reconstructs
+fn query(file_path: &Path, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    if config.query.all {
+        let accounts = query::balances_at(open_local_csv(file_path, "queries")?, config.query.at_tx, config, report)?;
+        let listed = accounts.into_iter().filter(|(_, a)| config.output.empty_accounts || !a.is_empty());
+        let (page, next) = query::Filter::new(&config.query).page(listed);
+        write_out(&page.into_iter().collect(), &OutputOptions { sort: true, ..config.output.clone() })?;
+        if let Some(next) = next {
+            eprintln!("more accounts follow, the next page with --after {}", next);
+        }
+        return Ok(());
+    }
+    let (client, at) = match (config.query.client, config.query.at_tx) {
+        (Some(client), Some(at)) => (client, at),
+        _ => return Err("query needs --client and --at-tx".into())
+    };
+    let file = open_local_csv(file_path, "queries")?;
+    let account = query::balance_at(file, client, at, config, report)?
+        .ok_or_else(|| format!("client {} had no account after row {}", client, at))?;
+    Ok(write_out(&std::iter::once((client, account)).collect(), &config.output)?)
+}
+
+/// writes out the table `txn history` lists
+fn history(file_path: &Path, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    let client = config.query.client.ok_or("history needs --client")?;
+    let history = history::History::read(open_local_csv(file_path, "histories")?, client, config, report)?;
+    write_text(&history, &config.output)
+}
+
+/// a table to `options.path`, or stdout
+fn write_text(text: &dyn std::fmt::Display, options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
+    match &options.path {
+        Some(path) => std::fs::write(path, text.to_string())
+            .map_err(|e| TxnCliError::io(path, e))?,
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            write!(stdout, "{}", text)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// for the commands that only read a csv file from disk
+fn open_local_csv(file_path: &Path, what: &str) -> Result<std::fs::File, Box<dyn std::error::Error>> {
+    if file_path.to_str().is_none_or(is_remote) || !matches!(InputFormat::from_path(file_path), InputFormat::Csv) {
+        return Err(format!("{} are only supported on a local csv file", what).into());
+    }
+    Ok(std::fs::File::open(file_path).map_err(|e| TxnCliError::io(file_path, e))?)
+}
+
+/// http(s) & object storage inputs
+fn is_remote(input: &str) -> bool {
+    http::is_url(input) || object::Location::parse(input).is_some()
+}
+
+/// checks invariants, then writes balances out
+fn finish(accounts: &Accounts, config: &Config, report: &Report) -> Result<(), Box<dyn std::error::Error>> {
+    check_invariants(accounts)?;
+    if config.dry_run {
+        // validation only, the report stands in for the output
+        print!("{}", report);
+        std::io::stdout().flush()?;
+        Ok(())
+    } else {
+        Ok(write_out(accounts, &config.output)?)
+    }
+}
+
+/// applies the error policy to a malformatted row, record or batch
+fn malformatted(config: &Config, report: &mut Report, what: &str, detail: impl Into<pipeline::RowError>)
+                -> Result<(), Box<dyn std::error::Error>> {
+    let detail = detail.into();
+    match config.on_error {
+        ErrorPolicy::Abort => Err(TxnCliError::parse(what, &detail).into()),
+        ErrorPolicy::Skip | ErrorPolicy::Quarantine => {
+            eprintln!("skipping malformatted {}: {}", what, detail);
+            report.skip();
+            Ok(())
+        }
+    }
+}
+
+fn process_csv(accounts: &mut Accounts, file_path: &Path, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    let file = match std::fs::File::open(file_path) {
+        Ok(f) => f,
+        Err(e) => return Err(TxnCliError::io(file_path, e).into())
+    };
+    if config.on_error == ErrorPolicy::Quarantine {
+        return quarantine::process(accounts, file, file_path, config, report);
+    }
+    if config.checkpoint.every > 0 || config.checkpoint.resume {
+        return process_csv_checkpointed(accounts, file, file_path, config, report);
+    }
+    if config.mmap {
+        let mut columns = Columns::default();
+        mmap::process(&file, config.parse_threads, byte_record_parser(config),
+                      |txn| apply_batched(accounts, &mut columns, txn, config, report))?;
+        return record_columns(accounts, &mut columns, config, report);
+    }
+    #[cfg(feature = "io-uring")]
+    let file = uring::open(file);
+    process_csv_reader(accounts, file, config, report)
+}
+
+/// as `process_csv_reader`, saving a checkpoint every `checkpoint.every` rows and, with `checkpoint.resume`,
+/// first restoring the last one and seeking past the rows it covers, or with `checkpoint.replay_tolerant` reading
+/// them again
+fn process_csv_checkpointed(accounts: &mut Accounts, mut file: std::fs::File, file_path: &Path, config: &Config, report: &mut Report)
+                            -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{Seek, SeekFrom};
+
+    let input = std::fs::canonicalize(file_path)?;
+    let mut base = 0;
+    let key = checkpoint::Key::read(&config.checkpoint)?;
+    let resumed = match config.checkpoint.resume {
+        true => checkpoint::Checkpoint::load(&config.checkpoint.dir, key.as_ref())?,
+        false => None
+    };
+    if let Some(checkpoint) = resumed.as_ref().filter(|_| config.checkpoint.replay_tolerant) {
+        // the input, whatever's become of it, is read again from the start, the report counting just this run
+        *accounts = checkpoint.accounts()?;
+    } else if let Some(checkpoint) = resumed {
+        if checkpoint.input != input {
+            return Err(format!("checkpoint in {} was taken from {}", config.checkpoint.dir.display(), checkpoint.input.display()).into());
+        }
+        if file.metadata()?.len() < checkpoint.offset {
+            return Err("input is shorter than the checkpoint offset".into());
+        }
+        *accounts = checkpoint.accounts()?;
+        *report = Report { operations: report.operations.take(), ..checkpoint.report };
+        base = checkpoint.offset;
+        file.seek(SeekFrom::Start(base))?;
+    }
+
+    // past the header when resuming
+    let reader = csv::ReaderBuilder::new().has_headers(base == 0).from_reader(file);
+    let mut rows = 0u64;
+    let mut records = reader.into_records();
+    while let Some(row) = records.next() {
+        apply_row(accounts, row, config, report)?;
+        rows += 1;
+        if config.checkpoint.every > 0 && rows % config.checkpoint.every == 0 {
+            let offset = base + records.reader().position().byte();
+            checkpoint::Checkpoint::new(&input, offset, accounts, report).save(&config.checkpoint.dir, key.as_ref())?;
+        }
+    }
+    Ok(())
+}
+
+fn process_csv_reader<R: std::io::Read + Send + 'static>(accounts: &mut Accounts, reader: R, config: &Config, report: &mut Report)
+                                                        -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(lateness) = config.reorder.lateness {
+        let schedule = match &config.schedule.path {
+            Some(path) => Some(schedule::Schedule::load(path, config.amount_precision())?),
+            None => None
+        };
+        return process_csv_reordered(accounts, reader, lateness, schedule, config, report);
+    }
+    if config.parse_threads > 1 {
+        let mut columns = Columns::default();
+        pipeline::run(reader, config.parse_threads, byte_record_parser(config),
+                      |txn| apply_batched(accounts, &mut columns, txn, config, report))?;
+        return record_columns(accounts, &mut columns, config, report);
+    }
+    let options = CsvOptions { fast_parse: config.fast_parse, amount_locale: config.amount_locale,
+                          amount_policy: config.amount_policy, ..CsvOptions::default() };
+    process_source(accounts, &mut CsvSource::new(reader, options, config.amount_precision()), config, report)
+}
+
+/// executes a source's transactions under the error policy: the loop every single pass over an input runs
+fn process_source<S: TxnSource + ?Sized>(accounts: &mut Accounts, source: &mut S, config: &Config, report: &mut Report)
+                                         -> Result<(), Box<dyn std::error::Error>> {
+    let mut columns = Columns::default();
+    while let Some(txn) = source.next_txn() {
+        match txn {
+            Ok(t) if config.columnar => batch(accounts, &mut columns, t, config, report)?,
+            Ok(t) => record(accounts, t, config, report)?,
+            Err(e) if e.is_fatal() => return Err(TxnCliError::parse(e.what(), &e.into()).into()),
+            Err(e) => {
+                let rows = e.row_count();
+                malformatted(config, report, e.what(), e)?;
+                // the report counts rows, and every row of a batch went with it
+                report.skipped += rows - 1;
+            }
+        }
+    }
+    record_columns(accounts, &mut columns, config, report)
+}
+
+/// rows executed in timestamp order, see reorder.rs, with the schedule's occurrences among them
+fn process_csv_reordered<R: std::io::Read>(accounts: &mut Accounts, reader: R, lateness: u64,
+                                           mut schedule: Option<schedule::Schedule>, config: &Config, report: &mut Report)
+                                           -> Result<(), Box<dyn std::error::Error>> {
+    let mut buffer = reorder::Reorder::new(lateness);
+    for row in csv::Reader::from_reader(reader).into_records() {
+        let row = row.map_err(pipeline::RowError::from).and_then(|mut r| {
+            r.trim();
+            Ok(r.deserialize::<reorder::TimestampedRow>(None)?.into_txn(config.amount_precision())?)
+        });
+        let (timestamp, txn) = match row {
+            Ok(t) => t,
+            Err(e) => {
+                malformatted(config, report, "row", e)?;
+                continue;
+            }
+        };
+        // buffered ahead of the row, and never late: all those at or before the newest timestamp are already in
+        for (at, occurrence) in schedule.iter_mut().flat_map(|s| s.due(timestamp)) {
+            buffer.push(at, occurrence).expect("a scheduled occurrence is newer than the watermark");
+        }
+        if let Err(late) = buffer.push(timestamp, txn) {
+            report.record(Err(late));
+            continue;
+        }
+        while let Some(txn) = buffer.pop_ready() {
+            record(accounts, txn, config, report)?;
+        }
+    }
+    while let Some(txn) = buffer.pop() {
+        record(accounts, txn, config, report)?;
+    }
+    Ok(())
+}
+
+/// the row parser the parallel paths run, per `fast_parse`
+fn byte_record_parser(config: &Config) -> Box<pipeline::ParseRecord> {
+    let (precision, format) = (config.amount_precision(), locale::AmountFormat::of(config));
+    if config.fast_parse {
+        Box::new(move |r| {
+            locale::localize_bytes(r, format)?;
+            Ok(fastparse::parse_record(r, precision)?)
+        })
+    } else {
+        Box::new(move |r| {
+            locale::localize_bytes(r, format)?;
+            deserialize_byte_record(r, precision)
+        })
+    }
+}
+
+fn apply_row(accounts: &mut Accounts, row: csv::Result<csv::StringRecord>, config: &Config, report: &mut Report)
+             -> Result<(), Box<dyn std::error::Error>> {
+    let mut d = match row {
+        Ok(d) => d,
+        Err(e) => return malformatted(config, report, "row", e)
+    };
+
+    apply_txn(accounts, read_record(&mut d, config), config, report)
+}
+
+fn apply_txn<E: Into<pipeline::RowError>>(accounts: &mut Accounts, txn: Result<Txn, E>, config: &Config, report: &mut Report)
+                                    -> Result<(), Box<dyn std::error::Error>> {
+    match txn {
+        Ok(t) => record(accounts, t, config, report),
+        Err(e) => malformatted(config, report, "row", e)
+    }
+}
+
+/// as `apply_txn`, but batched under `--columnar`
+fn apply_batched<E: Into<pipeline::RowError>>(accounts: &mut Accounts, columns: &mut Columns, txn: Result<Txn, E>, config: &Config,
+                                              report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    match txn {
+        Ok(t) if config.columnar => batch(accounts, columns, t, config, report),
+        txn => apply_txn(accounts, txn, config, report)
+    }
+}
+
+/// adds the transaction to the batch, executing it once it's full. what's left is executed by `record_columns` once
+/// the input's been read
+fn batch(accounts: &mut Accounts, columns: &mut Columns, txn: Txn, config: &Config, report: &mut Report)
+         -> Result<(), Box<dyn std::error::Error>> {
+    columns.push(txn);
+    match columns.len() < columnar::BATCH {
+        true => Ok(()),
+        false => record_columns(accounts, columns, config, report)
+    }
+}
+
+/// executes & reports a batch under `--columnar` (see columnar.rs), emptying it, then checks the estimated memory
+fn record_columns(accounts: &mut Accounts, columns: &mut Columns, config: &Config, report: &mut Report)
+                  -> Result<(), Box<dyn std::error::Error>> {
+    if columns.is_empty() {
+        return Ok(());
+    }
+    let results = columnar::execute_columns_recorded(accounts, columns, config, report);
+    if let Some(operations) = &mut report.operations {
+        columns.clients().iter().for_each(|client| operations.count(*client));
+    }
+    for (txntype, result) in columns.types().iter().zip(results) {
+        report.record(result);
+        telemetry::observe(txntype, result);
+    }
+    columns.clear();
+    if let Some(limit) = config.limits.max_memory {
+        memory::check(accounts, limit).map_err(EngineError::Memory)?;
+    }
+    Ok(())
+}
+
+/// executes & reports a transaction, checking the estimated memory against `limits.max_memory` as the logs grow
+fn record(accounts: &mut Accounts, txn: Txn, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    if config.checkpoint.replay_tolerant && replayed(accounts, &txn) {
+        report.replayed += 1;
+        return Ok(());
+    }
+    if let Some(operations) = &mut report.operations {
+        operations.count(txn.client);
+    }
+    let txntype = txn.txntype.clone();
+    // the report tallies chargeback losses from the events
+    let result = execute_recorded(accounts, txn, config, report);
+    report.record(result);
+    telemetry::observe(&txntype, result);
+    if let Some(limit) = config.limits.max_memory {
+        if result.is_ok() && report.applied % memory::CHECK_EVERY == 0 {
+            memory::check(accounts, limit).map_err(EngineError::Memory)?;
+        }
+    }
+    Ok(())
+}
+
+/// follows a growing csv file, emitting balances (or the report, when dry running) after each poll that found rows.
+/// only returns on error, i.e. malformatted input under `on_error = "abort"`
+fn tail_csv(accounts: &mut Accounts, file_path: &Path, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tail = match tail::Tail::open(file_path) {
+        Ok(t) => t,
+        Err(e) => return Err(TxnCliError::io(file_path, e).into())
+    };
+
+    loop {
+        let rows = tail.poll()?;
+        if !rows.is_empty() {
+            for row in rows {
+                apply_row(accounts, row, config, report)?;
+            }
+            finish(accounts, config, report)?;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(config.tail.poll_ms));
+    }
+}
+
+enum InputFormat {
+    Csv,
+    Arrow,
+    Avro,
+    Ofx,
+    Qif,
+    Iso20022,
+    Json
+}
+
+impl InputFormat {
+    /// recognised by extension, anything else is read as csv
+    fn from_path(file_path: &Path) -> Self {
+        let ext = file_path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("arrow") | Some("arrows") | Some("feather") | Some("ipc") => InputFormat::Arrow,
+            Some("avro") => InputFormat::Avro,
+            Some("ofx") | Some("qfx") => InputFormat::Ofx,
+            Some("qif") => InputFormat::Qif,
+            Some("xml") => InputFormat::Iso20022,
+            Some("json") | Some("jsonl") | Some("ndjson") => InputFormat::Json,
+            _ => InputFormat::Csv
+        }
+    }
+}
+
+/// the error policy applies per row, or per batch when a whole batch is unreadable (i.e. a missing column)
+#[cfg(feature = "arrow")]
+fn process_arrow(accounts: &mut Accounts, file_path: &Path, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    let file = match std::fs::File::open(file_path) {
+        Ok(f) => f,
+        Err(e) => return Err(TxnCliError::io(file_path, e).into())
+    };
+    process_source(accounts, &mut arrow::ArrowSource::new(file, config.amount_precision())?, config, report)
+}
+
+#[cfg(not(feature = "arrow"))]
+fn process_arrow(_accounts: &mut Accounts, _file_path: &Path, _config: &Config, _report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    Err("Arrow input requires building with the `arrow` feature".into())
+}
+
+#[cfg(feature = "avro")]
+fn process_avro(accounts: &mut Accounts, file_path: &Path, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    let file = match std::fs::File::open(file_path) {
+        Ok(f) => f,
+        Err(e) => return Err(TxnCliError::io(file_path, e).into())
+    };
+
+    process_source(accounts, &mut avro::read_txns(std::io::BufReader::new(file), config.amount_precision())?, config, report)
+}
+
+#[cfg(not(feature = "avro"))]
+fn process_avro(_accounts: &mut Accounts, _file_path: &Path, _config: &Config, _report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    Err("Avro input requires building with the `avro` feature".into())
+}
+
+/// statements are small, so they're parsed whole. a malformatted statement always aborts,
+/// skipping part of a statement would silently misstate the balance.
+fn process_statement(accounts: &mut Accounts, file_path: &Path, config: &Config, report: &mut Report,
+                     parse: impl Fn(&str) -> Result<Vec<Txn>, String>) -> Result<(), Box<dyn std::error::Error>> {
+    let content = match std::fs::read_to_string(file_path) {
+        Ok(c) => c,
+        Err(e) => return Err(TxnCliError::io(file_path, e).into())
+    };
+
+    let txns = match parse(&content) {
+        Ok(t) => t,
+        Err(e) => return Err(TxnCliError::Parse { what: "statement".into(), line: None, detail: e }.into())
+    };
+
+    process_source(accounts, &mut Generator(txns.into_iter()), config, report)
+}
+
+fn process_json(accounts: &mut Accounts, file_path: &Path, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    let file = match std::fs::File::open(file_path) {
+        Ok(f) => f,
+        Err(e) => return Err(TxnCliError::io(file_path, e).into())
+    };
+    process_source(accounts, &mut JsonSource::new(std::io::BufReader::new(file), config.amount_precision()), config, report)
+}
+
+#[cfg(feature = "iso20022")]
+fn process_iso20022(accounts: &mut Accounts, file_path: &Path, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    process_statement(accounts, file_path, config, report, |xml| iso20022::parse(xml, config.amount_precision()))
+}
+
+#[cfg(not(feature = "iso20022"))]
+fn process_iso20022(_accounts: &mut Accounts, _file_path: &Path, _config: &Config, _report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    Err("ISO 20022 input requires building with the `iso20022` feature".into())
+}
+
+#[cfg(test)]
+mod engine_tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::{Config, SettlementOptions};
+    use crate::{AccountKind, Accounts, amount, Balance, check_invariants, ClientId, deposit, Event, execute, execute_with, get_account_mut,
+                get_balance, is_locked, prune_with, Rejection, Rounding, Txn, TxnId, withdraw};
+
+    #[test]
+    fn test_chargeback() {
+        let mut accounts = Accounts::default();
+        let client = 1;
+
+        // deposit 10 (tx 1), then 2 (tx 2)
+        execute(&mut accounts, Txn::deposit(client, 1, dec!(10)));
+        execute(&mut accounts, Txn::deposit(client, 2, dec!(2)));
+        assert_eq!(get_balance(&accounts, client).available, dec!(12.0));
+
+        // dispute tx 2
+        execute(&mut accounts, Txn::dispute(client, 2));
+        let balance = get_balance(&accounts, client);
+        assert_eq!(balance.available, dec!(10.0));
+        assert_eq!(balance.held, dec!(2.0));
+        assert_eq!(balance.total, dec!(12.0));
+
+        // chargeback
+        execute(&mut accounts, Txn::chargeback(client, 2));
+        let balance = get_balance(&accounts, client);
+        assert!(is_locked(&accounts, ClientId::from(client)));
+        assert_eq!(balance.held, dec!(0));
+        assert_eq!(balance.available, dec!(10));
+        assert_eq!(balance.total, dec!(10));
+        assert_eq!(accounts[&ClientId::from(client)].chargeback_loss, dec!(2))
+    }
+
+    #[test]
+    fn test_chargeback_undisputed() {
+        let mut accounts = Accounts::default();
+        let client = 1;
+
+        // start with a total
+        execute(&mut accounts, Txn::deposit(client, 1, dec!(10)));
+        assert_eq!(get_balance(&accounts, client).total, dec!(10.0));
+
+        // attempt a chargeback & assert nothing happened
+        execute(&mut accounts, Txn::chargeback(client, 1));
+        assert_eq!(get_balance(&accounts, client).total, dec!(10.0));
+    }
+
+    #[test]
+    fn test_locked() {
+        let mut accounts = Accounts::default();
+        let client = 1;
+
+        // start with an initial total
+        execute(&mut accounts, Txn::deposit(client, 1, dec!(10)));
+
+        // lock the account
+        get_account_mut(&mut accounts, client).apply(&Event::AccountLocked).unwrap();
+        assert!(is_locked(&accounts, ClientId::from(client)));
+
+        // assert we can no longer deposit
+        execute(&mut accounts, Txn::deposit(client, 2, dec!(2.0)));
+        assert_eq!(get_balance(&accounts, client).available, dec!(10.0));
+
+        // & assert we can not withdraw
+        execute(&mut accounts, Txn::deposit(client, 3, dec!(1.0)));
+        assert_eq!(get_balance(&accounts, client).available, dec!(10.0));
+    }
+
+    #[test]
+    fn test_lock_policy() {
+        let mut config = Config::default();
+        config.locked.deposits = true;
+        config.locked.disputes = true;
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(1, 1, dec!(10)));
+        get_account_mut(&mut accounts, 1).apply(&Event::AccountLocked).unwrap();
+
+        // a frozen account takes deposits, and disputes of them, but pays nothing out
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(1, 2, dec!(5)), &config), Ok(()));
+        assert_eq!(execute_with(&mut accounts, Txn::withdrawal(1, 3, dec!(1)), &config), Err(Rejection::Locked));
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 2), &config), Ok(()));
+        assert_eq!(execute_with(&mut accounts, Txn::resolve(1, 2), &config), Err(Rejection::Locked));
+        assert_eq!(get_balance(&accounts, 1).total, dec!(15));
+        assert_eq!(get_balance(&accounts, 1).held, dec!(5));
+        assert!(is_locked(&accounts, ClientId(1)));
+    }
+
+    #[test]
+    fn test_dispute_resolve() {
+        let mut accounts = Accounts::default();
+
+        // dispute
+        let tx = 10;
+        execute(&mut accounts, Txn::deposit(1, tx, dec!(10.0)));
+        execute(&mut accounts, Txn::dispute(1, tx));
+        let balance = get_balance(&accounts, 1);
+        assert_eq!(balance.available, dec!(0));
+        assert_eq!(balance.held, dec!(10.0));
+        assert_eq!(balance.total, dec!(10.0));
+
+        // resolve
+        execute(&mut accounts, Txn::resolve(1, tx));
+        let balance = get_balance(&accounts, 1);
+        assert_eq!(balance.available, dec!(10.0));
+        assert_eq!(balance.held, dec!(0));
+        assert_eq!(balance.total, dec!(10.0));
+    }
+
+    #[test]
+    fn test_dispute() {
+        let mut accounts = Accounts::default();
+
+        // deposit 10 (tx 1), then 2 (tx 2)
+        execute(&mut accounts, Txn::deposit(1, 1, dec!(10.0)));
+        execute(&mut accounts, Txn::deposit(1, 2, dec!(2.0)));
+        assert_eq!(get_balance(&accounts, 1).available, dec!(12.0));
+
+        // dispute tx 1
+        // assert available is 2 & held is 10
+        execute(&mut accounts, Txn::dispute(1, 1));
+        let balance = get_balance(&accounts, 1);
+        assert_eq!(balance.available, dec!(2.0));
+        assert_eq!(balance.held, dec!(10.0));
+
+        // total must remain as available + held
+        assert_eq!(balance.available.checked_add(balance.held).unwrap(), dec!(12.0));
+    }
+
+    #[test]
+    fn test_dispute_invalid_transaction() {
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(1, 1, dec!(10.0)));
+        assert_eq!(get_balance(&accounts, 1).available, dec!(10.0));
+
+        // dispute an invalid txn id & assert it was ignored
+        execute(&mut accounts, Txn::dispute(1, 50));
+        assert_eq!(get_balance(&accounts, 1).available, dec!(10.0));
+    }
+
+    #[test]
+    fn test_dispute_withdrawal_policy() {
+        let mut config = Config::default();
+        config.disputes.withdrawals = false;
+        let mut accounts = Accounts::default();
+
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(1, 1, dec!(10.0)), &config), Ok(()));
+        assert_eq!(execute_with(&mut accounts, Txn::withdrawal(1, 2, dec!(4.0)), &config), Ok(()));
+
+        // withdrawal disputes are ignored, deposit disputes still hold funds
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 2), &config), Err(Rejection::WithdrawalDispute));
+        assert_eq!(get_balance(&accounts, 1).held, dec!(0));
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 1), &config), Ok(()));
+        assert_eq!(get_balance(&accounts, 1).held, dec!(10.0));
+    }
+
+    #[test]
+    fn test_declined_withdrawal_dispute() {
+        let config = Config::default();
+        let mut accounts = Accounts::default();
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(1, 1, dec!(1)), &config), Ok(()));
+        assert_eq!(execute_with(&mut accounts, Txn::withdrawal(1, 2, dec!(5)), &config), Err(Rejection::InsufficientFunds));
+
+        // its id's taken, but it held nothing back to charge back
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 2), &config), Err(Rejection::Declined));
+        assert_eq!(get_balance(&accounts, 1).available, dec!(1));
+        assert_eq!(get_balance(&accounts, 1).held, dec!(0));
+    }
+
+    #[test]
+    fn test_reused_disputed_id() {
+        let config = Config::default();
+        let mut accounts = Accounts::default();
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(1, 1, dec!(1)), &config), Ok(()));
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 1), &config), Ok(()));
+
+        // charging back 5 would leave held at -4
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(1, 1, dec!(5)), &config), Err(Rejection::AlreadyDisputed));
+        assert_eq!(execute_with(&mut accounts, Txn::chargeback(1, 1), &config), Ok(()));
+        assert_eq!(get_balance(&accounts, 1).total, dec!(0));
+        assert_eq!(get_balance(&accounts, 1).held, dec!(0));
+    }
+
+    #[test]
+    fn test_redispute_policy() {
+        let mut config = Config::default();
+        let mut accounts = Accounts::default();
+        for tx in 1..=2 {
+            execute(&mut accounts, Txn::deposit(1, tx, dec!(5)));
+            execute(&mut accounts, Txn::dispute(1, tx));
+            execute(&mut accounts, Txn::resolve(1, tx));
+        }
+
+        // a resolved dispute may be raised again by default
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 1), &config), Ok(()));
+        config.disputes.redisputes = false;
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 2), &config), Err(Rejection::Redispute));
+        assert_eq!(get_balance(&accounts, 1).held, dec!(5));
+
+        // a chargeback is final: no resolve after it, nor a second refund were the account unlocked
+        assert_eq!(execute_with(&mut accounts, Txn::chargeback(1, 1), &config), Ok(()));
+        get_account_mut(&mut accounts, 1).locked = false;
+        assert_eq!(execute_with(&mut accounts, Txn::resolve(1, 1), &config), Err(Rejection::NotDisputed));
+        config.disputes.redisputes = true;
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 1), &config), Err(Rejection::ChargedBack));
+        assert_eq!(get_balance(&accounts, 1).total, dec!(5));
+    }
+
+    #[test]
+    fn test_dispute_other_clients_txn() {
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(1, 1, dec!(10)));
+        execute(&mut accounts, Txn::dispute(1, 1));
+        execute(&mut accounts, Txn::deposit(2, 2, dec!(3)));
+        let config = Config::default();
+
+        // client 1's disputed tx 1 by client 2, who has an account, and client 3, who hasn't
+        let cases = [
+            (Txn::dispute(2, 1), Rejection::UnknownTxn),
+            (Txn::resolve(2, 1), Rejection::NotDisputed),
+            (Txn::chargeback(2, 1), Rejection::NotDisputed),
+            (Txn::dispute(3, 1), Rejection::UnknownTxn),
+            (Txn::resolve(3, 1), Rejection::NotDisputed),
+            (Txn::chargeback(3, 1), Rejection::NotDisputed),
+            (Txn::dispute(3, 2), Rejection::UnknownTxn)
+        ];
+        for (txn, rejection) in cases {
+            assert_eq!(execute_with(&mut accounts, txn.clone(), &config), Err(rejection), "{:?}", txn);
+        }
+        assert!(!accounts.contains_key(&ClientId(3)));
+        assert_eq!(get_balance(&accounts, 1).held, dec!(10));
+        assert_eq!(get_balance(&accounts, 2).available, dec!(3));
+
+        // were another client's transaction logged against an account, i.e. one restored from elsewhere
+        let foreign = Txn::deposit(1, 3, dec!(1));
+        get_account_mut(&mut accounts, 2).txnlog.insert(foreign);
+        get_account_mut(&mut accounts, 2).disputes.insert(TxnId(3));
+        for txn in [Txn::resolve(2, 3), Txn::chargeback(2, 3)] {
+            assert_eq!(execute_with(&mut accounts, txn, &config), Err(Rejection::WrongClient));
+        }
+        get_account_mut(&mut accounts, 2).disputes.clear();
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(2, 3), &config), Err(Rejection::WrongClient));
+        assert_eq!(get_balance(&accounts, 2).held, dec!(0));
+    }
+
+    #[test]
+    fn test_balance_overflow() {
+        #[cfg(not(feature = "fixed-point"))]
+        let max = rust_decimal::Decimal::MAX;
+        #[cfg(feature = "fixed-point")]
+        let max = dec!(922337203685477);
+        let config = Config::default();
+        let mut accounts = Accounts::default();
+
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(1, 1, max), &config), Ok(()));
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(1, 2, max), &config), Err(Rejection::Overflow));
+        assert_eq!(get_balance(&accounts, 1).total, max);
+
+        // the overflowing deposit was never logged, so it can't be disputed
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 2), &config), Err(Rejection::UnknownTxn));
+        assert!(check_invariants(&accounts).is_ok());
+    }
+
+    #[test]
+    fn test_max_amount() {
+        let mut config = Config::default();
+        config.limits.max_amount = Some(dec!(100));
+        let mut accounts = Accounts::default();
+
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(1, 1, dec!(100)), &config), Ok(()));
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(1, 2, dec!(100.0001)), &config), Err(Rejection::OverLimit));
+        assert_eq!(execute_with(&mut accounts, Txn::withdrawal(1, 3, dec!(100.0001)), &config), Err(Rejection::OverLimit));
+        assert_eq!(get_balance(&accounts, 1).total, dec!(100));
+
+        // the over-limit deposit was never logged, so it can't be disputed
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 2), &config), Err(Rejection::UnknownTxn));
+        assert_eq!(get_balance(&accounts, 1).held, dec!(0));
+    }
+
+    #[test]
+    fn test_invariants() {
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(1, 1, dec!(10)));
+        execute(&mut accounts, Txn::withdrawal(1, 2, dec!(10)));
+        execute(&mut accounts, Txn::dispute(1, 1));
+        execute(&mut accounts, Txn::chargeback(1, 1));
+        // negative available & total after charging back spent funds is legitimate
+        assert!(check_invariants(&accounts).is_ok());
+
+        get_account_mut(&mut accounts, 2).balance.total = amount(dec!(1));
+        assert!(check_invariants(&accounts).is_err());
+    }
+
+    #[test]
+    fn test_deposit_withdraw() {
+        let mut accounts = Accounts::default();
+
+        deposit(get_account_mut(&mut accounts, 1), ClientId(1), TxnId(1), amount(dec!(42.0)), &mut ()).unwrap();
+        assert_eq!(dec!(42), get_balance(&accounts, 1).available);
+
+        assert_eq!(withdraw(get_account_mut(&mut accounts, 1), ClientId(1), TxnId(2), amount(dec!(42.0)), &SettlementOptions::default(), &mut ()), Ok(()));
+        assert_eq!(dec!(0), get_balance(&accounts, 1).available);
+    }
+
+    #[test]
+    fn test_withdraw_exceeds_available() {
+        let mut accounts = Accounts::default();
+        deposit(get_account_mut(&mut accounts, 1), ClientId(1), TxnId(1), amount(dec!(42.0)), &mut ()).unwrap();
+
+        let withdrawal = amount(dec!(0.0001));
+        assert_eq!(withdraw(get_account_mut(&mut accounts, 1), ClientId(1), TxnId(2), withdrawal, &SettlementOptions::default(), &mut ()), Ok(()));
+        let expected = dec!(41.9999);
+        assert_eq!(get_balance(&accounts, 1).available, expected);
+
+        assert_eq!(withdraw(get_account_mut(&mut accounts, 1), ClientId(1), TxnId(2), amount(dec!(42.0)), &SettlementOptions::default(), &mut ()), Err(Rejection::InsufficientFunds));
+        assert_eq!(get_balance(&accounts, 1).available, expected);
+    }
+
+    #[test]
+    fn test_settlement_delay() {
+        let mut config = Config::default();
+        config.settlement.delay = 2;
+        let mut accounts = Accounts::default();
+        execute_with(&mut accounts, Txn::deposit(1, 1, dec!(10)), &config).unwrap();
+        execute_with(&mut accounts, Txn::withdrawal(1, 2, dec!(4)), &config).unwrap();
+        // held, the total unchanged, until the account has seen 2 more deposits & withdrawals
+        assert_eq!(get_balance(&accounts, 1), Balance { available: amount(dec!(6)), held: amount(dec!(4)), total: amount(dec!(10)) });
+        assert_eq!(execute_with(&mut accounts, Txn::withdrawal(1, 3, dec!(7)), &config), Err(Rejection::InsufficientFunds));
+        assert_eq!(get_balance(&accounts, 1).held, dec!(4));
+        execute_with(&mut accounts, Txn::deposit(1, 4, dec!(1)), &config).unwrap();
+        assert_eq!(get_balance(&accounts, 1), Balance { available: amount(dec!(7)), held: amount(dec!(0)), total: amount(dec!(7)) });
+        assert!(accounts[&ClientId(1)].settling.is_empty());
+
+        // held withdrawals still settle once the delay's turned off
+        execute_with(&mut accounts, Txn::withdrawal(1, 5, dec!(2)), &config).unwrap();
+        config.settlement.delay = 0;
+        execute_with(&mut accounts, Txn::withdrawal(1, 6, dec!(1)), &config).unwrap();
+        assert_eq!(get_balance(&accounts, 1), Balance { available: amount(dec!(4)), held: amount(dec!(0)), total: amount(dec!(4)) });
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut config = Config::default();
+        config.retention.keep_last = Some(4);
+        config.settlement.delay = 8;
+        let mut accounts = Accounts::default();
+        execute_with(&mut accounts, Txn::deposit(1, 1, dec!(10)), &config).unwrap();
+        execute_with(&mut accounts, Txn::withdrawal(1, 2, dec!(1)), &config).unwrap();
+        execute_with(&mut accounts, Txn::deposit(1, 3, dec!(1)), &config).unwrap();
+        execute_with(&mut accounts, Txn::dispute(1, 3), &config).unwrap();
+        for tx in 4..=7 {
+            execute_with(&mut accounts, Txn::deposit(1, tx, dec!(1)), &config).unwrap();
+        }
+        // the settling 2 & disputed 3 don't count towards the quarter past 4
+        assert_eq!(accounts[&ClientId(1)].pruned, 0);
+        execute_with(&mut accounts, Txn::deposit(1, 8, dec!(1)), &config).unwrap();
+        // 1 & 4 are pruned, 2 & 3 kept among the newest 4
+        let account = &accounts[&ClientId(1)];
+        assert_eq!((account.txnlog.len(), account.pruned), (6, 2));
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 1), &config), Err(Rejection::Pruned));
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 4), &config), Err(Rejection::Pruned));
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 9), &config), Err(Rejection::UnknownTxn));
+        // below the newest pruned, but never logged by this account
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 0), &config), Err(Rejection::UnknownTxn));
+        // the withdrawal settles on time, pruned transactions counting towards its delay
+        execute_with(&mut accounts, Txn::deposit(1, 9, dec!(1)), &config).unwrap();
+        assert!(!accounts[&ClientId(1)].settling.is_empty());
+        execute_with(&mut accounts, Txn::deposit(1, 10, dec!(1)), &config).unwrap();
+        assert_eq!(get_balance(&accounts, 1).held, dec!(1));
+        assert!(accounts[&ClientId(1)].settling.is_empty());
+
+        // pruned through a tx, whatever's left that's undisputed
+        execute_with(&mut accounts, Txn::resolve(1, 3), &config).unwrap();
+        prune_with(&mut accounts, TxnId(9));
+        let account = &accounts[&ClientId(1)];
+        let mut logged: Vec<TxnId> = account.txnlog.keys().copied().collect();
+        logged.sort_unstable();
+        assert_eq!(logged, vec![TxnId(10)]);
+        assert_eq!(account.logged(), 10);
+        assert!((1..=9u32).map(TxnId::from).all(|tx| account.pruned_txs.contains(tx)));
+        assert!(account.resolved.is_empty());
+        assert_eq!(get_balance(&accounts, 1).total, dec!(17));
+    }
+
+    #[test]
+    fn test_retention_by_tx() {
+        let mut config = Config::default();
+        config.retention.keep_last = Some(4);
+        let mut accounts = Accounts::default();
+        // 1 logged last is the oldest by tx, so it's pruned first, with 10
+        for tx in [10, 11, 12, 13, 14, 1] {
+            execute_with(&mut accounts, Txn::deposit(1, tx, dec!(1)), &config).unwrap();
+        }
+        let account = &accounts[&ClientId(1)];
+        let mut logged: Vec<TxnId> = account.txnlog.keys().copied().collect();
+        logged.sort_unstable();
+        assert_eq!(logged, [11, 12, 13, 14u32].map(TxnId::from));
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 1), &config), Err(Rejection::Pruned));
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 10), &config), Err(Rejection::Pruned));
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(1, 5), &config), Err(Rejection::UnknownTxn));
+    }
+
+    #[test]
+    fn test_retention_pinned() {
+        let mut config = Config::default();
+        config.retention.keep_last = Some(20);
+        let mut accounts = Accounts::default();
+        // more disputes open than a quarter of those kept
+        for tx in 1..=6 {
+            execute_with(&mut accounts, Txn::deposit(1, tx, dec!(1)), &config).unwrap();
+            execute_with(&mut accounts, Txn::dispute(1, tx), &config).unwrap();
+        }
+        let mut prunes = 0;
+        for tx in 7..=106 {
+            let pruned = accounts[&ClientId(1)].pruned;
+            execute_with(&mut accounts, Txn::deposit(1, tx, dec!(1)), &config).unwrap();
+            prunes += usize::from(accounts[&ClientId(1)].pruned > pruned);
+        }
+        // still pruned once every quarter of growth, not on every deposit
+        assert!(prunes <= 100 / 5, "pruned on {} of 100 deposits", prunes);
+        let account = &accounts[&ClientId(1)];
+        assert!(account.txnlog.len() <= 6 + 25);
+        assert!((1..=6u32).map(TxnId::from).all(|tx| account.txnlog.get(&tx).is_some()));
+    }
+
+    #[test]
+    fn test_account_kinds() {
+        let mut config = Config::default();
+        config.kinds.merchants = "10-19".parse().unwrap();
+        config.kinds.escrow = "20".parse().unwrap();
+        config.kinds.reserve = dec!(0.1);
+        let mut accounts = Accounts::default();
+        for txn in [Txn::deposit(10, 1, dec!(100)), Txn::deposit(10, 2, dec!(5)), Txn::deposit(20, 3, dec!(5)), Txn::deposit(1, 4, dec!(5))] {
+            execute_with(&mut accounts, txn, &config).unwrap();
+        }
+        assert_eq!(accounts[&ClientId(10)].kind, AccountKind::Merchant);
+        assert_eq!(accounts[&ClientId(20)].kind, AccountKind::Escrow);
+        assert_eq!(accounts[&ClientId(1)].kind, AccountKind::Customer);
+        // a tenth of each merchant deposit is held in reserve
+        assert_eq!(get_balance(&accounts, 10), Balance { available: amount(dec!(94.5)), held: amount(dec!(10.5)), total: amount(dec!(105)) });
+
+        // the reserve covers a chargeback of 5, the merchant carries on unlocked
+        execute_with(&mut accounts, Txn::dispute(10, 2), &config).unwrap();
+        execute_with(&mut accounts, Txn::chargeback(10, 2), &config).unwrap();
+        assert_eq!(get_balance(&accounts, 10), Balance { available: amount(dec!(94.5)), held: amount(dec!(5.5)), total: amount(dec!(100)) });
+        assert!(!is_locked(&accounts, ClientId(10)));
+        // but not one of 100, which is charged back as a customer's would be
+        execute_with(&mut accounts, Txn::dispute(10, 1), &config).unwrap();
+        execute_with(&mut accounts, Txn::chargeback(10, 1), &config).unwrap();
+        assert_eq!(get_balance(&accounts, 10), Balance { available: amount(dec!(-5.5)), held: amount(dec!(5.5)), total: amount(dec!(0)) });
+        assert!(is_locked(&accounts, ClientId(10)));
+        // both are the merchant's losses
+        assert_eq!(accounts[&ClientId(10)].chargeback_loss, dec!(105));
+
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(20, 3), &config), Err(Rejection::EscrowDispute));
+        assert!(check_invariants(&accounts).is_ok());
+    }
+
+    #[test]
+    fn test_reserve_overflow() {
+        #[cfg(not(feature = "fixed-point"))]
+        let max = rust_decimal::Decimal::MAX;
+        #[cfg(feature = "fixed-point")]
+        let max = dec!(922337203685477);
+        let mut config = Config::default();
+        config.kinds.merchants = "10".parse().unwrap();
+        config.kinds.reserve = dec!(0.5);
+        let mut accounts = Accounts::default();
+        // a disputed deposit of 0.6 max leaves 0.9 max held, against a total of 0.6
+        let (first, second) = ((max * dec!(0.6)).round(), (max * dec!(0.3)).round());
+        execute_with(&mut accounts, Txn::deposit(10, 1, first), &config).unwrap();
+        execute_with(&mut accounts, Txn::dispute(10, 1), &config).unwrap();
+        let before = accounts[&ClientId(10)].clone();
+
+        // the total takes 0.3 max more, held not its half on top
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(10, 2, second), &config), Err(Rejection::Overflow));
+        assert_eq!(accounts[&ClientId(10)], before);
+        assert_eq!(execute_with(&mut accounts, Txn::dispute(10, 2), &config), Err(Rejection::UnknownTxn));
+        // one the reserve fits into is taken
+        assert_eq!(execute_with(&mut accounts, Txn::deposit(10, 3, dec!(2)), &config), Ok(()));
+        assert_eq!(accounts[&ClientId(10)].reserve, before.reserve.to_decimal() + dec!(1));
+        assert!(check_invariants(&accounts).is_ok());
+    }
+
+    #[test]
+    fn test_reserve_rounding() {
+        let mut config = Config::default();
+        config.kinds.merchants = "10".parse().unwrap();
+        config.kinds.reserve = dec!(0.5);
+        // half of 0.0025 is 0.00125, a tie at 4 places
+        for (rounding, reserve) in [(Rounding::HalfEven, dec!(0.0012)), (Rounding::HalfUp, dec!(0.0013)), (Rounding::Down, dec!(0.0012))] {
+            config.rounding = rounding;
+            let mut accounts = Accounts::default();
+            execute_with(&mut accounts, Txn::deposit(10, 1, dec!(0.0025)), &config).unwrap();
+            assert_eq!(accounts[&ClientId(10)].reserve, reserve, "{:?}", rounding);
+        }
+    }
+
+    #[test]
+    fn test_withdraw_empty_account() {
+        let mut accounts = Accounts::default();
+
+        assert_eq!(withdraw(get_account_mut(&mut accounts, 1), ClientId(1), TxnId(1), amount(dec!(1)), &SettlementOptions::default(), &mut ()), Err(Rejection::InsufficientFunds));
+        assert_eq!(dec!(0), get_balance(&accounts, 1).available);
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use rust_decimal::Decimal;
+    use rust_decimal::prelude::FromStr;
+    use rust_decimal_macros::dec;
+
+    use crate::config::OutputOptions;
+    use crate::{Account, Accounts, amount, Balance, ClientId, ClientRepr, CURRENCY_PRECISION, deposit, deserialize_record, execute, get_account_mut,
+                get_balance, single_pass, Txn, TxnId, TxnRepr, TxnType, write_out};
+
+    #[test]
+    fn test_deposit() {
+        let mut accounts = Accounts::default();
+        deposit(get_account_mut(&mut accounts, 1), ClientId(1), TxnId(1), amount(dec!(3.14)), &mut ()).unwrap();
+        let acc = get_balance(&accounts, 1);
+        assert_eq!(acc.available, dec!(3.14));
+        assert_eq!(acc.total, dec!(3.14));
+    }
+
+    #[test]
+    fn test_txn_eq() {
+        assert_eq!(Txn::withdrawal(1, 2, Decimal::new(1, 0)),
+        Txn::withdrawal(1, 2, dec!(1.0)));
+
+        assert_ne!(Txn::withdrawal(1, 2, Decimal::new(1, 0)),
+        Txn::withdrawal(1, 2, dec!(1.0001)));
+    }
+
+    #[test]
+    fn test_decimal_truncate() {
+        assert_eq!(dec!(3.14159).round_dp(4), dec!(3.1416));
+    }
+
+    #[test]
+    fn test_txn_precision() {
+        assert_eq!(Txn::withdrawal(1, 2, dec!(1.11111)),
+                   Txn::new(TxnType::Withdrawal, ClientId(1), TxnId(2), Some(amount(dec!(1.1111)))));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let mut record = csv::StringRecord::from(vec!["deposit", "1", "2", "3.1459"]);
+        assert_eq!(deserialize_record(&mut record, CURRENCY_PRECISION).unwrap(), Txn::deposit(1, 2, dec!(3.1459)));
+    }
+
+    #[test]
+    fn test_deserialize_missing_amount() {
+        let mut record = csv::StringRecord::from(vec!["dispute", "1", "2", ""]);
+        assert_eq!(deserialize_record(&mut record, CURRENCY_PRECISION).unwrap(), Txn::dispute(1, 2));
+    }
+
+    #[test]
+    fn test_deserialize_whitespace() {
+        let mut record = csv::StringRecord::from(vec!["    withdrawal", " 1", " 2 ", "3   "]);
+        assert_eq!(deserialize_record(&mut record, CURRENCY_PRECISION).unwrap(), Txn::withdrawal(1, 2, Decimal::from_str("3.0").unwrap()));
+    }
+
+    #[test]
+    fn test_deserialize_decimal() {
+        let mut record = csv::StringRecord::from(vec!["deposit", "1", "2", "3.1459265"]);
+        println!("out: {:?}", deserialize_record(&mut record, CURRENCY_PRECISION).unwrap());
+        assert_eq!(deserialize_record(&mut record, CURRENCY_PRECISION).unwrap(), Txn::deposit(1, 2, dec!(3.1459)));
+    }
+
+    #[test]
+    fn test_deserialize_decimal_precision() {
+        let mut record = csv::StringRecord::from(vec!["deposit", "1", "2", "3.1459265"]);
+        assert_eq!(deserialize_record(&mut record, CURRENCY_PRECISION).unwrap(), Txn::deposit(1, 2, dec!(3.1459)));
+    }
+
+    #[test]
+    fn test_deserialize_invalid_client_id() {
+        let mut underflow = csv::StringRecord::from(vec!["deposit", (u16::MIN as i32 - 1).to_string().as_str(), "1", "3.1459265"]);
+        let mut overflow = csv::StringRecord::from(vec!["deposit", (ClientRepr::MAX as i128 + 1).to_string().as_str(), "2", "3.1459265"]);
+        assert!(deserialize_record(&mut underflow, CURRENCY_PRECISION).is_err());
+        assert!(deserialize_record(&mut overflow, CURRENCY_PRECISION).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_amount_rules() {
+        let mut deposit = csv::StringRecord::from(vec!["deposit", "1", "2", ""]);
+        let mut dispute = csv::StringRecord::from(vec!["dispute", "1", "2", "3.0"]);
+        assert_eq!(deserialize_record(&mut deposit, CURRENCY_PRECISION).unwrap_err().to_string(), "amount required");
+        assert_eq!(deserialize_record(&mut dispute, CURRENCY_PRECISION).unwrap_err().to_string(), "amount not allowed");
+    }
+
+    #[test]
+    fn test_deserialize_invalid_txn_id() {
+        let mut underflow = csv::StringRecord::from(vec!["deposit", "1", (u32::MIN as i128 - 1).to_string().as_str(), "3.1459265"]);
+        let mut overflow = csv::StringRecord::from(vec!["deposit", "1", (TxnRepr::MAX as i128 + 1).to_string().as_str(), "3.1459265"]);
+        assert!(deserialize_record(&mut underflow, CURRENCY_PRECISION).is_err());
+        assert!(deserialize_record(&mut overflow, CURRENCY_PRECISION).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_field_context() {
+        let csv = "type,client,tx,amount\ndeposit,1,1,1\ndeposit,1,2,\" 12,50 \"\nrefund,1,3,1\ndeposit,x,4,1\n";
+        let errors: Vec<String> = csv::Reader::from_reader(csv.as_bytes()).records()
+            .map(|r| deserialize_record(&mut r.unwrap(), CURRENCY_PRECISION))
+            .filter_map(|t| t.err().map(|e| e.to_string()))
+            .collect();
+        // the value trimmed, as it was read
+        assert_eq!(errors, ["line 3: amount '12,50' is not a valid decimal",
+                            "line 4: type 'refund' is not a valid transaction type",
+                            "line 5: client 'x' is not a valid client id"]);
+    }
+
+    #[test]
+    fn test_write_out() {
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(2, 1, dec!(1.5)));
+        execute(&mut accounts, Txn::deposit(1, 2, dec!(10)));
+        execute(&mut accounts, Txn::dispute(1, 2));
+        // opens an account, nothing more
+        execute(&mut accounts, Txn::withdrawal(3, 3, dec!(1)));
+
+        let path = std::env::temp_dir().join(format!("txn-write-out-{}.csv", std::process::id()));
+        // a buffer smaller than a row still writes every row whole
+        let mut options = OutputOptions { path: Some(path.clone()), sort: true, buffer_size: 4, ..OutputOptions::default() };
+        let written = |options: &OutputOptions| {
+            write_out(&accounts, options).unwrap();
+            std::fs::read_to_string(&path).unwrap()
+        };
+        assert_eq!(written(&options), "client,available,held,total,locked\n1,0.0000,10.0000,10.0000,false\n2,1.5000,0.0000,1.5000,false\n3,0.0000,0.0000,0.0000,false\n");
+        options.empty_accounts = false;
+        assert_eq!(written(&options), "client,available,held,total,locked\n1,0.0000,10.0000,10.0000,false\n2,1.5000,0.0000,1.5000,false\n");
+        std::fs::remove_file(&path).unwrap();
+
+        let unwritable = OutputOptions { path: Some(std::env::temp_dir()), ..OutputOptions::default() };
+        assert!(write_out(&accounts, &unwritable).is_err());
+    }
+
+    #[test]
+    fn test_serde() {
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(1, 2, dec!(2.5)));
+        execute(&mut accounts, Txn::deposit(1, 1, dec!(1)));
+        execute(&mut accounts, Txn::dispute(1, 2));
+
+        let account = &accounts[&ClientId(1)];
+        let json = serde_json::to_string(account).unwrap();
+        assert_eq!(json, concat!(r#"{"balance":{"available":"1","held":"2.5","total":"3.5"},"disputes":[2],"resolved":[],"#,
+                                 r#""charged_back":[],"txnlog":["#,
+                                 r#"{"type":"deposit","client":1,"tx":1,"amount":"1"},"#,
+                                 r#"{"type":"deposit","client":1,"tx":2,"amount":"2.5"}],"locked":false,"funded":true,"#,
+                                 r#""settling":[],"kind":"customer","reserve":"0","name":null,"currency":null,"#,
+                                 r#""chargeback_loss":"0","adjusted":"0","forgotten":false,"pruned":0,"pruned_txs":[]}"#));
+        assert_eq!(&serde_json::from_str::<Account>(&json).unwrap(), account);
+
+        let dispute = Txn::dispute(1, 2);
+        let json = serde_json::to_string(&dispute).unwrap();
+        assert_eq!(json, r#"{"type":"dispute","client":1,"tx":2,"amount":null}"#);
+        assert_eq!(serde_json::from_str::<Txn>(&json).unwrap(), dispute);
+        assert_eq!(serde_json::from_str::<Txn>(r#"{"type":"dispute","client":1,"tx":2}"#).unwrap(), dispute);
+
+        assert!(serde_json::from_str::<Balance>(r#"{"available":"1","held":"1","total":"3"}"#).is_err());
+        let twice = concat!(r#"{"balance":{"available":"0","held":"0","total":"0"},"disputes":[],"txnlog":["#,
+                            r#"{"type":"deposit","client":1,"tx":1,"amount":"1"},"#,
+                            r#"{"type":"deposit","client":1,"tx":1,"amount":"1"}],"locked":false}"#);
+        assert!(serde_json::from_str::<Account>(twice).is_err());
+    }
+
+    #[test]
+    fn test_single_pass() {
+        let check = |args: &[&str]| {
+            let cli = crate::cli::parse(args.iter().map(std::ffi::OsString::from)).unwrap();
+            let config = crate::config::Config::resolve(None, Vec::new(), &cli.overrides).unwrap();
+            single_pass(&config, &cli)
+        };
+        assert!(check(&["--tenants", "--stream-output", "in.csv"]).unwrap_err()
+            .starts_with("--stream-output is only supported processing a local csv file in one pass"));
+        assert!(check(&["--tenants", "--clients", "clients.csv", "in.csv"]).unwrap_err().ends_with("--reorder-lateness, --clients"));
+        assert!(check(&["--on-error", "quarantine", "s3://bucket/in.csv"]).is_err());
+        assert!(check(&["--on-error", "quarantine", "--parse-threads", "2", "in.csv"]).is_err());
+        assert!(check(&["--stream-output", "in.json"]).is_err());
+        assert!(check(&["query", "--tenants", "in.csv"]).is_err());
+        // reordering reads for any command but tail, from wherever
+        assert!(check(&["query", "--reorder-lateness", "10", "s3://bucket/in.csv"]).is_ok());
+        assert!(check(&["tail", "--reorder-lateness", "10", "in.csv"]).is_err());
+        assert!(check(&["--tenants", "--on-error", "quarantine", "in.csv"]).unwrap_err().starts_with("--on-error quarantine"));
+        assert!(check(&["--stream-output", "--output-shards", "1", "in.csv"]).is_ok());
+        assert!(check(&["in.csv", "--parse-threads", "2"]).is_ok());
+    }
+}
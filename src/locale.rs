@@ -0,0 +1,243 @@
+//! `--amount-locale`: csv amounts written with a decimal comma or thousands separators, `1.234,56` or `1,234.56`,
+//! read as the plain decimals the parsers take. it's opt in: under the default `strict` an amount is a plain decimal
+//! and anything else is malformatted, as it always was.
+//! - `comma`: `,` separates the decimals, `.` or a space the thousands: `1.234,56`, `1 234,5`, `12,50`
+//! - `dot`: `.` separates the decimals, `,` or a space the thousands: `1,234.56`
+//! - `auto`: by the separators the amount has, the last being the decimal one when there are two kinds. an amount
+//!   with a single separator followed by three digits, `1,234` or `1.234`, could be either, so it's malformatted
+//!
+//! thousands have to be grouped in threes by the one separator throughout: `1.23.4` and `1.234 567` are
+//! malformatted in any locale.
+//!
+//! `--amount-policy` (see excess.rs) is applied here too, to the amount as normalized.
+
+use std::borrow::Cow;
+
+use crate::config::{AmountLocale, AmountPolicy, Config};
+use crate::excess;
+use crate::on_line;
+use crate::pipeline::RowError;
+use crate::Precision;
+
+/// the amount's column
+const AMOUNT: usize = 3;
+
+/// how a csv amount's text is read before parsing: its locale, and what's done with one past the precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AmountFormat {
+    pub(crate) locale: AmountLocale,
+    pub(crate) policy: Option<AmountPolicy>,
+    pub(crate) precision: Precision
+}
+
+impl AmountFormat {
+    pub(crate) fn of(config: &Config) -> Self {
+        AmountFormat { locale: config.amount_locale, policy: config.amount_policy, precision: config.amount_precision() }
+    }
+
+    /// whether amounts are read as they're written
+    fn is_plain(&self) -> bool {
+        self.locale == AmountLocale::Strict && self.policy.is_none()
+    }
+}
+
+/// the amount as a plain decimal, or why it doesn't read as one in `locale`
+pub(crate) fn normalize(amount: &str, locale: AmountLocale) -> Result<Cow<'_, str>, String> {
+    let locale = match locale {
+        AmountLocale::Strict => return Ok(Cow::Borrowed(amount)),
+        AmountLocale::Auto => detect(amount)?,
+        locale => locale
+    };
+    let (decimal, grouping): (char, &[char]) = match locale {
+        AmountLocale::Comma => (',', &['.', ' ']),
+        _ => ('.', &[',', ' '])
+    };
+    if !amount.contains(|c| c == decimal || grouping.contains(&c)) || (decimal == '.' && !amount.contains(grouping)) {
+        return Ok(Cow::Borrowed(amount));
+    }
+    let invalid = || format!("amount '{}' is not a valid decimal in the {} locale", amount, name(locale));
+    let (sign, unsigned) = match amount.strip_prefix(['-', '+']) {
+        Some(unsigned) => (&amount[..1], unsigned),
+        None => ("", amount)
+    };
+    let (integer, fraction) = match unsigned.rfind(decimal) {
+        Some(i) => (&unsigned[..i], Some(&unsigned[i + 1..])),
+        None => (unsigned, None)
+    };
+    let integer = match integer.chars().find(|c| grouping.contains(c)) {
+        Some(separator) => {
+            let mut groups = integer.split(separator);
+            let first = groups.next().unwrap_or_default();
+            if first.is_empty() || first.len() > 3 || groups.any(|g| g.len() != 3) {
+                return Err(invalid());
+            }
+            Cow::Owned(integer.replace(separator, ""))
+        },
+        None => Cow::Borrowed(integer)
+    };
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if !digits(&integer) || !fraction.is_none_or(digits) {
+        return Err(invalid());
+    }
+    Ok(Cow::Owned(match fraction {
+        Some(fraction) => format!("{}{}.{}", sign, integer, fraction),
+        None => format!("{}{}", sign, integer)
+    }))
+}
+
+/// the locale an amount's separators say it's in, under `auto`
+fn detect(amount: &str) -> Result<AmountLocale, String> {
+    match (amount.rfind('.'), amount.rfind(',')) {
+        (Some(dot), Some(comma)) => Ok(if dot > comma { AmountLocale::Dot } else { AmountLocale::Comma }),
+        (None, None) => Ok(AmountLocale::Dot),
+        (Some(at), None) | (None, Some(at)) => {
+            let separator = &amount[at..at + 1];
+            let once = amount.matches(separator).count() == 1;
+            if once && amount.len() - at - 1 == 3 {
+                return Err(format!("amount '{}' is ambiguous, '{}' could separate thousands or decimals", amount, separator));
+            }
+            // a separator used once separates the decimals, one used more the thousands
+            Ok(match (separator, once) {
+                (".", true) | (",", false) => AmountLocale::Dot,
+                _ => AmountLocale::Comma
+            })
+        }
+    }
+}
+
+fn name(locale: AmountLocale) -> &'static str {
+    match locale {
+        AmountLocale::Strict => "strict",
+        AmountLocale::Comma => "comma",
+        AmountLocale::Dot => "dot",
+        AmountLocale::Auto => "auto"
+    }
+}
+
+/// the amount as `format` has it read, any decision about it logged on its line
+fn read(amount: &str, format: AmountFormat, position: Option<&csv::Position>) -> Result<Option<String>, RowError> {
+    let read = normalize(amount, format.locale).and_then(|a| excess::apply(a, format.policy, format.precision));
+    match read {
+        Ok((amount, decision)) => {
+            if let Some(decision) = decision {
+                eprintln!("{}", on_line(position, decision));
+            }
+            Ok(match amount {
+                Cow::Owned(amount) => Some(amount),
+                Cow::Borrowed(_) => None
+            })
+        },
+        Err(e) => Err(on_line(position, e))
+    }
+}
+
+/// the record's amount normalized in place, trimming the record
+pub(crate) fn localize(record: &mut csv::StringRecord, format: AmountFormat) -> Result<(), RowError> {
+    if format.is_plain() {
+        return Ok(());
+    }
+    record.trim();
+    let amount = match record.get(AMOUNT) {
+        Some(amount) => match read(amount, format, record.position())? {
+            Some(amount) => amount,
+            None => return Ok(())
+        },
+        None => return Ok(())
+    };
+    let mut localized: csv::StringRecord = record.iter().enumerate()
+        .map(|(i, field)| if i == AMOUNT { amount.as_str() } else { field })
+        .collect();
+    localized.set_position(record.position().cloned());
+    *record = localized;
+    Ok(())
+}
+
+/// as `localize`, for the byte records the parallel paths & the hand-written parser read. an amount that isn't
+/// utf-8 is left for the parser to reject
+pub(crate) fn localize_bytes(record: &mut csv::ByteRecord, format: AmountFormat) -> Result<(), RowError> {
+    if format.is_plain() {
+        return Ok(());
+    }
+    record.trim();
+    let amount = match record.get(AMOUNT).and_then(|a| std::str::from_utf8(a).ok()) {
+        Some(amount) => match read(amount, format, record.position())? {
+            Some(amount) => amount,
+            None => return Ok(())
+        },
+        None => return Ok(())
+    };
+    let mut localized: csv::ByteRecord = record.iter().enumerate()
+        .map(|(i, field)| if i == AMOUNT { amount.as_bytes() } else { field })
+        .collect();
+    localized.set_position(record.position().cloned());
+    *record = localized;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{AmountLocale, AmountPolicy};
+
+    use super::{localize, localize_bytes, normalize, AmountFormat};
+
+    fn format(locale: AmountLocale) -> AmountFormat {
+        AmountFormat { locale, policy: None, precision: 4.into() }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            (AmountLocale::Comma, "1.234,56", "1234.56"),
+            (AmountLocale::Comma, "12,50", "12.50"),
+            (AmountLocale::Comma, "-1 234 567,5", "-1234567.5"),
+            (AmountLocale::Comma, "1234", "1234"),
+            (AmountLocale::Dot, "1,234.56", "1234.56"),
+            (AmountLocale::Dot, "1,234", "1234"),
+            (AmountLocale::Dot, "2.5", "2.5"),
+            (AmountLocale::Auto, "1.234,56", "1234.56"),
+            (AmountLocale::Auto, "1,234.56", "1234.56"),
+            (AmountLocale::Auto, "12,50", "12.50"),
+            (AmountLocale::Auto, "1.234.567", "1234567"),
+            (AmountLocale::Auto, "1 234", "1234"),
+            (AmountLocale::Strict, "1.234,56", "1.234,56")
+        ];
+        for (locale, amount, plain) in cases {
+            assert_eq!(normalize(amount, locale).as_deref(), Ok(plain), "{} in {:?}", amount, locale);
+        }
+    }
+
+    #[test]
+    fn test_rejected() {
+        for (locale, amount) in [(AmountLocale::Comma, "1.5"), (AmountLocale::Comma, "1.23.4"), (AmountLocale::Dot, "12,50"),
+                                 (AmountLocale::Dot, "1,234 567"), (AmountLocale::Dot, "1,234.5.6"), (AmountLocale::Comma, "1,2-3")] {
+            assert!(normalize(amount, locale).is_err(), "{} in {:?}", amount, locale);
+        }
+        assert_eq!(normalize("1,234", AmountLocale::Auto).unwrap_err(),
+                   "amount '1,234' is ambiguous, ',' could separate thousands or decimals");
+        assert_eq!(normalize("1.23.4", AmountLocale::Comma).unwrap_err(), "amount '1.23.4' is not a valid decimal in the comma locale");
+    }
+
+    #[test]
+    fn test_localize() {
+        let mut record = csv::StringRecord::from(vec!["deposit", "1", "2", " 1.234,5 "]);
+        localize(&mut record, format(AmountLocale::Comma)).unwrap();
+        assert_eq!(&record[3], "1234.5");
+        let mut record = csv::ByteRecord::from(vec!["deposit", "1", "2", "1,234.5"]);
+        localize_bytes(&mut record, format(AmountLocale::Auto)).unwrap();
+        assert_eq!(&record[3], b"1234.5");
+        let mut record = csv::StringRecord::from(vec!["dispute", "1", "2"]);
+        localize(&mut record, format(AmountLocale::Auto)).unwrap();
+        assert_eq!(record.len(), 3);
+    }
+
+    #[test]
+    fn test_localize_policy() {
+        let truncate = AmountFormat { policy: Some(AmountPolicy::Truncate), ..format(AmountLocale::Comma) };
+        let mut record = csv::StringRecord::from(vec!["deposit", "1", "2", "1.234,56789"]);
+        localize(&mut record, truncate).unwrap();
+        assert_eq!(&record[3], "1234.5678");
+        let reject = AmountFormat { policy: Some(AmountPolicy::Reject), ..format(AmountLocale::Strict) };
+        let mut record = csv::ByteRecord::from(vec!["deposit", "1", "2", "1e10"]);
+        assert_eq!(localize_bytes(&mut record, reject).unwrap_err().to_string(), "amount '1e10' is in scientific notation");
+    }
+}
@@ -0,0 +1,178 @@
+//! `--manifest run.json`: once a `txn process` run has written its output (or printed its report, on a dry run), a
+//! json record of what it processed and how, for an orchestrator to check & keep:
+//! ```json
+//! {"version":"1.0.0","input":{"path":"in.csv","sha256":"9f86..."},"config_sha256":"2c26...","dry_run":false,
+//!  "applied":41,"skipped":1,"replayed":0,"rejected":2,"rejections":{"insufficient funds":2},"chargeback_loss":"0",
+//!  "accounts":3,"state_sha256":"fcde...","started_at":1718000000,"finished_at":1718000002,"elapsed_ms":1520}
+//! ```
+//! - `input.sha256` is the input file's, as `--digests` lists it. remote inputs have none
+//! - `config_sha256` is of the settings the run resolved to, with the http bearer token & checkpoint key left out,
+//!   so two runs with it equal ran alike
+//! - `state_sha256` is of every account as a checkpoint holds it, in client order, so two runs with it equal ended
+//!   in the same state whatever order their output was written in
+//! - `started_at` & `finished_at` are unix seconds
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::report::Report;
+use crate::{Accounts, digest, is_remote, TxnCliError};
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    version: &'static str,
+    input: Input<'a>,
+    config_sha256: String,
+    dry_run: bool,
+    applied: u64,
+    skipped: u64,
+    replayed: u64,
+    rejected: u64,
+    /// rejections by reason, as the run report words them
+    rejections: BTreeMap<String, u64>,
+    chargeback_loss: String,
+    accounts: usize,
+    state_sha256: String,
+    started_at: u64,
+    finished_at: u64,
+    elapsed_ms: u64
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+    path: &'a Path,
+    sha256: Option<String>
+}
+
+/// writes the manifest of the run over `input` that started at `started`, if `manifest.path` asks for one
+pub(crate) fn write(input: &Path, accounts: &Accounts, config: &Config, report: &Report, started: SystemTime, clock: &dyn Clock)
+                    -> Result<(), Box<dyn std::error::Error>> {
+    let path = match &config.manifest.path {
+        Some(path) => path,
+        None => return Ok(())
+    };
+    let sha256 = match input.to_str().is_some_and(|p| !is_remote(p)) {
+        true => {
+            let file = std::fs::File::open(input).map_err(|e| TxnCliError::io(input, e))?;
+            Some(digest::digest(std::io::BufReader::new(file)).map_err(|e| TxnCliError::io(input, e))?)
+        },
+        false => None
+    };
+    let manifest = Manifest {
+        version: env!("CARGO_PKG_VERSION"),
+        input: Input { path: input, sha256 },
+        config_sha256: config_sha256(config),
+        dry_run: config.dry_run,
+        applied: report.applied,
+        skipped: report.skipped,
+        replayed: report.replayed,
+        rejected: report.rejected_total(),
+        rejections: report.rejected.iter().map(|(reason, count)| (reason.to_string(), *count)).collect(),
+        chargeback_loss: report.chargeback_loss.normalize().to_string(),
+        accounts: accounts.len(),
+        state_sha256: state_sha256(accounts)?,
+        started_at: unix_seconds(started),
+        finished_at: unix_seconds(clock.now()),
+        elapsed_ms: u64::try_from(clock.since(started).as_millis()).unwrap_or(u64::MAX)
+    };
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path).map_err(|e| TxnCliError::io(path, e))?);
+    serde_json::to_writer_pretty(&mut out, &manifest)?;
+    out.flush().map_err(|e| TxnCliError::io(path, e))?;
+    Ok(())
+}
+
+/// of the settings as resolved, their debug form holding every one in a fixed order, the secrets left out
+fn config_sha256(config: &Config) -> String {
+    let mut config = config.clone();
+    config.http.bearer_token = None;
+    config.checkpoint.key = None;
+    digest::hex(Sha256::digest(format!("{:?}", config).as_bytes()))
+}
+
+fn state_sha256(accounts: &Accounts) -> Result<String, serde_json::Error> {
+    let mut clients: Vec<_> = accounts.iter().collect();
+    clients.sort_unstable_by_key(|(client, _)| **client);
+    let mut hasher = Sha256::new();
+    serde_json::to_writer(&mut hasher, &clients)?;
+    Ok(digest::hex(hasher.finalize()))
+}
+
+fn unix_seconds(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use rust_decimal_macros::dec;
+
+    use crate::clock::{Clock, MockClock};
+    use crate::config::Config;
+    use crate::report::Report;
+    use crate::{Accounts, execute, Rejection, Txn};
+
+    use super::{config_sha256, state_sha256, write};
+
+    #[test]
+    fn test_hashes() {
+        let (mut a, mut b) = (Accounts::default(), Accounts::default());
+        for client in 1..50 {
+            execute(&mut a, Txn::deposit(client, client.into(), dec!(1.5)));
+        }
+        for client in (1..50).rev() {
+            execute(&mut b, Txn::deposit(client, client.into(), dec!(1.5)));
+        }
+        // the same state however it was reached
+        assert_eq!(state_sha256(&a).unwrap(), state_sha256(&b).unwrap());
+        execute(&mut b, Txn::dispute(1, 1));
+        assert_ne!(state_sha256(&a).unwrap(), state_sha256(&b).unwrap());
+
+        let mut config = Config::default();
+        let default = config_sha256(&config);
+        config.http.bearer_token = Some("secret".into());
+        assert_eq!(config_sha256(&config), default);
+        config.precision = 2;
+        assert_ne!(config_sha256(&config), default);
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir().join(format!("txn-manifest-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        std::fs::write(&input, "test").unwrap();
+        let mut config = Config::default();
+        config.manifest.path = Some(dir.join("run.json"));
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(1, 1, dec!(1)));
+        let mut report = Report::default();
+        report.record(Ok(()));
+        report.record(Err(Rejection::InsufficientFunds));
+
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1_718_000_000));
+        let started = clock.now();
+        clock.advance(Duration::from_millis(2500));
+        write(&input, &accounts, &config, &report, started, &clock).unwrap();
+        let manifest: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("run.json")).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(manifest["input"]["sha256"], "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08");
+        assert_eq!(manifest["applied"], 1);
+        assert_eq!(manifest["rejected"], 1);
+        assert_eq!(manifest["rejections"]["insufficient funds"], 1);
+        assert_eq!(manifest["accounts"], 1);
+        assert_eq!(manifest["state_sha256"], state_sha256(&accounts).unwrap());
+        assert_eq!(manifest["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(manifest["started_at"], 1_718_000_000);
+        assert_eq!(manifest["finished_at"], 1_718_000_002);
+        assert_eq!(manifest["elapsed_ms"], 2500);
+    }
+}
@@ -0,0 +1,123 @@
+//! `--max-memory 4G`: a cap on the engine's approximate memory, accounts plus their transaction logs. the estimate
+//! is taken from map capacities & the transaction logs' pages (see txnlog.rs) every `CHECK_EVERY` applied transactions, and a run over the cap stops with an error
+//! rather than growing until the OOM killer ends it with no output at all.
+//!
+//! there's no on-disk store yet to spill transaction logs to (`storage` is memory only), so exceeding the cap aborts.
+//! the estimate covers the engine's own maps, not allocator overhead or the reader's buffers, so leave some headroom.
+
+use std::mem::size_of;
+
+use crate::{Account, Accounts, ClientId, Settling, TxnId};
+
+/// applied transactions between estimates
+pub(crate) const CHECK_EVERY: u64 = 64 * 1024;
+
+/// bytes held by a hash table with room for `capacity` entries: a control byte per bucket besides the entry,
+/// and buckets kept at most 7/8 full
+fn table_bytes<T>(capacity: usize) -> u64 {
+    (capacity as u64 * (size_of::<T>() as u64 + 1)).saturating_mul(8) / 7
+}
+
+/// approximate bytes held by the accounts & their transaction logs
+pub(crate) fn estimate(accounts: &Accounts) -> u64 {
+    let logs: u64 = accounts.values()
+        .map(|a| a.txnlog.bytes() + table_bytes::<TxnId>(a.disputes.capacity())
+             + table_bytes::<TxnId>(a.resolved.capacity()) + table_bytes::<TxnId>(a.charged_back.capacity())
+             + a.pruned_txs.bytes()
+             + (a.settling.capacity() * size_of::<Settling>()) as u64)
+        .sum();
+    table_bytes::<(ClientId, Account)>(accounts.capacity()) + logs
+}
+
+/// errors out once the estimate is over `limit`
+pub(crate) fn check(accounts: &Accounts, limit: u64) -> Result<(), String> {
+    let used = estimate(accounts);
+    if used <= limit {
+        return Ok(());
+    }
+    let logged: usize = accounts.values().map(|a| a.txnlog.len()).sum();
+    Err(format!("memory cap of {} exceeded: ~{} held by {} accounts & {} logged transactions",
+                format_size(limit), format_size(used), accounts.len(), logged))
+}
+
+/// `4G`, `512M`, `64k`, `1GiB` or a plain byte count. multiples are binary
+pub(crate) fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits);
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => return None
+    };
+    number.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::report::Report;
+    use crate::{Accounts, execute, record, Txn};
+
+    use super::{CHECK_EVERY, check, estimate, format_size, parse_size};
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4G"), Some(4 << 30));
+        assert_eq!(parse_size("512m"), Some(512 << 20));
+        assert_eq!(parse_size("1GiB"), Some(1 << 30));
+        assert_eq!(parse_size("64 KB"), Some(64 << 10));
+        assert_eq!(parse_size("1000"), Some(1000));
+        assert_eq!(parse_size("G"), None);
+        assert_eq!(parse_size("1.5G"), None);
+        assert_eq!(parse_size("4X"), None);
+        assert_eq!(parse_size("99999999999T"), None);
+        assert_eq!(format_size(3 << 29), "1.5GiB");
+    }
+
+    #[test]
+    fn test_estimate_grows() {
+        let mut accounts = Accounts::default();
+        assert_eq!(estimate(&accounts), 0);
+        for tx in 0..10_000 {
+            execute(&mut accounts, Txn::deposit(1, tx, dec!(1)));
+        }
+        let used = estimate(&accounts);
+        // at least the logged transactions themselves
+        assert!(used > 10_000 * std::mem::size_of::<Txn>() as u64, "{}", used);
+
+        assert!(check(&accounts, used).is_ok());
+        let error = check(&accounts, used - 1).unwrap_err();
+        assert!(error.contains("1 accounts & 10000 logged transactions"), "{}", error);
+    }
+
+    #[test]
+    fn test_record_stops_over_cap() {
+        let mut config = Config::default();
+        config.limits.max_memory = Some(64 * 1024);
+        let mut accounts = Accounts::default();
+        let mut report = Report::default();
+
+        let last = CHECK_EVERY as u32 - 1;
+        for tx in 0..last {
+            record(&mut accounts, Txn::deposit(1, tx, dec!(1)), &config, &mut report).unwrap();
+        }
+        assert!(record(&mut accounts, Txn::deposit(1, last, dec!(1)), &config, &mut report).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! `--mmap`: maps a local csv file into memory and splits it into line aligned chunks, which are parsed in
+//! parallel on a rayon pool (`parse_threads` threads, or one per core when that's left at 1). chunks are parsed
+//! a window at a time and their transactions executed in file order before the next window starts, so memory is
+//! bounded by the window rather than the file.
+//!
+//! as with `tail`, rows are split on line breaks, so quoted fields can't span lines. built with `--features simd`
+//! the chunks are split into records by a vectorized scan (see scan.rs).
+
+#[cfg(feature = "mmap")]
+use crate::pipeline::RowError;
+#[cfg(feature = "mmap")]
+use crate::Txn;
+
+/// bytes per chunk, before extending to the next line break
+#[cfg(feature = "mmap")]
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+/// chunks in flight per pool thread
+#[cfg(feature = "mmap")]
+const CHUNKS_PER_THREAD: usize = 2;
+
+/// splits off the header line, then the rest into chunks of at least `size` bytes that end on a line break
+/// (bar the last, if the file doesn't end with one)
+#[cfg(any(feature = "mmap", test))]
+pub(crate) fn split_lines(data: &[u8], size: usize) -> Vec<&[u8]> {
+    let body = match data.iter().position(|&b| b == b'\n') {
+        Some(i) => &data[i + 1..],
+        None => return Vec::new()
+    };
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < body.len() {
+        let end = match body[(start + size).min(body.len())..].iter().position(|&b| b == b'\n') {
+            Some(i) => (start + size).min(body.len()) + i + 1,
+            None => body.len()
+        };
+        chunks.push(&body[start..end]);
+        start = end;
+    }
+    chunks
+}
+
+#[cfg(feature = "mmap")]
+pub(crate) fn process<P, E>(file: &std::fs::File, threads: usize, parse_record: P,
+                            mut apply: impl FnMut(Result<Txn, RowError>) -> Result<(), E>) -> Result<(), E>
+    where P: Fn(&mut csv::ByteRecord) -> Result<Txn, RowError> + Send + Sync,
+          E: From<Box<dyn std::error::Error>>
+{
+    // SAFETY: the file mustn't be modified while it's mapped, same as for any reader over it
+    let data = unsafe { memmap2::Mmap::map(file) }.map_err(|e| E::from(e.into()))?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(if threads > 1 { threads } else { 0 })
+        .build()
+        .map_err(|e| E::from(e.into()))?;
+
+    execute_chunks(&pool, &data, CHUNK_SIZE, &parse_record, &mut apply)
+}
+
+#[cfg(feature = "mmap")]
+fn execute_chunks<P, E>(pool: &rayon::ThreadPool, data: &[u8], chunk_size: usize, parse_record: &P,
+                        apply: &mut impl FnMut(Result<Txn, RowError>) -> Result<(), E>) -> Result<(), E>
+    where P: Fn(&mut csv::ByteRecord) -> Result<Txn, RowError> + Send + Sync
+{
+    use rayon::prelude::*;
+
+    let chunks = split_lines(data, chunk_size);
+    for window in chunks.chunks(pool.current_num_threads() * CHUNKS_PER_THREAD) {
+        // collected in window order, whichever chunk finishes first
+        let parsed: Vec<Vec<Result<Txn, RowError>>> = pool.install(|| {
+            window.par_iter().map(|chunk| parse_chunk(chunk, parse_record)).collect()
+        });
+        for txn in parsed.into_iter().flatten() {
+            apply(txn)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "mmap")]
+fn parse_chunk(chunk: &[u8], parse_record: &impl Fn(&mut csv::ByteRecord) -> Result<Txn, RowError>) -> Vec<Result<Txn, RowError>> {
+    #[cfg(feature = "simd")]
+    let mut records = crate::scan::records(chunk);
+    #[cfg(not(feature = "simd"))]
+    let mut records = csv::ReaderBuilder::new().has_headers(false).from_reader(chunk);
+    // the one record, each row read into it in turn
+    let mut record = csv::ByteRecord::new();
+    let mut txns = Vec::new();
+    loop {
+        match records.read_byte_record(&mut record) {
+            Ok(true) => txns.push(parse_record(&mut record)),
+            Ok(false) => return txns,
+            Err(e) => txns.push(Err(e.into()))
+        }
+    }
+}
+
+#[cfg(not(feature = "mmap"))]
+pub(crate) fn process<P, E>(_file: &std::fs::File, _threads: usize, _parse_record: P,
+                            _apply: impl FnMut(Result<crate::Txn, crate::pipeline::RowError>) -> Result<(), E>) -> Result<(), E>
+    where E: From<Box<dyn std::error::Error>>
+{
+    Err(E::from("memory mapped input requires building with the `mmap` feature".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_lines;
+
+    #[test]
+    fn test_split_lines() {
+        let data = b"type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0\ndeposit,1,3,1.0";
+        let chunks = split_lines(data, 20);
+        assert_eq!(chunks, vec![&b"deposit,1,1,1.0\ndeposit,1,2,1.0\n"[..], &b"deposit,1,3,1.0"[..]]);
+        assert_eq!(chunks.concat(), &data[22..]);
+
+        assert_eq!(split_lines(data, 1).len(), 3);
+        assert_eq!(split_lines(data, 1000).len(), 1);
+        assert!(split_lines(b"type,client,tx,amount\n", 10).is_empty());
+        assert!(split_lines(b"type,client,tx,amount", 10).is_empty());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_chunks_executed_in_order() {
+        use rust_decimal_macros::dec;
+
+        use crate::{CURRENCY_PRECISION, deserialize_byte_record, Txn};
+
+        let mut csv = String::from("type,client,tx,amount\n");
+        for tx in 0..10_000 {
+            csv.push_str(&format!("deposit,1,{},1.0\n", tx));
+        }
+        csv.push_str("bogus,1,1,1.0\n");
+
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+        let mut txns = Vec::new();
+        let result = super::execute_chunks(&pool, csv.as_bytes(), 1000,
+                                           &|r| deserialize_byte_record(r, CURRENCY_PRECISION.into()),
+                                           &mut |t| t.map(|t| txns.push(t)).map_err(|_| "malformatted"));
+        assert_eq!(result, Err("malformatted"));
+
+        let expected: Vec<Txn> = (0..10_000).map(|tx| Txn::deposit(1, tx, dec!(1))).collect();
+        assert_eq!(txns, expected);
+    }
+}
@@ -0,0 +1,142 @@
+//! node.js bindings (`--features node`), for calling the engine in-process from a node service rather than
+//! spawning the cli per batch. `npm run build` in the repo builds the addon, `txn.node`:
+//! ```js
+//! const { Engine } = require('./txn.node')
+//! const engine = new Engine()                 // or new Engine(fs.readFileSync('txn.toml', 'utf8'))
+//! engine.write({ type: 'deposit', client: 1, tx: 1, amount: '2.5' })      // null: applied
+//! engine.write({ type: 'withdrawal', client: 1, tx: 2, amount: '10' })    // 'insufficient funds'
+//! engine.snapshot()
+//! // [{ client: 1, available: '2.5', held: '0', total: '2.5', locked: false }]
+//! ```
+//! amounts are strings both ways, js numbers can't hold every amount exactly. ids are plain numbers, a `wide-ids`
+//! build taking them as far as js holds integers exactly (2^53).
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use napi::{Error, Result, Status};
+use napi_derive::napi;
+use rust_decimal::Decimal;
+
+use crate::config::Config;
+use crate::{ClientId, ClientRepr, Engine, Txn, TxnId, TxnRepr, TxnType};
+
+fn parse_txntype(s: &str) -> Option<TxnType> {
+    match s {
+        "deposit" => Some(TxnType::Deposit),
+        "withdrawal" => Some(TxnType::Withdrawal),
+        "dispute" => Some(TxnType::Dispute),
+        "resolve" => Some(TxnType::Resolve),
+        "chargeback" => Some(TxnType::Chargeback),
+        _ => None
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(Status::InvalidArg, message)
+}
+
+/// a transaction as a csv row has it
+#[napi(object)]
+pub struct NodeTxn {
+    pub r#type: String,
+    pub client: i64,
+    pub tx: i64,
+    pub amount: Option<String>
+}
+
+impl NodeTxn {
+    fn to_txn(&self) -> Result<Txn> {
+        let txntype = parse_txntype(&self.r#type)
+            .ok_or_else(|| invalid(format!("unknown transaction type '{}'", self.r#type)))?;
+        let client = ClientRepr::try_from(self.client).map(ClientId).map_err(|_| invalid(format!("invalid client {}", self.client)))?;
+        let tx = TxnRepr::try_from(self.tx).map(TxnId).map_err(|_| invalid(format!("invalid tx {}", self.tx)))?;
+        let amount = match &self.amount {
+            Some(amount) => Some(Decimal::from_str(amount.trim()).map_err(|_| invalid(format!("invalid amount '{}'", amount)))?),
+            None => None
+        };
+        Txn::builder(txntype, client, tx).amount(amount).build().map_err(|e| invalid(format!("{}: {}", self.r#type, e)))
+    }
+}
+
+/// a client's balance & lock
+#[napi(object)]
+pub struct NodeBalance {
+    pub client: i64,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool
+}
+
+#[napi(js_name = "Engine")]
+pub struct NodeEngine(Engine);
+
+#[napi]
+impl NodeEngine {
+    /// `config` is the text of a txn.toml
+    #[napi(constructor)]
+    pub fn new(config: Option<String>) -> Result<Self> {
+        let config = match config {
+            Some(toml) => Config::from_toml(&toml).map_err(invalid)?,
+            None => Config::default()
+        };
+        Ok(NodeEngine(Engine::new(config)))
+    }
+
+    /// null if the transaction was applied, else why it was declined. throws for one that's malformatted
+    #[napi]
+    pub fn write(&mut self, txn: NodeTxn) -> Result<Option<String>> {
+        Ok(self.0.execute(txn.to_txn()?).err().map(|r| r.to_string()))
+    }
+
+    /// every account's balance, by client id
+    #[napi]
+    pub fn snapshot(&self) -> Vec<NodeBalance> {
+        let mut balances: Vec<NodeBalance> = self.0.accounts().iter()
+            .map(|(client, account)| {
+                let b = account.balance;
+                let amount = |a: crate::Amount| a.to_decimal().normalize().to_string();
+                NodeBalance {
+                    // ids only get here from an i64
+                    client: client.0 as i64,
+                    available: amount(b.available),
+                    held: amount(b.held),
+                    total: amount(b.total),
+                    locked: account.locked
+                }
+            })
+            .collect();
+        balances.sort_unstable_by_key(|b| b.client);
+        balances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NodeEngine, NodeTxn};
+
+    fn txn(txntype: &str, client: i64, tx: i64, amount: Option<&str>) -> NodeTxn {
+        NodeTxn { r#type: txntype.to_string(), client, tx, amount: amount.map(String::from) }
+    }
+
+    #[test]
+    fn test_engine() {
+        let mut engine = NodeEngine::new(None).unwrap();
+        assert_eq!(engine.write(txn("deposit", 2, 1, Some("2.5"))).unwrap(), None);
+        assert_eq!(engine.write(txn("withdrawal", 2, 2, Some("10"))).unwrap().as_deref(), Some("insufficient funds"));
+        assert_eq!(engine.write(txn("deposit", 1, 3, Some("1"))).unwrap(), None);
+        assert_eq!(engine.write(txn("dispute", 2, 1, None)).unwrap(), None);
+
+        let snapshot = engine.snapshot();
+        assert_eq!(snapshot.iter().map(|b| b.client).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!((snapshot[1].available.as_str(), snapshot[1].held.as_str()), ("0", "2.5"));
+
+        assert!(engine.write(txn("bogus", 1, 4, None)).is_err());
+        assert!(engine.write(txn("deposit", 1, 4, None)).is_err());
+        assert!(engine.write(txn("dispute", 1, 3, Some("1"))).is_err());
+        assert!(engine.write(txn("deposit", -1, 4, Some("1"))).is_err());
+        assert!(engine.write(txn("deposit", 1, -1, Some("1"))).is_err());
+        assert!(NodeEngine::new(Some("bogus".into())).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! parallel csv pipeline, used when `parse_threads` > 1: a reader thread splits the input into batches of raw
+//! records, a pool of parser threads deserializes them, and the calling thread executes the transactions in
+//! input order. the channels between the stages are bounded, so a slow executor holds the reader back rather
+//! than the whole file piling up in memory.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::sync::mpsc::{Receiver, sync_channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::Txn;
+
+/// records per batch handed between stages
+const BATCH_SIZE: usize = 1024;
+/// batches buffered per channel
+const QUEUE_DEPTH: usize = 16;
+
+/// why a row didn't become a transaction
+pub(crate) type RowError = Box<dyn std::error::Error + Send + Sync>;
+/// what a raw record is parsed into a transaction with, per `fast_parse`
+pub(crate) type ParseRecord = dyn Fn(&mut csv::ByteRecord) -> Result<Txn, RowError> + Send + Sync;
+
+type Batch<T> = (usize, Vec<Result<T, RowError>>);
+
+/// reads, parses & hands every row to `apply` in input order. stops at the first error `apply` returns
+pub(crate) fn run<R, P, E>(reader: R, threads: usize, parse_record: P,
+                           mut apply: impl FnMut(Result<Txn, RowError>) -> Result<(), E>) -> Result<(), E>
+    where R: Read + Send + 'static,
+          P: Fn(&mut csv::ByteRecord) -> Result<Txn, RowError> + Send + Sync + 'static
+{
+    let (raw_tx, raw_rx) = sync_channel::<Batch<csv::ByteRecord>>(QUEUE_DEPTH);
+    let (parsed_tx, parsed_rx) = sync_channel::<Batch<Txn>>(QUEUE_DEPTH);
+    let parse_record = Arc::new(parse_record);
+
+    let reader = thread::spawn(move || {
+        let mut records = csv::Reader::from_reader(reader).into_byte_records();
+        for seq in 0.. {
+            let batch: Vec<_> = records.by_ref().take(BATCH_SIZE).map(|r| r.map_err(RowError::from)).collect();
+            // a send only fails once the executor has stopped
+            if batch.is_empty() || raw_tx.send((seq, batch)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let raw_rx = Arc::new(Mutex::new(raw_rx));
+    let parsers: Vec<_> = (0..threads).map(|_| {
+        let raw_rx = Arc::clone(&raw_rx);
+        let parsed_tx = parsed_tx.clone();
+        let parse_record = Arc::clone(&parse_record);
+        thread::spawn(move || parse(&raw_rx, &*parse_record, |batch| parsed_tx.send(batch).is_ok()))
+    }).collect();
+    // only the parsers hold these, so each side sees the other hang up
+    drop(raw_rx);
+    drop(parsed_tx);
+
+    let result = execute_in_order(parsed_rx, &mut apply);
+
+    // on an early return the dropped receiver unblocks, and so ends, the other stages
+    reader.join().expect("reader thread panicked");
+    for parser in parsers {
+        parser.join().expect("parser thread panicked");
+    }
+    result
+}
+
+fn parse(raw_rx: &Mutex<Receiver<Batch<csv::ByteRecord>>>, parse_record: &impl Fn(&mut csv::ByteRecord) -> Result<Txn, RowError>,
+         mut send: impl FnMut(Batch<Txn>) -> bool) {
+    loop {
+        // the lock is only held while waiting for a batch, not while parsing it
+        let batch = raw_rx.lock().unwrap().recv();
+        let (seq, records) = match batch {
+            Ok(b) => b,
+            Err(_) => return
+        };
+        let txns = records.into_iter().map(|r| r.and_then(|mut r| parse_record(&mut r))).collect();
+        if !send((seq, txns)) {
+            return;
+        }
+    }
+}
+
+/// batches arrive in whatever order the parsers finish them, and are held back until their turn
+fn execute_in_order<E>(parsed_rx: Receiver<Batch<Txn>>, apply: &mut impl FnMut(Result<Txn, RowError>) -> Result<(), E>)
+                       -> Result<(), E> {
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+    for (seq, txns) in parsed_rx {
+        pending.insert(seq, txns);
+        while let Some(txns) = pending.remove(&next) {
+            for txn in txns {
+                apply(txn)?;
+            }
+            next += 1;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::{CURRENCY_PRECISION, deserialize_byte_record, Txn};
+
+    use super::{BATCH_SIZE, QUEUE_DEPTH, run};
+
+    fn parse(record: &mut csv::ByteRecord) -> Result<Txn, super::RowError> {
+        deserialize_byte_record(record, CURRENCY_PRECISION.into())
+    }
+
+    #[test]
+    fn test_order_preserved() {
+        let rows = BATCH_SIZE * 5 + 3;
+        let mut csv = String::from("type,client,tx,amount\n");
+        for tx in 0..rows {
+            csv.push_str(&format!("deposit, 1, {}, 1.00001\n", tx));
+        }
+
+        let mut txns = Vec::new();
+        run(std::io::Cursor::new(csv), 4, parse, |t| -> Result<(), ()> {
+            txns.push(t.unwrap());
+            Ok(())
+        }).unwrap();
+        let expected: Vec<Txn> = (0..rows as u32).map(|tx| Txn::deposit(1, tx, dec!(1))).collect();
+        assert_eq!(txns, expected);
+    }
+
+    #[test]
+    fn test_stops_at_error() {
+        // far more than the channels hold, so the stages have to notice the executor stopped
+        let mut csv = String::from("type,client,tx,amount\nbogus,1,1,1\n");
+        for tx in 0..BATCH_SIZE * QUEUE_DEPTH * 4 {
+            csv.push_str(&format!("deposit,1,{},1.0\n", tx));
+        }
+
+        let mut applied = 0;
+        let result = run(std::io::Cursor::new(csv), 2, parse, |t| {
+            t.map(|_| applied += 1).map_err(|_| "malformatted")
+        });
+        assert_eq!(result, Err("malformatted"));
+        assert_eq!(applied, 0);
+    }
+}
@@ -0,0 +1,198 @@
+//! python bindings (`--features python`): a `txn` module for replaying transaction sets from a notebook without
+//! going through csv files and the cli. built & installed into the current virtualenv by maturin, with
+//! `maturin develop --release` (pyproject.toml turns the feature on).
+//! ```python
+//! import txn
+//! engine = txn.Engine()                                   # or txn.Engine("[limits]\nmax_amount = 100")
+//! engine.execute(txn.Txn("deposit", 1, 1, "2.5"))         # None: applied
+//! engine.execute(txn.Txn("withdrawal", 1, 2, 10))         # 'insufficient funds'
+//! engine.replay([txn.Txn("dispute", 1, 1)])               # [None]
+//! engine.balances()
+//! # {1: Balance(available=Decimal('0.0'), held=Decimal('2.5'), total=Decimal('2.5'), locked=False)}
+//! ```
+//! amounts are `decimal.Decimal`s, taken from anything `Decimal` takes (`"2.5"`, `10`), rounded to 4 places.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::config::Config;
+use crate::{Account, ClientId, ClientRepr, Engine, Txn, TxnId, TxnRepr, TxnType};
+
+fn parse_txntype(s: &str) -> Option<TxnType> {
+    match s {
+        "deposit" => Some(TxnType::Deposit),
+        "withdrawal" => Some(TxnType::Withdrawal),
+        "dispute" => Some(TxnType::Dispute),
+        "resolve" => Some(TxnType::Resolve),
+        "chargeback" => Some(TxnType::Chargeback),
+        _ => None
+    }
+}
+
+/// `Txn(type, client, tx, amount=None)`, amount given for deposits & withdrawals only
+#[pyclass(name = "Txn", module = "txn", frozen)]
+struct PyTxn(Txn);
+
+#[pymethods]
+impl PyTxn {
+    #[new]
+    #[pyo3(signature = (r#type, client, tx, amount = None))]
+    fn new(r#type: &str, client: ClientRepr, tx: TxnRepr, amount: Option<Decimal>) -> PyResult<Self> {
+        let txntype = parse_txntype(r#type)
+            .ok_or_else(|| PyValueError::new_err(format!("unknown transaction type '{}'", r#type)))?;
+        Txn::builder(txntype, ClientId(client), TxnId(tx)).amount(amount).build()
+            .map(PyTxn)
+            .map_err(|e| PyValueError::new_err(format!("{}: {}", r#type, e)))
+    }
+
+    #[getter]
+    fn r#type(&self) -> String {
+        format!("{:?}", self.0.txntype).to_lowercase()
+    }
+
+    #[getter]
+    fn client(&self) -> ClientRepr {
+        self.0.client.0
+    }
+
+    #[getter]
+    fn tx(&self) -> TxnRepr {
+        self.0.tx.0
+    }
+
+    #[getter]
+    fn amount(&self) -> Option<Decimal> {
+        self.0.amount.map(|a| a.to_decimal())
+    }
+
+    fn __repr__(&self) -> String {
+        match self.amount() {
+            Some(amount) => format!("Txn('{}', {}, {}, Decimal('{}'))", self.r#type(), self.0.client, self.0.tx, amount),
+            None => format!("Txn('{}', {}, {})", self.r#type(), self.0.client, self.0.tx)
+        }
+    }
+}
+
+/// a client's balance & lock
+#[pyclass(name = "Balance", module = "txn", frozen, eq, get_all)]
+#[derive(PartialEq)]
+struct PyBalance {
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool
+}
+
+impl From<&Account> for PyBalance {
+    fn from(account: &Account) -> Self {
+        let b = account.balance;
+        PyBalance {
+            available: b.available.to_decimal(),
+            held: b.held.to_decimal(),
+            total: b.total.to_decimal(),
+            locked: account.locked
+        }
+    }
+}
+
+#[pymethods]
+impl PyBalance {
+    fn __repr__(&self) -> String {
+        format!("Balance(available=Decimal('{}'), held=Decimal('{}'), total=Decimal('{}'), locked={})",
+                self.available, self.held, self.total, if self.locked { "True" } else { "False" })
+    }
+}
+
+/// `Engine(config=None)`, config being the text of a txn.toml
+#[pyclass(name = "Engine", module = "txn")]
+struct PyEngine(Engine);
+
+#[pymethods]
+impl PyEngine {
+    #[new]
+    #[pyo3(signature = (config = None))]
+    fn new(config: Option<&str>) -> PyResult<Self> {
+        let config = match config {
+            Some(toml) => Config::from_toml(toml).map_err(PyValueError::new_err)?,
+            None => Config::default()
+        };
+        Ok(PyEngine(Engine::new(config)))
+    }
+
+    /// None if the transaction was applied, else why it was declined
+    fn execute(&mut self, txn: &PyTxn) -> Option<String> {
+        self.0.execute(txn.0.clone()).err().map(|r| r.to_string())
+    }
+
+    /// executes every transaction in order, with each one's outcome as `execute` gives it
+    fn replay(&mut self, txns: &Bound<'_, PyAny>) -> PyResult<Vec<Option<String>>> {
+        let mut outcomes = Vec::new();
+        for txn in txns.try_iter()? {
+            let txn = txn?;
+            let txn = txn.cast::<PyTxn>()?;
+            outcomes.push(self.execute(txn.get()));
+        }
+        Ok(outcomes)
+    }
+
+    /// None if the client has no account
+    fn balance(&self, client: ClientRepr) -> Option<PyBalance> {
+        self.0.accounts().get(&ClientId(client)).map(PyBalance::from)
+    }
+
+    /// every account's balance, by client
+    fn balances(&self) -> std::collections::HashMap<ClientRepr, PyBalance> {
+        self.0.accounts().iter().map(|(client, account)| (client.0, PyBalance::from(account))).collect()
+    }
+}
+
+/// replays transactions through the txn engine
+#[pymodule(name = "txn")]
+mod module {
+    #[pymodule_export]
+    use super::{PyBalance, PyEngine, PyTxn};
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    const SCRIPT: &str = r#"
+from decimal import Decimal
+
+engine = txn.Engine()
+assert engine.execute(txn.Txn("deposit", 1, 1, "2.5")) is None
+assert engine.execute(txn.Txn("withdrawal", 1, 2, 10)) == "insufficient funds"
+assert engine.replay([txn.Txn("dispute", 1, 1), txn.Txn("deposit", 2, 3, 1)]) == [None, None]
+balances = engine.balances()
+assert engine.balance(1) == balances[1]
+assert balances[1].held == Decimal("2.5") and balances[1].available == 0 and not balances[1].locked
+assert balances[2].total == 1
+assert engine.balance(3) is None
+assert repr(txn.Txn("dispute", 1, 1)) == "Txn('dispute', 1, 1)"
+
+for bad in [("bogus", 1, 1), ("deposit", 1, 1), ("dispute", 1, 1, 5)]:
+    try:
+        txn.Txn(*bad)
+        assert False, bad
+    except ValueError:
+        pass
+
+limited = txn.Engine("[limits]\nmax_amount = 1")
+assert limited.execute(txn.Txn("deposit", 1, 1, 2)) == "amount over limit"
+"#;
+
+    #[test]
+    fn test_module() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("txn", pyo3::wrap_pymodule!(super::module)(py)).unwrap();
+            py.run(&CString::new(SCRIPT).unwrap(), Some(&globals), None).unwrap();
+        });
+    }
+}
@@ -0,0 +1,122 @@
+//! `--on-error quarantine`: malformatted rows are skipped as under `skip`, and each is also written to a `.bad` file
+//! beside the input (`transactions.csv.bad`), for whoever sent it to fix & resubmit just those. a `.bad` file is csv,
+//! every row as it was in the input, its byte offset there and why it was malformatted:
+//! ```csv
+//! offset,error,row
+//! 36,line 3: amount required,"deposit,1,2,"
+//! ```
+//! it's written afresh every run, just its header when no row was malformatted, and not at all on a dry run. the raw
+//! rows are read back from the input, so only a local csv file read in one pass is quarantined.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::report::Report;
+use crate::{Accounts, malformatted, read_record, record};
+
+#[derive(Serialize)]
+struct BadRow<'a> {
+    offset: u64,
+    error: &'a str,
+    row: &'a str
+}
+
+/// where an input's malformatted rows go
+pub(crate) fn path(input: &Path) -> PathBuf {
+    let mut path = input.as_os_str().to_owned();
+    path.push(".bad");
+    PathBuf::from(path)
+}
+
+pub(crate) fn process(accounts: &mut Accounts, file: File, input: &Path, config: &Config, report: &mut Report)
+                      -> Result<(), Box<dyn std::error::Error>> {
+    let raw = File::open(input)?;
+    let out: Box<dyn Write> = match config.dry_run {
+        true => Box::new(std::io::sink()),
+        false => {
+            let path = path(input);
+            Box::new(File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?)
+        }
+    };
+    process_reader(accounts, file, raw, out, config, report)
+}
+
+/// executes `reader`'s rows, writing the malformatted ones to `out` as `raw`, a second reader of the same input,
+/// has them
+fn process_reader<R: Read, S: Read + Seek, W: Write>(accounts: &mut Accounts, reader: R, mut raw: S, out: W, config: &Config,
+                                                    report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut out = csv::WriterBuilder::new().has_headers(false).from_writer(out);
+    // written here, so an empty file has it too
+    out.write_record(["offset", "error", "row"])?;
+    let mut row = csv::StringRecord::new();
+    loop {
+        let read = reader.read_record(&mut row);
+        // past the row, or as far as the reader got into it
+        let end = reader.position().byte();
+        let (start, error) = match read {
+            Ok(false) => break,
+            Ok(true) => match read_record(&mut row, config) {
+                Ok(txn) => {
+                    record(accounts, txn, config, report)?;
+                    continue;
+                },
+                Err(e) => (row.position().map(csv::Position::byte), e.to_string())
+            },
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            Err(e) => (e.position().map(csv::Position::byte), e.to_string())
+        };
+        let start = start.unwrap_or(end);
+        let mut bytes = vec![0; usize::try_from(end - start)?];
+        raw.seek(SeekFrom::Start(start))?;
+        raw.read_exact(&mut bytes)?;
+        let bytes = String::from_utf8_lossy(&bytes);
+        out.serialize(BadRow { offset: start, error: &error, row: bytes.trim_end_matches(['\r', '\n']) })?;
+        malformatted(config, report, "row", error.as_str())?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::path::Path;
+
+    use rust_decimal_macros::dec;
+
+    use crate::config::{Config, ErrorPolicy};
+    use crate::report::Report;
+    use crate::{Accounts, get_balance};
+
+    use super::{path, process_reader};
+
+    #[test]
+    fn test_quarantine() {
+        let csv = "type,client,tx,amount\ndeposit,1,1,5\ndeposit,1,2,\r\nwithdrawal,1,3,1\n\"deposit\",1\ndeposit,x,4,1\ndeposit,1,5,1";
+        let config = Config { on_error: ErrorPolicy::Quarantine, ..Config::default() };
+        let (mut accounts, mut report, mut out) = (Accounts::default(), Report::default(), Vec::new());
+        process_reader(&mut accounts, csv.as_bytes(), Cursor::new(csv), &mut out, &config, &mut report).unwrap();
+
+        assert_eq!(get_balance(&accounts, 1).available, dec!(5));
+        assert_eq!((report.applied, report.skipped), (3, 3));
+        let bad = String::from_utf8(out).unwrap();
+        let rows: Vec<csv::StringRecord> = csv::Reader::from_reader(bad.as_bytes()).into_records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!((&rows[0][0], &rows[0][1], &rows[0][2]), ("36", "line 3: amount required", "deposit,1,2,"));
+        // the row's own bytes, quotes & all
+        assert_eq!(&rows[1][2], "\"deposit\",1");
+        assert_eq!(&csv[rows[2][0].parse::<usize>().unwrap()..][..15], "deposit,x,4,1\nd");
+        assert_eq!(&rows[2][1], "line 6: client 'x' is not a valid client id");
+    }
+
+    #[test]
+    fn test_path() {
+        assert_eq!(path(Path::new("in/transactions.csv")), Path::new("in/transactions.csv.bad"));
+    }
+}
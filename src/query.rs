@@ -0,0 +1,160 @@
+//! `txn query --client C --at-tx N <file>`: the client's balance as it stood after the first N rows of a csv
+//! input, counting from 1 and counting malformatted rows, for narrowing down where a balance went wrong.
+//!
+//! a transaction only ever touches its own client's account, so only the client's rows are executed, and reading
+//! stops at row N. the engine's events are the same either way, so this is the balance replaying an `EventLog`
+//! up to N would give.
+//!
+//! `txn query --all <file>` lists every client's instead, after N rows with `--at-tx` or else the whole file, a
+//! page at a time as `Filter` picks them out. the query api's `GET /accounts` (see api.rs) pages the same way.
+
+use std::convert::TryFrom;
+use std::io::Read;
+
+use rust_decimal::Decimal;
+
+use crate::config::{Config, QueryOptions};
+use crate::report::Report;
+use crate::{Account, Accounts, ClientId, malformatted, read_record, record};
+
+/// which accounts a listing takes, and a page of how many from where: in client order, so a page starts after the
+/// client the one before ended on however the accounts changed meanwhile
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Filter {
+    /// only the locked accounts, or only those that aren't
+    pub(crate) locked: Option<bool>,
+    /// only those whose total is at least this
+    pub(crate) min_balance: Option<Decimal>,
+    /// only the clients after this one
+    pub(crate) after: Option<ClientId>,
+    /// at most this many, None for all of them
+    pub(crate) limit: Option<usize>
+}
+
+impl Filter {
+    pub(crate) fn new(options: &QueryOptions) -> Self {
+        Filter { locked: options.filter_locked, min_balance: options.min_balance, after: options.after, limit: options.limit }
+    }
+
+    fn takes(&self, client: ClientId, account: &Account) -> bool {
+        self.after.is_none_or(|after| client > after)
+            && self.locked.is_none_or(|locked| account.locked == locked)
+            && self.min_balance.is_none_or(|min| account.balance.total.to_decimal() >= min)
+    }
+
+    /// the page of `accounts` the filter takes, in client order, and the client the next page starts after if there
+    /// are more
+    pub(crate) fn page<A, I>(&self, accounts: I) -> (Vec<(ClientId, A)>, Option<ClientId>)
+        where A: std::borrow::Borrow<Account>, I: IntoIterator<Item = (ClientId, A)>
+    {
+        let mut page: Vec<(ClientId, A)> = accounts.into_iter().filter(|(client, a)| self.takes(*client, a.borrow())).collect();
+        let more = match self.limit {
+            Some(limit) if page.len() > limit => {
+                // the page's clients, without sorting every client taken
+                page.select_nth_unstable_by_key(limit, |(client, _)| *client);
+                page.truncate(limit);
+                true
+            },
+            _ => false
+        };
+        page.sort_unstable_by_key(|(client, _)| *client);
+        let next = page.last().map(|(client, _)| *client).filter(|_| more);
+        (page, next)
+    }
+}
+
+/// the client's account after row `at`, None if it had none by then
+pub(crate) fn balance_at<R: Read>(reader: R, client: ClientId, at: u64, config: &Config, report: &mut Report)
+                                  -> Result<Option<Account>, Box<dyn std::error::Error>> {
+    let mut accounts = Accounts::default();
+    for row in csv::Reader::from_reader(reader).into_records().take(usize::try_from(at).unwrap_or(usize::MAX)) {
+        let txn = row.map_err(crate::pipeline::RowError::from)
+            .and_then(|mut r| read_record(&mut r, config));
+        match txn {
+            Ok(t) if t.client == client => record(&mut accounts, t, config, report)?,
+            Ok(_) => {},
+            Err(e) => malformatted(config, report, "row", e)?
+        }
+    }
+    Ok(accounts.remove(&client))
+}
+
+/// every client's account after row `at`, or the whole input
+pub(crate) fn balances_at<R: Read>(reader: R, at: Option<u64>, config: &Config, report: &mut Report)
+                                   -> Result<Accounts, Box<dyn std::error::Error>> {
+    let mut accounts = Accounts::default();
+    let rows = at.map_or(usize::MAX, |at| usize::try_from(at).unwrap_or(usize::MAX));
+    for row in csv::Reader::from_reader(reader).into_records().take(rows) {
+        let txn = row.map_err(crate::pipeline::RowError::from)
+            .and_then(|mut r| read_record(&mut r, config));
+        match txn {
+            Ok(t) => record(&mut accounts, t, config, report)?,
+            Err(e) => malformatted(config, report, "row", e)?
+        }
+    }
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::{Config, ErrorPolicy};
+    use crate::report::Report;
+    use crate::{Accounts, ClientId, execute, Txn};
+
+    use super::{balance_at, balances_at, Filter};
+
+    const CSV: &str = "type,client,tx,amount\ndeposit,1,1,10\ndeposit,2,2,5\nwithdrawal,1,3,4\nbogus\ndispute,1,1,\n";
+
+    #[test]
+    fn test_balance_at() {
+        let config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        let at = |client, row| balance_at(CSV.as_bytes(), ClientId(client), row, &config, &mut Report::default()).unwrap()
+            .map(|a| a.balance);
+
+        assert_eq!(at(1, 0), None);
+        assert_eq!(at(1, 2).unwrap().available, dec!(10));
+        assert_eq!(at(1, 3).unwrap().available, dec!(6));
+        // the malformatted row counts
+        assert_eq!(at(1, 4).unwrap().held, dec!(0));
+        assert_eq!(at(1, 5).unwrap().held, dec!(10));
+        assert_eq!(at(2, 100).unwrap().total, dec!(5));
+        assert_eq!(at(3, 100), None);
+    }
+
+    #[test]
+    fn test_balances_at() {
+        let config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        let accounts = balances_at(CSV.as_bytes(), Some(3), &config, &mut Report::default()).unwrap();
+        assert_eq!(accounts[&ClientId(1)].balance.available, dec!(6));
+        assert_eq!(accounts.len(), 2);
+        let accounts = balances_at(CSV.as_bytes(), None, &config, &mut Report::default()).unwrap();
+        assert_eq!(accounts[&ClientId(1)].balance.held, dec!(10));
+    }
+
+    #[test]
+    fn test_page() {
+        let mut accounts = Accounts::default();
+        for client in 1..=20 {
+            execute(&mut accounts, Txn::deposit(client, client.into(), (client * 10).into()));
+        }
+        execute(&mut accounts, Txn::dispute(7, 7));
+        execute(&mut accounts, Txn::chargeback(7, 7));
+        let clients = |filter: &Filter| {
+            let (page, next) = filter.page(accounts.iter().map(|(client, a)| (*client, a)));
+            (page.into_iter().map(|(client, _)| client.0).collect::<Vec<_>>(), next.map(|c| c.0))
+        };
+
+        assert_eq!(clients(&Filter::default()).0, (1..=20).collect::<Vec<_>>());
+        let first = Filter { limit: Some(8), ..Filter::default() };
+        assert_eq!(clients(&first), ((1..=8).collect(), Some(8)));
+        let last = Filter { after: Some(ClientId(16)), ..first.clone() };
+        assert_eq!(clients(&last), (vec![17, 18, 19, 20], None));
+        assert_eq!(clients(&Filter { after: Some(ClientId(12)), ..first }), ((13..=20).collect(), None));
+
+        assert_eq!(clients(&Filter { locked: Some(true), ..Filter::default() }).0, vec![7]);
+        let rich = Filter { min_balance: Some(dec!(150)), locked: Some(false), limit: Some(2), ..Filter::default() };
+        assert_eq!(clients(&rich), (vec![15, 16], Some(16)));
+    }
+}
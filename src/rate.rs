@@ -0,0 +1,164 @@
+//! `[rate]`: how many transactions a second the server takes from each connection (`--rate-limit`) and from all of
+//! them together (`--global-rate-limit`), so a bursty client can't swamp the engine & what it writes to. each rate is
+//! a token bucket holding a second's worth: a burst that size goes through at once, the lines after it at the rate.
+//! a line over either is, by `rate.policy`:
+//! - `reject`: answered `throttled: <why>` and not executed, the line protocol's 429, for the client to send again
+//! - `wait`: held until it's within the rates, the connection unread meanwhile, so a client writing faster is held
+//!   back by its socket filling up
+//!
+//! the rates are read from the config current when each line is, so a reload changes them for the lines after it.
+//! buckets are timed by the server's `Clock`, so tests throttle the same every run.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::clock::Clock;
+use crate::config::{RateOptions, RatePolicy};
+
+const SECOND: Duration = Duration::from_secs(1);
+
+/// a token bucket kept as the time it'll next be full (a generic cell rate algorithm), so it's refilled exactly
+/// whatever the rate, and a changed rate applies from the next line
+#[derive(Debug)]
+pub(crate) struct Bucket {
+    full_at: SystemTime
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Bucket { full_at: UNIX_EPOCH }
+    }
+}
+
+impl Bucket {
+    /// how long until a line is within `rate` at `now`, zero if it is already
+    fn wait(&self, rate: u32, now: SystemTime) -> Duration {
+        let burst = SECOND - interval(rate);
+        self.full_at.duration_since(now).unwrap_or_default().saturating_sub(burst)
+    }
+
+    fn take(&mut self, rate: u32, now: SystemTime) {
+        self.full_at = self.full_at.max(now) + interval(rate);
+    }
+}
+
+/// between lines at `rate`
+fn interval(rate: u32) -> Duration {
+    SECOND / rate
+}
+
+/// a connection's bucket, and the one all connections share
+pub(crate) struct Limiter<'a> {
+    own: Bucket,
+    global: &'a Mutex<Bucket>,
+    clock: &'a dyn Clock
+}
+
+impl<'a> Limiter<'a> {
+    pub(crate) fn new(global: &'a Mutex<Bucket>, clock: &'a dyn Clock) -> Self {
+        Limiter { own: Bucket::default(), global, clock }
+    }
+
+    /// admits the line just read: Ok once it's within the rates, having waited for them under `wait`, the rate it's
+    /// over under `reject`
+    pub(crate) fn admit(&mut self, options: &RateOptions) -> Result<(), String> {
+        if options.per_connection.is_none() && options.global.is_none() {
+            return Ok(());
+        }
+        loop {
+            let now = self.clock.now();
+            let mut global = self.global.lock().unwrap();
+            let own_wait = options.per_connection.map_or(Duration::ZERO, |rate| self.own.wait(rate, now));
+            let global_wait = options.global.map_or(Duration::ZERO, |rate| global.wait(rate, now));
+            if own_wait.is_zero() && global_wait.is_zero() {
+                if let Some(rate) = options.per_connection {
+                    self.own.take(rate, now);
+                }
+                if let Some(rate) = options.global {
+                    global.take(rate, now);
+                }
+                return Ok(());
+            }
+            match (options.policy, options.per_connection, options.global) {
+                (RatePolicy::Reject, Some(rate), _) if !own_wait.is_zero() => {
+                    return Err(format!("over {} transactions/s per connection", rate));
+                },
+                (RatePolicy::Reject, _, Some(rate)) => return Err(format!("over {} transactions/s across connections", rate)),
+                _ => {
+                    // other connections take from the global bucket while this one waits
+                    drop(global);
+                    self.clock.sleep(own_wait.max(global_wait));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::clock::{Clock, MockClock};
+    use crate::config::{RateOptions, RatePolicy};
+
+    use super::{Bucket, Limiter};
+
+    fn clock() -> MockClock {
+        MockClock::new(UNIX_EPOCH + Duration::from_secs(1_718_000_000))
+    }
+
+    #[test]
+    fn test_bucket() {
+        let clock = clock();
+        let mut bucket = Bucket::default();
+        // a second's worth at once
+        for _ in 0..4 {
+            assert_eq!(bucket.wait(4, clock.now()), Duration::ZERO);
+            bucket.take(4, clock.now());
+        }
+        assert_eq!(bucket.wait(4, clock.now()), Duration::from_millis(250));
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(bucket.wait(4, clock.now()), Duration::ZERO);
+        bucket.take(4, clock.now());
+        assert_eq!(bucket.wait(4, clock.now()), Duration::from_millis(250));
+        // full again after a quiet second, and no fuller
+        clock.advance(Duration::from_secs(5));
+        for _ in 0..4 {
+            bucket.take(4, clock.now());
+        }
+        assert_eq!(bucket.wait(4, clock.now()), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_reject() {
+        let clock = clock();
+        let global = Mutex::default();
+        let options = RateOptions { per_connection: Some(2), global: Some(3), policy: RatePolicy::Reject };
+        let (mut a, mut b) = (Limiter::new(&global, &clock), Limiter::new(&global, &clock));
+        assert_eq!(a.admit(&options), Ok(()));
+        assert_eq!(a.admit(&options), Ok(()));
+        assert_eq!(a.admit(&options), Err("over 2 transactions/s per connection".into()));
+        assert_eq!(b.admit(&options), Ok(()));
+        assert_eq!(b.admit(&options), Err("over 3 transactions/s across connections".into()));
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(b.admit(&options), Ok(()));
+        assert_eq!(a.admit(&options), Err("over 3 transactions/s across connections".into()));
+        assert_eq!(a.admit(&RateOptions::default()), Ok(()));
+    }
+
+    #[test]
+    fn test_wait() {
+        let clock = clock();
+        let global = Mutex::default();
+        let start = clock.now();
+        let options = RateOptions { per_connection: Some(10), global: None, policy: RatePolicy::Wait };
+        let mut limiter = Limiter::new(&global, &clock);
+        for _ in 0..25 {
+            assert_eq!(limiter.admit(&options), Ok(()));
+        }
+        // the first 10 at once, the rest a tenth of a second apart
+        assert_eq!(clock.since(start), Duration::from_millis(1500));
+    }
+}
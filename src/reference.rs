@@ -0,0 +1,297 @@
+//! `txn verify [--reference naive] <file>`: a csv input run through the engine and through a reference
+//! implementation of the same rules, side by side, and the accounts each ends with diffed, so a rewrite of the
+//! engine for speed can be checked against something too plain to get wrong:
+//! ```text
+//! client  field      engine  reference
+//! 3       available    12.5        2.5
+//! 3       total        12.5        2.5
+//! ```
+//! when they agree it says how many transactions & clients they agreed on. any difference ends the run as a failed
+//! invariant check would.
+//!
+//! the naive reference keeps each client's deposits & withdrawals and what's become of them, and works a balance out
+//! from those afresh whenever it needs one: total is what the applied transactions moved, held what's under dispute,
+//! available the difference. there are no events, fixed-point amounts or undo. only the rules it models are run, on
+//! both sides: the dispute & lock policies from the config, the defaults otherwise (no settlement delay, account
+//! kinds, limits or currencies). its decimals don't overflow, so amounts near the engine's limits differ.
+
+use std::fmt;
+use std::io::Read;
+
+use rust_decimal::Decimal;
+
+use crate::config::{Config, Reference};
+use crate::report::Report;
+use crate::{Accounts, ClientId, execute_with, malformatted, Map, read_record, Txn, TxnId, TxnType};
+
+/// the rules a reference models: the config's dispute & lock policies, and the defaults otherwise
+pub(crate) fn rules(config: &Config) -> Config {
+    Config { disputes: config.disputes.clone(), locked: config.locked.clone(), ..Config::default() }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Disputed,
+    Resolved,
+    ChargedBack
+}
+
+#[derive(Default)]
+struct Client {
+    /// what each applied deposit, withdrawal & chargeback moved
+    moved: Vec<Decimal>,
+    /// deposits & withdrawals by id, a declined withdrawal without an amount. a reused id's the latest
+    logged: Map<TxnId, (TxnType, Option<Decimal>)>,
+    status: Map<TxnId, Status>,
+    locked: bool
+}
+
+impl Client {
+    fn total(&self) -> Decimal {
+        self.moved.iter().sum()
+    }
+
+    fn held(&self) -> Decimal {
+        self.status.iter().filter(|(_, s)| **s == Status::Disputed)
+            .filter_map(|(tx, _)| self.logged.get(tx).and_then(|(_, amount)| *amount))
+            .sum()
+    }
+
+    fn available(&self) -> Decimal {
+        self.total() - self.held()
+    }
+}
+
+/// the transactions run as plainly as they can be, under `config`'s rules, which are taken as `rules` gives them
+fn naive(txns: &[Txn], config: &Config) -> Map<ClientId, Client> {
+    let mut clients: Map<ClientId, Client> = Map::default();
+    for txn in txns {
+        let (tx, amount) = (txn.tx, txn.amount.map(|a| a.to_decimal()));
+        if clients.get(&txn.client).is_some_and(|c| c.locked && !config.locked.accepts(&txn.txntype)) {
+            continue;
+        }
+        let client = match txn.txntype {
+            TxnType::Deposit | TxnType::Withdrawal => clients.entry(txn.client).or_default(),
+            // nothing to dispute without an account, and none's opened
+            _ => match clients.get_mut(&txn.client) {
+                Some(c) => c,
+                None => continue
+            }
+        };
+        let status = client.status.get(&tx).copied();
+        match txn.txntype {
+            // a disputed transaction keeps its id
+            TxnType::Deposit | TxnType::Withdrawal if status == Some(Status::Disputed) => (),
+            TxnType::Deposit => {
+                let amount = amount.unwrap_or_default();
+                client.moved.push(amount);
+                client.logged.insert(tx, (TxnType::Deposit, Some(amount)));
+            },
+            TxnType::Withdrawal => {
+                let amount = amount.unwrap_or_default();
+                let declined = client.available() < amount;
+                if !declined {
+                    client.moved.push(-amount);
+                }
+                client.logged.insert(tx, (TxnType::Withdrawal, Some(amount).filter(|_| !declined)));
+            },
+            TxnType::Dispute => {
+                let disputable = match client.logged.get(&tx) {
+                    Some((_, None)) | None => false,
+                    Some((TxnType::Withdrawal, _)) if !config.disputes.withdrawals => false,
+                    Some(_) => match status {
+                        None => true,
+                        Some(Status::Resolved) => config.disputes.redisputes,
+                        Some(Status::Disputed) | Some(Status::ChargedBack) => false
+                    }
+                };
+                if disputable {
+                    client.status.insert(tx, Status::Disputed);
+                }
+            },
+            TxnType::Resolve if status == Some(Status::Disputed) => {
+                client.status.insert(tx, Status::Resolved);
+            },
+            TxnType::Chargeback if status == Some(Status::Disputed) => {
+                let amount = client.logged.get(&tx).and_then(|(_, amount)| *amount).unwrap_or_default();
+                client.moved.push(-amount);
+                client.status.insert(tx, Status::ChargedBack);
+                client.locked = true;
+            },
+            TxnType::Resolve | TxnType::Chargeback => ()
+        }
+    }
+    clients
+}
+
+/// a balance the engine and the reference disagree on
+#[derive(Debug, PartialEq, Eq)]
+struct Difference {
+    client: ClientId,
+    field: &'static str,
+    engine: String,
+    reference: String
+}
+
+pub(crate) struct Verification {
+    reference: Reference,
+    txns: usize,
+    clients: usize,
+    /// by client
+    differences: Vec<Difference>
+}
+
+impl Verification {
+    pub(crate) fn read<R: Read>(reader: R, config: &Config, report: &mut Report) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut txns = Vec::new();
+        for record in csv::Reader::from_reader(reader).into_records() {
+            let txn = record.map_err(crate::pipeline::RowError::from)
+                .and_then(|mut r| read_record(&mut r, config));
+            match txn {
+                Ok(t) => txns.push(t),
+                Err(e) => malformatted(config, report, "row", e)?
+            }
+        }
+        Ok(Self::run(&txns, config, report))
+    }
+
+    /// the transactions through the engine, recorded in the report, and through the config's reference
+    pub(crate) fn run(txns: &[Txn], config: &Config, report: &mut Report) -> Self {
+        let reference = config.verify.reference;
+        let config = rules(config);
+        let mut accounts = Accounts::default();
+        for txn in txns {
+            report.record(execute_with(&mut accounts, txn.clone(), &config));
+        }
+        let clients = match reference {
+            Reference::Naive => naive(txns, &config)
+        };
+        Self { reference, txns: txns.len(), clients: accounts.len().max(clients.len()), differences: diff(&accounts, &clients) }
+    }
+
+    pub(crate) fn agrees(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    pub(crate) fn disagreement(&self) -> String {
+        let mut clients: Vec<ClientId> = self.differences.iter().map(|d| d.client).collect();
+        clients.dedup();
+        let (plural, verb) = if clients.len() == 1 { ("", "differs") } else { ("s", "differ") };
+        format!("{} client{} {} from the {} reference", clients.len(), plural, verb, self.reference_name())
+    }
+
+    fn reference_name(&self) -> &'static str {
+        match self.reference {
+            Reference::Naive => "naive"
+        }
+    }
+}
+
+fn diff(accounts: &Accounts, clients: &Map<ClientId, Client>) -> Vec<Difference> {
+    let mut ids: Vec<&ClientId> = accounts.keys().chain(clients.keys()).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let shown = |d: Option<Decimal>| d.map_or_else(|| "-".to_string(), |d| d.normalize().to_string());
+    let mut differences = Vec::new();
+    for id in ids {
+        let (account, client) = (accounts.get(id), clients.get(id));
+        let fields = [
+            ("available", account.map(|a| a.balance.available.to_decimal()), client.map(Client::available)),
+            ("held", account.map(|a| a.balance.held.to_decimal()), client.map(Client::held)),
+            ("total", account.map(|a| a.balance.total.to_decimal()), client.map(Client::total))
+        ];
+        for (field, engine, reference) in fields {
+            if engine != reference {
+                differences.push(Difference { client: *id, field, engine: shown(engine), reference: shown(reference) });
+            }
+        }
+        let (engine, reference) = (account.map(|a| a.locked), client.map(|c| c.locked));
+        if engine != reference {
+            let shown = |l: Option<bool>| l.map_or_else(|| "-".to_string(), |l| l.to_string());
+            differences.push(Difference { client: *id, field: "locked", engine: shown(engine), reference: shown(reference) });
+        }
+    }
+    differences
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.agrees() {
+            return writeln!(f, "{} transactions over {} clients, the engine and the {} reference agree", self.txns,
+                            self.clients, self.reference_name());
+        }
+        let header = ["client", "field", "engine", "reference"].map(String::from);
+        let cells: Vec<[String; 4]> = self.differences.iter()
+            .map(|d| [d.client.to_string(), d.field.to_string(), d.engine.clone(), d.reference.clone()])
+            .collect();
+        let mut widths = [0; 4];
+        for row in std::iter::once(&header).chain(&cells) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in std::iter::once(&header).chain(&cells) {
+            // the client & field left aligned, balances right
+            let line = format!("{:<w0$}  {:<w1$}  {:>w2$}  {:>w3$}", row[0], row[1], row[2], row[3],
+                               w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3]);
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::{Config, ErrorPolicy};
+    use crate::report::Report;
+    use crate::{Accounts, Amount, ClientId, execute_with, Txn};
+
+    use super::{diff, naive, Verification};
+
+    const CSV: &str = "type,client,tx,amount\ndeposit,1,1,10\ndeposit,1,2,5\nwithdrawal,1,3,20\ndispute,1,3,\n\
+                       dispute,1,2,\ndeposit,1,2,7\nresolve,1,2,\ndispute,1,2,\nwithdrawal,2,4,1\nbogus\n\
+                       deposit,2,5,3\ndispute,2,5,\nchargeback,2,5,\ndeposit,2,6,1\ndispute,3,1,\nwithdrawal,1,7,4\n\
+                       dispute,1,7,\nchargeback,1,7,\n";
+
+    #[test]
+    fn test_agrees() {
+        let config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        let mut report = Report::default();
+        let verification = Verification::read(CSV.as_bytes(), &config, &mut report).unwrap();
+        assert_eq!(verification.to_string(), "17 transactions over 2 clients, the engine and the naive reference agree\n");
+        assert_eq!(report.skipped, 1);
+
+        for (withdrawals, redisputes) in [(false, false), (false, true), (true, false)] {
+            let mut config = config.clone();
+            config.disputes.withdrawals = withdrawals;
+            config.disputes.redisputes = redisputes;
+            config.locked.deposits = true;
+            let verification = Verification::read(CSV.as_bytes(), &config, &mut Report::default()).unwrap();
+            assert!(verification.agrees(), "{}", verification);
+        }
+    }
+
+    #[test]
+    fn test_differences() {
+        let config = Config::default();
+        let txns = [Txn::deposit(1, 1, dec!(5)), Txn::deposit(2, 2, dec!(3)), Txn::dispute(2, 2)];
+        let mut verification = Verification::run(&txns, &config, &mut Report::default());
+        assert!(verification.agrees());
+
+        // an engine that lost what client 2's dispute held
+        let mut accounts = Accounts::default();
+        for txn in &txns {
+            execute_with(&mut accounts, txn.clone(), &config).unwrap();
+        }
+        accounts.get_mut(&ClientId::from(2u16)).unwrap().balance.held = Amount::ZERO;
+        verification.differences = diff(&accounts, &naive(&txns, &config));
+
+        assert_eq!(verification.to_string(), "\
+client  field  engine  reference
+2       held        0          3
+");
+        assert_eq!(verification.disagreement(), "1 client differs from the naive reference");
+    }
+}
@@ -0,0 +1,164 @@
+//! `--clients clients.csv`: who the clients are. each row names a client, and optionally its name, currency and
+//! account kind:
+//! ```csv
+//! client,name,currency,kind
+//! 1,Ada Lovelace,GBP,
+//! 1000,Acme Ltd,USD,merchant
+//! ```
+//! every listed client's account is opened before the input is read, its kind taken from here over `[kinds]`. a
+//! deposit or withdrawal naming a currency other than its account's is declined (`currency mismatch`). only input
+//! that carries a currency is checked: json's `"currency"`, or an embedder's `TxnBuilder::currency`. csv rows have
+//! none.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::event::{Event, Sink};
+use crate::{AccountKind, Accounts, ClientId, emit, open_account};
+
+/// an iso 4217 code, `USD`, held upper cased
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub fn as_str(&self) -> &str {
+        // only ever ascii letters
+        std::str::from_utf8(&self.0).expect("a currency is ascii")
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let code = s.trim().to_ascii_uppercase();
+        match <[u8; 3]>::try_from(code.as_bytes()) {
+            Ok(code) if code.iter().all(u8::is_ascii_uppercase) => Ok(Currency(code)),
+            _ => Err(format!("invalid currency '{}'", s))
+        }
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+impl From<Currency> for String {
+    fn from(currency: Currency) -> Self {
+        currency.as_str().to_string()
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub(crate) struct Client {
+    client: ClientId,
+    name: Option<String>,
+    currency: Option<Currency>,
+    kind: Option<AccountKind>
+}
+
+pub(crate) fn load(path: &Path) -> Result<Vec<Client>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("clients {}: {}", path.display(), e))?;
+    read(file).map_err(|e| format!("clients {}: {}", path.display(), e))
+}
+
+fn read<R: std::io::Read>(reader: R) -> Result<Vec<Client>, String> {
+    let mut clients: Vec<Client> = Vec::new();
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    for (line, client) in reader.deserialize::<Client>().enumerate() {
+        // 1 for the header, 1 for counting from 1
+        let line = line + 2;
+        let client = client.map_err(|e| format!("line {}: {}", line, e))?;
+        if clients.iter().any(|c| c.client == client.client) {
+            return Err(format!("line {}: client {} listed twice", line, client.client));
+        }
+        clients.push(client);
+    }
+    Ok(clients)
+}
+
+/// opens the clients' accounts with their metadata. a client that already has an account is left as it is
+pub(crate) fn open<S: Sink>(accounts: &mut Accounts, clients: Vec<Client>, config: &Config, sink: &mut S) {
+    for Client { client, name, currency, kind } in clients {
+        if accounts.contains_key(&client) {
+            continue;
+        }
+        let mut account = open_account(client, config, sink);
+        if let Some(kind) = kind.filter(|k| *k != account.kind) {
+            emit(&mut account, client, Event::AccountClassified(kind), sink).expect("a kind always applies");
+        }
+        if name.is_some() || currency.is_some() {
+            emit(&mut account, client, Event::AccountRegistered { name, currency }, sink).expect("metadata always applies");
+        }
+        accounts.insert(client, account);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::event::EventLog;
+    use crate::{AccountKind, Accounts, ClientId, execute_with, Rejection, Txn, TxnId, TxnType};
+
+    use super::{Currency, open, read};
+
+    #[test]
+    fn test_currency() {
+        assert_eq!(" usd".parse::<Currency>().unwrap().as_str(), "USD");
+        for invalid in ["US", "USDT", "U5D", "€€"] {
+            assert!(invalid.parse::<Currency>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        let clients = read("client,name,currency,kind\n1,Ada Lovelace,gbp,\n1000, Acme Ltd ,USD,merchant\n2,,,\n".as_bytes()).unwrap();
+        assert_eq!(clients.len(), 3);
+        assert_eq!(clients[1].name.as_deref(), Some("Acme Ltd"));
+        assert_eq!(clients[2].currency, None);
+        assert!(read("client,name,currency,kind\n1,,,\n1,,,\n".as_bytes()).is_err());
+        assert!(read("client,name,currency,kind\n1,,dollars,\n".as_bytes()).is_err());
+        assert!(read("client,name,currency,kind\n1,,,bank\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_open() {
+        let clients = read("client,name,currency,kind\n1,Ada Lovelace,GBP,\n1000,Acme Ltd,USD,escrow\n".as_bytes()).unwrap();
+        let mut config = Config::default();
+        config.kinds.merchants = "1000".parse().unwrap();
+        let (mut log, mut accounts) = (EventLog::default(), Accounts::default());
+        open(&mut accounts, clients, &config, &mut log);
+        assert_eq!(log.replay().unwrap(), accounts);
+        let acme = &accounts[&ClientId(1000)];
+        assert_eq!((acme.name.as_deref(), acme.kind), (Some("Acme Ltd"), AccountKind::Escrow));
+
+        let txn = |txntype, tx: u32, currency: &str| Txn::builder(txntype, ClientId(1), TxnId::from(tx)).amount(dec!(1))
+            .currency(currency.parse::<Currency>().unwrap()).build().unwrap();
+        execute_with(&mut accounts, txn(TxnType::Deposit, 1, "GBP"), &config).unwrap();
+        assert_eq!(execute_with(&mut accounts, txn(TxnType::Deposit, 2, "USD"), &config), Err(Rejection::CurrencyMismatch));
+        assert_eq!(execute_with(&mut accounts, txn(TxnType::Withdrawal, 3, "EUR"), &config), Err(Rejection::CurrencyMismatch));
+        // no currency, no check
+        execute_with(&mut accounts, Txn::withdrawal(1, 4, dec!(1)), &config).unwrap();
+        // nor for an account without one
+        execute_with(&mut accounts, Txn::builder(TxnType::Deposit, ClientId(2), TxnId(6)).amount(dec!(1))
+            .currency("EUR".parse::<Currency>().unwrap()).build().unwrap(), &config).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+//! server mode reloads its config file when it changes, so limits, the dispute policy & lock rules can be changed
+//! without a restart losing the accounts. the file is checked every second and layered under the environment &
+//! command line as it was at startup. only `[limits]`, `[disputes]`, `[locked]` & `[rate]` are taken from a reload: everything
+//! else applies on the next restart, and a reload that changes it says so. a file that no longer parses is reported
+//! and the running config kept.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::config::Config;
+use crate::server::State;
+
+const POLL: Duration = Duration::from_secs(1);
+
+pub(crate) struct Watch {
+    path: PathBuf,
+    env: Vec<(String, String)>,
+    overrides: Vec<(&'static str, String)>,
+    /// the file's modification time & length when last read
+    seen: Option<(SystemTime, u64)>
+}
+
+impl Watch {
+    /// watches `path`, as read at startup
+    pub(crate) fn new(path: PathBuf, env: Vec<(String, String)>, overrides: Vec<(&'static str, String)>) -> Self {
+        let mut watch = Watch { path, env, overrides, seen: None };
+        watch.seen = watch.stamp();
+        watch
+    }
+
+    fn stamp(&self) -> Option<(SystemTime, u64)> {
+        let metadata = std::fs::metadata(&self.path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    /// the config resolved afresh, if the file changed since it was last read
+    pub(crate) fn poll(&mut self) -> Option<Result<Config, String>> {
+        let stamp = self.stamp();
+        // a file gone missing, i.e. mid-replace by an editor, is left until it's back
+        if stamp.is_none() || stamp == self.seen {
+            return None;
+        }
+        self.seen = stamp;
+        Some(Config::resolve(Some(&self.path), self.env.clone(), &self.overrides))
+    }
+}
+
+/// `current` with the reloadable settings of `reloaded`, and whether `reloaded` changes anything else
+pub(crate) fn merge(current: &Config, reloaded: Config) -> (Config, bool) {
+    let mut merged = current.clone();
+    merged.limits = reloaded.limits.clone();
+    merged.disputes = reloaded.disputes.clone();
+    merged.locked = reloaded.locked.clone();
+    merged.rate = reloaded.rate.clone();
+    let restart = merged != reloaded;
+    (merged, restart)
+}
+
+/// applies the file's changes to the server, until it stops
+pub(crate) fn run(mut watch: Watch, state: Arc<State>) {
+    loop {
+        std::thread::sleep(POLL);
+        match watch.poll() {
+            Some(Ok(reloaded)) => {
+                let (merged, restart) = merge(&state.config(), reloaded);
+                state.set_config(merged);
+                match restart {
+                    true => eprintln!("{}: reloaded limits, disputes, lock rules & rates, other changes need a restart", watch.path.display()),
+                    false => eprintln!("{}: reloaded", watch.path.display())
+                }
+            },
+            Some(Err(e)) => eprintln!("config reload failed, keeping the running config: {}", e),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::{Config, ErrorPolicy};
+
+    use super::{merge, Watch};
+
+    #[test]
+    fn test_merge() {
+        let current = Config::default();
+        let mut reloaded = Config::default();
+        reloaded.limits.max_amount = Some(dec!(5));
+        reloaded.disputes.withdrawals = false;
+        reloaded.locked.deposits = true;
+        reloaded.rate.per_connection = Some(10);
+        let (merged, restart) = merge(&current, reloaded.clone());
+        assert_eq!(merged, reloaded);
+        assert!(!restart);
+
+        reloaded.on_error = ErrorPolicy::Skip;
+        let (merged, restart) = merge(&current, reloaded);
+        assert_eq!(merged.limits.max_amount, Some(dec!(5)));
+        assert_eq!(merged.on_error, ErrorPolicy::Abort);
+        assert!(restart);
+    }
+
+    #[test]
+    fn test_poll() {
+        let path = std::env::temp_dir().join(format!("txn-reload-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "[limits]\nmax_amount = 10\n").unwrap();
+        let overrides = vec![("precision", "2".to_string())];
+        let mut watch = Watch::new(path.clone(), Vec::new(), overrides);
+        assert!(watch.poll().is_none());
+
+        std::fs::write(&path, "[limits]\nmax_amount = 100\n").unwrap();
+        let config = watch.poll().unwrap().unwrap();
+        assert_eq!(config.limits.max_amount, Some(dec!(100)));
+        // still layered under the command line
+        assert_eq!(config.precision, 2);
+        assert!(watch.poll().is_none());
+
+        std::fs::write(&path, "[limits]\nmax_amount = \"lots\"\n").unwrap();
+        assert!(watch.poll().unwrap().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+//! `--reorder-lateness N`: rows carry a fifth `timestamp` column (an integer, in whatever unit the producer uses),
+//! and are buffered and executed in timestamp order rather than file order. input merged from several
+//! partitions is often only slightly out of order, so N bounds how far behind the newest timestamp seen a row
+//! may arrive: the watermark trails the newest timestamp by N, rows the watermark passes are executed, and a row
+//! older than the watermark arrives too late to be placed and is rejected (`late arrival`).
+//!
+//! rows with equal timestamps keep their file order. whatever is still buffered at the end of input is executed.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{ClientId, Precision, Rejection, Txn, TxnError, TxnId, TxnType};
+
+/// a csv row with its timestamp
+#[derive(Deserialize)]
+pub(crate) struct TimestampedRow {
+    #[serde(rename = "type")]
+    txntype: TxnType,
+    client: ClientId,
+    tx: TxnId,
+    amount: Option<Decimal>,
+    timestamp: u64
+}
+
+impl TimestampedRow {
+    pub(crate) fn into_txn(self, precision: Precision) -> Result<(u64, Txn), TxnError> {
+        Ok((self.timestamp, Txn::builder(self.txntype, self.client, self.tx).amount(self.amount).precision(precision).build()?))
+    }
+}
+
+struct Pending {
+    timestamp: u64,
+    /// arrival order, to break ties
+    seq: u64,
+    txn: Txn
+}
+
+// reversed, so the max-heap pops the earliest first
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.timestamp, other.seq).cmp(&(self.timestamp, self.seq))
+    }
+}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        (self.timestamp, self.seq) == (other.timestamp, other.seq)
+    }
+}
+
+impl Eq for Pending {}
+
+pub(crate) struct Reorder {
+    lateness: u64,
+    /// newest timestamp seen, less the lateness
+    watermark: u64,
+    pending: BinaryHeap<Pending>,
+    seq: u64
+}
+
+impl Reorder {
+    pub(crate) fn new(lateness: u64) -> Self {
+        Reorder { lateness, watermark: 0, pending: BinaryHeap::new(), seq: 0 }
+    }
+
+    /// buffers the transaction until the watermark passes it, unless it already has
+    pub(crate) fn push(&mut self, timestamp: u64, txn: Txn) -> Result<(), Rejection> {
+        if timestamp < self.watermark {
+            return Err(Rejection::Late);
+        }
+        self.watermark = self.watermark.max(timestamp.saturating_sub(self.lateness));
+        self.pending.push(Pending { timestamp, seq: self.seq, txn });
+        self.seq += 1;
+        Ok(())
+    }
+
+    /// the earliest buffered transaction, if the watermark has passed it
+    pub(crate) fn pop_ready(&mut self) -> Option<Txn> {
+        match self.pending.peek() {
+            Some(p) if p.timestamp <= self.watermark => self.pop(),
+            _ => None
+        }
+    }
+
+    /// the earliest buffered transaction, ready or not, for the end of input
+    pub(crate) fn pop(&mut self) -> Option<Txn> {
+        self.pending.pop().map(|p| p.txn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::{Config, ErrorPolicy};
+    use crate::report::Report;
+    use crate::{Accounts, get_balance, process_csv_reordered, Rejection, Txn, TxnRepr};
+
+    use super::Reorder;
+
+    fn drain(reorder: &mut Reorder, ready: bool) -> Vec<TxnRepr> {
+        let mut txs = Vec::new();
+        while let Some(txn) = if ready { reorder.pop_ready() } else { reorder.pop() } {
+            txs.push(txn.tx.0);
+        }
+        txs
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut reorder = Reorder::new(10);
+        for (tx, timestamp) in [(1, 100), (2, 95), (3, 105), (4, 100)] {
+            reorder.push(timestamp, Txn::deposit(1, tx, dec!(1))).unwrap();
+        }
+        // the watermark, 95, only lets the earliest out
+        assert_eq!(drain(&mut reorder, true), vec![2]);
+
+        reorder.push(112, Txn::deposit(1, 5, dec!(1))).unwrap();
+        // ties in arrival order
+        assert_eq!(drain(&mut reorder, true), vec![1, 4]);
+        assert_eq!(reorder.push(101, Txn::deposit(1, 6, dec!(1))), Err(Rejection::Late));
+        reorder.push(102, Txn::deposit(1, 7, dec!(1))).unwrap();
+
+        assert_eq!(drain(&mut reorder, false), vec![7, 3, 5]);
+    }
+
+    #[test]
+    fn test_no_lateness() {
+        let mut reorder = Reorder::new(0);
+        reorder.push(5, Txn::deposit(1, 1, dec!(1))).unwrap();
+        reorder.push(5, Txn::deposit(1, 2, dec!(1))).unwrap();
+        assert_eq!(drain(&mut reorder, true), vec![1, 2]);
+        assert_eq!(reorder.push(4, Txn::deposit(1, 3, dec!(1))), Err(Rejection::Late));
+    }
+
+    #[test]
+    fn test_process_reordered() {
+        let csv = "type,client,tx,amount,timestamp\nwithdrawal,1,2,5,110\ndeposit,1,1,10,100\ndeposit,1,3,1,200\n\
+                   withdrawal,1,4,1,120\ndeposit,1,5,1\n";
+        let mut accounts = Accounts::default();
+        let mut report = Report::default();
+        let config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        process_csv_reordered(&mut accounts, csv.as_bytes(), 60, None, &config, &mut report).unwrap();
+
+        // the withdrawal waits on the deposit before it, the late one is declined & the untimestamped one skipped
+        assert_eq!(get_balance(&accounts, 1).total, dec!(6));
+        assert_eq!(report.applied, 3);
+        assert_eq!(report.rejected.get(&Rejection::Late), Some(&1));
+        assert_eq!(report.skipped, 1);
+    }
+}
@@ -0,0 +1,407 @@
+//! replication: a primary server (`--replicate-to 10.0.0.2:7100`) ships every transaction it executes to its
+//! standbys over tcp, and a standby (`--standby-listen 10.0.0.2:7100`) executes them in the same order, keeping a hot
+//! copy of the accounts to fail over to. the engine is deterministic, so under the same config a standby ends up
+//! where the primary is, rejections & all.
+//!
+//! the primary's log of transactions shipped, one json line each, is its write-ahead log:
+//! `{"seq":42,"txn":{"type":"deposit","client":1,"tx":42,"amount":"2.5"},"outcome":"ok"}`. it's kept in memory, the
+//! newest `--replication-horizon` of them (a million unless it's said). a standby connected to says which `seq`
+//! it's up to, `next 42`, and is sent the log from there on, so one restarted (from nothing) or cut off catches up
+//! from the log, then follows it live. that's the catch-up horizon: a standby further behind than the log reaches,
+//! or started afresh once the primary's executed past it, is refused, and has to be started from the primary's
+//! accounts some other way. the primary connects to standbys as it starts, and again at most once a second while one
+//! is unreachable.
+//!
+//! each standby is shipped to from a thread of its own, woken as the log grows, so the transactions aren't held up
+//! by one that's slow or unreachable. connecting, and each write, give up after `TIMEOUT`, the standby then being
+//! reconnected to & caught up.
+//!
+//! the standby authenticates its primary: with `--auth-keys`, which a standby needs, the primary's first line is
+//! `auth <key>`, an admin's, from its `--auth-key-file`, answered `next <seq>`, or `unauthorized`/`forbidden: <why>`
+//! and the connection closed. with `--tls-cert` the standby takes its primary through TLS, and a primary with
+//! `--tls-ca` ships through TLS, verifying the standby's certificate (see tls.rs). the primary's known by its key, so
+//! `--tls-client-ca` doesn't apply to it.
+//!
+//! - the log is one order for every transaction, so a replicating primary executes one at a time
+//! - a transaction is queued for every standby before it's answered, but not acknowledged: one a standby hadn't read
+//!   when the primary died is lost to it
+//! - a standby whose outcome differs from the primary's, i.e. run under another config, says so on stderr and
+//!   carries on
+//! - a standby answers transactions `standby: <why>` and isn't ready (`/readyz`) until an admin sends it
+//!   `promote`. from then on it's a server like any other, and takes no more from a primary. promotion is left to an
+//!   operator, so a primary that's only cut off for a moment doesn't leave two servers taking transactions
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Role;
+use crate::config::{Config, TlsOptions};
+use crate::server::{Engine, State, TcpSplit};
+use crate::{Rejection, Txn};
+
+/// between attempts at connecting to a standby that was unreachable
+const RETRY: Duration = Duration::from_secs(1);
+/// a standby that can't be connected to, say where it's up to or take a write by now isn't waited on, nor a primary
+/// that hasn't authenticated
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// the most entries written to a standby at once, the log's lock held only to copy them
+const BATCH: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Entry {
+    seq: u64,
+    txn: Txn,
+    /// `ok`, or the rejection
+    outcome: String
+}
+
+fn outcome(result: Result<(), Rejection>) -> String {
+    match result {
+        Ok(()) => "ok".to_string(),
+        Err(r) => r.to_string()
+    }
+}
+
+/// a primary's log & the standbys it's shipped to
+pub(crate) struct Primary {
+    log: Arc<Mutex<Log>>,
+    /// wakes each standby's thread, there being more of the log to ship. one wake-up pending is as good as many, so
+    /// each holds one at most
+    standbys: Vec<SyncSender<()>>
+}
+
+/// the newest transactions shipped, as far back as the horizon
+struct Log {
+    /// `Entry`s as json, oldest first
+    entries: VecDeque<Arc<str>>,
+    /// the seq of the oldest, 1 until the log's reached its horizon
+    first: u64,
+    horizon: usize
+}
+
+/// how a primary reaches its standbys
+struct Link {
+    /// the admin's key it authenticates with
+    key: Option<String>,
+    tls: TlsOptions
+}
+
+impl Primary {
+    /// ships to each standby `[replication]` lists, from a thread each, those unreachable tried again once a `RETRY`
+    pub(crate) fn connect(config: &Config) -> Result<Self, String> {
+        let key = config.admin.key_file.as_deref().map(crate::auth::read_key).transpose()?;
+        let link = Arc::new(Link { key, tls: config.tls.clone() });
+        let log = Arc::new(Mutex::new(Log { entries: VecDeque::new(), first: 1, horizon: config.replication.horizon }));
+        let standbys = config.replication.to.iter()
+            .map(|address| {
+                let (wake, woken) = mpsc::sync_channel(1);
+                let (address, link, log) = (address.clone(), Arc::clone(&link), Arc::clone(&log));
+                std::thread::spawn(move || ship(&address, &link, &log, &woken));
+                wake
+            })
+            .collect();
+        Ok(Primary { log, standbys })
+    }
+
+    /// executes `txn` & logs it for the standbys, one transaction at a time
+    pub(crate) fn execute(&self, engine: &Engine, txn: Txn, config: &Arc<Config>) -> Result<(), Rejection> {
+        let mut log = self.log.lock().unwrap();
+        let result = engine.execute(txn.clone(), config);
+        log.push(txn, outcome(result));
+        drop(log);
+        for standby in &self.standbys {
+            // full is a wake-up pending already
+            let _ = standby.try_send(());
+        }
+        result
+    }
+}
+
+impl Log {
+    fn push(&mut self, txn: Txn, outcome: String) {
+        let entry = Entry { seq: self.first + self.entries.len() as u64, txn, outcome };
+        self.entries.push_back(serde_json::to_string(&entry).expect("an entry serializes").into());
+        if self.entries.len() > self.horizon {
+            self.entries.pop_front();
+            self.first += 1;
+        }
+    }
+
+    /// up to a `BATCH` of entries from seq `next` on, Err if the log doesn't reach it
+    fn from(&self, next: u64) -> Result<Vec<Arc<str>>, String> {
+        if next == 0 || next > self.first + self.entries.len() as u64 {
+            // it was promoted, or followed another primary
+            return Err(format!("it's up to {}, past this primary's log", next));
+        }
+        if next < self.first {
+            return Err(format!("it's up to {}, behind this primary's log, which keeps the newest {} from {}", next, self.horizon,
+                               self.first));
+        }
+        Ok(self.entries.range((next - self.first) as usize..).take(BATCH).cloned().collect())
+    }
+}
+
+impl Link {
+    /// connects & authenticates, the writing half & the seq the standby says it's up to
+    fn connect(&self, address: &str) -> io::Result<(BufWriter<Box<dyn Write + Send>>, u64)> {
+        let (reader, writer) = crate::server::tcp_connect(address, &self.tls, TIMEOUT)?;
+        let mut writer = BufWriter::new(writer);
+        if let Some(key) = &self.key {
+            writeln!(writer, "auth {}", key)?;
+            writer.flush()?;
+        }
+        let mut next = String::new();
+        BufReader::new(reader).read_line(&mut next)?;
+        let next = next.trim().strip_prefix("next ").and_then(|n| n.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("expected next <seq>, got '{}'", next.trim())))?;
+        Ok((writer, next))
+    }
+}
+
+/// ships the log to the standby at `address`, reconnecting & catching it up whenever it's cut off, until the primary's
+/// gone
+fn ship(address: &str, link: &Link, log: &Mutex<Log>, woken: &Receiver<()>) {
+    let mut standby = None;
+    loop {
+        let (writer, next) = match &mut standby {
+            Some(standby) => standby,
+            None => match link.connect(address) {
+                Ok(connected) => standby.insert(connected),
+                Err(e) => {
+                    eprintln!("standby {}: {}", address, e);
+                    match back_off(woken) {
+                        true => continue,
+                        false => return
+                    }
+                }
+            }
+        };
+        let batch = log.lock().unwrap().from(*next);
+        let shipped = match batch {
+            Ok(batch) if batch.is_empty() => match woken.recv() {
+                Ok(()) => continue,
+                Err(_) => return
+            },
+            Ok(batch) => batch.iter()
+                .try_for_each(|entry| writeln!(writer, "{}", entry))
+                .and_then(|()| writer.flush())
+                .map(|()| batch.len() as u64)
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e)
+        };
+        match shipped {
+            Ok(shipped) => *next += shipped,
+            Err(e) => {
+                eprintln!("standby {}: {}, reconnecting", address, e);
+                standby = None;
+                if !back_off(woken) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// waits out a `RETRY` however often it's woken meanwhile, false if the primary's gone
+fn back_off(woken: &Receiver<()>) -> bool {
+    let until = Instant::now() + RETRY;
+    loop {
+        match woken.recv_timeout(until.saturating_duration_since(Instant::now())) {
+            Ok(()) => continue,
+            Err(RecvTimeoutError::Timeout) => return true,
+            Err(RecvTimeoutError::Disconnected) => return false
+        }
+    }
+}
+
+/// binds `address`, then follows whichever primary connects to it on a thread of its own, until promoted
+pub(crate) fn follow(address: &str, state: Arc<State>) -> Result<(), Box<dyn std::error::Error>> {
+    // the primary's known by its key, not by a certificate
+    let split = crate::server::tcp_split(&TlsOptions { client_ca: None, ..state.config().tls.clone() })?;
+    let listener = TcpListener::bind(address).map_err(|e| format!("Error listening on {}: {}", address, e))?;
+    std::thread::spawn(move || {
+        // one primary at a time, so the log is applied in order
+        for stream in listener.incoming() {
+            if !state.standby.load(Ordering::Acquire) {
+                // promoted, the stream's dropped unanswered
+                continue;
+            }
+            if let Err(e) = stream.and_then(|s| apply(s, &split, &state)) {
+                eprintln!("replication error: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// authenticates the primary, then applies its log from where the standby is up to, until the primary's gone or the
+/// standby's promoted
+fn apply(stream: TcpStream, split: &TcpSplit, state: &State) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let (reader, mut writer) = split(stream.try_clone()?)?;
+    let (mut reader, mut buf) = (BufReader::new(reader), Vec::new());
+    if let Some(keys) = &state.keys {
+        let line = crate::server::read_line(&mut reader, &mut buf)?.unwrap_or_default();
+        let refused = match keys.authenticate(&line) {
+            Ok(Role::Admin) => None,
+            Ok(role) => Some(format!("forbidden: shipping to a standby is an admin's, this key is a {}", role)),
+            Err(why) => Some(format!("unauthorized: {}", why))
+        };
+        if let Some(refused) = refused {
+            writeln!(writer, "{}", refused)?;
+            writer.flush()?;
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("primary {}", refused)));
+        }
+    }
+    // authenticated, a primary with nothing to ship is waited on
+    stream.set_read_timeout(None)?;
+    writeln!(writer, "next {}", state.replicated.load(Ordering::Acquire) + 1)?;
+    writer.flush()?;
+    while let Some(line) = crate::server::read_line(&mut reader, &mut buf)? {
+        if !state.standby.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        let entry: Entry = serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let expected = state.replicated.load(Ordering::Acquire) + 1;
+        if entry.seq != expected {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("expected seq {}, got {}", expected, entry.seq)));
+        }
+        let config = state.config();
+        let (tx, txntype) = (entry.txn.tx, entry.txn.txntype.clone());
+        // the primary ships no duplicates, the standby's index is only kept for once it's promoted
+        state.admit(&entry.txn)?;
+        let result = state.engine.execute(entry.txn, &config);
+        state.executed(tx);
+        state.release(&txntype, tx, result)?;
+        state.report.lock().unwrap().record(result);
+        if outcome(result) != entry.outcome {
+            eprintln!("standby diverged from the primary at seq {}: {} there, {} here", entry.seq, entry.outcome, outcome(result));
+        }
+        state.replicated.store(entry.seq, Ordering::Release);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use rust_decimal_macros::dec;
+
+    use crate::auth::Keys;
+    use crate::config::Config;
+    use crate::server::State;
+    use crate::{ClientId, Rejection, Txn};
+
+    use super::{follow, Log, Primary};
+
+    // the digests of "foo" & "bar"
+    const KEYS: &str = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  submitter\n\
+                        fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9  admin\n";
+
+    /// a port nothing's listening on yet
+    fn free() -> String {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string()
+    }
+
+    /// a standby following on `address`, authenticating its primary
+    fn following(address: &str) -> Arc<State> {
+        let mut config = Config::default();
+        config.replication.listen = Some(address.to_string());
+        let mut state = State::new(config);
+        state.keys = Some(Keys::parse(KEYS).unwrap());
+        let state = Arc::new(state);
+        follow(address, Arc::clone(&state)).unwrap();
+        state
+    }
+
+    /// a primary shipping to `to`, authenticating with `key`
+    fn primary(to: &[String], key: &str, name: &str) -> (Primary, PathBuf) {
+        let path = std::env::temp_dir().join(format!("txn-replica-{}-{}.key", name, std::process::id()));
+        std::fs::write(&path, key).unwrap();
+        let mut config = Config::default();
+        config.replication.to = to.to_vec();
+        config.admin.key_file = Some(path.clone());
+        (Primary::connect(&config).unwrap(), path)
+    }
+
+    fn wait_for(state: &State, seq: u64) {
+        let start = Instant::now();
+        while state.replicated.load(Ordering::Acquire) < seq {
+            assert!(start.elapsed() < Duration::from_secs(5), "standby stuck at {}", state.replicated.load(Ordering::Acquire));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_replicate() {
+        let (address, late) = (free(), free());
+        let standby = following(&address);
+        let primary_state = State::new(Config::default());
+        let (primary, key) = primary(&[address, late.clone()], "bar", "replicate");
+        let config = Arc::new(Config::default());
+        let txns = [Txn::deposit(1, 1, dec!(5)), Txn::withdrawal(1, 2, dec!(9)), Txn::deposit(2, 3, dec!(1)), Txn::dispute(1, 1)];
+        for txn in txns {
+            primary.execute(&primary_state.engine, txn, &config).unwrap_or(());
+        }
+        wait_for(&standby, 4);
+        let (there, here) = (primary_state.engine.balances(), standby.engine.balances());
+        assert_eq!(here.len(), 2);
+        for (client, account) in &there {
+            assert_eq!((here[client].balance, here[client].locked), (account.balance, account.locked));
+        }
+        assert_eq!(standby.report.lock().unwrap().rejected[&Rejection::InsufficientFunds], 1);
+
+        // a standby started afresh catches up from the log
+        let restarted = following(&late);
+        primary.execute(&primary_state.engine, Txn::resolve(1, 1), &config).unwrap();
+        wait_for(&restarted, 5);
+        assert_eq!(restarted.engine.balances()[&ClientId(1)].balance.held, dec!(0));
+        assert_eq!(restarted.engine.balances()[&ClientId(1)].balance.available, dec!(5));
+        std::fs::remove_file(key).unwrap();
+    }
+
+    #[test]
+    fn test_refused() {
+        // promoted, and a primary whose key isn't an admin's
+        let (promoted, submitter) = (free(), free());
+        let promoted_state = following(&promoted);
+        promoted_state.standby.store(false, Ordering::Release);
+        let submitter_state = following(&submitter);
+        let primary_state = State::new(Config::default());
+        let config = Arc::new(Config::default());
+        let (to_promoted, admin_key) = primary(&[promoted], "bar", "promoted");
+        let (to_submitter, submitter_key) = primary(&[submitter], "foo", "submitter");
+        to_promoted.execute(&primary_state.engine, Txn::deposit(1, 1, dec!(5)), &config).unwrap();
+        to_submitter.execute(&primary_state.engine, Txn::deposit(1, 2, dec!(5)), &config).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(promoted_state.engine.balances().is_empty());
+        assert!(submitter_state.engine.balances().is_empty());
+        std::fs::remove_file(admin_key).unwrap();
+        std::fs::remove_file(submitter_key).unwrap();
+    }
+
+    #[test]
+    fn test_horizon() {
+        let mut log = Log { entries: Default::default(), first: 1, horizon: 2 };
+        for tx in 1..=4 {
+            log.push(Txn::deposit(1, tx, dec!(1)), "ok".into());
+        }
+        assert_eq!(log.first, 3);
+        assert!(log.from(2).unwrap_err().contains("behind this primary's log"));
+        assert!(log.from(3).unwrap()[0].starts_with(r#"{"seq":3,"#));
+        assert_eq!(log.from(5).unwrap().len(), 0);
+        assert!(log.from(6).unwrap_err().contains("past this primary's log"));
+    }
+}
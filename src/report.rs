@@ -0,0 +1,110 @@
+//! run summary: how many transactions were applied, skipped as malformatted or declined by the engine, and why.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{Event, Sink};
+use crate::stats::Operations;
+use crate::{ClientId, Rejection};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Report {
+    pub(crate) applied: u64,
+    /// malformatted rows, records or batches passed over under `on_error = "skip"`
+    pub(crate) skipped: u64,
+    /// transactions passed over as already applied, under `--replay-tolerant`
+    #[serde(default)]
+    pub(crate) replayed: u64,
+    pub(crate) rejected: BTreeMap<Rejection, u64>,
+    /// what chargebacks took from every account
+    #[serde(default)]
+    pub(crate) chargeback_loss: Decimal,
+    /// each client's transactions, counted under `--stats`
+    #[serde(skip)]
+    pub(crate) operations: Option<Operations>
+}
+
+impl Report {
+    pub(crate) fn record(&mut self, result: Result<(), Rejection>) {
+        match result {
+            Ok(()) => self.applied += 1,
+            Err(r) => *self.rejected.entry(r).or_insert(0) += 1
+        }
+    }
+
+    pub(crate) fn skip(&mut self) {
+        self.skipped += 1;
+    }
+
+    pub(crate) fn rejected_total(&self) -> u64 {
+        self.rejected.values().sum()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "applied: {}", self.applied)?;
+        writeln!(f, "skipped: {}", self.skipped)?;
+        if self.replayed > 0 {
+            writeln!(f, "replayed: {}", self.replayed)?;
+        }
+        writeln!(f, "rejected: {}", self.rejected_total())?;
+        for (reason, count) in &self.rejected {
+            writeln!(f, "  {}: {}", reason, count)?;
+        }
+        if !self.chargeback_loss.is_zero() {
+            writeln!(f, "chargeback losses: {}", self.chargeback_loss.normalize())?;
+        }
+        Ok(())
+    }
+}
+
+/// tallies the losses of the chargebacks applied
+impl Sink for Report {
+    fn record(&mut self, _client: ClientId, event: Event) {
+        if let Event::FundsChargedBack { amount, .. } | Event::ReserveChargedBack { amount, .. } = event {
+            // past what a decimal holds, it stays at its largest
+            self.chargeback_loss = self.chargeback_loss.checked_add(amount.to_decimal()).unwrap_or(Decimal::MAX);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::{Accounts, execute_recorded, Rejection, Txn};
+
+    use super::Report;
+
+    #[test]
+    fn test_report() {
+        let mut report = Report::default();
+        report.record(Ok(()));
+        report.record(Err(Rejection::NotDisputed));
+        report.record(Err(Rejection::InsufficientFunds));
+        report.record(Err(Rejection::InsufficientFunds));
+        report.skip();
+
+        assert_eq!(report.applied, 1);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(report.rejected_total(), 3);
+        assert_eq!(report.to_string(), "applied: 1\nskipped: 1\nrejected: 3\n  insufficient funds: 2\n  not disputed: 1\n");
+    }
+
+    #[test]
+    fn test_chargeback_losses() {
+        let (mut accounts, config, mut report) = (Accounts::default(), Config::default(), Report::default());
+        for txn in [Txn::deposit(1, 1, dec!(2.5)), Txn::deposit(2, 2, dec!(1)), Txn::dispute(1, 1), Txn::chargeback(1, 1),
+                    Txn::dispute(2, 2), Txn::chargeback(2, 2), Txn::chargeback(2, 2)] {
+            let result = execute_recorded(&mut accounts, txn, &config, &mut report);
+            report.record(result);
+        }
+        assert_eq!(report.chargeback_loss, dec!(3.5));
+        assert!(report.to_string().ends_with("chargeback losses: 3.5\n"), "{}", report);
+    }
+}
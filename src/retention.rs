@@ -0,0 +1,169 @@
+//! `[retention]`: how long deposits & withdrawals are kept in their account's log, where they're only needed for
+//! disputes, so a long-running server's memory is bounded by how far back a dispute may reach rather than by all it's
+//! ever taken. a transaction's age goes by its tx, ids being given out in increasing order, and one disputed or still
+//! settling is kept whatever its age.
+//!
+//! - `keep_last = 1000` keeps the newest 1000 per account. the log is pruned back to them once it's grown a quarter
+//!   past, so it holds up to 1250
+//! - `keep_days = 90`, when serving, prunes what was logged over 90 days ago. every minute the server notes the
+//!   newest tx it's executed, and prunes through the one it noted 90 days before. it goes by the server's clock, so a
+//!   standby prunes on its own, and a restart starts the 90 days over
+//!
+//! pruning is an event, `TransactionPruned`. the account keeps how many it's pruned & their txs as up to 16 runs,
+//! checkpointed with it, and a dispute of one of those is rejected as `pruned transaction` rather than `unknown
+//! transaction`. an account's txs aren't contiguous, other accounts' come between, so past 16 runs the two nearest
+//! are joined and the txs between them, ones it never logged among them, are taken as pruned too. a pruned tx reused
+//! by a deposit or withdrawal is taken as a new transaction, not a duplicate.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::server::State;
+use crate::TxnId;
+
+/// how many runs of pruned txs an account keeps
+const RUNS: usize = 16;
+
+/// between the server's notes of the newest tx
+const SAMPLE: Duration = Duration::from_secs(60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// the newest tx executed at each note, for as far back as transactions are kept
+#[derive(Debug, Default)]
+struct Samples {
+    samples: VecDeque<(SystemTime, TxnId)>,
+    /// what's been pruned through already
+    pruned: Option<TxnId>
+}
+
+impl Samples {
+    /// notes `newest` at `now`, and returns the tx to prune through if the note `keep` ago is newer than the last
+    fn sample(&mut self, now: SystemTime, newest: Option<TxnId>, keep: Duration) -> Option<TxnId> {
+        if let Some(newest) = newest {
+            self.samples.push_back((now, newest));
+        }
+        let cutoff = now.checked_sub(keep)?;
+        // the newest note at or before the cutoff is the one wanted, those before it are done with
+        while self.samples.get(1).is_some_and(|(at, _)| *at <= cutoff) {
+            self.samples.pop_front();
+        }
+        let through = self.samples.front().filter(|(at, _)| *at <= cutoff).map(|(_, tx)| *tx)?;
+        if Some(through) <= self.pruned {
+            return None;
+        }
+        self.pruned = Some(through);
+        Some(through)
+    }
+}
+
+/// the txs pruned from an account's log, as runs of first & last tx in order, `[[1,3],[7,7]]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub(crate) struct PrunedTxs(Vec<(TxnId, TxnId)>);
+
+impl PrunedTxs {
+    /// the run `tx` would be in or go before
+    fn run(&self, tx: TxnId) -> usize {
+        self.0.partition_point(|&(_, last)| last < tx)
+    }
+
+    pub(crate) fn contains(&self, tx: TxnId) -> bool {
+        self.0.get(self.run(tx)).is_some_and(|&(first, _)| first <= tx)
+    }
+
+    pub(crate) fn insert(&mut self, tx: TxnId) {
+        let i = self.run(tx);
+        if self.contains(tx) {
+            return;
+        }
+        let extends = i > 0 && self.0[i - 1].1.as_u64() + 1 == tx.as_u64();
+        let precedes = self.0.get(i).is_some_and(|&(first, _)| tx.as_u64() + 1 == first.as_u64());
+        match (extends, precedes) {
+            (true, true) => {
+                self.0[i - 1].1 = self.0.remove(i).1;
+            }
+            (true, false) => self.0[i - 1].1 = tx,
+            (false, true) => self.0[i].0 = tx,
+            (false, false) => self.0.insert(i, (tx, tx))
+        }
+        if self.0.len() > RUNS {
+            let nearest = (1..self.0.len()).min_by_key(|&i| self.0[i].0.as_u64() - self.0[i - 1].1.as_u64());
+            if let Some(i) = nearest {
+                self.0[i - 1].1 = self.0.remove(i).1;
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// the bytes its runs take
+    pub(crate) fn bytes(&self) -> u64 {
+        (self.0.capacity() * std::mem::size_of::<(TxnId, TxnId)>()) as u64
+    }
+}
+
+/// prunes the server's accounts under `keep_days`, on a thread of its own, until the process ends
+pub(crate) fn run(state: Arc<State>) {
+    let mut samples = Samples::default();
+    loop {
+        state.clock.sleep(SAMPLE);
+        let keep = match state.config().retention.keep_days {
+            Some(days) => Duration::from_secs(days.saturating_mul(DAY.as_secs())),
+            None => continue
+        };
+        let newest = *state.newest.lock().unwrap();
+        if let Some(through) = samples.sample(state.clock.now(), newest, keep) {
+            state.engine.prune(through);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::TxnId;
+
+    use super::{PrunedTxs, Samples, RUNS};
+
+    #[test]
+    fn test_sample() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_718_000_000);
+        let keep = Duration::from_secs(300);
+        let mut samples = Samples::default();
+        let at = |minutes: u32| start + Duration::from_secs(u64::from(minutes) * 60);
+        // nothing's old enough for the first five minutes
+        for minute in 0..5 {
+            assert_eq!(samples.sample(at(minute), Some(TxnId::from(minute * 10)), keep), None);
+        }
+        assert_eq!(samples.sample(at(5), Some(TxnId(50)), keep), Some(TxnId(0)));
+        assert_eq!(samples.sample(at(6), None, keep), Some(TxnId(10)));
+        // nothing new's been executed since, so it's pruned through no further than the last note
+        assert_eq!(samples.sample(at(11), None, keep), Some(TxnId(50)));
+        assert_eq!(samples.sample(at(12), None, keep), None);
+        assert_eq!(samples.samples.len(), 1);
+    }
+
+    #[test]
+    fn test_pruned_txs() {
+        let mut pruned = PrunedTxs::default();
+        for tx in [3, 1, 2, 7, 5u32] {
+            pruned.insert(TxnId::from(tx));
+        }
+        assert_eq!(serde_json::to_string(&pruned).unwrap(), "[[1,3],[5,5],[7,7]]");
+        pruned.insert(TxnId(6));
+        assert_eq!(serde_json::to_string(&pruned).unwrap(), "[[1,3],[5,7]]");
+        assert!(pruned.contains(TxnId(2)) && !pruned.contains(TxnId(4)) && !pruned.contains(TxnId(8)));
+        // every other tx, as an account's are among others', keeps to RUNS runs however many are pruned
+        for tx in (100..10_000u32).step_by(2) {
+            pruned.insert(TxnId::from(tx));
+        }
+        assert_eq!(pruned.0.len(), RUNS);
+        assert!(pruned.contains(TxnId(9_998)) && pruned.contains(TxnId(2)) && !pruned.contains(TxnId(10_000)));
+    }
+}
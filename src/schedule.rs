@@ -0,0 +1,154 @@
+//! `--schedule schedule.csv`: recurring deposits & withdrawals (monthly fees, standing orders...) interleaved with
+//! timestamped input (`--reorder-lateness`). each schedule row recurs from `start` every `every` timestamp units,
+//! `count` times or without end:
+//! ```csv
+//! type,client,amount,start,every,count
+//! withdrawal,1,2.5,100,30,12
+//! ```
+//! an occurrence is executed as if it were an input row with its timestamp, once the input reaches that timestamp:
+//! occurrences after the last input row's timestamp aren't materialized. txn ids are synthesized by hashing the
+//! schedule row & occurrence, as statement imports' are, so pick input ids that keep clear of them.
+
+use std::path::Path;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::statement::TxnIds;
+use crate::{Amount, ClientId, Precision, Txn, TxnId, TxnType};
+
+#[derive(Deserialize)]
+struct Row {
+    #[serde(rename = "type")]
+    txntype: TxnType,
+    client: ClientId,
+    amount: Decimal,
+    start: u64,
+    every: u64,
+    count: Option<u64>
+}
+
+struct Recurring {
+    txntype: TxnType,
+    client: ClientId,
+    amount: Amount,
+    /// the next occurrence's timestamp, None once they've all been
+    next: Option<u64>,
+    every: u64,
+    /// occurrences left, None for unending
+    left: Option<u64>,
+    /// occurrences so far
+    seen: u64
+}
+
+pub(crate) struct Schedule {
+    recurring: Vec<Recurring>,
+    ids: TxnIds
+}
+
+impl Schedule {
+    pub(crate) fn load(path: &Path, precision: Precision) -> Result<Self, String> {
+        let file = std::fs::File::open(path).map_err(|e| format!("schedule {}: {}", path.display(), e))?;
+        Schedule::read(file, precision).map_err(|e| format!("schedule {}: {}", path.display(), e))
+    }
+
+    fn read<R: std::io::Read>(reader: R, precision: Precision) -> Result<Self, String> {
+        let mut recurring = Vec::new();
+        let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+        for (line, row) in reader.deserialize::<Row>().enumerate() {
+            // 1 for the header, 1 for counting from 1
+            let line = line + 2;
+            let row = row.map_err(|e| format!("line {}: {}", line, e))?;
+            if !matches!(row.txntype, TxnType::Deposit | TxnType::Withdrawal) {
+                return Err(format!("line {}: only deposits & withdrawals recur", line));
+            }
+            if row.every == 0 {
+                return Err(format!("line {}: every must be above 0", line));
+            }
+            // held to the rules of an input row
+            let amount = Txn::builder(row.txntype.clone(), row.client, TxnId(0)).amount(row.amount).precision(precision).build()
+                .map_err(|e| format!("line {}: {}", line, e))?
+                .amount();
+            recurring.push(Recurring {
+                txntype: row.txntype,
+                client: row.client,
+                amount,
+                next: (row.count != Some(0)).then_some(row.start),
+                every: row.every,
+                left: row.count,
+                seen: 0
+            });
+        }
+        Ok(Schedule { recurring, ids: TxnIds::default() })
+    }
+
+    /// the occurrences not yet given at or before `timestamp`, in timestamp order (schedule order for ties)
+    pub(crate) fn due(&mut self, timestamp: u64) -> Vec<(u64, Txn)> {
+        let mut due = Vec::new();
+        for (row, r) in self.recurring.iter_mut().enumerate() {
+            while let Some(next) = r.next.filter(|n| *n <= timestamp) {
+                let tx = self.ids.next(&format!("schedule:{}:{}", row, r.seen));
+                due.push((next, row, Txn::new(r.txntype.clone(), r.client, tx, Some(r.amount))));
+                r.seen += 1;
+                r.left = r.left.map(|l| l - 1);
+                r.next = match r.left {
+                    Some(0) => None,
+                    _ => next.checked_add(r.every)
+                };
+            }
+        }
+        due.sort_by_key(|(timestamp, row, _)| (*timestamp, *row));
+        due.into_iter().map(|(timestamp, _, txn)| (timestamp, txn)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::report::Report;
+    use crate::{Accounts, ClientRepr, CURRENCY_PRECISION, get_balance, process_csv_reordered, Rejection};
+
+    use super::Schedule;
+
+    fn schedule(csv: &str) -> Result<Schedule, String> {
+        Schedule::read(csv.as_bytes(), CURRENCY_PRECISION.into())
+    }
+
+    #[test]
+    fn test_due() {
+        let mut schedule = schedule("type,client,amount,start,every,count\nwithdrawal,1,2.5,10,10,3\ndeposit,2,1,15,5,\n").unwrap();
+        let due = |s: &mut Schedule, t| -> Vec<(u64, ClientRepr)> {
+            s.due(t).into_iter().map(|(timestamp, txn)| (timestamp, txn.client.0)).collect()
+        };
+        assert_eq!(due(&mut schedule, 9), vec![]);
+        assert_eq!(due(&mut schedule, 20), vec![(10, 1), (15, 2), (20, 1), (20, 2)]);
+        // given once only, and the withdrawal's 3 are done at 30
+        assert_eq!(due(&mut schedule, 20), vec![]);
+        assert_eq!(due(&mut schedule, 45), vec![(25, 2), (30, 1), (30, 2), (35, 2), (40, 2), (45, 2)]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(schedule("type,client,amount,start,every,count\ndispute,1,1,0,10,\n").is_err());
+        assert!(schedule("type,client,amount,start,every,count\ndeposit,1,1,0,0,\n").is_err());
+        assert!(schedule("type,client,amount,start,every,count\ndeposit,1,lots,0,1,\n").is_err());
+        assert_eq!(schedule("type,client,amount,start,every,count\ndeposit,1,-1,0,1,\n").err().unwrap(), "line 2: amount negative");
+    }
+
+    #[test]
+    fn test_interleaved() {
+        // a monthly fee of 1 from 100, every 30
+        let schedule = schedule("type,client,amount,start,every,count\nwithdrawal,1,1,100,30,\n").unwrap();
+        let csv = "type,client,tx,amount,timestamp\ndeposit,1,1,1,90\nwithdrawal,1,2,1,125\ndeposit,1,3,5,140\n";
+        let mut accounts = Accounts::default();
+        let mut report = Report::default();
+        process_csv_reordered(&mut accounts, csv.as_bytes(), 0, Some(schedule), &Config::default(), &mut report).unwrap();
+
+        // the fee at 100 takes the deposit, leaving nothing for the withdrawal at 125 or the fee at 130
+        assert_eq!(get_balance(&accounts, 1).total, dec!(5));
+        assert_eq!(report.applied, 3);
+        assert_eq!(report.rejected.get(&Rejection::InsufficientFunds), Some(&2));
+    }
+}
@@ -0,0 +1,970 @@
+//! server mode: `--listen unix:/var/run/txn.sock` accepts newline-delimited transactions, one csv row
+//! (`deposit,1,1,1.0`, no header) per line, from any number of connections. `--listen tcp:0.0.0.0:7000` takes them
+//! over tcp instead, through TLS with `--tls-cert` (see tls.rs).
+//!
+//! every line is answered with `ok`, `rejected: <reason>`, `malformatted: <error>` or `throttled: <why>`, by a
+//! standby with `standby: <why>`, and an admin operation that couldn't be carried out with `failed: <error>`. under `on_error = "abort"`
+//! a malformatted line closes its connection, the server carries on, as a line over `MAX_LINE` bytes or that isn't
+//! utf-8 always does. balances are written out whenever a connection closes. past `MAX_CONNECTIONS` at once a
+//! connection's answered `throttled` and closed.
+//!
+//! connections apply their transactions concurrently through a `ConcurrentEngine`, so clients only wait on
+//! each other when their accounts share a shard, or with `--actors` through an actor per client (see actor.rs).
+//! `--threads 1` takes connections one at a time instead, each read to its end on the accepting thread through
+//! the sequential engine, so balances come out as they would from the connections' lines in one file. one that
+//! sends nothing for `IDLE` is closed, so it doesn't hold up those waiting their turn. `--threads 8` serves 8
+//! connections at once rather than `MAX_CONNECTIONS`.
+//!
+//! the config file is reloaded when it changes, applying new limits & dispute policies to the transactions that
+//! follow (see reload.rs).
+//!
+//! `--rate-limit` & `--global-rate-limit` cap the transactions a second taken from each connection & from all of
+//! them, a line over either answered `throttled: <why>` or held until it isn't (see rate.rs).
+//!
+//! `--auth-keys` has connections authenticate with an api key first, its role saying whether they may run admin
+//! operations as well as send transactions (see auth.rs). a tcp listener needs them, as without every connection is
+//! an admin. `snapshot`, an admin's, writes the balances out,
+//! `promote` makes a standby take transactions, `adjust` corrects a client's balance and `forget` erases who a
+//! client is, both recorded in `--audit-log` (see admin.rs).
+//!
+//! `--dedup-index` rejects a deposit or withdrawal reusing a tx seen before, for any client, as `duplicate
+//! transaction` (see dedup.rs).
+//!
+//! `--replicate-to` ships every transaction to standbys, which `--standby-listen` for them, keeping a copy of the
+//! accounts to fail over to once promoted (see replica.rs).
+//!
+//! `--health-listen` answers liveness & readiness probes over http (see health.rs), and `--api-listen` queries of
+//! the accounts (see api.rs).
+//!
+//! `--tui` shows a dashboard of the server in the terminal (see tui.rs), balances are then only written out to
+//! an `--output` file.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::actor::Actors;
+use crate::admin::{Adjustment, Audit};
+use crate::auth::{Keys, Role};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{Config, ErrorPolicy, TlsOptions};
+use crate::dedup::Dedup;
+use crate::pipeline::RowError;
+use crate::rate::{Bucket, Limiter};
+use crate::replica::Primary;
+use crate::reload::Watch;
+use crate::report::Report;
+use crate::{Account, Accounts, adjust_with, Amount, ClientId, ConcurrentEngine, execute_with, finish, forget_with, prune_with, read_record, Rejection, Txn,
+            TxnId, TxnType, validate_with};
+
+/// chargebacks kept for the dashboard
+const RECENT_CHARGEBACKS: usize = 10;
+/// the longest line taken, in bytes. a longer one closes its connection
+const MAX_LINE: usize = 64 * 1024;
+/// connections served at once, a thread each, unless `--threads` says. any more are turned away
+const MAX_CONNECTIONS: usize = 1024;
+/// under `--threads 1`, how long a connection may send nothing before it's closed
+const IDLE: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Address {
+    Unix(PathBuf),
+    /// `host:port`
+    Tcp(String)
+}
+
+impl Address {
+    pub(crate) fn parse(address: &str) -> Result<Self, String> {
+        match (address.strip_prefix("unix:"), address.strip_prefix("tcp:")) {
+            (Some(path), _) if !path.is_empty() => Ok(Address::Unix(PathBuf::from(path))),
+            (_, Some(host)) if host.contains(':') => Ok(Address::Tcp(host.to_string())),
+            _ => Err(format!("invalid listen address '{}', expected unix:<path> or tcp:<host:port>", address))
+        }
+    }
+}
+
+pub(crate) enum Engine {
+    Shared(ConcurrentEngine),
+    Actors(Actors),
+    /// `--threads 1`, only ever locked by the accepting thread and the dashboard
+    Serial(Mutex<Accounts>)
+}
+
+impl Engine {
+    pub(crate) fn execute(&self, txn: Txn, config: &Arc<Config>) -> Result<(), Rejection> {
+        match self {
+            Engine::Shared(engine) => engine.execute(txn, config),
+            Engine::Actors(actors) => actors.execute(txn, config),
+            Engine::Serial(accounts) => execute_with(&mut accounts.lock().unwrap(), txn, config)
+        }
+    }
+
+    /// what `execute` would come to for `txn`, applying nothing
+    pub(crate) fn validate(&self, txn: Txn, config: &Arc<Config>) -> Result<(), Rejection> {
+        match self {
+            Engine::Shared(engine) => engine.validate(txn, config),
+            Engine::Actors(actors) => actors.validate(txn, config),
+            Engine::Serial(accounts) => {
+                let account = accounts.lock().unwrap().get(&txn.client).cloned();
+                validate_with(account.as_ref(), txn, config)
+            }
+        }
+    }
+
+    /// an admin's adjustment of the client's balance, see admin.rs
+    pub(crate) fn adjust(&self, client: ClientId, amount: Amount, config: &Arc<Config>) -> Result<(), Rejection> {
+        match self {
+            Engine::Shared(engine) => engine.adjust(client, amount, config),
+            Engine::Actors(actors) => actors.adjust(client, amount, config),
+            Engine::Serial(accounts) => adjust_with(&mut accounts.lock().unwrap(), client, amount, config)
+        }
+    }
+
+    /// prunes every account's transactions up to `through`, see retention.rs
+    pub(crate) fn prune(&self, through: TxnId) {
+        match self {
+            Engine::Shared(engine) => engine.prune(through),
+            Engine::Actors(actors) => actors.prune(through),
+            Engine::Serial(accounts) => prune_with(&mut accounts.lock().unwrap(), through)
+        }
+    }
+
+    /// an admin's erasure of who the client is, see admin.rs
+    pub(crate) fn forget(&self, client: ClientId) -> Result<(), String> {
+        match self {
+            Engine::Shared(engine) => engine.forget(client),
+            Engine::Actors(actors) => actors.forget(client),
+            Engine::Serial(accounts) => forget_with(&mut accounts.lock().unwrap(), client)
+        }
+    }
+
+    pub(crate) fn balances(&self) -> Accounts {
+        match self {
+            Engine::Shared(engine) => engine.balances(),
+            Engine::Actors(actors) => actors.balances(),
+            Engine::Serial(accounts) => accounts.lock().unwrap().iter().map(|(client, a)| (*client, a.summary())).collect()
+        }
+    }
+
+    /// the client's account along with what it has under dispute, None if it has none
+    pub(crate) fn account(&self, client: ClientId) -> Option<Account> {
+        match self {
+            Engine::Shared(engine) => engine.account(client),
+            Engine::Actors(actors) => actors.account(client),
+            Engine::Serial(accounts) => accounts.lock().unwrap().get(&client).map(Account::with_disputes)
+        }
+    }
+}
+
+pub(crate) struct State {
+    /// swapped whole on reload, each line is executed under the config current when it's read
+    config: RwLock<Arc<Config>>,
+    pub(crate) engine: Engine,
+    pub(crate) report: Mutex<Report>,
+    /// held while a snapshot's written out, so they don't interleave
+    snapshots: Mutex<()>,
+    /// the latest chargebacks applied, oldest first
+    pub(crate) chargebacks: Mutex<VecDeque<(ClientId, TxnId)>>,
+    /// accepting transactions
+    pub(crate) ready: AtomicBool,
+    /// transactions executed, applied or not
+    pub(crate) executed: AtomicU64,
+    /// the newest tx executed, which `[retention] keep_days` goes by
+    pub(crate) newest: Mutex<Option<TxnId>>,
+    /// what executions, the ingestion lag & rate limits are timed by
+    pub(crate) clock: Arc<dyn Clock>,
+    /// `rate.global`'s, shared by every connection
+    rate: Mutex<Bucket>,
+    /// what connections authenticate with, None to take any as an admin
+    pub(crate) keys: Option<Keys>,
+    /// what transactions are shipped through as a primary, see replica.rs
+    pub(crate) primary: Option<Primary>,
+    /// following a primary, not taking transactions until promoted
+    pub(crate) standby: AtomicBool,
+    /// the seq of the primary's last transaction applied, as a standby
+    pub(crate) replicated: AtomicU64,
+    /// what admins' adjustments are recorded in, None to take none
+    pub(crate) audit: Option<Audit>,
+    /// every deposit & withdrawal's tx, None to detect no duplicates, see dedup.rs
+    pub(crate) dedup: Option<Dedup>,
+    /// connections being served on threads of their own
+    connections: AtomicUsize,
+    /// and the most there may be
+    max_connections: usize,
+    started: SystemTime,
+    /// when the last transaction was executed, in ms since `started` plus one, 0 for never
+    last_executed: AtomicU64
+}
+
+impl State {
+    pub(crate) fn new(config: Config) -> Self {
+        State::with_clock(config, Arc::new(SystemClock))
+    }
+
+    pub(crate) fn with_clock(config: Config, clock: Arc<dyn Clock>) -> Self {
+        let engine = match (config.threads, config.actors) {
+            (Some(1), _) => Engine::Serial(Mutex::default()),
+            (_, true) => Engine::Actors(Actors::new()),
+            (_, false) => Engine::Shared(ConcurrentEngine::default())
+        };
+        let standby = config.replication.listen.is_some();
+        let max_connections = config.threads.unwrap_or(MAX_CONNECTIONS);
+        State {
+            config: RwLock::new(Arc::new(config)),
+            engine,
+            report: Mutex::default(),
+            snapshots: Mutex::default(),
+            chargebacks: Mutex::default(),
+            ready: AtomicBool::new(false),
+            executed: AtomicU64::new(0),
+            newest: Mutex::default(),
+            started: clock.now(),
+            clock,
+            rate: Mutex::default(),
+            keys: None,
+            primary: None,
+            standby: AtomicBool::new(standby),
+            replicated: AtomicU64::new(0),
+            audit: None,
+            dedup: None,
+            connections: AtomicUsize::new(0),
+            max_connections,
+            last_executed: AtomicU64::new(0)
+        }
+    }
+
+    /// a server of `accounts` as a snapshot has them, taking no transactions, see api.rs
+    pub(crate) fn read_only(config: Config, accounts: Accounts) -> Result<Self, String> {
+        let keys = config.auth.keys_file.as_deref().map(Keys::load).transpose()?;
+        let state = State { keys, engine: Engine::Serial(Mutex::new(accounts)), ..State::new(config) };
+        state.ready.store(true, Ordering::Release);
+        Ok(state)
+    }
+
+    /// takes one of `max_connections` for a connection, false if they're all taken
+    fn open(&self) -> bool {
+        self.connections.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < self.max_connections).then_some(n + 1)).is_ok()
+    }
+
+    /// the read timeout a connection's given, so under `--threads 1` an idle one doesn't hold up the rest
+    fn idle(&self) -> Option<Duration> {
+        matches!(self.engine, Engine::Serial(_)).then_some(IDLE)
+    }
+
+    fn close(&self) {
+        self.connections.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap())
+    }
+
+    pub(crate) fn set_config(&self, config: Config) {
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+
+    pub(crate) fn last_executed(&self) -> Option<SystemTime> {
+        match self.last_executed.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(self.started + Duration::from_millis(ms - 1))
+        }
+    }
+
+    pub(crate) fn executed(&self, tx: TxnId) {
+        self.executed.fetch_add(1, Ordering::Relaxed);
+        let mut newest = self.newest.lock().unwrap();
+        *newest = (*newest).max(Some(tx));
+        let ms = self.clock.since(self.started).as_millis() as u64 + 1;
+        self.last_executed.fetch_max(ms, Ordering::Relaxed);
+    }
+
+    /// takes a deposit or withdrawal's tx into `--dedup-index`, false if it's been seen before
+    pub(crate) fn admit(&self, txn: &Txn) -> io::Result<bool> {
+        match (&self.dedup, &txn.txntype) {
+            (Some(dedup), TxnType::Deposit | TxnType::Withdrawal) => dedup.admit(txn.tx),
+            _ => Ok(true)
+        }
+    }
+
+    /// gives a tx `admit` took back to `--dedup-index` when the engine didn't log its transaction, so it can be sent
+    /// again: only applied deposits & withdrawals, and withdrawals declined for insufficient funds, are logged
+    pub(crate) fn release(&self, txntype: &TxnType, tx: TxnId, result: Result<(), Rejection>) -> io::Result<()> {
+        let logged = match result {
+            Ok(()) => true,
+            Err(r) => r == Rejection::InsufficientFunds && *txntype == TxnType::Withdrawal
+        };
+        match (&self.dedup, txntype) {
+            (Some(dedup), TxnType::Deposit | TxnType::Withdrawal) if !logged => dedup.release(tx),
+            _ => Ok(())
+        }
+    }
+
+    /// whether `--dedup-index` would reject the transaction as a duplicate, taking nothing
+    pub(crate) fn duplicate(&self, txn: &Txn) -> io::Result<bool> {
+        match (&self.dedup, &txn.txntype) {
+            (Some(dedup), TxnType::Deposit | TxnType::Withdrawal) => dedup.seen(txn.tx),
+            _ => Ok(false)
+        }
+    }
+}
+
+/// a connection's reading & writing ends
+pub(crate) type Halves = (Box<dyn Read + Send>, Box<dyn Write + Send>);
+/// splits an accepted tcp stream into its halves
+pub(crate) type TcpSplit = Box<dyn Fn(TcpStream) -> io::Result<Halves> + Send>;
+
+/// serves until the listener fails, reloading the config `watch`ed if there is one
+pub(crate) fn serve(address: &Address, config: Config, watch: Option<Watch>) -> Result<(), Box<dyn std::error::Error>> {
+    let (tui, health, storage) = (config.tui, config.health.listen.clone(), config.storage);
+    let keys = config.auth.keys_file.as_deref().map(Keys::load).transpose()?;
+    let audit = match &config.audit.path {
+        Some(path) => Some(Audit::open(path).map_err(|e| format!("audit log {}: {}", path.display(), e))?),
+        None => None
+    };
+    let dedup = match &config.dedup.index {
+        Some(path) => Some(Dedup::open(path, config.dedup.expected).map_err(|e| format!("dedup index {}: {}", path.display(), e))?),
+        None => None
+    };
+    let state = State { keys, audit, dedup, ..State::new(config) };
+    let primary = match state.config().replication.to.as_slice() {
+        [] => None,
+        _ => Some(Primary::connect(&state.config())?)
+    };
+    let state = Arc::new(State { primary, ..state });
+    if let Some(address) = &state.config().replication.listen {
+        crate::replica::follow(address, Arc::clone(&state))?;
+    }
+    if let Some(health) = &health {
+        crate::health::serve(health, Arc::clone(&state), storage)?;
+    }
+    if let Some(api) = &state.config().api.listen {
+        crate::api::serve(api, Arc::clone(&state))?;
+    }
+    if state.config().retention.keep_days.is_some() {
+        let state = Arc::clone(&state);
+        std::thread::spawn(move || crate::retention::run(state));
+    }
+    if let Some(watch) = watch {
+        let state = Arc::clone(&state);
+        std::thread::spawn(move || crate::reload::run(watch, state));
+    }
+
+    match address {
+        Address::Unix(path) => serve_unix(path, state, tui),
+        Address::Tcp(address) => {
+            if state.config().tls.cert.is_none() {
+                eprintln!("warning: serving {} without --tls-cert, api keys & transactions cross the network in the clear", address);
+            }
+            let (split, idle) = (tcp_split(&state.config().tls)?, state.idle());
+            let split = move |stream: TcpStream| {
+                stream.set_read_timeout(idle)?;
+                split(stream)
+            };
+            let listener = match TcpListener::bind(address) {
+                Ok(l) => l,
+                Err(e) => return Err(format!("Error listening on {}: {}", address, e).into())
+            };
+            listen(state, tui, move |state| accept(listener.incoming(), split, state))
+        }
+    }
+}
+
+#[cfg(unix)]
+fn serve_unix(path: &Path, state: Arc<State>, tui: bool) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!("{}: path exists and is not a socket", path.display()).into());
+        }
+        // a socket nobody answers on is left over from a previous run
+        if UnixStream::connect(path).is_err() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    let listener = match UnixListener::bind(path) {
+        Ok(l) => l,
+        Err(e) => return Err(format!("Error listening on {}: {}", path.display(), e).into())
+    };
+    let idle = state.idle();
+    let split = move |stream: UnixStream| -> io::Result<Halves> {
+        stream.set_read_timeout(idle)?;
+        Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+    };
+    listen(state, tui, move |state| accept(listener.incoming(), split, state))
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_path: &Path, _state: Arc<State>, _tui: bool) -> Result<(), Box<dyn std::error::Error>> {
+    Err("unix sockets aren't supported on this platform".into())
+}
+
+/// how an accepted tcp stream is split, through TLS when `[tls]` sets a certificate
+#[cfg(feature = "tls")]
+pub(crate) fn tcp_split(options: &TlsOptions) -> Result<TcpSplit, Box<dyn std::error::Error>> {
+    Ok(match crate::tls::Acceptor::new(options)? {
+        Some(acceptor) => Box::new(move |stream| acceptor.accept(stream)),
+        None => Box::new(tcp_halves)
+    })
+}
+
+#[cfg(not(feature = "tls"))]
+pub(crate) fn tcp_split(options: &TlsOptions) -> Result<TcpSplit, Box<dyn std::error::Error>> {
+    if options.cert.is_some() {
+        return Err("TLS requires building with the `tls` feature".into());
+    }
+    Ok(Box::new(tcp_halves))
+}
+
+fn tcp_halves(stream: TcpStream) -> io::Result<Halves> {
+    Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+}
+
+/// connects to the tcp server at `address`, through TLS when `[tls]` sets a `ca`, giving up on connecting, or on a
+/// read or write once connected, after `timeout`
+pub(crate) fn tcp_connect(address: &str, options: &TlsOptions, timeout: Duration) -> io::Result<Halves> {
+    let mut failed = io::Error::new(io::ErrorKind::NotFound, format!("{} resolves to no address", address));
+    for resolved in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&resolved, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return tcp_secure(stream, address, options);
+            },
+            Err(e) => failed = e
+        }
+    }
+    Err(failed)
+}
+
+/// a connected tcp stream's halves, through TLS to `address`'s host when `[tls]` sets a `ca`
+#[cfg(feature = "tls")]
+fn tcp_secure(stream: TcpStream, address: &str, options: &TlsOptions) -> io::Result<Halves> {
+    match &options.ca {
+        Some(ca) => {
+            let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+            crate::tls::connect(stream, ca, host.trim_start_matches('[').trim_end_matches(']'))
+        },
+        None => tcp_halves(stream)
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+fn tcp_secure(stream: TcpStream, _address: &str, options: &TlsOptions) -> io::Result<Halves> {
+    if options.ca.is_some() {
+        return Err(io::Error::other("TLS requires building with the `tls` feature"));
+    }
+    tcp_halves(stream)
+}
+
+/// takes connections through `accept` once the server's ready, alongside the dashboard under `--tui`
+fn listen<A>(state: Arc<State>, tui: bool, accept: A) -> Result<(), Box<dyn std::error::Error>>
+    where A: FnOnce(&Arc<State>) + Send + 'static
+{
+    state.ready.store(true, Ordering::Release);
+    if tui {
+        let shared = Arc::clone(&state);
+        std::thread::spawn(move || accept(&shared));
+        // the dashboard quitting stops the server
+        return dashboard(&state);
+    }
+    accept(&state);
+    Ok(())
+}
+
+fn accept<S, I, F>(incoming: I, split: F, state: &Arc<State>)
+    where I: Iterator<Item = io::Result<S>>, F: Fn(S) -> io::Result<Halves>
+{
+    for stream in incoming {
+        let halves = match stream.and_then(&split) {
+            Ok(h) => h,
+            Err(e) => {
+                // i.e. a connection aborted before it was accepted, or out of fds. neither should end the server
+                eprintln!("accept error: {}", e);
+                continue;
+            }
+        };
+        if let Engine::Serial(_) = state.engine {
+            connection(halves, state);
+            continue;
+        }
+        if !state.open() {
+            let (_, mut writer) = halves;
+            let _ = writeln!(writer, "throttled: {} connections open already", state.max_connections);
+            continue;
+        }
+        let state = Arc::clone(state);
+        std::thread::spawn(move || {
+            connection(halves, &state);
+            state.close();
+        });
+    }
+}
+
+/// reads the connection to its end, then writes the balances out
+fn connection((reader, writer): Halves, state: &State) {
+    if let Err(e) = handle(BufReader::new(reader), writer, state) {
+        eprintln!("connection error: {}", e);
+    }
+    let config = state.config();
+    if config.tui && config.output.path.is_none() {
+        // stdout is the dashboard's
+        return;
+    }
+    if let Err(e) = snapshot(state) {
+        eprintln!("Error: {}", e);
+    }
+}
+
+/// what an admin may send besides transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operation<'a> {
+    Snapshot,
+    Promote,
+    /// the words after `adjust`, see admin.rs
+    Adjust(&'a str),
+    /// the word after `forget`, see admin.rs
+    Forget(&'a str)
+}
+
+impl<'a> Operation<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let line = line.trim();
+        match line.split_once(' ').unwrap_or((line, "")) {
+            ("snapshot", "") => Some(Operation::Snapshot),
+            ("promote", "") => Some(Operation::Promote),
+            ("adjust", args) => Some(Operation::Adjust(args)),
+            ("forget", args) => Some(Operation::Forget(args)),
+            _ => None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Snapshot => "snapshot",
+            Operation::Promote => "promote",
+            Operation::Adjust(_) => "adjust",
+            Operation::Forget(_) => "forget"
+        }
+    }
+
+    /// Ok(Err) if it's declined, as an adjustment can be. `key` is the id of the key the admin authenticated with
+    fn run(&self, key: Option<&str>, state: &State) -> Result<Result<(), Rejection>, Box<dyn std::error::Error>> {
+        match self {
+            Operation::Snapshot => snapshot(state).map(Ok),
+            Operation::Promote => match state.standby.swap(false, Ordering::AcqRel) {
+                true => Ok(Ok(())),
+                false => Err("not a standby".into())
+            },
+            Operation::Adjust(args) => Ok(crate::admin::adjust(&Adjustment::parse(args)?, key, state)?),
+            Operation::Forget(args) => {
+                let client = args.trim().parse().map_err(|_| format!("expected forget <client>, got '{}'", args.trim()))?;
+                crate::admin::forget(client, key, state)?;
+                Ok(Ok(()))
+            }
+        }
+    }
+}
+
+/// writes the balances out as they stand, one snapshot at a time
+fn snapshot(state: &State) -> Result<(), Box<dyn std::error::Error>> {
+    let config = state.config();
+    if config.tui && config.output.path.is_none() {
+        return Err("stdout is the dashboard's, a snapshot needs --output".into());
+    }
+    let _writing = state.snapshots.lock().unwrap();
+    // copied, so connections carry on recording into it while the balances are written
+    let report = state.report.lock().unwrap().clone();
+    finish(&state.engine.balances(), &config, &report)
+}
+
+#[cfg(feature = "tui")]
+fn dashboard(state: &State) -> Result<(), Box<dyn std::error::Error>> {
+    crate::tui::run(state)
+}
+
+#[cfg(not(feature = "tui"))]
+fn dashboard(_state: &State) -> Result<(), Box<dyn std::error::Error>> {
+    Err("the dashboard requires building with the `tui` feature".into())
+}
+
+/// applies each line read, answering on `out`
+pub(crate) fn handle<R: BufRead, W: Write>(mut reader: R, mut out: W, state: &State) -> io::Result<()> {
+    let mut limiter = Limiter::new(&state.rate, &*state.clock);
+    let mut role = match state.keys {
+        Some(_) => None,
+        None => Some(Role::Admin)
+    };
+    // the id of the key authenticated with, for the audit log
+    let mut key = None;
+    let mut buf = Vec::new();
+    loop {
+        let line = match read_line(&mut reader, &mut buf) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => return writeln!(out, "malformatted: {}", e),
+            Err(e) => return Err(e)
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let role = match (role, &state.keys) {
+            (Some(role), _) => role,
+            (None, Some(keys)) => {
+                match keys.authenticate(&line) {
+                    Ok(authenticated) => {
+                        role = Some(authenticated);
+                        key = line.trim().strip_prefix("auth ").map(crate::auth::id);
+                        writeln!(out, "ok")?;
+                        continue;
+                    },
+                    Err(e) => return writeln!(out, "unauthorized: {}", e)
+                }
+            },
+            (None, None) => unreachable!("without keys every connection is an admin")
+        };
+
+        let config = state.config();
+        if let Err(e) = limiter.admit(&config.rate) {
+            writeln!(out, "throttled: {}", e)?;
+            continue;
+        }
+        if let Some(operation) = Operation::parse(&line) {
+            match role {
+                Role::Submitter => writeln!(out, "forbidden: {} is an admin operation, this key is a {}", operation.name(), role)?,
+                Role::Admin => match operation.run(key.as_deref(), state) {
+                    Ok(Ok(())) => writeln!(out, "ok")?,
+                    Ok(Err(r)) => writeln!(out, "rejected: {}", r)?,
+                    Err(e) => writeln!(out, "failed: {}", e)?
+                }
+            }
+            continue;
+        }
+        if state.standby.load(Ordering::Acquire) {
+            writeln!(out, "standby: not taking transactions until promoted")?;
+            continue;
+        }
+        let txn = match parse_line(&line, &config) {
+            Ok(t) => t,
+            Err(e) => {
+                writeln!(out, "malformatted: {}", e)?;
+                match config.on_error {
+                    ErrorPolicy::Abort => return Ok(()),
+                    ErrorPolicy::Skip | ErrorPolicy::Quarantine => {
+                        state.report.lock().unwrap().skip();
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let txntype = txn.txntype.clone();
+        let (chargeback, tx) = ((txntype == TxnType::Chargeback).then_some((txn.client, txn.tx)), txn.tx);
+        // a duplicate's never executed, nor shipped to standbys
+        let result = match state.admit(&txn) {
+            Ok(true) => {
+                let result = match &state.primary {
+                    Some(primary) => primary.execute(&state.engine, txn, &config),
+                    None => state.engine.execute(txn, &config)
+                };
+                state.executed(tx);
+                if let Err(e) = state.release(&txntype, tx, result) {
+                    eprintln!("dedup index: {}", e);
+                }
+                result
+            },
+            Ok(false) => Err(Rejection::Duplicate),
+            Err(e) => {
+                writeln!(out, "failed: {}", e)?;
+                continue;
+            }
+        };
+        state.report.lock().unwrap().record(result);
+        crate::telemetry::observe(&txntype, result);
+        if let (Some(chargeback), Ok(())) = (chargeback, result) {
+            let mut chargebacks = state.chargebacks.lock().unwrap();
+            if chargebacks.len() == RECENT_CHARGEBACKS {
+                chargebacks.pop_front();
+            }
+            chargebacks.push_back(chargeback);
+        }
+        match result {
+            Ok(()) => writeln!(out, "ok")?,
+            Err(r) => writeln!(out, "rejected: {}", r)?
+        }
+    }
+    Ok(())
+}
+
+/// the next line without its line ending, None at the end. reads no more than `MAX_LINE` bytes of it, a longer
+/// line being InvalidData as one that isn't utf-8 is
+pub(crate) fn read_line<R: BufRead>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<Option<String>> {
+    buf.clear();
+    if reader.take(MAX_LINE as u64 + 1).read_until(b'\n', buf)? == 0 {
+        return Ok(None);
+    }
+    if buf.last() == Some(&b'\n') {
+        buf.pop();
+        if buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+    } else if buf.len() > MAX_LINE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("line longer than {} bytes", MAX_LINE)));
+    }
+    String::from_utf8(buf.clone()).map(Some).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "line isn't valid utf-8"))
+}
+
+pub(crate) fn parse_line(line: &str, config: &Config) -> Result<Txn, RowError> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(line.as_bytes());
+    let mut record = csv::StringRecord::new();
+    reader.read_record(&mut record)?;
+    read_record(&mut record, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use rust_decimal_macros::dec;
+
+    use crate::auth::Keys;
+    use crate::clock::{Clock, MockClock};
+    use crate::config::{Config, ErrorPolicy, RatePolicy};
+    use crate::dedup::Dedup;
+    use crate::{Accounts, ClientId, execute_with, TxnId};
+
+    use super::{Address, handle, IDLE, MAX_CONNECTIONS, MAX_LINE, parse_line, State};
+
+    fn run(input: &str, state: &State) -> String {
+        let mut out = Vec::new();
+        handle(input.as_bytes(), &mut out, state).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!(Address::parse("unix:/var/run/txn.sock"), Ok(Address::Unix(PathBuf::from("/var/run/txn.sock"))));
+        assert!(Address::parse("unix:").is_err());
+        assert_eq!(Address::parse("tcp:127.0.0.1:9000"), Ok(Address::Tcp("127.0.0.1:9000".into())));
+        assert!(Address::parse("tcp:127.0.0.1").is_err());
+        assert!(Address::parse("udp:127.0.0.1:9000").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_existing_file_is_kept() {
+        let path = std::env::temp_dir().join(format!("txn-server-test-{}", std::process::id()));
+        std::fs::write(&path, "important").unwrap();
+        let err = super::serve(&Address::Unix(path.clone()), Config::default(), None).unwrap_err();
+        assert!(err.to_string().contains("not a socket"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "important");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_handle() {
+        let state = State::new(Config::default());
+        let out = run("deposit,1,1,2.5\nwithdrawal, 1, 2, 5.0\n\ndispute,1,1,\n", &state);
+        assert_eq!(out, "ok\nrejected: insufficient funds\nok\n");
+
+        assert_eq!(state.engine.balances()[&ClientId(1)].balance.held, dec!(2.5));
+        assert_eq!(state.report.lock().unwrap().applied, 2);
+    }
+
+    #[test]
+    fn test_line_too_long() {
+        let state = State::new(Config::default());
+        // a line of MAX_LINE is taken, one longer closes the connection before the rest's read
+        let longest = format!("deposit,1,1,1{}", " ".repeat(MAX_LINE - 13));
+        let out = run(&format!("{}\n{} \ndeposit,1,2,1\n", longest, longest), &state);
+        assert_eq!(out, format!("ok\nmalformatted: line longer than {} bytes\n", MAX_LINE));
+        assert_eq!(state.report.lock().unwrap().applied, 1);
+
+        let mut out = Vec::new();
+        handle(&b"deposit,1,3,1\ndeposit,1,4,\xff\n"[..], &mut out, &state).unwrap();
+        assert_eq!(out, b"ok\nmalformatted: line isn't valid utf-8\n");
+    }
+
+    #[test]
+    fn test_max_connections() {
+        let state = State::new(Config::default());
+        assert!((0..MAX_CONNECTIONS).all(|_| state.open()));
+        assert!(!state.open());
+        state.close();
+        assert!(state.open());
+
+        let state = State::new(Config { threads: Some(4), ..Config::default() });
+        assert!((0..4).all(|_| state.open()));
+        assert!(!state.open());
+        assert_eq!(state.idle(), None);
+        // one at a time, an idle connection is cut off rather than holding up the rest
+        assert_eq!(State::new(Config { threads: Some(1), ..Config::default() }).idle(), Some(IDLE));
+    }
+
+    #[test]
+    fn test_handle_actors() {
+        let state = State::new(Config { actors: true, ..Config::default() });
+        let out = run("deposit,1,1,2.5\ndeposit,2,2,1\nwithdrawal,1,3,1\ndispute,1,9,\n", &state);
+        assert_eq!(out, "ok\nok\nok\nrejected: unknown transaction\n");
+        assert!(state.chargebacks.lock().unwrap().is_empty());
+
+        let balances = state.engine.balances();
+        assert_eq!(balances[&ClientId(1)].balance.available, dec!(1.5));
+        assert_eq!(balances[&ClientId(2)].balance.total, dec!(1));
+    }
+
+    #[test]
+    fn test_handle_serial() {
+        let state = State::new(Config { threads: Some(1), ..Config::default() });
+        let connections = ["deposit,1,1,2.5\ndeposit,2,2,1\n", "dispute,1,1,\nwithdrawal,2,3,0.5\nchargeback,1,1,\n"];
+        for connection in connections {
+            run(connection, &state);
+        }
+
+        // as the connections' lines would run from one file
+        let mut expected = Accounts::default();
+        for line in connections.concat().lines() {
+            let _ = execute_with(&mut expected, parse_line(line, &Config::default()).unwrap(), &Config::default());
+        }
+        let balances = state.engine.balances();
+        assert_eq!(balances.len(), expected.len());
+        for (client, account) in &expected {
+            assert_eq!((balances[client].balance, balances[client].locked), (account.balance, account.locked));
+        }
+    }
+
+    #[test]
+    fn test_handle_malformatted() {
+        let input = "bogus,1,1,1.0\ndeposit,1,2,1.0\n";
+
+        let state = State::new(Config::default());
+        assert!(run(input, &state).starts_with("malformatted: "));
+        assert!(state.engine.balances().is_empty());
+        // closed the connection rather than skipping
+        assert_eq!(state.report.lock().unwrap().skipped, 0);
+
+        state.set_config(Config { on_error: ErrorPolicy::Skip, ..Config::default() });
+        assert!(run(input, &state).ends_with("\nok\n"));
+        assert_eq!(state.report.lock().unwrap().skipped, 1);
+    }
+
+    #[test]
+    fn test_reloaded_config() {
+        let mut config = Config { actors: true, ..Config::default() };
+        config.limits.max_amount = Some(dec!(5));
+        let state = State::new(config.clone());
+        assert_eq!(run("deposit,1,1,10\n", &state), "rejected: amount over limit\n");
+
+        config.limits.max_amount = None;
+        state.set_config(config);
+        assert_eq!(run("deposit,1,2,10\n", &state), "ok\n");
+        assert_eq!(state.engine.balances()[&ClientId(1)].balance.total, dec!(10));
+    }
+
+    #[test]
+    fn test_throttled() {
+        let clock = Arc::new(MockClock::new(UNIX_EPOCH + Duration::from_secs(1_718_000_000)));
+        let mut config = Config::default();
+        config.rate.per_connection = Some(2);
+        config.rate.global = Some(3);
+        let state = State::with_clock(config.clone(), clock.clone());
+        assert_eq!(run("deposit,1,1,1\ndeposit,1,2,1\ndeposit,1,3,1\n", &state),
+                   "ok\nok\nthrottled: over 2 transactions/s per connection\n");
+        assert_eq!(run("deposit,2,4,1\ndeposit,2,5,1\n", &state), "ok\nthrottled: over 3 transactions/s across connections\n");
+        // throttled lines aren't executed
+        assert_eq!(state.report.lock().unwrap().applied, 3);
+        assert_eq!(state.engine.balances()[&ClientId(1)].balance.total, dec!(2));
+
+        config.rate.policy = RatePolicy::Wait;
+        state.set_config(config);
+        clock.advance(Duration::from_secs(1));
+        let waited = clock.now();
+        let input: String = (10..16).map(|tx| format!("deposit,3,{},1\n", tx)).collect();
+        assert_eq!(run(&input, &state), "ok\n".repeat(6));
+        assert_eq!(state.report.lock().unwrap().applied, 9);
+        // a burst of 2, then a line every half second
+        assert_eq!(clock.since(waited), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_auth() {
+        let keys = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  submitter\n\
+                    fcde2b2edba56bf408601fb721fe9b5c338d10ee429ea04fae5511b68fbf8fb9  admin\n";
+        let dir = std::env::temp_dir().join(format!("txn-server-auth-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("keys.txt"), keys).unwrap();
+        let mut config = Config::default();
+        config.output.path = Some(dir.join("out.csv"));
+        let state = State { keys: Some(Keys::load(&dir.join("keys.txt")).unwrap()), ..State::new(config) };
+
+        assert_eq!(run("deposit,1,1,1\ndeposit,1,2,1\n", &state), "unauthorized: authenticate first, with auth <key>\n");
+        assert_eq!(run("auth baz\ndeposit,1,3,1\n", &state), "unauthorized: unknown key\n");
+        assert!(state.engine.balances().is_empty());
+        assert_eq!(run("auth foo\ndeposit,1,4,1\nsnapshot\n", &state),
+                   "ok\nok\nforbidden: snapshot is an admin operation, this key is a submitter\n");
+        assert!(!dir.join("out.csv").exists());
+        assert_eq!(run("auth bar\nsnapshot\n", &state), "ok\nok\n");
+        let written = std::fs::read_to_string(dir.join("out.csv")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, "client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n");
+    }
+
+    #[test]
+    fn test_standby() {
+        let mut config = Config::default();
+        config.replication.listen = Some("127.0.0.1:0".into());
+        let state = State::new(config);
+        assert_eq!(run("deposit,1,1,1\n", &state), "standby: not taking transactions until promoted\n");
+        assert!(state.engine.balances().is_empty());
+        assert_eq!(run("promote\ndeposit,1,1,1\npromote\n", &state), "ok\nok\nfailed: not a standby\n");
+        assert_eq!(state.engine.balances()[&ClientId(1)].balance.total, dec!(1));
+    }
+
+    #[test]
+    fn test_recent_chargebacks() {
+        let state = State::new(Config::default());
+        let input: String = (1..=12).map(|c| format!("deposit,{0},{0},1\ndispute,{0},{0},\nchargeback,{0},{0},\n", c)).collect();
+        run(&input, &state);
+        // the ten latest, oldest first
+        let expected: Vec<(ClientId, TxnId)> = (3..=12u16).map(|c| (ClientId::from(c), TxnId::from(u32::from(c)))).collect();
+        assert_eq!(state.chargebacks.lock().unwrap().iter().copied().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let path = std::env::temp_dir().join(format!("txn-server-dedup-test-{}.idx", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut state = State::new(Config::default());
+        state.dedup = Some(Dedup::open(&path, 1000).unwrap());
+        // another client's tx, and a declined withdrawal's, are duplicates all the same. a dispute's reuse isn't
+        let out = run("deposit,1,1,2\ndeposit,2,1,5\nwithdrawal,1,2,9\ndeposit,1,2,1\ndispute,1,1,\n", &state);
+        assert_eq!(out, "ok\nrejected: duplicate transaction\nrejected: insufficient funds\nrejected: duplicate transaction\nok\n");
+        assert_eq!(state.engine.balances()[&ClientId(1)].balance.held, dec!(2));
+        assert!(!state.engine.balances().contains_key(&ClientId(2)));
+        assert_eq!(state.executed.load(std::sync::atomic::Ordering::Relaxed), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dedup_retry() {
+        let path = std::env::temp_dir().join(format!("txn-server-dedup-retry-test-{}.idx", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut config = Config::default();
+        config.limits.max_amount = Some(dec!(10));
+        let mut state = State::new(config);
+        state.dedup = Some(Dedup::open(&path, 1000).unwrap());
+        // rejected unlogged, a deposit or withdrawal can be sent again under its tx, and then it's taken
+        let out = run("deposit,1,1,50\ndeposit,1,1,5\nwithdrawal,1,2,50\nwithdrawal,1,2,1\ndeposit,1,1,5\nwithdrawal,1,2,1\n", &state);
+        assert_eq!(out, "rejected: amount over limit\nok\nrejected: amount over limit\nok\nrejected: duplicate transaction\n\
+                         rejected: duplicate transaction\n");
+        assert_eq!(state.engine.balances()[&ClientId(1)].balance.available, dec!(4));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
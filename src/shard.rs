@@ -0,0 +1,152 @@
+//! `--output-shards n`: balances written to n files at once, a thread each, for outputs too big for one writer to
+//! keep up with. `--output` names them with a `{shard}` placeholder, `--output out/part-{shard}.csv` writing
+//! `out/part-0.csv` to `out/part-3.csv` for 4 shards, and every client's account is in one of them, picked by a hash
+//! of its id. each part is a whole output of its own, in the format its extension names, sorted with `--sort`.
+//!
+//! `txn merge-output out/part-*.csv` combines csv parts into one output, `--output` or stdout, sorted by client.
+//! the parts have to have the same header, so all be enriched or none, and no client may be in two of them.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::config::OutputOptions;
+use crate::{Accounts, ClientId, ClientRepr, Hasher, OutputFormat, write_part};
+
+/// what `--output` names shard files with
+const PLACEHOLDER: &str = "{shard}";
+
+/// the shard a client's account is written to
+fn shard_of(client: &ClientId, shards: usize) -> usize {
+    (Hasher::default().hash_one(client) % shards as u64) as usize
+}
+
+/// each shard of the accounts to its own file, in parallel
+pub(crate) fn write_out(accounts: &Accounts, options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let shards = options.shards;
+    // every path's checked before any thread's started
+    let paths = (0..shards).map(|shard| path(options, shard)).collect::<Result<Vec<_>, _>>()?;
+    std::thread::scope(|s| {
+        let threads: Vec<_> = paths.into_iter().enumerate()
+            .map(|(shard, path)| s.spawn(move || {
+                let options = OutputOptions { path: Some(path), ..options.clone() };
+                write_part(accounts, &options, &|c| shard_of(c, shards) == shard).map_err(|e| e.to_string())
+            }))
+            .collect();
+        threads.into_iter().try_for_each(|t| t.join().expect("output shard panicked"))
+    })?;
+    Ok(())
+}
+
+fn path(options: &OutputOptions, shard: usize) -> Result<PathBuf, String> {
+    let path = options.path.as_ref().and_then(|p| p.to_str()).filter(|p| p.contains(PLACEHOLDER))
+        .ok_or("--output-shards writes a file per shard, --output has to name them with {shard}")?;
+    Ok(PathBuf::from(path.replace(PLACEHOLDER, &shard.to_string())))
+}
+
+/// `txn merge-output`: the parts as one output, to `options.path` or stdout
+pub(crate) fn merge(parts: &[PathBuf], options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let buffer_size = usize::try_from(options.buffer_size).unwrap_or(usize::MAX);
+    for path in parts.iter().chain(&options.path) {
+        if !matches!(OutputFormat::from_path(path), OutputFormat::Csv) {
+            return Err(format!("{}: only csv outputs are merged", path.display()).into());
+        }
+    }
+    match &options.path {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("Error writing output file {}: {}", path.display(), e))?;
+            merge_to(parts, file, buffer_size)
+        },
+        None => merge_to(parts, std::io::stdout().lock(), buffer_size)
+    }
+}
+
+fn merge_to<W: Write>(parts: &[PathBuf], out: W, buffer_size: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let mut header = None;
+    // (client, part, row)
+    let mut rows = Vec::new();
+    for (part, path) in parts.iter().enumerate() {
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let mut reader = csv::Reader::from_path(path).map_err(|e| error(&e))?;
+        let headers = reader.byte_headers().map_err(|e| error(&e))?.clone();
+        match &header {
+            None => header = Some(headers),
+            Some(first) if *first != headers => return Err(error(&format!("header differs from {}'s", parts[0].display())).into()),
+            Some(_) => ()
+        }
+        for row in reader.into_byte_records() {
+            let row = row.map_err(|e| error(&e))?;
+            let client = std::str::from_utf8(&row[0]).ok().and_then(|c| c.parse::<ClientRepr>().ok())
+                .ok_or_else(|| error(&format!("line {}: invalid client", row.position().map_or(0, |p| p.line()))))?;
+            rows.push((client, part, row));
+        }
+    }
+    rows.sort_unstable_by_key(|(client, part, _)| (*client, *part));
+    if let Some(pair) = rows.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        let (first, second) = (parts[pair[0].1].display(), parts[pair[1].1].display());
+        return Err(format!("client {} is in both {} and {}", pair[0].0, first, second).into());
+    }
+
+    let mut writer = csv::WriterBuilder::new().buffer_capacity(buffer_size).from_writer(out);
+    if let Some(header) = header {
+        writer.write_byte_record(&header)?;
+    }
+    for (_, _, row) in rows {
+        writer.write_byte_record(&row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rust_decimal_macros::dec;
+
+    use crate::config::OutputOptions;
+    use crate::{Accounts, execute, Txn, write_out};
+
+    use super::{merge_to, path};
+
+    #[test]
+    fn test_paths() {
+        let options = OutputOptions { path: Some(PathBuf::from("out/part-{shard}.csv")), shards: 4, ..OutputOptions::default() };
+        assert_eq!(path(&options, 3), Ok(PathBuf::from("out/part-3.csv")));
+        assert!(path(&OutputOptions { shards: 4, ..OutputOptions::default() }, 0).is_err());
+    }
+
+    #[test]
+    fn test_write_and_merge() {
+        let dir = std::env::temp_dir().join(format!("txn-shard-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut accounts = Accounts::default();
+        for client in 1..=50 {
+            execute(&mut accounts, Txn::deposit(client, u32::from(client), dec!(1.5)));
+        }
+        let options = OutputOptions { path: Some(dir.join("part-{shard}.csv")), shards: 3, ..OutputOptions::default() };
+        write_out(&accounts, &options).unwrap();
+        let parts: Vec<_> = (0..3).map(|shard| dir.join(format!("part-{}.csv", shard))).collect();
+        let written: Vec<_> = parts.iter().map(|p| std::fs::read_to_string(p).unwrap()).collect();
+
+        let mut merged = Vec::new();
+        merge_to(&parts, &mut merged, 64).unwrap();
+        // one part twice
+        let twice = merge_to(&[parts[0].clone(), parts[0].clone()], Vec::new(), 64).err().unwrap().to_string();
+        std::fs::write(&parts[2], "client,available,held,total,locked,name,currency,kind\n").unwrap();
+        let enriched = merge_to(&parts, Vec::new(), 64).err().unwrap().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // the shards split the clients between them
+        assert!(written.iter().all(|w| w.lines().count() > 1 && w.lines().count() < 51));
+        let merged = String::from_utf8(merged).unwrap();
+        let expected: String = std::iter::once("client,available,held,total,locked\n".to_string())
+            .chain((1..=50).map(|c| format!("{},1.5000,0.0000,1.5000,false\n", c)))
+            .collect();
+        assert_eq!(merged, expected);
+        assert!(twice.contains("is in both"), "{}", twice);
+        assert!(enriched.contains("header differs"), "{}", enriched);
+    }
+}
@@ -0,0 +1,220 @@
+//! deterministic simulation (`--features simulation`): transaction sequences generated from a seed, run through the
+//! engine with the engine's invariants checked after every one of them. `txn fuzz` runs `--runs` sequences of
+//! `--rows` transactions from `--seed` on, and a violation names the seed to replay it with `--runs 1`:
+//! ```text
+//! Error: invariant violated: seed 42, transaction 1873 (chargeback client 3 tx 911): client 3 total is 12.5, what its applied transactions add up to is 2.5
+//! ```
+//! the sequences are structurally valid, deposits & withdrawals of fresh transaction ids over a few dozen clients,
+//! with disputes, resolves & chargebacks of transactions they've had, and now & then one of another client's, an
+//! unknown one or a reused id, so rejections are exercised too. the invariants, for every account touched:
+//! - available + held is total, and held is never negative
+//! - money is conserved: total is the account's applied deposits, less its applied withdrawals & chargebacks
+//! - a rejected transaction leaves its account as it was
+//!
+//! `generate` & `check` take a seed, so a proptest (or any other driver) can pick the seeds instead. the dispute &
+//! lock policies come from the config, nothing else: settlement delays, account kinds & limits are left at their
+//! defaults, which conservation as counted here assumes.
+
+use std::fmt;
+
+use rust_decimal::Decimal;
+
+use crate::config::Config;
+use crate::reference::rules;
+use crate::report::Report;
+use crate::{Accounts, Balance, check_account, ClientId, execute_with, Map, Txn, TxnId, TxnType};
+
+/// clients a sequence is spread over, few enough that they see plenty of each other's transactions. chargebacks are
+/// rare enough that most of them aren't locked early on
+const CLIENTS: u64 = 64;
+
+/// xorshift, enough to spread ids & amounts without pulling in a rng
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift never leaves zero, so the seed's mixed with a constant rather than used as is
+        Rng((seed ^ 0x2545_f491_4f6c_dd1d).max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// `rows` transactions, the same ones for the same seed
+pub fn generate(seed: u64, rows: usize) -> Vec<Txn> {
+    let mut rng = Rng::new(seed);
+    // the deposits & withdrawals each client's made
+    let mut issued: Vec<Vec<u32>> = vec![Vec::new(); CLIENTS as usize];
+    let mut next_tx = 0u32;
+    let mut txns = Vec::with_capacity(rows);
+    while txns.len() < rows {
+        let client = rng.below(CLIENTS) as usize;
+        let roll = rng.below(100);
+        let txntype = match roll {
+            0..=49 => TxnType::Deposit,
+            50..=74 => TxnType::Withdrawal,
+            75..=86 => TxnType::Dispute,
+            87..=96 => TxnType::Resolve,
+            _ => TxnType::Chargeback
+        };
+        let tx = match txntype {
+            TxnType::Deposit | TxnType::Withdrawal if rng.below(50) > 0 || next_tx == 0 => {
+                next_tx += 1;
+                issued[client].push(next_tx);
+                next_tx
+            },
+            // a reused id, applied again and taking the log entry over
+            TxnType::Deposit | TxnType::Withdrawal => 1 + rng.below(u64::from(next_tx)) as u32,
+            _ => match (rng.below(10), issued[client].len() as u64) {
+                // another client's, or one never made
+                (0, _) | (_, 0) => 1 + rng.below(u64::from(next_tx) + 10) as u32,
+                // mostly a recent one, as disputes tend to be
+                (_, made) => issued[client][(made - 1 - rng.below(made.min(8))) as usize]
+            }
+        };
+        let amount = match txntype {
+            TxnType::Deposit | TxnType::Withdrawal => Some(Decimal::new(1 + rng.below(1_000_000) as i64, 4)),
+            _ => None
+        };
+        let txn = Txn::builder(txntype, ClientId::from(client as u16), TxnId::from(tx)).amount(amount).build()
+            .expect("generated transactions are well formed");
+        txns.push(txn);
+    }
+    txns
+}
+
+/// an invariant that failed, and the transaction after which it did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub seed: u64,
+    /// counted from 1
+    pub step: usize,
+    pub txn: String,
+    pub what: String
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "seed {}, transaction {} ({}): {}", self.seed, self.step, self.txn, self.what)
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// runs the seed's transactions, checking the invariants after each, into the report
+pub(crate) fn check(seed: u64, rows: usize, config: &Config, report: &mut Report) -> Result<(), Violation> {
+    let config = rules(config);
+    let mut accounts = Accounts::default();
+    // what each client's applied transactions add up to, and what each deposit & withdrawal moved
+    let mut expected: Map<ClientId, Decimal> = Map::default();
+    let mut amounts: Map<(ClientId, TxnId), Decimal> = Map::default();
+    for (i, txn) in generate(seed, rows).into_iter().enumerate() {
+        let violation = |txn: &Txn, what: String| Violation {
+            seed,
+            step: i + 1,
+            txn: format!("{:?} client {} tx {}", txn.txntype, txn.client, txn.tx).to_lowercase(),
+            what
+        };
+        let (client, key, amount) = (txn.client, (txn.client, txn.tx), txn.amount.map(|a| a.to_decimal()));
+        let state = |accounts: &Accounts| accounts.get(&client).map_or((Balance::default(), false), |a| (a.balance, a.locked));
+        let before = state(&accounts);
+        let described = txn.clone();
+        let outcome = execute_with(&mut accounts, txn, &config);
+        report.record(outcome);
+        if outcome.is_err() {
+            if state(&accounts) != before {
+                return Err(violation(&described, format!("client {} changed though it was rejected", client)));
+            }
+            continue;
+        }
+
+        let total = expected.entry(client).or_default();
+        match described.txntype {
+            TxnType::Deposit => {
+                *total += amount.unwrap_or_default();
+                amounts.insert(key, amount.unwrap_or_default());
+            },
+            TxnType::Withdrawal => {
+                *total -= amount.unwrap_or_default();
+                amounts.insert(key, amount.unwrap_or_default());
+            },
+            TxnType::Chargeback => *total -= amounts.get(&key).copied().unwrap_or_default(),
+            TxnType::Dispute | TxnType::Resolve => ()
+        }
+        let account = &accounts[&client];
+        check_account(&client, account).map_err(|e| violation(&described, e.to_string()))?;
+        let actual = account.balance.total.to_decimal();
+        if actual != *total {
+            return Err(violation(&described, format!("client {} total is {}, what its applied transactions add up to is {}",
+                                                     client, actual.normalize(), total.normalize())));
+        }
+    }
+    Ok(())
+}
+
+/// `txn fuzz`: `fuzz.runs` seeds from `fuzz.seed` on, each of `fuzz.rows` transactions, stopping at the first
+/// violation
+pub(crate) fn fuzz(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let options = &config.fuzz;
+    let mut report = Report::default();
+    for seed in (options.seed..).take(options.runs as usize) {
+        check(seed, options.rows, config, &mut report).map_err(|v| crate::EngineError::Invariant(v.to_string()))?;
+    }
+    println!("{} runs of {} transactions from seed {}, every invariant held: {} applied, {} rejected",
+             options.runs, options.rows, options.seed, report.applied, report.rejected_total());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::config::Config;
+    use crate::reference::Verification;
+    use crate::report::Report;
+    use crate::TxnType;
+
+    use super::{check, generate};
+
+    #[test]
+    fn test_deterministic() {
+        assert_eq!(generate(7, 500), generate(7, 500));
+        assert_ne!(generate(7, 500), generate(8, 500));
+        let txns = generate(0, 2000);
+        for txntype in [TxnType::Deposit, TxnType::Withdrawal, TxnType::Dispute, TxnType::Resolve, TxnType::Chargeback] {
+            assert!(txns.iter().any(|t| t.txntype == txntype), "no {:?}", txntype);
+        }
+    }
+
+    #[test]
+    fn test_checked() {
+        let mut report = Report::default();
+        for seed in 0..20 {
+            check(seed, 2000, &Config::default(), &mut report).unwrap();
+        }
+        // rejections are exercised, not just applied transactions
+        assert!(report.applied > 0 && report.rejected_total() > 0);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_invariants_hold(seed in any::<u64>(), withdrawals in any::<bool>(), redisputes in any::<bool>()) {
+            let mut config = Config::default();
+            config.disputes.withdrawals = withdrawals;
+            config.disputes.redisputes = redisputes;
+            prop_assert_eq!(check(seed, 300, &config, &mut Report::default()), Ok(()));
+            // and the engine ends where the naive reference does
+            let verification = Verification::run(&generate(seed, 300), &config, &mut Report::default());
+            prop_assert!(verification.agrees(), "{}", verification);
+        }
+    }
+}
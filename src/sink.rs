@@ -0,0 +1,778 @@
+//! `AccountSink`: where balances go once a run's done, a row per account. `write_out` picks one by the output
+//! path's extension, and an embedder can implement the trait to stream balances into its own systems.
+//!
+//! csv (`CsvSink`, the default and stdout's format) and newline-delimited json (`.json`, `.jsonl`, `.ndjson`,
+//! `JsonSink`) are always built; parquet (`.parquet`, `--features parquet`), sqlite (`.db`, `.sqlite`,
+//! `--features sqlite`) and postgres (a `postgres://` url, `--features postgres`) are optional.
+
+use std::io::Write;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserialize, Serialize};
+
+use crate::{Account, AccountKind, Accounts, Amount, ClientId, Currency, CURRENCY_PRECISION, Txn, TxnId};
+#[cfg(any(feature = "parquet", feature = "sqlite", feature = "postgres"))]
+use crate::TxnCliError;
+
+/// an account's balances & lock, as output, and who the client is for enriched output
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountRow {
+    pub client: ClientId,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+    #[serde(skip)]
+    pub name: Option<String>,
+    #[serde(skip)]
+    pub currency: Option<Currency>,
+    #[serde(skip)]
+    pub kind: AccountKind,
+    #[serde(skip)]
+    pub chargeback_loss: Amount,
+    /// the transactions under dispute and what each holds, in id order
+    #[serde(skip)]
+    pub disputed: Vec<(TxnId, Amount)>,
+    /// deposits & withdrawals the account has logged
+    #[serde(skip)]
+    pub txn_count: u64
+}
+
+impl AccountRow {
+    pub fn new(client: ClientId, account: &Account) -> Self {
+        let balance = account.balance;
+        let mut disputed: Vec<_> = account.disputes.iter()
+            .map(|tx| (*tx, account.txnlog.get(tx).map_or(Amount::ZERO, Txn::amount)))
+            .collect();
+        disputed.sort_unstable_by_key(|(tx, _)| *tx);
+        AccountRow {
+            client,
+            available: balance.available,
+            held: balance.held,
+            total: balance.total,
+            locked: account.locked,
+            name: account.name.clone(),
+            currency: account.currency,
+            kind: account.kind,
+            chargeback_loss: account.chargeback_loss,
+            disputed,
+            txn_count: account.logged()
+        }
+    }
+}
+
+/// a column written after all the others when asked for, `--columns +disputes,+txn_count`. each is a name & a count
+/// of the row, so another is a variant and an arm in `name` & `value`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    /// disputes open on the account
+    Disputes,
+    /// deposits & withdrawals the account has logged
+    TxnCount
+}
+
+impl Column {
+    pub const ALL: [Column; 2] = [Column::Disputes, Column::TxnCount];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Disputes => "disputes",
+            Column::TxnCount => "txn_count"
+        }
+    }
+
+    fn value(self, row: &AccountRow) -> u64 {
+        match self {
+            Column::Disputes => row.disputed.len() as u64,
+            Column::TxnCount => row.txn_count
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Column::ALL.iter().copied().find(|c| c.name() == s).ok_or_else(|| format!("unknown column '{}'", s))
+    }
+}
+
+/// the columns' values for a row, a field each in csv
+struct Cells<'a> {
+    columns: &'a [Column],
+    row: &'a AccountRow
+}
+
+impl Serialize for Cells<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.columns.len()))?;
+        for column in self.columns {
+            seq.serialize_element(&column.value(self.row))?;
+        }
+        seq.end()
+    }
+}
+
+/// and a key each in json
+struct Keyed<'a>(Cells<'a>);
+
+impl Serialize for Keyed<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.columns.len()))?;
+        for column in self.0.columns {
+            map.serialize_entry(column.name(), &column.value(self.0.row))?;
+        }
+        map.end()
+    }
+}
+
+pub trait AccountSink {
+    fn write(&mut self, row: &AccountRow) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// after the last row: flushes, and closes the output where it has to be
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// writes every account to the sink, in client order when `sort`, then finishes it
+pub fn write_accounts<S: AccountSink + ?Sized>(accounts: &Accounts, sort: bool, sink: &mut S)
+                                               -> Result<(), Box<dyn std::error::Error>> {
+    write_some(accounts.iter(), sort, sink)
+}
+
+/// as `write_accounts`, for those of the accounts picked out
+pub(crate) fn write_some<'a, S: AccountSink + ?Sized>(accounts: impl Iterator<Item = (&'a ClientId, &'a Account)>,
+                                                      sort: bool, sink: &mut S) -> Result<(), Box<dyn std::error::Error>> {
+    let mut accounts: Vec<_> = accounts.collect();
+    if sort {
+        accounts.sort_unstable_by_key(|(client, _)| **client);
+    }
+    for (client, account) in accounts {
+        sink.write(&AccountRow::new(*client, account))?;
+    }
+    sink.finish()
+}
+
+/// the header every csv output starts with
+const HEADER: [&str; 5] = ["client", "available", "held", "total", "locked"];
+/// and the columns enriched output goes on with
+const ENRICHED: [&str; 3] = ["name", "currency", "kind"];
+/// and the column output with losses goes on with
+const LOSSES: &str = "chargeback_loss";
+/// and last, the disputes making up held, as `tx:amount` pairs separated by spaces
+const HELD_BREAKDOWN: &str = "held_breakdown";
+
+/// `tx:amount` for each of the row's disputes, `4:2.5000 7:10.0000`
+fn held_breakdown(row: &AccountRow, decimals: u32) -> String {
+    row.disputed.iter().map(|(tx, amount)| format!("{}:{}", tx, fixed(*amount, decimals).0)).collect::<Vec<_>>().join(" ")
+}
+
+/// the amount with exactly `decimals` decimal places, rounded if it has more: `10` & `10.0` are both `10.0000`
+fn fixed(amount: Amount, decimals: u32) -> Fixed {
+    let mut decimal = amount.to_decimal().round_dp(decimals);
+    decimal.rescale(decimals);
+    Fixed(decimal)
+}
+
+/// a decimal serialized as it's displayed, every decimal place kept, where `serde-float` would make a float of it
+struct Fixed(Decimal);
+
+impl Serialize for Fixed {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+
+/// csv under a `client,available,held,total,locked` header, the header written even with no rows. amounts have
+/// `CURRENCY_PRECISION` decimal places unless `decimals` says otherwise
+pub struct CsvSink<W: Write> {
+    writer: csv::Writer<W>,
+    header: bool,
+    decimals: u32,
+    enriched: bool,
+    losses: bool,
+    held_breakdown: bool,
+    columns: Vec<Column>
+}
+
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    client: ClientId,
+    available: Fixed,
+    held: Fixed,
+    total: Fixed,
+    locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chargeback_loss: Option<Fixed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    held_breakdown: Option<String>,
+    columns: Cells<'a>
+}
+
+#[derive(Serialize)]
+struct EnrichedCsvRow<'a> {
+    client: ClientId,
+    available: Fixed,
+    held: Fixed,
+    total: Fixed,
+    locked: bool,
+    name: Option<&'a str>,
+    currency: Option<Currency>,
+    kind: AccountKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chargeback_loss: Option<Fixed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    held_breakdown: Option<String>,
+    columns: Cells<'a>
+}
+
+impl<W: Write> CsvSink<W> {
+    /// buffered `buffer_size` bytes at a time, the csv writer's own buffer standing in for a BufWriter
+    pub fn new(out: W, buffer_size: usize) -> Self {
+        let writer = csv::WriterBuilder::new().has_headers(false).buffer_capacity(buffer_size).from_writer(out);
+        CsvSink { writer, header: false, decimals: CURRENCY_PRECISION, enriched: false, losses: false, held_breakdown: false,
+                  columns: Vec::new() }
+    }
+
+    /// amounts written with this many decimal places, `1.5` as `1.50` with 2
+    pub fn decimals(self, decimals: u32) -> Self {
+        CsvSink { decimals, ..self }
+    }
+
+    /// with `name,currency,kind` columns after the balances
+    pub fn enriched(self, enriched: bool) -> Self {
+        CsvSink { enriched, ..self }
+    }
+
+    /// with a `chargeback_loss` column last, what the account has lost to chargebacks
+    pub fn losses(self, losses: bool) -> Self {
+        CsvSink { losses, ..self }
+    }
+
+    /// with a `held_breakdown` column last, the disputed transactions making up held, `4:2.5000 7:10.0000`
+    pub fn held_breakdown(self, held_breakdown: bool) -> Self {
+        CsvSink { held_breakdown, ..self }
+    }
+
+    /// with these columns after all the others, in the order given
+    pub fn columns(self, columns: &[Column]) -> Self {
+        CsvSink { columns: columns.to_vec(), ..self }
+    }
+
+    fn header(&mut self) -> csv::Result<()> {
+        if !self.header {
+            self.header = true;
+            let (enriched, losses, held_breakdown) = (self.enriched, self.losses, self.held_breakdown);
+            let enriched = ENRICHED.iter().filter(|_| enriched);
+            let losses = std::iter::once(&LOSSES).filter(|_| losses);
+            let held_breakdown = std::iter::once(&HELD_BREAKDOWN).filter(|_| held_breakdown);
+            let columns = self.columns.iter().map(|c| c.name());
+            self.writer.write_record(HEADER.iter().copied().chain(enriched.copied()).chain(losses.copied())
+                .chain(held_breakdown.copied()).chain(columns))?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> AccountSink for CsvSink<W> {
+    fn write(&mut self, row: &AccountRow) -> Result<(), Box<dyn std::error::Error>> {
+        self.header()?;
+        let decimals = self.decimals;
+        let chargeback_loss = self.losses.then(|| fixed(row.chargeback_loss, decimals));
+        let held_breakdown = self.held_breakdown.then(|| held_breakdown(row, decimals));
+        let columns = Cells { columns: &self.columns, row };
+        if self.enriched {
+            self.writer.serialize(EnrichedCsvRow {
+                client: row.client,
+                available: fixed(row.available, decimals),
+                held: fixed(row.held, decimals),
+                total: fixed(row.total, decimals),
+                locked: row.locked,
+                name: row.name.as_deref(),
+                currency: row.currency,
+                kind: row.kind,
+                chargeback_loss,
+                held_breakdown,
+                columns
+            })?;
+            return Ok(());
+        }
+        self.writer.serialize(CsvRow {
+            client: row.client,
+            available: fixed(row.available, decimals),
+            held: fixed(row.held, decimals),
+            total: fixed(row.total, decimals),
+            locked: row.locked,
+            chargeback_loss,
+            held_breakdown,
+            columns
+        })?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.header()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// an object per line: `{"client":1,"available":"1.5","held":"0","total":"1.5","locked":false}`, amounts as
+/// decimal strings
+pub struct JsonSink<W: Write> {
+    out: std::io::BufWriter<W>,
+    enriched: bool,
+    losses: bool,
+    held_breakdown: bool,
+    columns: Vec<Column>
+}
+
+#[derive(Serialize)]
+struct EnrichedJsonRow<'a> {
+    #[serde(flatten)]
+    row: &'a AccountRow,
+    #[serde(flatten)]
+    enrichment: Option<Enrichment<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chargeback_loss: Option<Amount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    held_breakdown: Option<Vec<Held>>,
+    #[serde(flatten)]
+    columns: Keyed<'a>
+}
+
+#[derive(Serialize)]
+struct Held {
+    tx: TxnId,
+    amount: Amount
+}
+
+#[derive(Serialize)]
+struct Enrichment<'a> {
+    name: Option<&'a str>,
+    currency: Option<Currency>,
+    kind: AccountKind
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(out: W, buffer_size: usize) -> Self {
+        JsonSink { out: std::io::BufWriter::with_capacity(buffer_size, out), enriched: false, losses: false, held_breakdown: false,
+                   columns: Vec::new() }
+    }
+
+    /// with `"name"`, `"currency"` & `"kind"` after the balances
+    pub fn enriched(self, enriched: bool) -> Self {
+        JsonSink { enriched, ..self }
+    }
+
+    /// with `"chargeback_loss"` last
+    pub fn losses(self, losses: bool) -> Self {
+        JsonSink { losses, ..self }
+    }
+
+    /// with `"held_breakdown"` last, `[{"tx":4,"amount":"2.5"}]`
+    pub fn held_breakdown(self, held_breakdown: bool) -> Self {
+        JsonSink { held_breakdown, ..self }
+    }
+
+    /// with a key each for these columns last, `"disputes":1`
+    pub fn columns(self, columns: &[Column]) -> Self {
+        JsonSink { columns: columns.to_vec(), ..self }
+    }
+}
+
+impl<W: Write> AccountSink for JsonSink<W> {
+    fn write(&mut self, row: &AccountRow) -> Result<(), Box<dyn std::error::Error>> {
+        match self.enriched || self.losses || self.held_breakdown || !self.columns.is_empty() {
+            true => serde_json::to_writer(&mut self.out, &EnrichedJsonRow {
+                row,
+                enrichment: self.enriched.then_some(Enrichment { name: row.name.as_deref(), currency: row.currency, kind: row.kind }),
+                chargeback_loss: self.losses.then_some(row.chargeback_loss),
+                held_breakdown: self.held_breakdown.then(|| row.disputed.iter().map(|&(tx, amount)| Held { tx, amount }).collect()),
+                columns: Keyed(Cells { columns: &self.columns, row })
+            })?,
+            false => serde_json::to_writer(&mut self.out, row)?
+        }
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// a parquet file of `client` (uint16), `available`, `held` & `total` (decimal128) and `locked` (boolean), written a
+/// row group of `ROW_GROUP` accounts at a time
+#[cfg(feature = "parquet")]
+pub struct ParquetSink<W: Write + Send> {
+    writer: Option<parquet::arrow::ArrowWriter<W>>,
+    schema: std::sync::Arc<arrow_schema::Schema>,
+    scale: u32,
+    rows: Vec<AccountRow>
+}
+
+#[cfg(feature = "parquet")]
+const ROW_GROUP: usize = 64 * 1024;
+
+#[cfg(feature = "parquet")]
+impl<W: Write + Send> ParquetSink<W> {
+    /// amounts are stored at `scale` decimal places, rounded if they have more
+    pub fn new(out: W, scale: u32) -> Result<Self, TxnCliError> {
+        use arrow_array::types::ArrowPrimitiveType;
+        use arrow_schema::{DataType, Field, Schema};
+
+        use crate::id::ClientArrow;
+
+        let amount = DataType::Decimal128(38, scale as i8);
+        let schema = std::sync::Arc::new(Schema::new(vec![
+            Field::new("client", ClientArrow::DATA_TYPE, false),
+            Field::new("available", amount.clone(), false),
+            Field::new("held", amount.clone(), false),
+            Field::new("total", amount, false),
+            Field::new("locked", DataType::Boolean, false)
+        ]));
+        let writer = parquet::arrow::ArrowWriter::try_new(out, schema.clone(), None).map_err(TxnCliError::storage)?;
+        Ok(ParquetSink { writer: Some(writer), schema, scale, rows: Vec::new() })
+    }
+
+    fn flush_rows(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        use arrow_array::{ArrayRef, BooleanArray, Decimal128Array, PrimitiveArray, RecordBatch};
+
+        use crate::id::ClientArrow;
+
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let scale = self.scale;
+        let amounts = |amount: fn(&AccountRow) -> Amount| -> Result<ArrayRef, Box<dyn std::error::Error>> {
+            let values: Vec<i128> = self.rows.iter().map(|r| {
+                fixed(amount(r), scale).0.mantissa()
+            }).collect();
+            Ok(std::sync::Arc::new(Decimal128Array::from(values).with_precision_and_scale(38, scale as i8)?))
+        };
+        let columns: Vec<ArrayRef> = vec![
+            std::sync::Arc::new(PrimitiveArray::<ClientArrow>::from_iter_values(self.rows.iter().map(|r| r.client.0))),
+            amounts(|r| r.available)?,
+            amounts(|r| r.held)?,
+            amounts(|r| r.total)?,
+            std::sync::Arc::new(BooleanArray::from(self.rows.iter().map(|r| r.locked).collect::<Vec<_>>()))
+        ];
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.as_mut().ok_or("parquet output already finished")?.write(&batch)?;
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl<W: Write + Send> AccountSink for ParquetSink<W> {
+    fn write(&mut self, row: &AccountRow) -> Result<(), Box<dyn std::error::Error>> {
+        self.rows.push(row.clone());
+        if self.rows.len() == ROW_GROUP {
+            self.flush_rows()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.flush_rows()?;
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+/// a `balances` table in a sqlite database, replaced whole in one transaction: `client` integer primary key,
+/// `available`, `held` & `total` as decimal text (sqlite has no decimal type, a real would round) and `locked` 0 or 1
+#[cfg(feature = "sqlite")]
+pub struct SqliteSink {
+    connection: rusqlite::Connection
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteSink {
+    pub fn new(path: &std::path::Path) -> Result<Self, TxnCliError> {
+        let connection = rusqlite::Connection::open(path).map_err(TxnCliError::storage)?;
+        connection.execute_batch("BEGIN;
+            DROP TABLE IF EXISTS balances;
+            CREATE TABLE balances (client INTEGER PRIMARY KEY, available TEXT NOT NULL, held TEXT NOT NULL,
+                                   total TEXT NOT NULL, locked INTEGER NOT NULL);").map_err(TxnCliError::storage)?;
+        Ok(SqliteSink { connection })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl AccountSink for SqliteSink {
+    fn write(&mut self, row: &AccountRow) -> Result<(), Box<dyn std::error::Error>> {
+        let amount = |a: Amount| a.to_decimal().normalize().to_string();
+        self.connection.prepare_cached("INSERT INTO balances VALUES (?1, ?2, ?3, ?4, ?5)")?
+            .execute(rusqlite::params![db_client(row.client, "sqlite")?, amount(row.available), amount(row.held), amount(row.total), row.locked])?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connection.execute_batch("COMMIT;")?;
+        Ok(())
+    }
+}
+
+/// balances upserted into a postgres `balances` table, and each one written appended to `balance_audit`, all in
+/// one transaction committed once the last is: `client` bigint, `available`, `held` & `total` numeric and `locked`
+/// boolean in both, with the transaction's time as `updated_at` & `written_at`. the tables are created if missing,
+/// and clients in `balances` the run didn't write are left as they were, so an existing ledger's kept current
+#[cfg(feature = "postgres")]
+pub struct PostgresSink {
+    client: postgres::Client,
+    upsert: postgres::Statement,
+    audit: postgres::Statement
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresSink {
+    /// connects to the `postgres://` url, without tls
+    pub fn new(url: &str) -> Result<Self, TxnCliError> {
+        let mut client = postgres::Client::connect(url, postgres::NoTls).map_err(TxnCliError::storage)?;
+        client.batch_execute("
+            CREATE TABLE IF NOT EXISTS balances (client BIGINT PRIMARY KEY, available NUMERIC NOT NULL,
+                                                 held NUMERIC NOT NULL, total NUMERIC NOT NULL, locked BOOLEAN NOT NULL,
+                                                 updated_at TIMESTAMPTZ NOT NULL);
+            CREATE TABLE IF NOT EXISTS balance_audit (id BIGSERIAL PRIMARY KEY, client BIGINT NOT NULL,
+                                                      available NUMERIC NOT NULL, held NUMERIC NOT NULL,
+                                                      total NUMERIC NOT NULL, locked BOOLEAN NOT NULL,
+                                                      written_at TIMESTAMPTZ NOT NULL);
+            BEGIN;").map_err(TxnCliError::storage)?;
+        // amounts go as text, cast to numeric, so they're never rounded through a float
+        let upsert = client.prepare("
+            INSERT INTO balances VALUES ($1, $2::text::numeric, $3::text::numeric, $4::text::numeric, $5, now())
+            ON CONFLICT (client) DO UPDATE SET available = excluded.available, held = excluded.held,
+                total = excluded.total, locked = excluded.locked, updated_at = excluded.updated_at").map_err(TxnCliError::storage)?;
+        let audit = client.prepare("
+            INSERT INTO balance_audit (client, available, held, total, locked, written_at)
+            VALUES ($1, $2::text::numeric, $3::text::numeric, $4::text::numeric, $5, now())").map_err(TxnCliError::storage)?;
+        Ok(PostgresSink { client, upsert, audit })
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl AccountSink for PostgresSink {
+    fn write(&mut self, row: &AccountRow) -> Result<(), Box<dyn std::error::Error>> {
+        let amount = |a: Amount| a.to_decimal().normalize().to_string();
+        let (available, held, total) = (amount(row.available), amount(row.held), amount(row.total));
+        let params: [&(dyn postgres::types::ToSql + Sync); 5] = [&db_client(row.client, "postgres")?, &available, &held, &total, &row.locked];
+        self.client.execute(&self.upsert, &params)?;
+        self.client.execute(&self.audit, &params)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.client.batch_execute("COMMIT;")?;
+        Ok(())
+    }
+}
+
+/// sqlite's & postgres' integers are i64s, which hold any narrow id but not every wide one
+#[cfg(all(any(feature = "sqlite", feature = "postgres"), not(feature = "wide-ids")))]
+fn db_client(client: ClientId, _db: &str) -> Result<i64, String> {
+    Ok(client.0.into())
+}
+
+#[cfg(all(any(feature = "sqlite", feature = "postgres"), feature = "wide-ids"))]
+fn db_client(client: ClientId, db: &str) -> Result<i64, String> {
+    use std::convert::TryFrom;
+
+    i64::try_from(client.0).map_err(|_| format!("client {} is past {}'s integers", client, db))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::{Accounts, ClientId, Event, execute, Txn};
+
+    use super::{Column, CsvSink, JsonSink, write_accounts};
+
+    fn accounts() -> Accounts {
+        let mut accounts = Accounts::default();
+        execute(&mut accounts, Txn::deposit(2, 1, dec!(1.5)));
+        execute(&mut accounts, Txn::deposit(1, 2, dec!(10)));
+        execute(&mut accounts, Txn::dispute(1, 2));
+        accounts
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut out = Vec::new();
+        write_accounts(&accounts(), true, &mut CsvSink::new(&mut out, 4)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "client,available,held,total,locked\n1,0.0000,10.0000,10.0000,false\n2,1.5000,0.0000,1.5000,false\n");
+
+        let mut out = Vec::new();
+        write_accounts(&accounts(), true, &mut CsvSink::new(&mut out, 4).decimals(2)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "client,available,held,total,locked\n1,0.00,10.00,10.00,false\n2,1.50,0.00,1.50,false\n");
+
+        let mut out = Vec::new();
+        write_accounts(&Accounts::default(), true, &mut CsvSink::new(&mut out, 4)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "client,available,held,total,locked\n");
+    }
+
+    #[test]
+    fn test_json() {
+        let mut out = Vec::new();
+        write_accounts(&accounts(), true, &mut JsonSink::new(&mut out, 64)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            r#"{"client":1,"available":"0","held":"10","total":"10","locked":false}"#, "\n",
+            r#"{"client":2,"available":"1.5","held":"0","total":"1.5","locked":false}"#, "\n"));
+    }
+
+    #[test]
+    fn test_enriched() {
+        let mut accounts = accounts();
+        accounts.get_mut(&ClientId(1)).unwrap().apply(&Event::AccountRegistered {
+            name: Some("Acme, Ltd".into()),
+            currency: Some("USD".parse().unwrap())
+        }).unwrap();
+
+        let mut out = Vec::new();
+        write_accounts(&accounts, true, &mut CsvSink::new(&mut out, 4).enriched(true)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!("client,available,held,total,locked,name,currency,kind\n",
+                                                            "1,0.0000,10.0000,10.0000,false,\"Acme, Ltd\",USD,customer\n",
+                                                            "2,1.5000,0.0000,1.5000,false,,,customer\n"));
+        let mut out = Vec::new();
+        write_accounts(&accounts, true, &mut JsonSink::new(&mut out, 64).enriched(true)).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with(concat!(
+            r#"{"client":1,"available":"0","held":"10","total":"10","locked":false,"name":"Acme, Ltd","currency":"USD","#,
+            r#""kind":"customer"}"#, "\n")));
+    }
+
+    #[test]
+    fn test_losses() {
+        let mut accounts = accounts();
+        execute(&mut accounts, Txn::chargeback(1, 2));
+
+        let mut out = Vec::new();
+        write_accounts(&accounts, true, &mut CsvSink::new(&mut out, 4).enriched(true).losses(true)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!("client,available,held,total,locked,name,currency,kind,chargeback_loss\n",
+                                                            "1,0.0000,0.0000,0.0000,true,,,customer,10.0000\n",
+                                                            "2,1.5000,0.0000,1.5000,false,,,customer,0.0000\n"));
+        let mut out = Vec::new();
+        write_accounts(&accounts, true, &mut JsonSink::new(&mut out, 64).losses(true)).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with(concat!(
+            r#"{"client":1,"available":"0","held":"0","total":"0","locked":true,"chargeback_loss":"10"}"#, "\n")));
+    }
+
+    #[test]
+    fn test_held_breakdown() {
+        let mut accounts = accounts();
+        execute(&mut accounts, Txn::deposit(1, 3, dec!(2.5)));
+        execute(&mut accounts, Txn::dispute(1, 3));
+
+        let mut out = Vec::new();
+        write_accounts(&accounts, true, &mut CsvSink::new(&mut out, 4).losses(true).held_breakdown(true)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!("client,available,held,total,locked,chargeback_loss,held_breakdown\n",
+                                                            "1,0.0000,12.5000,12.5000,false,0.0000,2:10.0000 3:2.5000\n",
+                                                            "2,1.5000,0.0000,1.5000,false,0.0000,\n"));
+        let mut out = Vec::new();
+        write_accounts(&accounts, true, &mut JsonSink::new(&mut out, 64).held_breakdown(true)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            r#"{"client":1,"available":"0","held":"12.5","total":"12.5","locked":false,"#,
+            r#""held_breakdown":[{"tx":2,"amount":"10"},{"tx":3,"amount":"2.5"}]}"#, "\n",
+            r#"{"client":2,"available":"1.5","held":"0","total":"1.5","locked":false,"held_breakdown":[]}"#, "\n"));
+    }
+
+    #[test]
+    fn test_columns() {
+        let mut accounts = accounts();
+        execute(&mut accounts, Txn::deposit(1, 3, dec!(2.5)));
+        let columns = [Column::TxnCount, Column::Disputes];
+
+        let mut out = Vec::new();
+        write_accounts(&accounts, true, &mut CsvSink::new(&mut out, 4).losses(true).columns(&columns)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!("client,available,held,total,locked,chargeback_loss,txn_count,disputes\n",
+                                                            "1,2.5000,10.0000,12.5000,false,0.0000,2,1\n",
+                                                            "2,1.5000,0.0000,1.5000,false,0.0000,1,0\n"));
+        let mut out = Vec::new();
+        write_accounts(&accounts, true, &mut JsonSink::new(&mut out, 64).columns(&columns)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            r#"{"client":1,"available":"2.5","held":"10","total":"12.5","locked":false,"txn_count":2,"disputes":1}"#, "\n",
+            r#"{"client":2,"available":"1.5","held":"0","total":"1.5","locked":false,"txn_count":1,"disputes":0}"#, "\n"));
+        assert_eq!("txn_count".parse(), Ok(Column::TxnCount));
+        assert!("+disputes".parse::<Column>().is_err());
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet() {
+        use arrow_array::cast::AsArray;
+        use arrow_array::types::Decimal128Type;
+
+        use crate::id::ClientArrow;
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let path = std::env::temp_dir().join(format!("txn-sink-test-{}.parquet", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        write_accounts(&accounts(), true, &mut super::ParquetSink::new(file, 4).unwrap()).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap()).unwrap().build().unwrap();
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.column(0).as_primitive::<ClientArrow>().values(), &[1, 2]);
+        assert_eq!(batch.column(2).as_primitive::<Decimal128Type>().value_as_string(0), "10.0000");
+        assert_eq!(batch.column(1).as_primitive::<Decimal128Type>().value_as_string(1), "1.5000");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite() {
+        let path = std::env::temp_dir().join(format!("txn-sink-test-{}.db", std::process::id()));
+        // replaced, not appended to
+        for _ in 0..2 {
+            write_accounts(&accounts(), false, &mut super::SqliteSink::new(&path).unwrap()).unwrap();
+        }
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        let rows: Vec<(i64, String, String, bool)> = connection
+            .prepare("SELECT client, available, held, locked FROM balances ORDER BY client").unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))).unwrap()
+            .map(Result::unwrap)
+            .collect();
+        drop(connection);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rows, vec![(1, "0".into(), "10".into(), false), (2, "1.5".into(), "0".into(), false)]);
+    }
+
+    /// against the server `TXN_TEST_POSTGRES` names, in a schema of its own, and passed over without one
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn test_postgres() {
+        let url = match std::env::var("TXN_TEST_POSTGRES") {
+            Ok(url) => url,
+            Err(_) => return
+        };
+        let schema = format!("txn_sink_test_{}", std::process::id());
+        let mut client = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+        client.batch_execute(&format!("DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}", schema)).unwrap();
+        let sep = if url.contains('?') { '&' } else { '?' };
+        let in_schema = format!("{}{}options=-csearch_path%3D{}", url, sep, schema);
+
+        // upserted, not replaced, and audited each time
+        write_accounts(&accounts(), false, &mut super::PostgresSink::new(&in_schema).unwrap()).unwrap();
+        let mut accounts = accounts();
+        execute(&mut accounts, Txn::resolve(1, 2));
+        accounts.remove(&ClientId(2));
+        write_accounts(&accounts, false, &mut super::PostgresSink::new(&in_schema).unwrap()).unwrap();
+
+        let query = |client: &mut postgres::Client, sql: &str| -> Vec<(i64, String, String, bool)> {
+            client.query(sql, &[]).unwrap().iter().map(|r| (r.get(0), r.get(1), r.get(2), r.get(3))).collect()
+        };
+        let balances = query(&mut client, &format!("SELECT client, available::text, held::text, locked FROM {}.balances ORDER BY client", schema));
+        let audit = query(&mut client, &format!("SELECT client, available::text, held::text, locked FROM {}.balance_audit ORDER BY id", schema));
+        client.batch_execute(&format!("DROP SCHEMA {} CASCADE", schema)).unwrap();
+        assert_eq!(balances, vec![(1, "10".into(), "0".into(), false), (2, "1.5".into(), "0".into(), false)]);
+        assert_eq!(audit.len(), 3);
+        assert_eq!(audit[2], (1, "10".into(), "0".into(), false));
+    }
+}
@@ -0,0 +1,256 @@
+//! `TxnSource`: where transactions come from, one at a time, whatever the format. the cli's runs, `Engine::process`
+//! and `process_csv` take any source, so a new input format is a new source and leaves the engine alone.
+//!
+//! csv (`CsvSource`), newline-delimited json (`JsonSource`) and plain iterators or generator closures (`Generator`)
+//! are sources, as are the arrow & avro readers when built with their features.
+
+use std::fmt;
+use std::io::{BufRead, Read};
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::engine::CsvOptions;
+use crate::locale::AmountFormat;
+use crate::{ClientId, Currency, deserialize_record, fastparse, locale, Precision, Txn, TxnId, TxnType};
+
+pub trait TxnSource {
+    /// the next transaction, or why the next row, record or line isn't one. None once the input's exhausted
+    fn next_txn(&mut self) -> Option<Result<Txn, SourceError>>;
+}
+
+/// input that isn't a transaction. the error policy decides whether a run carries on past it, unless it's fatal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceError {
+    what: &'static str,
+    detail: String,
+    rows: u64,
+    /// of the input, where it has lines
+    line: Option<u64>,
+    fatal: bool
+}
+
+impl SourceError {
+    /// `what` names the unit that was malformatted: "row", "record", "line"...
+    pub fn malformatted(what: &'static str, detail: impl fmt::Display) -> Self {
+        SourceError { what, detail: detail.to_string(), rows: 1, line: None, fatal: false }
+    }
+
+    /// the source can't go on, i.e. reading failed. ends the run under either error policy
+    pub fn fatal(what: &'static str, detail: impl fmt::Display) -> Self {
+        SourceError { fatal: true, ..SourceError::malformatted(what, detail) }
+    }
+
+    /// how many transactions went with it, where a whole batch of them did
+    pub fn rows(self, rows: u64) -> Self {
+        SourceError { rows, ..self }
+    }
+
+    /// the line of the input it's on
+    pub fn on_line(self, line: Option<u64>) -> Self {
+        SourceError { line, ..self }
+    }
+
+    pub fn what(&self) -> &'static str {
+        self.what
+    }
+
+    pub fn row_count(&self) -> u64 {
+        self.rows
+    }
+
+    pub fn line(&self) -> Option<u64> {
+        self.line
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        self.fatal
+    }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.detail)
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+/// csv under a `type,client,tx,amount` header, as the cli reads it
+pub struct CsvSource<R> {
+    reader: csv::Reader<R>,
+    fast_parse: bool,
+    /// how amounts are read, with the precision
+    format: AmountFormat,
+    /// reused for every row
+    bytes: csv::ByteRecord,
+    string: csv::StringRecord
+}
+
+impl<R: Read> CsvSource<R> {
+    pub fn new(reader: R, options: CsvOptions, precision: impl Into<Precision>) -> Self {
+        let precision = precision.into();
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(options.has_headers)
+            .delimiter(options.delimiter)
+            .from_reader(reader);
+        CsvSource {
+            reader,
+            fast_parse: options.fast_parse,
+            format: AmountFormat { locale: options.amount_locale, policy: options.amount_policy, precision },
+            bytes: csv::ByteRecord::new(),
+            string: csv::StringRecord::new()
+        }
+    }
+}
+
+impl<R: Read> TxnSource for CsvSource<R> {
+    fn next_txn(&mut self) -> Option<Result<Txn, SourceError>> {
+        let (precision, format) = (self.format.precision, self.format);
+        let read = match self.fast_parse {
+            true => self.reader.read_byte_record(&mut self.bytes).map(|more| more.then(|| {
+                let line = self.bytes.position().map(csv::Position::line);
+                locale::localize_bytes(&mut self.bytes, format)
+                    .and_then(|()| Ok(fastparse::parse_record(&self.bytes, precision)?))
+                    .map_err(|e| SourceError::malformatted("row", e).on_line(line))
+            })),
+            false => self.reader.read_record(&mut self.string).map(|more| more.then(|| {
+                let line = self.string.position().map(csv::Position::line);
+                locale::localize(&mut self.string, format)
+                    .and_then(|()| deserialize_record(&mut self.string, precision))
+                    .map_err(|e| SourceError::malformatted("row", e).on_line(line))
+            }))
+        };
+        let line = |e: &csv::Error| e.position().map(csv::Position::line);
+        match read {
+            Ok(txn) => txn,
+            // no row to skip past, the reader would likely fail the same way again
+            Err(e) if e.is_io_error() => Some(Err(SourceError::fatal("row", &e).on_line(line(&e)))),
+            Err(e) => Some(Err(SourceError::malformatted("row", &e).on_line(line(&e))))
+        }
+    }
+}
+
+/// newline-delimited json, an object per line as a txn serializes: `{"type":"deposit","client":1,"tx":1,"amount":"2.5"}`,
+/// with an optional `"currency":"USD"`. amounts are decimal strings, a json number may not be the amount meant. blank
+/// lines are passed over
+pub struct JsonSource<R> {
+    reader: R,
+    precision: Precision,
+    line: String,
+    /// lines read so far
+    lines: u64
+}
+
+#[derive(Deserialize)]
+struct JsonRecord {
+    #[serde(rename = "type")]
+    txntype: TxnType,
+    client: ClientId,
+    tx: TxnId,
+    #[serde(default)]
+    amount: Option<String>,
+    #[serde(default)]
+    currency: Option<Currency>
+}
+
+impl<R: BufRead> JsonSource<R> {
+    pub fn new(reader: R, precision: impl Into<Precision>) -> Self {
+        let precision = precision.into();
+        JsonSource { reader, precision, line: String::new(), lines: 0 }
+    }
+
+    fn parse(&self) -> Result<Txn, String> {
+        let record: JsonRecord = serde_json::from_str(&self.line).map_err(|e| e.to_string())?;
+        let amount = match &record.amount {
+            Some(a) => Some(Decimal::from_str(a.trim()).map_err(|_| format!("invalid amount '{}'", a))?),
+            None => None
+        };
+        Txn::builder(record.txntype, record.client, record.tx).amount(amount).precision(self.precision).currency(record.currency).build()
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl<R: BufRead> TxnSource for JsonSource<R> {
+    fn next_txn(&mut self) -> Option<Result<Txn, SourceError>> {
+        loop {
+            self.line.clear();
+            let read = self.reader.read_line(&mut self.line);
+            self.lines += 1;
+            let line = Some(self.lines);
+            match read {
+                Ok(0) => return None,
+                Ok(_) if self.line.trim().is_empty() => continue,
+                Ok(_) => return Some(self.parse().map_err(|e| SourceError::malformatted("line", e).on_line(line))),
+                Err(e) => return Some(Err(SourceError::fatal("line", e).on_line(line)))
+            }
+        }
+    }
+}
+
+/// transactions from an iterator, or a generator closure through `std::iter::from_fn`: synthetic loads, tests, or
+/// transactions an embedder already holds
+pub struct Generator<I>(pub I);
+
+impl<I: Iterator<Item = Txn>> TxnSource for Generator<I> {
+    fn next_txn(&mut self) -> Option<Result<Txn, SourceError>> {
+        self.0.next().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::engine::CsvOptions;
+    use crate::{CURRENCY_PRECISION, Txn};
+
+    use super::{CsvSource, Generator, JsonSource, SourceError, TxnSource};
+
+    fn drain(source: &mut impl TxnSource) -> Vec<Result<Txn, SourceError>> {
+        std::iter::from_fn(|| source.next_txn()).collect()
+    }
+
+    #[test]
+    fn test_csv() {
+        let csv = "type,client,tx,amount\ndeposit,1,1,2.5\ndispute,1,1,5\n";
+        for fast_parse in [false, true] {
+            let options = CsvOptions { fast_parse, ..CsvOptions::default() };
+            let txns = drain(&mut CsvSource::new(csv.as_bytes(), options, CURRENCY_PRECISION));
+            assert_eq!(txns[0], Ok(Txn::deposit(1, 1, dec!(2.5))));
+            let error = txns[1].clone().unwrap_err();
+            assert_eq!((error.what(), error.is_fatal()), ("row", false));
+            // the offending row's line
+            assert!(error.to_string().starts_with("line 3: "), "{}", error);
+            assert_eq!(error.line(), Some(3));
+            assert_eq!(txns.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let json = concat!(r#"{"type":"deposit","client":1,"tx":1,"amount":"2.55555"}"#, "\n\n",
+                           r#"{"type":"dispute","client":1,"tx":1}"#, "\n",
+                           r#"{"type":"deposit","client":1,"tx":2,"amount":2.5}"#, "\n",
+                           r#"{"type":"withdrawal","client":1,"tx":3,"amount":"lots"}"#, "\n",
+                           "not json\n");
+        let txns = drain(&mut JsonSource::new(json.as_bytes(), CURRENCY_PRECISION));
+        assert_eq!(txns[..2], [Ok(Txn::deposit(1, 1, dec!(2.5556))), Ok(Txn::dispute(1, 1))]);
+        assert_eq!(txns[3].clone().unwrap_err().to_string(), "invalid amount 'lots'");
+        // blank lines are counted
+        assert_eq!(txns[3].clone().unwrap_err().line(), Some(5));
+        assert!(txns[2..].iter().all(|t| t.as_ref().is_err_and(|e| e.what() == "line" && !e.is_fatal())));
+        assert_eq!(txns.len(), 5);
+    }
+
+    #[test]
+    fn test_generator() {
+        let mut tx = 0;
+        let mut source = Generator(std::iter::from_fn(|| {
+            tx += 1;
+            (tx <= 3).then(|| Txn::deposit(1, tx, dec!(1)))
+        }));
+        assert_eq!(drain(&mut source).len(), 3);
+    }
+}
@@ -0,0 +1,271 @@
+//! bank statement (ofx/qfx & qif) import.
+//!
+//! statements describe a single account, so every entry is booked against one client, `statement.client` in the
+//! config (`STATEMENT_CLIENT` by default).
+//! credits become deposits and debits withdrawals. statements carry no engine txn ids, so ids are
+//! synthesized by hashing the bank's FITID (ofx) or the entry's contents (qif), which keeps re-imports
+//! of the same export stable.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use crate::{ClientId, Precision, Txn, TxnError, TxnId, TxnType};
+
+/// default client statements are booked against
+pub const STATEMENT_CLIENT: ClientId = ClientId(1);
+
+/// ofx 1.x is sgml (closing tags optional), ofx 2.x is xml. both are handled by reading each
+/// `<STMTTRN>` aggregate and taking the text up to the next tag for the elements we need.
+pub fn parse_ofx(content: &str, client: ClientId, precision: impl Into<Precision>) -> Result<Vec<Txn>, String> {
+    let precision = precision.into();
+    let mut ids = TxnIds::default();
+    let mut txns = Vec::new();
+
+    let upper = content.to_ascii_uppercase();
+    let mut rest = 0;
+    while let Some(start) = upper[rest..].find("<STMTTRN>") {
+        let start = rest + start + "<STMTTRN>".len();
+        let end = upper[start..].find("</STMTTRN>").map_or(upper.len(), |e| start + e);
+        let block = &content[start..end];
+        let block_upper = &upper[start..end];
+        rest = end;
+
+        let fitid = element(block, block_upper, "FITID").ok_or("STMTTRN without FITID")?;
+        let amount = element(block, block_upper, "TRNAMT").ok_or("STMTTRN without TRNAMT")?;
+        // the ofx spec permits a comma as the decimal separator
+        let amount = parse_amount(&amount.replace(',', "."))
+            .ok_or_else(|| format!("FITID {}: invalid TRNAMT '{}'", fitid, amount))?;
+
+        let txn = to_txn(client, ids.next(fitid), amount, precision)
+            .map_err(|e| format!("FITID {}: {}", fitid, e))?;
+        if let Some(txn) = txn {
+            txns.push(txn);
+        }
+    }
+    Ok(txns)
+}
+
+/// qif entries are `^` terminated groups of lines, each prefixed by a field code.
+/// only non-investment account types are understood.
+pub fn parse_qif(content: &str, client: ClientId, precision: impl Into<Precision>) -> Result<Vec<Txn>, String> {
+    let precision = precision.into();
+    let mut ids = TxnIds::default();
+    let mut txns = Vec::new();
+
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut entry: Vec<&str> = Vec::new();
+    let mut amount: Option<Decimal> = None;
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('!') {
+            continue;
+        }
+        if line.starts_with('^') {
+            if let Some(amount) = amount.take() {
+                // no bank id in qif, so the entry itself is the identity
+                let txn = to_txn(client, ids.next(&entry.join("\n")), amount, precision)
+                    .map_err(|e| format!("line {}: {}", i + 1, e))?;
+                if let Some(txn) = txn {
+                    txns.push(txn);
+                }
+            }
+            entry.clear();
+            continue;
+        }
+
+        entry.push(line);
+        let mut chars = line.chars();
+        let code = chars.next();
+        let value = chars.as_str();
+        if code == Some('T') || code == Some('U') {
+            amount = Some(parse_qif_amount(value)
+                .ok_or_else(|| format!("line {}: invalid amount '{}'", i + 1, value))?);
+        }
+    }
+
+    if amount.is_some() {
+        return Err("unterminated qif entry (missing '^')".into());
+    }
+    Ok(txns)
+}
+
+fn to_txn(client: ClientId, tx: TxnId, amount: Decimal, precision: Precision) -> Result<Option<Txn>, TxnError> {
+    if amount.is_zero() {
+        return Ok(None);
+    }
+    let txntype = if amount.is_sign_negative() { TxnType::Withdrawal } else { TxnType::Deposit };
+    Txn::builder(txntype, client, tx).amount(amount.abs()).precision(precision).build().map(Some)
+}
+
+/// thousands separators are common in qif amounts, but so is a decimal comma. commas are only accepted as
+/// grouping (`1,500.00`, `1,500`), anything else (`12,50`) is rejected rather than guessed at.
+fn parse_qif_amount(value: &str) -> Option<Decimal> {
+    let value = value.trim();
+    let (int, frac) = match value.find('.') {
+        Some(i) => value.split_at(i),
+        None => (value, "")
+    };
+    if frac.contains(',') {
+        return None;
+    }
+
+    let mut groups = int.split(',');
+    let first = groups.next()?.trim_start_matches(['+', '-']);
+    let grouped = groups.all(|g| g.len() == 3 && g.bytes().all(|b| b.is_ascii_digit()));
+    if !grouped || (int.contains(',') && (first.is_empty() || first.len() > 3)) {
+        return None;
+    }
+    parse_amount(&value.replace(',', ""))
+}
+
+fn parse_amount(s: &str) -> Option<Decimal> {
+    Decimal::from_str(s.trim().trim_start_matches('+')).ok()
+}
+
+/// value of an sgml/xml element: text after `<NAME>` up to the next tag or line end
+fn element<'a>(block: &'a str, block_upper: &str, name: &str) -> Option<&'a str> {
+    let tag = format!("<{}>", name);
+    let start = block_upper.find(&tag)? + tag.len();
+    let len = block[start..].find(['<', '\n', '\r']).unwrap_or(block.len() - start);
+    let value = block[start..start + len].trim();
+    if value.is_empty() { None } else { Some(value) }
+}
+
+/// deterministic txn ids: fnv-1a of the source identity, probing forward on collision.
+/// repeated identities (i.e. two identical qif entries) therefore get distinct, order-stable ids.
+#[derive(Default)]
+pub(crate) struct TxnIds {
+    used: HashSet<TxnId>
+}
+
+impl TxnIds {
+    pub(crate) fn next(&mut self, identity: &str) -> TxnId {
+        let mut id = identity.bytes()
+            .fold(0x811c_9dc5u32, |hash, b| (hash ^ b as u32).wrapping_mul(0x0100_0193));
+        while !self.used.insert(TxnId::from(id)) {
+            id = id.wrapping_add(1);
+        }
+        TxnId::from(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use rust_decimal::Decimal;
+
+    use crate::{ClientId, CURRENCY_PRECISION, Txn, TxnId, TxnType};
+
+    use super::{parse_ofx, parse_qif, STATEMENT_CLIENT, TxnIds};
+
+    fn txn(txntype: TxnType, tx: TxnId, amount: Decimal) -> Txn {
+        Txn::builder(txntype, STATEMENT_CLIENT, tx).amount(amount).build().unwrap()
+    }
+
+    const OFX_SGML: &str = "OFXHEADER:100
+DATA:OFXSGML
+
+<OFX>
+<BANKMSGSRSV1><STMTTRNRS><STMTRS>
+<BANKTRANLIST>
+<STMTTRN>
+<TRNTYPE>CREDIT
+<DTPOSTED>20211001
+<TRNAMT>1500.00
+<FITID>2021100101
+<NAME>SALARY
+</STMTTRN>
+<STMTTRN>
+<TRNTYPE>DEBIT
+<DTPOSTED>20211002
+<TRNAMT>-12,5
+<FITID>2021100201
+</STMTTRN>
+</BANKTRANLIST>
+</STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>";
+
+    #[test]
+    fn test_ofx_sgml() {
+        let txns = parse_ofx(OFX_SGML, STATEMENT_CLIENT, CURRENCY_PRECISION).unwrap();
+        let mut ids = TxnIds::default();
+        assert_eq!(txns, vec![
+            txn(TxnType::Deposit, ids.next("2021100101"), dec!(1500.00)),
+            txn(TxnType::Withdrawal, ids.next("2021100201"), dec!(12.5)),
+        ]);
+    }
+
+    #[test]
+    fn test_ofx_xml() {
+        let xml = r#"<?xml version="1.0"?><OFX><STMTTRN><TRNTYPE>DEBIT</TRNTYPE><TRNAMT>-3.14159</TRNAMT>
+            <FITID>abc</FITID></STMTTRN><stmttrn><trnamt>0.00</trnamt><fitid>zero</fitid></stmttrn></OFX>"#;
+        let mut ids = TxnIds::default();
+        assert_eq!(parse_ofx(xml, STATEMENT_CLIENT, CURRENCY_PRECISION).unwrap(), vec![txn(TxnType::Withdrawal, ids.next("abc"), dec!(3.1416))]);
+    }
+
+    #[test]
+    fn test_ofx_ids_are_deterministic() {
+        assert_eq!(parse_ofx(OFX_SGML, STATEMENT_CLIENT, CURRENCY_PRECISION).unwrap(), parse_ofx(OFX_SGML, STATEMENT_CLIENT, CURRENCY_PRECISION).unwrap());
+    }
+
+    #[test]
+    fn test_ofx_invalid() {
+        assert!(parse_ofx("<STMTTRN><TRNAMT>1.0</STMTTRN>", STATEMENT_CLIENT, CURRENCY_PRECISION).is_err());
+        assert!(parse_ofx("<STMTTRN><TRNAMT>abc<FITID>1</STMTTRN>", STATEMENT_CLIENT, CURRENCY_PRECISION).is_err());
+    }
+
+    #[test]
+    fn test_qif() {
+        let qif = "!Type:Bank\nD10/01/2021\nT1,500.00\nPSalary\n^\nD10/02/2021\nT-12.50\nPCoffee\n^\n";
+        let txns = parse_qif(qif, STATEMENT_CLIENT, CURRENCY_PRECISION).unwrap();
+        assert_eq!(txns.len(), 2);
+        assert_eq!(txns[0], txn(TxnType::Deposit, txns[0].tx, dec!(1500)));
+        assert_eq!(txns[1], txn(TxnType::Withdrawal, txns[1].tx, dec!(12.5)));
+        assert_eq!(parse_qif(qif, STATEMENT_CLIENT, CURRENCY_PRECISION).unwrap(), txns);
+    }
+
+    #[test]
+    fn test_qif_duplicate_entries_get_distinct_ids() {
+        let qif = "!Type:Bank\nD10/01/2021\nT-1.00\n^\nD10/01/2021\nT-1.00\n^\n";
+        let txns = parse_qif(qif, STATEMENT_CLIENT, CURRENCY_PRECISION).unwrap();
+        assert_ne!(txns[0].tx, txns[1].tx);
+    }
+
+    #[test]
+    fn test_qif_bom_and_non_ascii() {
+        let qif = "\u{feff}!Type:Bank\nD10/01/2021\nT-4.20\nPCafé\n€memo\n^\n";
+        let txns = parse_qif(qif, STATEMENT_CLIENT, CURRENCY_PRECISION).unwrap();
+        assert_eq!(txns, vec![txn(TxnType::Withdrawal, txns[0].tx, dec!(4.2))]);
+    }
+
+    #[test]
+    fn test_qif_amount_commas() {
+        let amount = |a: &str| parse_qif(&format!("!Type:Bank\nT{}\n^\n", a), STATEMENT_CLIENT, CURRENCY_PRECISION)
+            .map(|t| t[0].amount().to_decimal());
+        assert_eq!(amount("1,500.00"), Ok(dec!(1500)));
+        assert_eq!(amount("-1,234,567.5"), Ok(dec!(1234567.5)));
+        assert_eq!(amount("1,500"), Ok(dec!(1500)));
+        // a decimal comma would otherwise be read as a 100x amount
+        assert!(amount("-12,50").is_err());
+        assert!(amount("1,5").is_err());
+        assert!(amount("1500,000.00").is_err());
+        assert!(amount("1.500,00").is_err());
+    }
+
+    #[test]
+    fn test_client() {
+        let txns = parse_ofx(OFX_SGML, ClientId(9), CURRENCY_PRECISION).unwrap();
+        assert!(txns.iter().all(|t| t.client == ClientId(9)));
+        let txns = parse_qif("!Type:Bank\nT1.00\n^\n", ClientId(9), CURRENCY_PRECISION).unwrap();
+        assert_eq!(txns[0].client, ClientId(9));
+    }
+
+    #[test]
+    fn test_qif_invalid() {
+        assert!(parse_qif("!Type:Bank\nTabc\n^\n", STATEMENT_CLIENT, CURRENCY_PRECISION).is_err());
+        assert!(parse_qif("!Type:Bank\nT1.00\n", STATEMENT_CLIENT, CURRENCY_PRECISION).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! `--stats`: counts every transaction executed against each client, applied or rejected, and once the file's
+//! processed prints on stderr what the workload came to, for sizing a deployment to it:
+//! ```text
+//! operations: 2000000 over 65000 clients, 30.8 a client
+//!
+//! hottest 10 clients
+//! client  operations  share  logged
+//! 60343           58   0.0%      58
+//! ...
+//!
+//! shards  busiest  share
+//! 2       1001546  50.1%
+//! ...
+//! 64        31688   1.6%
+//!
+//! logged     accounts
+//! 0                 0
+//! 1 - 9             1
+//! 10 - 99       64999
+//! ...
+//! largest log: 58, mean 30.8, in 278408 pages of 8 (68.0MiB, 0 free)
+//! memory estimate: ~130.8MiB
+//!
+//! accounts map: 65000 clients in 131072 buckets, 0 in a bucket another hashed to first, at most 1 to one
+//! ```
+//! - a hot client serializes the work on its account, whatever the shard count: a shard is at least as busy as
+//!   its hottest client. the busiest shard's share, with clients hashed as `output.shards` & the server's shards
+//!   hash them, says how much more shards would spread the work
+//! - the log sizes & estimate, as `--max-memory` takes it, are what a cache of the accounts would need to hold
+//! - collisions are clients the accounts map's hash puts in the same home bucket as another, as hashbrown probes
+//!   from it. many of them, with sequential ids, is the hash doing poorly by the workload's clients
+
+use std::fmt;
+use std::hash::BuildHasher;
+
+use crate::analyze::table;
+use crate::{Accounts, ClientId, Hasher, Map, memory, txnlog};
+
+/// clients listed as the hottest
+const HOTTEST: usize = 10;
+/// shard counts the busiest shard is given for, up to the server's 64
+const SHARDS: [usize; 6] = [2, 4, 8, 16, 32, 64];
+/// the log sizes tallied, in powers of ten
+const SIZES: [&str; 5] = ["0", "1 - 9", "10 - 99", "100 - 999", ">= 1000"];
+
+/// transactions executed against each client
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Operations(Map<ClientId, u64>);
+
+impl Operations {
+    pub(crate) fn count(&mut self, client: ClientId) {
+        *self.0.entry(client).or_insert(0) += 1;
+    }
+
+    fn total(&self) -> u64 {
+        self.0.values().sum()
+    }
+
+    /// up to `n` clients, most operations first
+    fn hottest(&self, n: usize) -> Vec<(ClientId, u64)> {
+        let mut clients: Vec<_> = self.0.iter().map(|(c, ops)| (*c, *ops)).collect();
+        clients.sort_unstable_by(|(a, x), (b, y)| y.cmp(x).then(a.cmp(b)));
+        clients.truncate(n);
+        clients
+    }
+
+    /// the operations of the busiest of `shards` shards
+    fn busiest(&self, shards: usize) -> u64 {
+        let mut counts = vec![0; shards];
+        for (client, ops) in &self.0 {
+            counts[(Hasher::default().hash_one(client) % shards as u64) as usize] += ops;
+        }
+        counts.into_iter().max().unwrap_or(0)
+    }
+}
+
+/// the accounts map's buckets, clients in a bucket another was first in, and the most in any one
+fn collisions(accounts: &Accounts) -> (usize, usize, usize) {
+    if accounts.is_empty() {
+        return (0, 0, 0);
+    }
+    // kept at most 7/8 full, a power of two of them
+    let buckets = (accounts.capacity() * 8).div_ceil(7).next_power_of_two();
+    let mut homes: Map<usize, usize> = Map::default();
+    for client in accounts.keys() {
+        *homes.entry(Hasher::default().hash_one(client) as usize & (buckets - 1)).or_insert(0) += 1;
+    }
+    (buckets, accounts.len() - homes.len(), homes.values().copied().max().unwrap_or(0))
+}
+
+pub(crate) struct Stats<'a> {
+    pub(crate) operations: &'a Operations,
+    pub(crate) accounts: &'a Accounts
+}
+
+fn share(part: u64, whole: u64) -> String {
+    format!("{:.1}%", part as f64 * 100.0 / whole.max(1) as f64)
+}
+
+impl fmt::Display for Stats<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (total, clients) = (self.operations.total(), self.operations.0.len());
+        writeln!(f, "operations: {} over {} clients, {:.1} a client", total, clients, total as f64 / clients.max(1) as f64)?;
+
+        writeln!(f, "\nhottest {} clients", HOTTEST)?;
+        let hottest = self.operations.hottest(HOTTEST).into_iter()
+            .map(|(c, ops)| {
+                let logged = self.accounts.get(&c).map_or(0, |a| a.txnlog.len());
+                vec![c.to_string(), ops.to_string(), share(ops, total), logged.to_string()]
+            })
+            .collect();
+        table(f, &["client", "operations", "share", "logged"], hottest)?;
+
+        writeln!(f)?;
+        let shards = SHARDS.iter()
+            .map(|&n| {
+                let busiest = self.operations.busiest(n);
+                vec![n.to_string(), busiest.to_string(), share(busiest, total)]
+            })
+            .collect();
+        table(f, &["shards", "busiest", "share"], shards)?;
+
+        writeln!(f)?;
+        let mut sizes = [0u64; 5];
+        let (mut largest, mut logged) = (0, 0);
+        for account in self.accounts.values() {
+            let len = account.txnlog.len();
+            let size = (0..SIZES.len() - 1).find(|i| len < 10usize.pow(*i as u32)).unwrap_or(SIZES.len() - 1);
+            sizes[size] += 1;
+            largest = largest.max(len);
+            logged += len;
+        }
+        let sizes = SIZES.iter().zip(&sizes).map(|(size, count)| vec![size.to_string(), count.to_string()]).collect();
+        table(f, &["logged", "accounts"], sizes)?;
+        let pool = txnlog::stats();
+        writeln!(f, "largest log: {}, mean {:.1}, in {} pages of {} ({}, {} free)", largest,
+                 logged as f64 / self.accounts.len().max(1) as f64, pool.pages, txnlog::PAGE,
+                 memory::format_size(pool.bytes), pool.free)?;
+        writeln!(f, "memory estimate: ~{}", memory::format_size(memory::estimate(self.accounts)))?;
+
+        let (buckets, collided, most) = collisions(self.accounts);
+        writeln!(f, "\naccounts map: {} clients in {} buckets, {} in a bucket another hashed to first, at most {} to one",
+                 self.accounts.len(), buckets, collided, most)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasher;
+
+    use rust_decimal_macros::dec;
+
+    use crate::config::Config;
+    use crate::{Accounts, ClientId, execute_with, Hasher, Set, Txn};
+
+    use super::{collisions, Operations, Stats};
+
+    #[test]
+    fn test_operations() {
+        let mut operations = Operations::default();
+        for client in [3, 1, 3, 2, 3, 1] {
+            operations.count(ClientId(client));
+        }
+        assert_eq!(operations.total(), 6);
+        assert_eq!(operations.hottest(2), [(ClientId(3), 3), (ClientId(1), 2)]);
+        // one shard holds everything, and however many there are the hottest client's in one of them
+        assert_eq!(operations.busiest(1), 6);
+        assert!((2..64).all(|n| (3..=6).contains(&operations.busiest(n))));
+    }
+
+    #[test]
+    fn test_collisions() {
+        let mut accounts = Accounts::default();
+        for client in 0..1000u16 {
+            execute_with(&mut accounts, Txn::deposit(client, u32::from(client), dec!(1)), &Config::default()).unwrap();
+        }
+        let (buckets, collided, most) = collisions(&accounts);
+        assert!(buckets.is_power_of_two() && buckets * 7 / 8 >= accounts.len(), "{} buckets", buckets);
+        let homes: Set<u64> = accounts.keys().map(|c| Hasher::default().hash_one(c) & (buckets as u64 - 1)).collect();
+        assert_eq!((collided, most > 1), (1000 - homes.len(), collided > 0));
+        assert_eq!(collisions(&Accounts::default()), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut accounts = Accounts::default();
+        let mut operations = Operations::default();
+        // the declined withdrawal is logged all the same
+        for txn in [Txn::deposit(1, 1, dec!(2)), Txn::deposit(1, 2, dec!(2)), Txn::withdrawal(1, 3, dec!(9)), Txn::deposit(2, 4, dec!(1))] {
+            operations.count(txn.client);
+            let _ = execute_with(&mut accounts, txn, &Config::default());
+        }
+        let stats = Stats { operations: &operations, accounts: &accounts }.to_string();
+        assert!(stats.starts_with("\
+operations: 4 over 2 clients, 2.0 a client
+
+hottest 10 clients
+client  operations  share  logged
+1                3  75.0%       3
+2                1  25.0%       1
+
+shards  busiest"), "{}", stats);
+        assert!(stats.contains("\
+logged     accounts
+0                 0
+1 - 9             2
+10 - 99           0
+100 - 999         0
+>= 1000           0
+largest log: 3, mean 2.0, in "), "{}", stats);
+        let (buckets, collided, most) = collisions(&accounts);
+        assert!(stats.ends_with(&format!("accounts map: 2 clients in {} buckets, {} in a bucket another hashed to first, at most {} to one\n",
+                                         buckets, collided, most)), "{}", stats);
+    }
+}
@@ -0,0 +1,142 @@
+//! `--stream-output`: balances written as the input goes, for inputs with more clients than fit in memory. the
+//! input has to be sorted by client, so every client's rows come together: once a row for a later client is read,
+//! the accounts so far are final, and they're checked, written out and dropped. a row for an earlier client than
+//! the one before it ends the run with an error, as its account has been written already.
+//!
+//! output is csv or json, in the input's client order (so sorted already), and only a local csv file read in one
+//! pass is streamed.
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use crate::config::{Config, OutputOptions};
+use crate::report::Report;
+use crate::sink::{AccountRow, AccountSink, CsvSink, JsonSink};
+use crate::{Account, Accounts, check_account, ClientId, malformatted, OutputFormat, read_record, record};
+
+/// processes the csv file, writing each account out once it's final, or printing the report when dry running
+pub(crate) fn run(file: std::fs::File, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    if config.dry_run {
+        process_csv(file, config, report, |_, _| Ok(()))?;
+        print!("{}", report);
+        return Ok(std::io::stdout().flush()?);
+    }
+    let options = &config.output;
+    let mut sink = open(options)?;
+    process_csv(file, config, report, |client, account| match options.empty_accounts || !account.is_empty() {
+        true => sink.write(&AccountRow::new(client, account)),
+        false => Ok(())
+    })?;
+    sink.finish()
+}
+
+/// the sink `options.path` names, stdout if none
+fn open(options: &OutputOptions) -> Result<Box<dyn AccountSink>, Box<dyn std::error::Error>> {
+    let buffer_size = usize::try_from(options.buffer_size).unwrap_or(usize::MAX);
+    let path = match &options.path {
+        Some(path) => path,
+        None => {
+            let sink = CsvSink::new(std::io::stdout().lock(), buffer_size).decimals(options.decimals).enriched(options.enriched).losses(options.losses)
+                .held_breakdown(options.held_breakdown).columns(&options.columns);
+            return Ok(Box::new(sink));
+        }
+    };
+    let create = || std::fs::File::create(path).map_err(|e| format!("Error writing output file {}: {}", path.display(), e));
+    match OutputFormat::from_path(path) {
+        OutputFormat::Csv => {
+            let sink = CsvSink::new(create()?, buffer_size).decimals(options.decimals).enriched(options.enriched).losses(options.losses)
+                .held_breakdown(options.held_breakdown).columns(&options.columns);
+            Ok(Box::new(sink))
+        },
+        OutputFormat::Json => {
+            let sink = JsonSink::new(create()?, buffer_size).enriched(options.enriched).losses(options.losses)
+                .held_breakdown(options.held_breakdown).columns(&options.columns);
+            Ok(Box::new(sink))
+        },
+        _ => Err(format!("{}: streamed output is csv or json", path.display()).into())
+    }
+}
+
+/// executes the rows, handing each account to `finalized` once the input's past its client
+fn process_csv<R: Read, F>(reader: R, config: &Config, report: &mut Report, mut finalized: F) -> Result<(), Box<dyn std::error::Error>>
+    where F: FnMut(ClientId, &Account) -> Result<(), Box<dyn std::error::Error>> {
+    let mut accounts = Accounts::default();
+    let mut last = None;
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut row = csv::StringRecord::new();
+    loop {
+        let read = match reader.read_record(&mut row) {
+            Ok(false) => break,
+            Ok(true) => read_record(&mut row, config),
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            Err(e) => Err(e.into())
+        };
+        let txn = match read {
+            Ok(txn) => txn,
+            Err(e) => {
+                malformatted(config, report, "row", e)?;
+                continue;
+            }
+        };
+        let line = row.position().map_or(0, csv::Position::line);
+        match last {
+            Some(last) if txn.client < last => {
+                return Err(format!("line {}: client {} after client {}, streamed output needs input sorted by client",
+                                   line, txn.client, last).into());
+            },
+            Some(last) if txn.client > last => finalize(&mut accounts, &mut finalized)?,
+            _ => ()
+        }
+        last = Some(txn.client);
+        record(&mut accounts, txn, config, report)?;
+    }
+    finalize(&mut accounts, &mut finalized)
+}
+
+fn finalize<F>(accounts: &mut Accounts, finalized: &mut F) -> Result<(), Box<dyn std::error::Error>>
+    where F: FnMut(ClientId, &Account) -> Result<(), Box<dyn std::error::Error>> {
+    for (client, account) in accounts.drain() {
+        check_account(&client, &account)?;
+        finalized(client, &account)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::config::{Config, ErrorPolicy};
+    use crate::report::Report;
+
+    use super::process_csv;
+
+    #[test]
+    fn test_finalized_in_order() {
+        let csv = "type,client,tx,amount\ndeposit,1,1,5\ndeposit,1,2,2\ndispute,1,2,\ndeposit,2,3,1\nbogus\n\
+                   withdrawal,2,4,1\nresolve,3,2,\ndeposit,7,5,3\n";
+        let config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        let mut report = Report::default();
+        let mut finalized = Vec::new();
+        process_csv(csv.as_bytes(), &config, &mut report, |client, account| {
+            finalized.push((client.0, account.balance.available.to_decimal(), account.balance.held.to_decimal()));
+            Ok(())
+        }).unwrap();
+
+        // client 3's resolve opened no account
+        assert_eq!(finalized, vec![(1, dec!(5), dec!(2)), (2, dec!(0), dec!(0)), (7, dec!(3), dec!(0))]);
+        assert_eq!(report.skipped, 1);
+    }
+
+    #[test]
+    fn test_unsorted() {
+        let csv = "type,client,tx,amount\ndeposit,2,1,5\ndeposit,1,2,2\n";
+        let mut written = 0;
+        let e = process_csv(csv.as_bytes(), &Config::default(), &mut Report::default(), |_, _| {
+            written += 1;
+            Ok(())
+        }).err().unwrap();
+        assert_eq!(e.to_string(), "line 3: client 1 after client 2, streamed output needs input sorted by client");
+        assert_eq!(written, 0);
+    }
+}
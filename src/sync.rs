@@ -0,0 +1,16 @@
+//! the locks, channels & threads accounts are handed between by the concurrent engine & the actors: std's, or
+//! built with `--cfg loom` loom's, which run a test's threads through every interleaving of their operations on
+//! them. only the loom tests run under loom, the rest need std's threads:
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib loom
+//! ```
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::{mpsc, Mutex, RwLock};
+#[cfg(not(loom))]
+pub(crate) use std::thread;
+
+#[cfg(loom)]
+pub(crate) use loom::sync::{mpsc, Mutex, RwLock};
+#[cfg(loom)]
+pub(crate) use loom::thread;
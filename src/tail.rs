@@ -0,0 +1,80 @@
+//! `txn tail`: follows a csv file as it's appended to, like `tail -f`.
+//!
+//! only complete lines are read, so a row caught mid-write is picked up on the next poll.
+//! quoted fields spanning lines aren't supported. the file shrinking (truncation, rotation) is an error,
+//! as rows already applied can't be told apart from new ones.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub(crate) struct Tail {
+    file: File,
+    /// bytes consumed so far, always at a line boundary
+    offset: u64,
+    header_read: bool
+}
+
+impl Tail {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self { file: File::open(path)?, offset: 0, header_read: false })
+    }
+
+    /// records appended since the last poll, empty if there are none yet
+    pub(crate) fn poll(&mut self) -> io::Result<Vec<csv::Result<csv::StringRecord>>> {
+        let len = self.file.metadata()?.len();
+        if len < self.offset {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "file truncated while tailing"));
+        }
+
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        let mut appended = Vec::new();
+        (&self.file).take(len - self.offset).read_to_end(&mut appended)?;
+        let complete = match appended.iter().rposition(|b| *b == b'\n') {
+            Some(i) => i + 1,
+            None => return Ok(Vec::new())
+        };
+        self.offset += complete as u64;
+
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(!self.header_read)
+            .from_reader(&appended[..complete]);
+        self.header_read = true;
+        Ok(reader.into_records().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    use super::Tail;
+
+    fn fields(rows: Vec<csv::Result<csv::StringRecord>>) -> Vec<Vec<String>> {
+        rows.into_iter().map(|r| r.unwrap().iter().map(String::from).collect()).collect()
+    }
+
+    #[test]
+    fn test_tail() {
+        let path = std::env::temp_dir().join(format!("txn-tail-test-{}.csv", std::process::id()));
+        std::fs::write(&path, "type,client,tx,amount\ndeposit,1,1,1.0\n").unwrap();
+        let mut tail = Tail::open(&path).unwrap();
+        assert_eq!(fields(tail.poll().unwrap()), vec![vec!["deposit", "1", "1", "1.0"]]);
+        assert!(tail.poll().unwrap().is_empty());
+
+        // a partial row waits for its newline
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"deposit,1,2,").unwrap();
+        assert!(tail.poll().unwrap().is_empty());
+        file.write_all(b"2.0\nwithdrawal,1,3,0.5\n").unwrap();
+        assert_eq!(fields(tail.poll().unwrap()), vec![
+            vec!["deposit", "1", "2", "2.0"],
+            vec!["withdrawal", "1", "3", "0.5"],
+        ]);
+
+        std::fs::write(&path, "type,client,tx,amount\n").unwrap();
+        assert!(tail.poll().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
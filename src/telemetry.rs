@@ -0,0 +1,151 @@
+//! OpenTelemetry export (`--features otel`): with `otel.endpoint` set to an OTLP/http collector
+//! (`http://localhost:4318`), every transaction executed, from a file or the server, is counted and traced:
+//! - `txn.transactions`, a counter by `txn.outcome` (`applied`, or the rejection: `insufficient funds`...),
+//!   for throughput & rejection rates
+//! - `txn.disputes`, a counter by `txn.kind` (`dispute`, `resolve`, `chargeback`), of those applied
+//! - an `ingest batch` span per 64k transactions, with the batch's `txn.rows`, `txn.applied` & `txn.rejected`
+//!
+//! metrics are pushed every minute, spans in the background as batches end, and both are flushed on exit. the
+//! standard `OTEL_EXPORTER_OTLP_HEADERS` & co still apply.
+
+#[cfg(not(feature = "otel"))]
+use crate::{Rejection, TxnType};
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::sync::{Mutex, OnceLock};
+
+    use opentelemetry::metrics::{Counter, MeterProvider};
+    use opentelemetry::trace::{Span, Tracer, TracerProvider};
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::{MetricExporter, Protocol, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::metrics::SdkMeterProvider;
+    use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+    use opentelemetry_sdk::Resource;
+
+    use crate::{Rejection, TxnType};
+
+    /// transactions per `ingest batch` span
+    const BATCH_ROWS: u64 = 64 * 1024;
+
+    static TELEMETRY: OnceLock<Telemetry> = OnceLock::new();
+
+    struct Telemetry {
+        tracer_provider: SdkTracerProvider,
+        meter_provider: SdkMeterProvider,
+        tracer: SdkTracer,
+        transactions: Counter<u64>,
+        disputes: Counter<u64>,
+        batch: Mutex<Batch>
+    }
+
+    #[derive(Default)]
+    struct Batch {
+        span: Option<opentelemetry_sdk::trace::Span>,
+        rows: u64,
+        applied: u64
+    }
+
+    impl Batch {
+        fn end(&mut self) {
+            if let Some(mut span) = self.span.take() {
+                span.set_attributes([
+                    KeyValue::new("txn.rows", self.rows as i64),
+                    KeyValue::new("txn.applied", self.applied as i64),
+                    KeyValue::new("txn.rejected", (self.rows - self.applied) as i64)
+                ]);
+                span.end();
+            }
+            *self = Batch::default();
+        }
+    }
+
+    pub(crate) fn init(endpoint: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let endpoint = endpoint.trim_end_matches('/');
+        let resource = Resource::builder().with_service_name("txn").build();
+        let spans = SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(format!("{}/v1/traces", endpoint))
+            .build()?;
+        let metrics = MetricExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(format!("{}/v1/metrics", endpoint))
+            .build()?;
+
+        let tracer_provider = SdkTracerProvider::builder().with_batch_exporter(spans).with_resource(resource.clone()).build();
+        let meter_provider = SdkMeterProvider::builder().with_periodic_exporter(metrics).with_resource(resource).build();
+        let meter = meter_provider.meter("txn");
+        let telemetry = Telemetry {
+            tracer: tracer_provider.tracer("txn"),
+            transactions: meter.u64_counter("txn.transactions").with_description("transactions executed").build(),
+            disputes: meter.u64_counter("txn.disputes").with_description("disputes, resolves & chargebacks applied").build(),
+            batch: Mutex::default(),
+            tracer_provider,
+            meter_provider
+        };
+        TELEMETRY.set(telemetry).map_err(|_| "telemetry already initialized".into())
+    }
+
+    /// counts & traces an executed transaction, if telemetry is on
+    pub(crate) fn observe(txntype: &TxnType, result: Result<(), Rejection>) {
+        let telemetry = match TELEMETRY.get() {
+            Some(t) => t,
+            None => return
+        };
+        let outcome = match result {
+            Ok(()) => "applied".to_string(),
+            Err(r) => r.to_string()
+        };
+        telemetry.transactions.add(1, &[KeyValue::new("txn.outcome", outcome)]);
+        let kind = match txntype {
+            TxnType::Dispute => Some("dispute"),
+            TxnType::Resolve => Some("resolve"),
+            TxnType::Chargeback => Some("chargeback"),
+            TxnType::Deposit | TxnType::Withdrawal => None
+        };
+        if let (Some(kind), Ok(())) = (kind, result) {
+            telemetry.disputes.add(1, &[KeyValue::new("txn.kind", kind)]);
+        }
+
+        let mut batch = telemetry.batch.lock().unwrap();
+        if batch.span.is_none() {
+            batch.span = Some(telemetry.tracer.start("ingest batch"));
+        }
+        batch.rows += 1;
+        batch.applied += result.is_ok() as u64;
+        if batch.rows == BATCH_ROWS {
+            batch.end();
+        }
+    }
+
+    /// ends the batch in progress & flushes what's left to export
+    pub(crate) fn shutdown() {
+        if let Some(telemetry) = TELEMETRY.get() {
+            telemetry.batch.lock().unwrap().end();
+            if let Err(e) = telemetry.tracer_provider.shutdown() {
+                eprintln!("telemetry: {}", e);
+            }
+            if let Err(e) = telemetry.meter_provider.shutdown() {
+                eprintln!("telemetry: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+pub(crate) use otel::{init, observe, shutdown};
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn init(_endpoint: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err("OpenTelemetry export requires building with the `otel` feature".into())
+}
+
+/// counts & traces an executed transaction, if telemetry is on
+#[cfg(not(feature = "otel"))]
+#[inline]
+pub(crate) fn observe(_txntype: &TxnType, _result: Result<(), Rejection>) {}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn shutdown() {}
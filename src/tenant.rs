@@ -0,0 +1,142 @@
+//! `--tenants`: one run over input for several tenants, kept apart. rows carry a fifth `tenant` column, each
+//! tenant's clients are its own (client 1 of one tenant isn't client 1 of another), and balances are written to a
+//! file per tenant, `--output` naming them with a `{tenant}` placeholder: `--output out/{tenant}.csv`.
+//!
+//! tenant names become file names, so they're letters, digits, `-` & `_`. the memory limit applies to each
+//! tenant's accounts on their own.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::config::{Config, OutputOptions};
+use crate::report::Report;
+use crate::{Accounts, check_invariants, ClientId, locale, malformatted, Map, Precision, record, Txn, TxnId, TxnType, write_out};
+
+/// what `--output` names tenant files with
+const PLACEHOLDER: &str = "{tenant}";
+
+type Tenants = Map<String, Accounts>;
+
+/// a csv row with its tenant
+#[derive(Deserialize)]
+struct TenantRow {
+    #[serde(rename = "type")]
+    txntype: TxnType,
+    client: ClientId,
+    tx: TxnId,
+    amount: Option<Decimal>,
+    tenant: String
+}
+
+impl TenantRow {
+    fn into_txn(self, precision: Precision) -> Result<(String, Txn), String> {
+        let valid = !self.tenant.is_empty()
+            && self.tenant.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid {
+            return Err(format!("invalid tenant '{}'", self.tenant));
+        }
+        let txn = Txn::builder(self.txntype, self.client, self.tx).amount(self.amount).precision(precision).build()
+            .map_err(|e| e.to_string())?;
+        Ok((self.tenant, txn))
+    }
+}
+
+/// processes the csv file for its tenants, writing each one's balances out, or the report when dry running
+pub(crate) fn run(file: std::fs::File, config: &Config, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    if !config.dry_run {
+        // before the input's read, not after
+        path(&config.output, "")?;
+    }
+    let mut tenants = Tenants::default();
+    process_csv(&mut tenants, file, config, report)?;
+    if config.dry_run {
+        for accounts in tenants.values() {
+            check_invariants(accounts)?;
+        }
+        print!("{}", report);
+        return Ok(std::io::stdout().flush()?);
+    }
+    write_out_all(&tenants, &config.output)
+}
+
+fn process_csv<R: std::io::Read>(tenants: &mut Tenants, reader: R, config: &Config, report: &mut Report)
+                                 -> Result<(), Box<dyn std::error::Error>> {
+    for row in csv::Reader::from_reader(reader).into_records() {
+        let row = row.map_err(|e| e.to_string()).and_then(|mut r| {
+            locale::localize(&mut r, locale::AmountFormat::of(config)).map_err(|e| e.to_string())?;
+            r.trim();
+            r.deserialize::<TenantRow>(None).map_err(|e| e.to_string())?.into_txn(config.amount_precision())
+        });
+        match row {
+            Ok((tenant, txn)) => record(tenants.entry(tenant).or_default(), txn, config, report)?,
+            Err(e) => malformatted(config, report, "row", e)?
+        }
+    }
+    Ok(())
+}
+
+/// each tenant's balances to its own file
+fn write_out_all(tenants: &Tenants, options: &OutputOptions) -> Result<(), Box<dyn std::error::Error>> {
+    for (tenant, accounts) in tenants {
+        check_invariants(accounts)?;
+        write_out(accounts, &OutputOptions { path: Some(path(options, tenant)?), ..options.clone() })?;
+    }
+    Ok(())
+}
+
+fn path(options: &OutputOptions, tenant: &str) -> Result<PathBuf, String> {
+    let path = options.path.as_ref().and_then(|p| p.to_str()).filter(|p| p.contains(PLACEHOLDER))
+        .ok_or("--tenants writes a file per tenant, --output has to name them with {tenant}")?;
+    Ok(PathBuf::from(path.replace(PLACEHOLDER, tenant)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rust_decimal_macros::dec;
+
+    use crate::config::{Config, ErrorPolicy, OutputOptions};
+    use crate::report::Report;
+    use crate::{ClientId, get_balance};
+
+    use super::{path, process_csv, Tenants, write_out_all};
+
+    #[test]
+    fn test_isolated() {
+        let csv = "type,client,tx,amount,tenant\ndeposit,1,1,5,acme\ndeposit,1,1,2,globex\nwithdrawal,1,2,3,globex\n\
+                   deposit,1,3,1,../etc\ndeposit,2,4,1,\n";
+        let config = Config { on_error: ErrorPolicy::Skip, ..Config::default() };
+        let (mut tenants, mut report) = (Tenants::default(), Report::default());
+        process_csv(&mut tenants, csv.as_bytes(), &config, &mut report).unwrap();
+
+        // the same client & tx ids, apart
+        assert_eq!(get_balance(&tenants["acme"], 1).available, dec!(5));
+        assert_eq!(get_balance(&tenants["globex"], 1).available, dec!(2));
+        assert_eq!(tenants.len(), 2);
+        assert_eq!(report.skipped, 2);
+        assert!(!tenants["acme"].contains_key(&ClientId(2)));
+    }
+
+    #[test]
+    fn test_paths() {
+        let options = OutputOptions { path: Some(PathBuf::from("out/{tenant}.csv")), ..OutputOptions::default() };
+        assert_eq!(path(&options, "acme"), Ok(PathBuf::from("out/acme.csv")));
+        assert!(path(&OutputOptions::default(), "acme").is_err());
+
+        let dir = std::env::temp_dir().join(format!("txn-tenant-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = OutputOptions { path: Some(dir.join("{tenant}.csv")), ..OutputOptions::default() };
+        let mut tenants = Tenants::default();
+        let config = Config::default();
+        process_csv(&mut tenants, "type,client,tx,amount,tenant\ndeposit,1,1,5,a\ndeposit,2,1,1,b\n".as_bytes(), &config,
+                    &mut Report::default()).unwrap();
+        write_out_all(&tenants, &options).unwrap();
+        let written = std::fs::read_to_string(dir.join("b.csv")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, "client,available,held,total,locked\n2,1.0000,0.0000,1.0000,false\n");
+    }
+}
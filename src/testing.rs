@@ -0,0 +1,91 @@
+//! proptest `Arbitrary` implementations (`--features testing`), so code embedding the engine can property test its
+//! integration without writing generators of its own: `any::<Txn>()`, `any::<TxnType>()`, `any::<Amount>()` and
+//! the ids. what they generate is what input could hold, as `TxnBuilder` would have built it: a deposit or
+//! withdrawal carries an amount and the rest don't, and an amount is never negative and has at most
+//! `CURRENCY_PRECISION` decimal places, up to a trillion so balances summing plenty of them still fit a fixed-point
+//! build. ids are any their width holds.
+
+use proptest::prelude::*;
+use proptest::strategy::BoxedStrategy;
+use rust_decimal::Decimal;
+
+use crate::amount::Amount;
+use crate::id::{ClientRepr, TxnRepr};
+use crate::{ClientId, CURRENCY_PRECISION, Txn, TxnId, TxnType};
+
+/// the largest amount generated, in units of the precision
+const MAX_UNITS: i64 = 1_000_000_000_000 * 10i64.pow(CURRENCY_PRECISION);
+
+impl Arbitrary for Amount {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (0..=MAX_UNITS).prop_map(|units| {
+            Amount::from_decimal(Decimal::new(units, CURRENCY_PRECISION), CURRENCY_PRECISION).expect("generated amounts fit")
+        }).boxed()
+    }
+}
+
+impl Arbitrary for TxnType {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(TxnType::Deposit),
+            Just(TxnType::Withdrawal),
+            Just(TxnType::Dispute),
+            Just(TxnType::Resolve),
+            Just(TxnType::Chargeback)
+        ].boxed()
+    }
+}
+
+impl Arbitrary for ClientId {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        any::<ClientRepr>().prop_map(ClientId).boxed()
+    }
+}
+
+impl Arbitrary for TxnId {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        any::<TxnRepr>().prop_map(TxnId).boxed()
+    }
+}
+
+impl Arbitrary for Txn {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (any::<TxnType>(), any::<ClientId>(), any::<TxnId>(), any::<Amount>()).prop_map(|(txntype, client, tx, amount)| {
+            let amount = match txntype {
+                TxnType::Deposit | TxnType::Withdrawal => Some(amount),
+                TxnType::Dispute | TxnType::Resolve | TxnType::Chargeback => None
+            };
+            Txn::new(txntype, client, tx, amount)
+        }).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::Txn;
+
+    proptest! {
+        #[test]
+        fn prop_txns_are_buildable(txn in any::<Txn>()) {
+            let built = Txn::builder(txn.txntype.clone(), txn.client, txn.tx).amount(txn.amount.map(|a| a.to_decimal())).build();
+            prop_assert_eq!(built, Ok(txn));
+        }
+    }
+}
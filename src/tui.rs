@@ -0,0 +1,149 @@
+//! `--tui`: a dashboard of the server in the terminal, for watching it without a metrics stack: throughput, the
+//! accounts holding the most disputed funds, the latest chargebacks, and why transactions were declined. redrawn
+//! from the server's state four times a second; `q`, esc or ctrl-c quits, stopping the server.
+
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+
+use crate::server::State;
+use crate::{Accounts, Balance, ClientId, Rejection, TxnId};
+
+const TICK: Duration = Duration::from_millis(250);
+/// accounts listed by held funds
+const TOP_HELD: usize = 10;
+
+/// what one frame shows
+struct Snapshot {
+    applied: u64,
+    skipped: u64,
+    rejected: Vec<(Rejection, u64)>,
+    /// rows a second since the last frame
+    rate: f64,
+    accounts: usize,
+    top_held: Vec<(ClientId, Balance)>,
+    /// newest first
+    chargebacks: Vec<(ClientId, TxnId)>
+}
+
+/// rows handled as of the last frame, to take the rate from
+struct Throughput {
+    seen: u64,
+    at: Instant
+}
+
+impl Throughput {
+    fn take(&mut self, state: &State) -> Snapshot {
+        let (applied, skipped, rejected) = {
+            let report = state.report.lock().unwrap();
+            (report.applied, report.skipped, report.rejected.iter().map(|(r, n)| (*r, *n)).collect::<Vec<_>>())
+        };
+        let seen = applied + skipped + rejected.iter().map(|(_, n)| n).sum::<u64>();
+        let now = Instant::now();
+        let rate = (seen - self.seen) as f64 / now.duration_since(self.at).as_secs_f64().max(f64::EPSILON);
+        self.seen = seen;
+        self.at = now;
+
+        let balances = state.engine.balances();
+        let chargebacks = state.chargebacks.lock().unwrap().iter().rev().copied().collect();
+        Snapshot { applied, skipped, rejected, rate, accounts: balances.len(), top_held: top_held(&balances, TOP_HELD), chargebacks }
+    }
+}
+
+/// the `n` accounts holding the most, most first
+fn top_held(accounts: &Accounts, n: usize) -> Vec<(ClientId, Balance)> {
+    let mut held: Vec<(ClientId, Balance)> = accounts.iter()
+        .filter(|(_, a)| a.balance.held > crate::Amount::ZERO)
+        .map(|(client, a)| (*client, a.balance))
+        .collect();
+    held.sort_unstable_by(|(c1, b1), (c2, b2)| b2.held.cmp(&b1.held).then(c1.cmp(c2)));
+    held.truncate(n);
+    held
+}
+
+/// draws until quit
+pub(crate) fn run(state: &State) -> Result<(), Box<dyn std::error::Error>> {
+    let mut terminal = ratatui::init();
+    let mut throughput = Throughput { seen: 0, at: Instant::now() };
+    let result = loop {
+        let snapshot = throughput.take(state);
+        if let Err(e) = terminal.draw(|frame| draw(frame, &snapshot)) {
+            break Err(e.into());
+        }
+        match event::poll(TICK) {
+            Ok(false) => continue,
+            Ok(true) => {},
+            Err(e) => break Err(e.into())
+        }
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if ctrl_c || key.code == KeyCode::Char('q') || key.code == KeyCode::Esc {
+                    break Ok(());
+                }
+            },
+            Ok(_) => {},
+            Err(e) => break Err(e.into())
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+fn draw(frame: &mut Frame, snapshot: &Snapshot) {
+    let [summary, body] = Layout::vertical([Constraint::Length(3), Constraint::Fill(1)]).areas(frame.area());
+    let [held, right] = Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(body);
+    let [chargebacks, rejections] = Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(right);
+
+    let rejected: u64 = snapshot.rejected.iter().map(|(_, n)| n).sum();
+    let line = format!("{:.0} rows/s   applied {}   rejected {}   skipped {}   accounts {}   (q quits)",
+                       snapshot.rate, snapshot.applied, rejected, snapshot.skipped, snapshot.accounts);
+    frame.render_widget(Paragraph::new(line).block(Block::bordered().title(" txn ")), summary);
+
+    let header = |cells: &[&'static str]| Row::new(cells.to_vec()).style(Style::default().add_modifier(Modifier::BOLD));
+    let rows = snapshot.top_held.iter()
+        .map(|(client, b)| Row::new([client.to_string(), b.held.to_string(), b.available.to_string(), b.total.to_string()]));
+    let table = Table::new(rows, [Constraint::Length(6), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1)])
+        .header(header(&["client", "held", "available", "total"]))
+        .block(Block::bordered().title(" most held "));
+    frame.render_widget(table, held);
+
+    let rows = snapshot.chargebacks.iter().map(|(client, tx)| Row::new([client.to_string(), tx.to_string()]));
+    let table = Table::new(rows, [Constraint::Length(6), Constraint::Fill(1)])
+        .header(header(&["client", "tx"]))
+        .block(Block::bordered().title(" recent chargebacks "));
+    frame.render_widget(table, chargebacks);
+
+    let rows = snapshot.rejected.iter().map(|(reason, n)| Row::new([reason.to_string(), n.to_string()]));
+    let table = Table::new(rows, [Constraint::Fill(1), Constraint::Length(12)])
+        .header(header(&["reason", "count"]))
+        .block(Block::bordered().title(" rejections "));
+    frame.render_widget(table, rejections);
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::{Accounts, ClientId, execute, Txn};
+
+    use super::top_held;
+
+    #[test]
+    fn test_top_held() {
+        let mut accounts = Accounts::default();
+        for (client, amount) in [(1, dec!(5)), (2, dec!(50)), (3, dec!(1)), (4, dec!(50))] {
+            execute(&mut accounts, Txn::deposit(client, client as u32, amount));
+            execute(&mut accounts, Txn::dispute(client, client as u32));
+        }
+        // holding nothing
+        execute(&mut accounts, Txn::deposit(5, 5, dec!(100)));
+
+        let top: Vec<_> = top_held(&accounts, 3).into_iter().map(|(client, b)| (client, b.held)).collect();
+        assert_eq!(top, vec![(ClientId(2), crate::amount(dec!(50))), (ClientId(4), crate::amount(dec!(50))), (ClientId(1), crate::amount(dec!(5)))]);
+    }
+}
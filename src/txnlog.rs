@@ -0,0 +1,328 @@
+//! an account's transaction log, the deposits & withdrawals it may yet need for a dispute. rather than a hash map
+//! per account, millions of which grow & rehash on their own and leave the heap fragmented, the transactions are
+//! kept in pages of `PAGE`, each log holding its pages & an index from tx to slot.
+//!
+//! - a log's transactions fill its pages in the order they're logged, with no gaps: one removed has the last put in
+//!   its place, and a page emptied goes back to its thread's pool for the next log that needs one
+//! - the index is sorted by tx, as ids are mostly logged in increasing order an insert's usually a push, and pruning
+//!   the oldest takes from the front
+//! - pages are all the one size, so the allocator reuses them as they are. a thread's pool keeps up to `FREE` of
+//!   them for reuse, frees any past that, and frees what it holds when the thread ends. `stats` says how many
+//!   pages there are, & how many are free
+//!
+//! nothing locks: a log owns its pages, and the pools are thread local, counted in atomics for `stats`.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::iter::FromIterator;
+use std::mem::size_of;
+use std::ops::Index;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+use crate::{Txn, TxnId};
+
+/// transactions a page holds
+pub(crate) const PAGE: usize = 8;
+
+/// pages a thread's pool keeps, any more given back being freed
+const FREE: usize = 4096;
+
+/// pages allocated & not yet freed, those in logs & those in pools
+static PAGES: AtomicU64 = AtomicU64::new(0);
+/// pages in pools, over every thread
+static FREE_PAGES: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// pages given back on this thread, to be taken again before any new one is allocated
+    static POOL: RefCell<Pool> = const { RefCell::new(Pool(Vec::new())) };
+}
+
+struct Pool(Vec<Vec<Txn>>);
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        let freed = self.0.len() as u64;
+        FREE_PAGES.fetch_sub(freed, Ordering::Relaxed);
+        PAGES.fetch_sub(freed, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct TxnLog {
+    /// each tx & its slot, in tx order
+    index: VecDeque<(TxnId, u32)>,
+    /// slot `s` is `pages[s / PAGE][s % PAGE]`. every page but the last is full
+    pages: Vec<Vec<Txn>>
+}
+
+/// the pool's pages, as `/healthz` reports them
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Stats {
+    /// every page allocated
+    pub(crate) pages: u64,
+    /// those of them in the pool, unused
+    pub(crate) free: u64,
+    pub(crate) bytes: u64
+}
+
+/// each count as it stands, the two read a moment apart while other threads take & give pages back
+pub(crate) fn stats() -> Stats {
+    let free = FREE_PAGES.load(Ordering::Relaxed);
+    let pages = PAGES.load(Ordering::Relaxed).max(free);
+    Stats { pages, free, bytes: pages * page_bytes() }
+}
+
+fn page_bytes() -> u64 {
+    (PAGE * size_of::<Txn>()) as u64
+}
+
+fn take_page() -> Vec<Txn> {
+    // None as the thread ends, its pool gone
+    let page = POOL.try_with(|pool| pool.borrow_mut().0.pop()).ok().flatten();
+    match page {
+        Some(page) => {
+            FREE_PAGES.fetch_sub(1, Ordering::Relaxed);
+            page
+        },
+        None => {
+            PAGES.fetch_add(1, Ordering::Relaxed);
+            Vec::with_capacity(PAGE)
+        }
+    }
+}
+
+fn give_pages(pages: impl Iterator<Item = Vec<Txn>>) {
+    for mut page in pages {
+        page.clear();
+        // freed if the pool's full, or gone as the thread ends
+        let kept = POOL.try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.0.len() == FREE {
+                return false;
+            }
+            pool.0.push(page);
+            true
+        });
+        match kept {
+            Ok(true) => FREE_PAGES.fetch_add(1, Ordering::Relaxed),
+            _ => PAGES.fetch_sub(1, Ordering::Relaxed)
+        };
+    }
+}
+
+impl TxnLog {
+    pub(crate) fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// bytes held by its index & pages
+    pub(crate) fn bytes(&self) -> u64 {
+        (self.index.capacity() * size_of::<(TxnId, u32)>()) as u64 + self.pages.len() as u64 * page_bytes()
+    }
+
+    fn slot(&self, slot: u32) -> &Txn {
+        &self.pages[slot as usize / PAGE][slot as usize % PAGE]
+    }
+
+    fn slot_mut(&mut self, slot: u32) -> &mut Txn {
+        &mut self.pages[slot as usize / PAGE][slot as usize % PAGE]
+    }
+
+    fn find(&self, tx: &TxnId) -> Result<usize, usize> {
+        self.index.binary_search_by_key(tx, |(tx, _)| *tx)
+    }
+
+    pub(crate) fn get(&self, tx: &TxnId) -> Option<&Txn> {
+        self.find(tx).ok().map(|i| self.slot(self.index[i].1))
+    }
+
+    pub(crate) fn contains_key(&self, tx: &TxnId) -> bool {
+        self.find(tx).is_ok()
+    }
+
+    /// logs the transaction under its tx, returning the one it replaces
+    pub(crate) fn insert(&mut self, txn: Txn) -> Option<Txn> {
+        let at = match self.find(&txn.tx) {
+            Ok(i) => return Some(std::mem::replace(self.slot_mut(self.index[i].1), txn)),
+            Err(at) => at
+        };
+        let slot = self.index.len() as u32;
+        if self.pages.last().is_none_or(|page| page.len() == PAGE) {
+            self.pages.push(take_page());
+        }
+        let tx = txn.tx;
+        self.pages.last_mut().expect("a page with room").push(txn);
+        self.index.insert(at, (tx, slot));
+        None
+    }
+
+    pub(crate) fn remove(&mut self, tx: &TxnId) -> Option<Txn> {
+        let (_, slot) = self.index.remove(self.find(tx).ok()?).expect("found");
+        let last = self.pages.last_mut().expect("a page holding the last slot");
+        let mut removed = last.pop().expect("the last slot");
+        if last.is_empty() {
+            give_pages(self.pages.pop().into_iter());
+        }
+        // the last slot's transaction fills the one removed
+        if slot as usize != self.index.len() {
+            let moved = removed.tx;
+            removed = std::mem::replace(self.slot_mut(slot), removed);
+            let i = self.find(&moved).expect("the moved transaction is indexed");
+            self.index[i].1 = slot;
+        }
+        Some(removed)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.index.clear();
+        self.release();
+    }
+
+    /// gives all its pages back to the pool
+    fn release(&mut self) {
+        if !self.pages.is_empty() {
+            give_pages(self.pages.drain(..));
+        }
+    }
+
+    /// the logged txs, in order
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &TxnId> + '_ {
+        self.index.iter().map(|(tx, _)| tx)
+    }
+
+    /// the logged transactions, in tx order
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Txn> + '_ {
+        self.index.iter().map(move |(_, slot)| self.slot(*slot))
+    }
+}
+
+impl Drop for TxnLog {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl Clone for TxnLog {
+    fn clone(&self) -> Self {
+        self.values().cloned().collect()
+    }
+}
+
+impl PartialEq for TxnLog {
+    fn eq(&self, other: &Self) -> bool {
+        self.values().eq(other.values())
+    }
+}
+
+impl Eq for TxnLog {}
+
+impl fmt::Debug for TxnLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.keys().zip(self.values())).finish()
+    }
+}
+
+impl FromIterator<Txn> for TxnLog {
+    fn from_iter<I: IntoIterator<Item = Txn>>(txns: I) -> Self {
+        let mut log = TxnLog::default();
+        for txn in txns {
+            log.insert(txn);
+        }
+        log
+    }
+}
+
+impl Index<&TxnId> for TxnLog {
+    type Output = Txn;
+
+    fn index(&self, tx: &TxnId) -> &Txn {
+        self.get(tx).expect("no transaction logged under that tx")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rust_decimal_macros::dec;
+
+    use crate::{Txn, TxnId};
+
+    use super::{stats, TxnLog, FREE, PAGE, POOL};
+
+    #[test]
+    fn test_log() {
+        let mut log = TxnLog::default();
+        for tx in [5, 1, 3, 2, 4] {
+            assert_eq!(log.insert(Txn::deposit(1, tx, dec!(1))), None);
+        }
+        assert_eq!(log.keys().map(|tx| tx.0).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        assert_eq!(log.insert(Txn::deposit(1, 3, dec!(2))), Some(Txn::deposit(1, 3, dec!(1))));
+        assert_eq!(log[&TxnId(3)], Txn::deposit(1, 3, dec!(2)));
+
+        // removing from the middle moves the last logged, 4, into its slot
+        assert_eq!(log.remove(&TxnId(2)), Some(Txn::deposit(1, 2, dec!(1))));
+        assert_eq!(log.remove(&TxnId(2)), None);
+        assert_eq!(log.values().map(|t| t.tx.0).collect::<Vec<_>>(), [1, 3, 4, 5]);
+        assert_eq!(log.pages[0].iter().map(|t| t.tx.0).collect::<Vec<_>>(), [5, 1, 3, 4]);
+        assert!(log.contains_key(&TxnId(4)) && !log.contains_key(&TxnId(2)));
+
+        let copy = log.clone();
+        assert_eq!(copy, log);
+        log.clear();
+        assert_eq!((log.len(), log.pages.len()), (0, 0));
+        assert_ne!(copy, log);
+    }
+
+    #[test]
+    fn test_matches_map() {
+        // a shuffle of inserts & removes, against a map doing the same
+        let (mut log, mut map) = (TxnLog::default(), BTreeMap::new());
+        let mut x: u32 = 1;
+        for round in 0..20_000 {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            let (n, tx) = (x % 500, TxnId::from(x % 500));
+            if round % 3 == 0 {
+                assert_eq!(log.remove(&tx), map.remove(&tx));
+            } else {
+                let txn = Txn::deposit(1, n, dec!(1));
+                assert_eq!(log.insert(txn.clone()), map.insert(tx, txn));
+            }
+            assert_eq!(log.pages.len(), map.len().div_ceil(PAGE));
+        }
+        assert!(log.values().eq(map.values()));
+        assert!(map.keys().all(|tx| log.get(tx) == map.get(tx)));
+    }
+
+    #[test]
+    fn test_stats() {
+        // other tests' logs take & give back pages alongside
+        let log: TxnLog = (0..PAGE as u32 * 4).map(|tx| Txn::deposit(1, tx, dec!(1))).collect();
+        drop(log);
+        let pool = stats();
+        assert!(pool.pages >= 4 && pool.free <= pool.pages, "{:?}", pool);
+        assert_eq!(pool.bytes, pool.pages * (PAGE * std::mem::size_of::<Txn>()) as u64);
+    }
+
+    #[test]
+    fn test_pool_bounded() {
+        // on a thread of its own, so its pool's only this test's
+        std::thread::spawn(|| {
+            let log: TxnLog = (0..((FREE + 10) * PAGE) as u32).map(|tx| Txn::deposit(1, tx, dec!(1))).collect();
+            assert_eq!(POOL.with(|pool| pool.borrow().0.len()), 0);
+            drop(log);
+            assert_eq!(POOL.with(|pool| pool.borrow().0.len()), FREE);
+            // taken again rather than allocated
+            let log: TxnLog = (0..PAGE as u32 * 2).map(|tx| Txn::deposit(1, tx, dec!(1))).collect();
+            assert_eq!(POOL.with(|pool| pool.borrow().0.len()), FREE - 2);
+            drop(log);
+        }).join().unwrap();
+        let pool = stats();
+        assert!(pool.free <= pool.pages, "{:?}", pool);
+    }
+}
@@ -0,0 +1,374 @@
+//! `--features io-uring`: on linux, a local csv file processed in one pass is read through an io_uring, `DEPTH`
+//! reads of `BLOCK` bytes kept in flight ahead of the parser. the parser takes each block as it completes while the
+//! kernel fills the next ones, rather than every read waiting on the disk in turn, which is what an nvme drive
+//! needs to be kept busy.
+//!
+//! the file's read as long as it was when opened through the ring, anything after through the file as usual. where
+//! a ring can't be set up (another os, a kernel before 5.6, or io_uring disabled by `kernel.io_uring_disabled` or a
+//! seccomp filter) the file's read as it would be without the feature.
+//!
+//! `cargo bench --bench uring --features io-uring` times it against the standard reader.
+
+use std::fs::File;
+use std::io::{self, Read};
+
+/// bytes per read
+#[cfg(target_os = "linux")]
+const BLOCK: usize = 1024 * 1024;
+/// reads in flight
+#[cfg(target_os = "linux")]
+const DEPTH: usize = 4;
+
+/// a file, read through a ring if one could be set up
+pub struct Reader(Source);
+
+enum Source {
+    #[cfg(target_os = "linux")]
+    Ring(ring::Ring),
+    File(File)
+}
+
+/// reads the file from the start, through a ring where there can be one
+pub fn open(file: File) -> Reader {
+    #[cfg(target_os = "linux")]
+    if let Ok(ring) = ring::Uring::new(DEPTH as u32) {
+        return Reader(Source::Ring(ring::Ring::new(ring, file)));
+    }
+    Reader(Source::File(file))
+}
+
+impl Reader {
+    /// whether it's reading through a ring, rather than falling back to the file
+    pub fn is_ring(&self) -> bool {
+        !matches!(self.0, Source::File(_))
+    }
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            #[cfg(target_os = "linux")]
+            Source::Ring(ring) => ring.read(buf),
+            Source::File(file) => file.read(buf)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod ring {
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::mem::size_of;
+    use std::os::fd::{AsRawFd, OwnedFd};
+    use std::os::unix::fs::FileExt;
+    use std::ptr::null_mut;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use rustix::io_uring::{io_uring_cqe, io_uring_enter, io_uring_params, io_uring_ptr, io_uring_setup, io_uring_sqe,
+                           io_uring_user_data, IoringEnterFlags, IoringOp, IORING_OFF_CQ_RING, IORING_OFF_SQES,
+                           IORING_OFF_SQ_RING};
+    use rustix::mm::{mmap, munmap, MapFlags, ProtFlags};
+
+    use super::{BLOCK, DEPTH};
+
+    /// the kernel's queues: submissions in, completions out, each shared through a mapping
+    pub(super) struct Uring {
+        fd: OwnedFd,
+        /// the submission ring, the completion ring & the submission entries, unmapped on drop
+        maps: Vec<(*mut c_void, usize)>,
+        sq_tail: *const AtomicU32,
+        sq_mask: u32,
+        sq_array: *mut u32,
+        sqes: *mut io_uring_sqe,
+        cq_head: *const AtomicU32,
+        cq_tail: *const AtomicU32,
+        cq_mask: u32,
+        cqes: *const io_uring_cqe
+    }
+
+    // SAFETY: the mappings are only reached through the ring, and it through `&mut self`, on whichever thread holds it
+    unsafe impl Send for Uring {}
+
+    fn map(fd: &OwnedFd, len: usize, offset: u64) -> io::Result<*mut c_void> {
+        // SAFETY: a fresh shared mapping of the ring's memory, nothing else is at the address the kernel picks
+        unsafe { mmap(null_mut(), len, ProtFlags::READ | ProtFlags::WRITE, MapFlags::SHARED | MapFlags::POPULATE, fd, offset) }
+            .map_err(io::Error::from)
+    }
+
+    impl Uring {
+        pub(super) fn new(entries: u32) -> io::Result<Self> {
+            let mut params = io_uring_params::default();
+            // SAFETY: no flags that read anything more than the params
+            let fd = unsafe { io_uring_setup(entries, &mut params) }?;
+            let (sq, cq) = (params.sq_off, params.cq_off);
+            let sizes = [(sq.array as usize + params.sq_entries as usize * size_of::<u32>(), IORING_OFF_SQ_RING),
+                         (cq.cqes as usize + params.cq_entries as usize * size_of::<io_uring_cqe>(), IORING_OFF_CQ_RING),
+                         (params.sq_entries as usize * size_of::<io_uring_sqe>(), IORING_OFF_SQES)];
+            let mut maps = Vec::new();
+            for (len, offset) in sizes {
+                match map(&fd, len, offset) {
+                    Ok(ptr) => maps.push((ptr, len)),
+                    Err(e) => {
+                        unmap(&maps);
+                        return Err(e);
+                    }
+                }
+            }
+            let (sq_ring, cq_ring) = (maps[0].0 as *mut u8, maps[1].0 as *mut u8);
+            // SAFETY: the offsets the kernel gave, within the mappings sized from them
+            unsafe {
+                Ok(Uring {
+                    sq_tail: sq_ring.add(sq.tail as usize) as *const AtomicU32,
+                    sq_mask: *(sq_ring.add(sq.ring_mask as usize) as *const u32),
+                    sq_array: sq_ring.add(sq.array as usize) as *mut u32,
+                    sqes: maps[2].0 as *mut io_uring_sqe,
+                    cq_head: cq_ring.add(cq.head as usize) as *const AtomicU32,
+                    cq_tail: cq_ring.add(cq.tail as usize) as *const AtomicU32,
+                    cq_mask: *(cq_ring.add(cq.ring_mask as usize) as *const u32),
+                    cqes: cq_ring.add(cq.cqes as usize) as *const io_uring_cqe,
+                    fd,
+                    maps
+                })
+            }
+        }
+
+        /// queues a read of `len` bytes at `offset` into `buf`, tagged `tag`, and submits it
+        ///
+        /// # Safety
+        /// `buf` must stay valid for `len` bytes until the read's completion has been taken
+        unsafe fn read(&mut self, file: &File, offset: u64, buf: *mut u8, len: usize, tag: u64) -> io::Result<()> {
+            let tail = (*self.sq_tail).load(Ordering::Relaxed);
+            let index = tail & self.sq_mask;
+            let mut sqe = io_uring_sqe { opcode: IoringOp::Read, fd: file.as_raw_fd(), ..Default::default() };
+            sqe.off_or_addr2.off = offset;
+            sqe.addr_or_splice_off_in.addr = io_uring_ptr::new(buf as *mut c_void);
+            sqe.len.len = len as u32;
+            sqe.user_data = io_uring_user_data::from_u64(tag);
+            self.sqes.add(index as usize).write(sqe);
+            self.sq_array.add(index as usize).write(index);
+            (*self.sq_tail).store(tail.wrapping_add(1), Ordering::Release);
+            loop {
+                match io_uring_enter(&self.fd, 1, 0, IoringEnterFlags::empty()) {
+                    Err(rustix::io::Errno::INTR) => continue,
+                    result => return result.map(|_| ()).map_err(io::Error::from)
+                }
+            }
+        }
+
+        /// waits for at least one read to complete, then takes every completion there is, as (tag, result)
+        fn complete(&mut self, completions: &mut Vec<(u64, i32)>) -> io::Result<()> {
+            // SAFETY: nothing's submitted, and the completion ring's only read
+            match unsafe { io_uring_enter(&self.fd, 0, 1, IoringEnterFlags::GETEVENTS) } {
+                Ok(_) | Err(rustix::io::Errno::INTR) => (),
+                Err(e) => return Err(e.into())
+            }
+            // SAFETY: the kernel's written the entries up to the tail, and doesn't reuse them until the head passes
+            unsafe {
+                let mut head = (*self.cq_head).load(Ordering::Relaxed);
+                let tail = (*self.cq_tail).load(Ordering::Acquire);
+                while head != tail {
+                    let cqe = &*self.cqes.add((head & self.cq_mask) as usize);
+                    completions.push((cqe.user_data.u64_(), cqe.res));
+                    head = head.wrapping_add(1);
+                }
+                (*self.cq_head).store(head, Ordering::Release);
+            }
+            Ok(())
+        }
+    }
+
+    fn unmap(maps: &[(*mut c_void, usize)]) {
+        for &(ptr, len) in maps {
+            // SAFETY: a mapping of that length made in `new`, not used after
+            let _ = unsafe { munmap(ptr, len) };
+        }
+    }
+
+    impl Drop for Uring {
+        fn drop(&mut self) {
+            unmap(&self.maps);
+        }
+    }
+
+    /// a block's buffer & the read filling it
+    struct Block {
+        buf: Box<[u8]>,
+        offset: u64,
+        /// bytes the read's for, fewer than `BLOCK` at the end of the file
+        len: usize,
+        filled: usize,
+        in_flight: bool,
+        error: Option<io::Error>
+    }
+
+    /// a file read a block at a time through the ring, block `n` into slot `n % DEPTH`
+    pub(super) struct Ring {
+        // dropped before the buffers, once nothing's left in flight
+        uring: Uring,
+        file: File,
+        /// what's read through the ring, the file's length when opened
+        len: u64,
+        blocks: Vec<Block>,
+        /// the block being taken from, and how far into it
+        current: u64,
+        taken: usize,
+        /// the next block to read
+        next: u64,
+        /// past `len`, where the file's read up to
+        after: u64,
+        completions: Vec<(u64, i32)>
+    }
+
+    impl Ring {
+        pub(super) fn new(uring: Uring, file: File) -> Self {
+            let len = file.metadata().map_or(0, |m| m.len());
+            let blocks = (0..DEPTH)
+                .map(|_| Block { buf: vec![0; BLOCK].into_boxed_slice(), offset: 0, len: 0, filled: 0, in_flight: false, error: None })
+                .collect();
+            let mut ring = Ring { uring, file, len, blocks, current: 0, taken: 0, next: 0, after: len, completions: Vec::new() };
+            for _ in 0..DEPTH {
+                ring.submit_next();
+            }
+            ring
+        }
+
+        /// reads the next block into its slot, if there's any left
+        fn submit_next(&mut self) {
+            let offset = self.next * BLOCK as u64;
+            if offset >= self.len {
+                return;
+            }
+            let slot = (self.next % DEPTH as u64) as usize;
+            let block = &mut self.blocks[slot];
+            *block = Block { buf: std::mem::take(&mut block.buf), offset, len: (self.len - offset).min(BLOCK as u64) as usize,
+                             filled: 0, in_flight: false, error: None };
+            self.next += 1;
+            self.resubmit(slot);
+        }
+
+        /// reads what's left of the slot's block
+        fn resubmit(&mut self, slot: usize) {
+            let block = &mut self.blocks[slot];
+            let (offset, len) = (block.offset + block.filled as u64, block.len - block.filled);
+            // SAFETY: the buffer's only dropped once the ring has nothing in flight, see `Drop`
+            let buf = unsafe { block.buf.as_mut_ptr().add(block.filled) };
+            match unsafe { self.uring.read(&self.file, offset, buf, len, slot as u64) } {
+                Ok(()) => block.in_flight = true,
+                Err(e) => block.error = Some(e)
+            }
+        }
+
+        /// takes the completions there are, waiting for at least one
+        fn complete(&mut self) -> io::Result<()> {
+            let mut completions = std::mem::take(&mut self.completions);
+            let result = self.uring.complete(&mut completions);
+            for (slot, res) in completions.drain(..) {
+                let block = &mut self.blocks[slot as usize];
+                block.in_flight = false;
+                match res {
+                    res if res < 0 => block.error = Some(io::Error::from_raw_os_error(-res)),
+                    // the file's shorter than it was, the block ends there
+                    0 => block.len = block.filled,
+                    res => {
+                        block.filled += res as usize;
+                        if block.filled < block.len {
+                            self.resubmit(slot as usize);
+                        }
+                    }
+                }
+            }
+            self.completions = completions;
+            result
+        }
+    }
+
+    impl Read for Ring {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            while self.current < self.next {
+                let slot = (self.current % DEPTH as u64) as usize;
+                while self.blocks[slot].in_flight {
+                    self.complete()?;
+                }
+                let block = &mut self.blocks[slot];
+                if let Some(e) = block.error.take() {
+                    return Err(e);
+                }
+                if block.filled < block.len {
+                    // a short read that couldn't be resubmitted
+                    return Err(io::Error::other("io_uring read fell short"));
+                }
+                if self.taken < block.filled {
+                    let n = buf.len().min(block.filled - self.taken);
+                    buf[..n].copy_from_slice(&block.buf[self.taken..self.taken + n]);
+                    self.taken += n;
+                    return Ok(n);
+                }
+                // the slot's free for the block `DEPTH` on
+                self.current += 1;
+                self.taken = 0;
+                self.submit_next();
+            }
+            let n = self.file.read_at(buf, self.after)?;
+            self.after += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Drop for Ring {
+        fn drop(&mut self) {
+            // the kernel may still be writing into the buffers
+            while self.blocks.iter().any(|b| b.in_flight) {
+                if self.complete().is_err() {
+                    // leaked rather than freed under a read
+                    std::mem::forget(std::mem::take(&mut self.blocks));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::open;
+
+    #[test]
+    fn test_matches_file() {
+        let path = std::env::temp_dir().join(format!("txn-uring-test-{}.csv", std::process::id()));
+        // a few blocks & a part
+        let data: Vec<u8> = (0..3_500_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+        for size in [1, 4096, 8192 * 1024] {
+            let mut reader = open(std::fs::File::open(&path).unwrap());
+            let (mut read, mut buf) = (Vec::new(), vec![0; size]);
+            // a byte at a time through the first few, the rest at `size`
+            loop {
+                let n = match read.len() < 100 {
+                    true => reader.read(&mut buf[..1]).unwrap(),
+                    false => reader.read(&mut buf).unwrap()
+                };
+                if n == 0 {
+                    break;
+                }
+                read.extend_from_slice(&buf[..n]);
+            }
+            assert!(read == data, "read {} bytes of {}, ring {}", read.len(), data.len(), reader.is_ring());
+        }
+
+        // dropped with reads in flight
+        let mut reader = open(std::fs::File::open(&path).unwrap());
+        reader.read_exact(&mut [0; 10]).unwrap();
+        drop(reader);
+
+        // what's appended after opening is read after the rest
+        let mut reader = open(std::fs::File::open(&path).unwrap());
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"more").unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read.len(), data.len() + 4);
+        std::fs::remove_file(&path).unwrap();
+    }
+}